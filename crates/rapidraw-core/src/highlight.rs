@@ -0,0 +1,89 @@
+//! Hue-preserving highlight compression applied to developed RAW data.
+
+/// Channel value where compression starts: sensor white after rescaling.
+pub const HIGHLIGHT_KNEE: f32 = 1.0;
+
+/// Pulls over-range colors toward their own minimum channel, so they fade
+/// to white instead of clipping to a shifted hue, then rescales so the
+/// brightest channel keeps its value. Desaturation grows linearly from
+/// nothing at `knee` to complete at `limit` (clamped to just above `knee`).
+#[inline]
+pub fn compress_highlights(rgb: [f32; 3], knee: f32, limit: f32) -> [f32; 3] {
+    let [r, g, b] = rgb;
+    let max_c = r.max(g).max(b);
+    if max_c <= knee {
+        return rgb;
+    }
+
+    let limit = limit.max(knee + 0.01);
+    let min_c = r.min(g).min(b);
+    let compression_factor = (1.0 - (max_c - knee) / (limit - knee)).clamp(0.0, 1.0);
+    let compressed = [
+        min_c + (r - min_c) * compression_factor,
+        min_c + (g - min_c) * compression_factor,
+        min_c + (b - min_c) * compression_factor,
+    ];
+    let compressed_max = compressed[0].max(compressed[1]).max(compressed[2]);
+
+    if compressed_max > 1e-6 {
+        let rescale = max_c / compressed_max;
+        [
+            compressed[0] * rescale,
+            compressed[1] * rescale,
+            compressed[2] * rescale,
+        ]
+    } else {
+        [max_c, max_c, max_c]
+    }
+}
+
+/// The step both RAW develop paths run on demosaiced RGB: scales sensor
+/// values by `rescale_factor`, drops negative noise and rolls off everything
+/// past sensor white with [`compress_highlights`].
+pub fn rescale_and_compress(
+    pixels: &mut [[f32; 3]],
+    rescale_factor: f32,
+    highlight_compression: f32,
+) {
+    for p in pixels {
+        let rgb = p.map(|c| (c * rescale_factor).max(0.0));
+        *p = compress_highlights(rgb, HIGHLIGHT_KNEE, highlight_compression);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_below_the_knee_are_untouched() {
+        let rgb = [0.9, 0.4, 0.1];
+        assert_eq!(compress_highlights(rgb, HIGHLIGHT_KNEE, 2.0), rgb);
+        assert_eq!(
+            compress_highlights([1.0, 0.2, 0.0], HIGHLIGHT_KNEE, 2.0),
+            [1.0, 0.2, 0.0]
+        );
+    }
+
+    #[test]
+    fn over_range_colors_fade_to_white_at_the_limit() {
+        let [r, g, b] = compress_highlights([3.0, 1.5, 1.2], HIGHLIGHT_KNEE, 3.0);
+        assert_eq!(r, 3.0);
+        assert!((g - 3.0).abs() < 1e-6 && (b - 3.0).abs() < 1e-6);
+
+        let partial = compress_highlights([2.0, 1.0, 0.5], HIGHLIGHT_KNEE, 3.0);
+        assert_eq!(partial[0], 2.0);
+        assert!(partial[1] > 1.0 && partial[2] > 0.5 && partial[2] < partial[1]);
+    }
+
+    #[test]
+    fn develop_step_rescales_clamps_and_compresses() {
+        // Desktop and WASM `develop_internal` both run this on demosaiced RGB.
+        let mut pixels = [[0.25, 0.1, -0.05], [0.9, 0.45, 0.3], [0.5, 0.5, 0.5]];
+        rescale_and_compress(&mut pixels, 2.0, 3.0);
+        assert_eq!(pixels[0], [0.5, 0.2, 0.0]);
+        assert_eq!(pixels[1][0], 1.8);
+        assert!((pixels[1][1] - 1.0636).abs() < 1e-4 && (pixels[1][2] - 0.8182).abs() < 1e-4);
+        assert_eq!(pixels[2], [1.0; 3]);
+    }
+}
@@ -0,0 +1,9 @@
+//! Color math shared by the desktop app (`src-tauri`) and the WASM pipeline
+//! (`pwa/wasm`), so both develop and grade images the same way.
+
+pub mod highlight;
+pub mod lut;
+pub mod luma;
+#[cfg(feature = "raw")]
+pub mod raw_sanity;
+pub mod srgb;
@@ -0,0 +1,41 @@
+//! Luminance weights shared by the pixel pipelines.
+//!
+//! RGB with sRGB/Rec.709 primaries uses `Rec709`. The WASM RAW develop builds a
+//! JPEG-style YCbCr split for chroma filtering, whose Cb/Cr matrices are
+//! derived from `Rec601`, so it keeps those weights. That split only exists
+//! with RAW support, hence the `raw` feature gate on `Rec601`.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LumaCoefficients {
+    #[cfg(feature = "raw")]
+    Rec601,
+    Rec709,
+}
+
+impl LumaCoefficients {
+    pub const fn weights(self) -> [f32; 3] {
+        match self {
+            #[cfg(feature = "raw")]
+            LumaCoefficients::Rec601 => [0.299, 0.587, 0.114],
+            LumaCoefficients::Rec709 => [0.2126, 0.7152, 0.0722],
+        }
+    }
+}
+
+#[inline(always)]
+pub fn luma(r: f32, g: f32, b: f32, coefficients: LumaCoefficients) -> f32 {
+    let [wr, wg, wb] = coefficients.weights();
+    wr * r + wg * g + wb * b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn white_has_unit_luma() {
+        assert!((luma(1.0, 1.0, 1.0, LumaCoefficients::Rec709) - 1.0).abs() < 1e-6);
+        #[cfg(feature = "raw")]
+        assert!((luma(1.0, 1.0, 1.0, LumaCoefficients::Rec601) - 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,232 @@
+//! 3D LUTs: `.cube` parsing and tetrahedral sampling.
+
+use anyhow::{Result, anyhow};
+
+pub const MIN_LUT_SIZE: usize = 2;
+pub const MAX_LUT_SIZE: usize = 65;
+
+/// A 3D LUT in `.cube` order (red varying fastest). Inputs are mapped from
+/// `domain_min..domain_max` onto the lattice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut3d {
+    pub size: usize,
+    pub data: Vec<f32>,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+}
+
+impl Lut3d {
+    /// A LUT over the default 0..1 domain, as read from formats that do not
+    /// record one (`.3dl`, HALD images).
+    pub fn new(size: usize, data: Vec<f32>) -> Self {
+        Self {
+            size,
+            data,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+        }
+    }
+
+    /// Looks `rgb` up, clamped to the LUT's domain.
+    pub fn sample(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let normalized = std::array::from_fn(|c| {
+            let range = self.domain_max[c] - self.domain_min[c];
+            (rgb[c] - self.domain_min[c]) / range
+        });
+        sample_lut_tetrahedral(&self.data, self.size, normalized)
+    }
+
+    /// Looks `rgb` up and blends the result with the input by `intensity`
+    /// (0 keeps the input, 1 is the full LUT).
+    pub fn apply(&self, rgb: [f32; 3], intensity: f32) -> [f32; 3] {
+        let mapped = self.sample(rgb);
+        std::array::from_fn(|c| rgb[c] + (mapped[c] - rgb[c]) * intensity)
+    }
+}
+
+fn parse_floats<const N: usize>(parts: &[&str], line_num: usize) -> Result<[f32; N]> {
+    if parts.len() < N {
+        return Err(anyhow!(
+            "line {line_num}: expected {N} values, found {}",
+            parts.len()
+        ));
+    }
+    let mut values = [0.0; N];
+    for (value, part) in values.iter_mut().zip(parts) {
+        *value = part
+            .parse()
+            .map_err(|e| anyhow!("line {line_num}: invalid number '{part}': {e}"))?;
+    }
+    Ok(values)
+}
+
+/// Parses the text of an Adobe/Resolve `.cube` 3D LUT. 1D LUTs are rejected.
+pub fn parse_cube(text: &str) -> Result<Lut3d> {
+    let mut size: Option<usize> = None;
+    let mut domain_min = [0.0; 3];
+    let mut domain_max = [1.0; 3];
+    let mut data = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line_num = index + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<&str> = trimmed.split_whitespace().collect();
+        match parts[0].to_ascii_uppercase().as_str() {
+            "TITLE" => {}
+            "DOMAIN_MIN" => domain_min = parse_floats(&parts[1..], line_num)?,
+            "DOMAIN_MAX" => domain_max = parse_floats(&parts[1..], line_num)?,
+            "LUT_1D_SIZE" => return Err(anyhow!("1D .cube LUTs are not supported")),
+            "LUT_3D_SIZE" => {
+                let [value] = parse_floats::<1>(&parts[1..], line_num)?;
+                let value = value as usize;
+                if !(MIN_LUT_SIZE..=MAX_LUT_SIZE).contains(&value) {
+                    return Err(anyhow!(
+                        "LUT size {value} is outside the supported range {MIN_LUT_SIZE}..={MAX_LUT_SIZE}"
+                    ));
+                }
+                size = Some(value);
+            }
+            keyword if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {}
+            _ => data.extend_from_slice(&parse_floats::<3>(&parts, line_num)?),
+        }
+    }
+
+    let size = size.ok_or_else(|| anyhow!("LUT_3D_SIZE not found in .cube file"))?;
+    let expected = size * size * size * 3;
+    if data.len() != expected {
+        return Err(anyhow!(
+            "LUT has {} values, expected {expected} for size {size}",
+            data.len()
+        ));
+    }
+    if (0..3).any(|c| domain_max[c] <= domain_min[c]) {
+        return Err(anyhow!(
+            "DOMAIN_MAX {domain_max:?} must be above DOMAIN_MIN {domain_min:?}"
+        ));
+    }
+    Ok(Lut3d {
+        size,
+        data,
+        domain_min,
+        domain_max,
+    })
+}
+
+/// Samples a `.cube`-ordered 3D LUT (red fastest) with tetrahedral
+/// interpolation. `rgb` is clamped to the 0..1 domain.
+pub fn sample_lut_tetrahedral(lut: &[f32], size: usize, rgb: [f32; 3]) -> [f32; 3] {
+    let max_index = (size - 1) as f32;
+    let scaled = rgb.map(|v| v.clamp(0.0, 1.0) * max_index);
+    let base = scaled.map(|v| (v.floor() as usize).min(size - 2));
+    let [fr, fg, fb] = [
+        scaled[0] - base[0] as f32,
+        scaled[1] - base[1] as f32,
+        scaled[2] - base[2] as f32,
+    ];
+
+    let node = |dr: usize, dg: usize, db: usize| -> [f32; 3] {
+        let idx = (((base[2] + db) * size + base[1] + dg) * size + base[0] + dr) * 3;
+        [lut[idx], lut[idx + 1], lut[idx + 2]]
+    };
+
+    let c000 = node(0, 0, 0);
+    let c111 = node(1, 1, 1);
+    // Pick the tetrahedron containing the point by ordering the fractions, then
+    // walk the cube edge by edge from c000 to c111.
+    let (w, c1, c2) = if fr >= fg {
+        if fg >= fb {
+            ([fr, fg, fb], node(1, 0, 0), node(1, 1, 0))
+        } else if fr >= fb {
+            ([fr, fb, fg], node(1, 0, 0), node(1, 0, 1))
+        } else {
+            ([fb, fr, fg], node(0, 0, 1), node(1, 0, 1))
+        }
+    } else if fb > fg {
+        ([fb, fg, fr], node(0, 0, 1), node(0, 1, 1))
+    } else if fb > fr {
+        ([fg, fb, fr], node(0, 1, 0), node(0, 1, 1))
+    } else {
+        ([fg, fr, fb], node(0, 1, 0), node(1, 1, 0))
+    };
+
+    let mut out = [0.0f32; 3];
+    for c in 0..3 {
+        out[c] =
+            c000[c] * (1.0 - w[0]) + c1[c] * (w[0] - w[1]) + c2[c] * (w[1] - w[2]) + c111[c] * w[2];
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `.cube` identity LUT of `size`, optionally with extra header lines.
+    fn identity_cube(size: usize, header: &str) -> String {
+        let mut text = format!("TITLE \"identity\"\n# comment\n{header}LUT_3D_SIZE {size}\n");
+        let max = (size - 1) as f32;
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    text.push_str(&format!(
+                        "{} {} {}\n",
+                        r as f32 / max,
+                        g as f32 / max,
+                        b as f32 / max
+                    ));
+                }
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn identity_cube_maps_colors_to_themselves() {
+        let lut = parse_cube(&identity_cube(5, "")).unwrap();
+        assert_eq!(lut.size, 5);
+        for rgb in [[0.0, 0.0, 0.0], [0.1, 0.6, 0.35], [1.0, 0.25, 0.9]] {
+            let out = lut.sample(rgb);
+            for c in 0..3 {
+                assert!((out[c] - rgb[c]).abs() < 1e-5, "{rgb:?} -> {out:?}");
+            }
+        }
+        // Out-of-range input clamps to the lattice.
+        assert_eq!(lut.sample([2.0, -1.0, 0.5])[..2], [1.0, 0.0]);
+    }
+
+    #[test]
+    fn domain_is_mapped_onto_the_lattice() {
+        let lut = parse_cube(&identity_cube(3, "DOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 2\n")).unwrap();
+        assert_eq!(lut.domain_max, [2.0; 3]);
+        let out = lut.sample([1.0, 0.5, 2.0]);
+        assert!((out[0] - 0.5).abs() < 1e-5 && (out[1] - 0.25).abs() < 1e-5);
+        assert!((out[2] - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn intensity_blends_with_the_input() {
+        let mut lut = parse_cube(&identity_cube(2, "")).unwrap();
+        lut.data.iter_mut().for_each(|v| *v = 1.0 - *v);
+        let rgb = [0.2, 0.4, 0.8];
+        assert_eq!(lut.apply(rgb, 0.0), rgb);
+        let half = lut.apply(rgb, 0.5);
+        for value in half {
+            assert!((value - 0.5).abs() < 1e-5, "{half:?}");
+        }
+    }
+
+    #[test]
+    fn malformed_cubes_are_rejected_with_a_reason() {
+        let error = |text: &str| parse_cube(text).unwrap_err().to_string();
+        assert!(error("0 0 0\n").contains("LUT_3D_SIZE not found"));
+        assert!(error("LUT_1D_SIZE 16\n").contains("1D"));
+        assert!(error("LUT_3D_SIZE 128\n").contains("outside the supported range"));
+        assert!(error("LUT_3D_SIZE 2\n0 0 0\n").contains("expected 24"));
+        assert!(error("LUT_3D_SIZE 2\n0 0.5\n").starts_with("line 2: expected 3 values"));
+        assert!(error("LUT_3D_SIZE 2\n0 x 0\n").contains("invalid number 'x'"));
+        assert!(error(&identity_cube(2, "DOMAIN_MIN 1 0 0\n")).contains("DOMAIN_MAX"));
+    }
+}
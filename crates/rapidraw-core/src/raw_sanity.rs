@@ -0,0 +1,168 @@
+//! Guards against RAW tags that would ruin a develop: unusable white/black
+//! levels and default crops that do not fit the sensor.
+
+use rawler::{
+    formats::tiff::Rational,
+    rawimage::{RawImage, RawImageData, WhiteLevel},
+};
+
+/// Level replacements for a file, worked out from plain values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LevelFallback {
+    /// New white level for every channel, if the recorded one is unusable.
+    white: Option<u32>,
+    /// Whether the black levels have to be reset to zero.
+    zero_black: bool,
+}
+
+fn level_fallback(
+    bps: usize,
+    white: Option<u32>,
+    blacks: &[f32],
+    integer_data: bool,
+) -> LevelFallback {
+    let bit_depth_max = match bps {
+        1..=16 => (1u32 << bps) - 1,
+        _ => u16::MAX as u32,
+    };
+    let black = blacks.first().copied();
+    let white_ok = white.is_some_and(|white| {
+        white > 0
+            && !(integer_data && white > u16::MAX as u32)
+            && black.is_none_or(|black| white as f32 > black)
+    });
+    let new_white = (!white_ok).then_some(bit_depth_max);
+
+    let effective_white = new_white.or(white).unwrap_or(bit_depth_max) as f32;
+    let black_ok = blacks.iter().all(|level| level.is_finite())
+        && black.is_none_or(|black| black < effective_white * 0.5);
+    LevelFallback {
+        white: new_white,
+        zero_black: !black_ok,
+    }
+}
+
+/// Replaces white and black levels that would ruin the exposure. A white
+/// level that is missing, zero, beyond the 16-bit sample range or not above
+/// the black level becomes the bit-depth maximum (16 bits when the depth is
+/// unknown); a black level that is not finite (a zero denominator) or at
+/// least half the white level becomes zero. Returns what was replaced, if anything.
+pub fn sanitize_levels(raw_image: &mut RawImage) -> Option<String> {
+    let white = raw_image.whitelevel.0.first().copied();
+    let blacks: Vec<f32> = raw_image
+        .blacklevel
+        .levels
+        .iter()
+        .map(|level| level.as_f32())
+        .collect();
+    let integer_data = matches!(raw_image.data, RawImageData::Integer(_));
+    let fallback = level_fallback(raw_image.bps, white, &blacks, integer_data);
+
+    let mut replaced = Vec::new();
+    if let Some(new_white) = fallback.white {
+        replaced.push(format!("white level {white:?} -> {new_white}"));
+        let channels = raw_image.whitelevel.0.len().max(1);
+        raw_image.whitelevel = WhiteLevel(vec![new_white; channels]);
+    }
+    if fallback.zero_black {
+        replaced.push(format!("black level {:?} -> 0", blacks.first()));
+        for level in raw_image.blacklevel.levels.iter_mut() {
+            *level = Rational { n: 0, d: 1 };
+        }
+    }
+
+    (!replaced.is_empty()).then(|| replaced.join(", "))
+}
+
+/// Whether `crop` (x, y, width, height) is non-empty and lies inside `area`.
+fn crop_fits(crop: (usize, usize, usize, usize), area: (usize, usize, usize, usize)) -> bool {
+    let (cx, cy, cw, ch) = crop;
+    let (ax, ay, aw, ah) = area;
+    cw > 0 && ch > 0 && cx >= ax && cy >= ay && cx + cw <= ax + aw && cy + ch <= ay + ah
+}
+
+/// ROI invariant: `active_area` and `crop_area` are both in full-sensor
+/// coordinates. Demosaic works on the active area when `CropActiveArea` runs,
+/// and `CropDefault` is then applied relative to the active-area origin, so
+/// the default crop must lie inside the active area (or the full sensor when
+/// there is none). Files whose tags break this would be cropped misaligned;
+/// for those the default crop is skipped and the whole active area is kept.
+pub fn default_crop_in_active_area(raw_image: &RawImage) -> bool {
+    let Some(crop) = raw_image.crop_area else {
+        return true;
+    };
+    let area = match raw_image.active_area {
+        Some(area) => (area.p.x, area.p.y, area.d.w, area.d.h),
+        None => (0, 0, raw_image.width, raw_image.height),
+    };
+    crop_fits((crop.p.x, crop.p.y, crop.d.w, crop.d.h), area)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sane_levels_are_kept() {
+        let fallback = level_fallback(14, Some(16383), &[512.0, 512.0], true);
+        assert_eq!(
+            fallback,
+            LevelFallback {
+                white: None,
+                zero_black: false
+            }
+        );
+    }
+
+    #[test]
+    fn missing_or_zero_white_falls_back_to_the_bit_depth() {
+        assert_eq!(level_fallback(12, None, &[], true).white, Some(4095));
+        assert_eq!(level_fallback(14, Some(0), &[], true).white, Some(16383));
+        assert_eq!(
+            level_fallback(0, None, &[], true).white,
+            Some(u16::MAX as u32)
+        );
+    }
+
+    #[test]
+    fn white_beyond_integer_samples_or_below_black_is_replaced() {
+        assert_eq!(
+            level_fallback(16, Some(70_000), &[], true).white,
+            Some(65535)
+        );
+        assert_eq!(level_fallback(16, Some(70_000), &[], false).white, None);
+        assert_eq!(
+            level_fallback(12, Some(200), &[256.0], true).white,
+            Some(4095)
+        );
+    }
+
+    #[test]
+    fn unusable_black_is_zeroed() {
+        assert!(level_fallback(12, Some(4095), &[f32::NAN], true).zero_black);
+        assert!(level_fallback(12, Some(4095), &[3000.0], true).zero_black);
+        let fallback = level_fallback(12, Some(4095), &[256.0, f32::INFINITY], true);
+        assert_eq!(fallback.white, None);
+        assert!(fallback.zero_black);
+    }
+
+    #[test]
+    fn crop_must_be_non_empty_and_inside_the_area() {
+        let area = (8, 8, 100, 80);
+        assert!(crop_fits((8, 8, 100, 80), area));
+        assert!(crop_fits((10, 12, 50, 40), area));
+        assert!(!crop_fits((10, 12, 0, 40), area));
+        assert!(!crop_fits((4, 12, 50, 40), area));
+        assert!(!crop_fits((60, 12, 50, 40), area));
+        assert!(!crop_fits((0, 0, 1, 1), (0, 0, 0, 0)));
+    }
+
+    #[test]
+    fn crop_is_checked_in_sensor_coordinates() {
+        // The default crop is not relative to the active-area origin.
+        let area = (16, 8, 100, 80);
+        assert!(!crop_fits((0, 0, 100, 80), area));
+        assert!(crop_fits((16, 8, 100, 80), area));
+        assert!(crop_fits((110, 80, 6, 8), area));
+    }
+}
@@ -0,0 +1,48 @@
+//! The sRGB transfer function (IEC 61966-2-1): a linear toe near black
+//! joined to a 2.4 power segment. A plain 2.2 power law is close in the
+//! midtones but lifts or crushes the deep shadows, so everything that encodes
+//! or decodes sRGB goes through this pair.
+
+/// sRGB-encoded value to linear light.
+#[inline(always)]
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Linear light to sRGB-encoded. Negative values clip to zero.
+#[inline(always)]
+pub fn linear_to_srgb(v: f32) -> f32 {
+    let v = v.max(0.0);
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_pair_round_trips_and_matches_the_standard() {
+        for i in 0..=255 {
+            let v = i as f32 / 255.0;
+            assert!((linear_to_srgb(srgb_to_linear(v)) - v).abs() < 1e-5, "{v}");
+        }
+        // 18% gray encodes to about 118/255; the toe is linear.
+        assert!((linear_to_srgb(0.18) * 255.0 - 117.8).abs() < 0.2);
+        assert_eq!(srgb_to_linear(0.02), 0.02 / 12.92);
+        assert_eq!(linear_to_srgb(-0.5), 0.0);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-6);
+        // Near black the linear toe sits well above a 2.2 power law.
+        for code in 1..=10 {
+            let v = code as f32 / 255.0;
+            assert!(srgb_to_linear(v) > v.powf(2.2) * 2.0, "{code}");
+        }
+    }
+}
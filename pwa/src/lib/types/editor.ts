@@ -1,6 +1,7 @@
 export type Adjustments = {
 	exposure: number;
 	contrast: number;
+	contrastMode: 'linear' | 'sCurve' | 'filmic';
 	highlights: number;
 	shadows: number;
 	temperature: number;
@@ -15,6 +16,7 @@ export type Adjustments = {
 export const DEFAULT_ADJUSTMENTS: Adjustments = {
 	exposure: 0,
 	contrast: 0,
+	contrastMode: 'sCurve',
 	highlights: 0,
 	shadows: 0,
 	temperature: 0,
@@ -1,11 +1,27 @@
+use crate::core::rng::pixel_noise;
 use image::DynamicImage;
-use serde::Deserialize;
+use anyhow::{anyhow, Result};
+use rapidraw_core::luma::{self, LumaCoefficients};
+use rapidraw_core::srgb::{linear_to_srgb, srgb_to_linear};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
 
-#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 #[serde(default)]
 pub struct SimpleAdjustments {
     pub exposure: f32,
+    /// Per-channel multipliers applied with exposure in linear light, for
+    /// quick color balancing; 1 leaves the channel unchanged.
+    #[serde(rename = "redGain")]
+    pub red_gain: f32,
+    #[serde(rename = "greenGain")]
+    pub green_gain: f32,
+    #[serde(rename = "blueGain")]
+    pub blue_gain: f32,
     pub contrast: f32,
+    /// How `contrast` shapes the tone response.
+    #[serde(rename = "contrastMode")]
+    pub contrast_mode: ContrastMode,
     pub highlights: f32,
     pub shadows: f32,
     pub temperature: f32,
@@ -15,8 +31,103 @@ pub struct SimpleAdjustments {
     pub clarity: f32,
     pub sharpness: f32,
     pub vignette: f32,
+    /// Film grain, 0..1.
+    #[serde(rename = "grainAmount")]
+    pub grain_amount: f32,
+    /// Seed of the grain pattern; equal seeds give byte-identical output.
+    #[serde(rename = "effectSeed")]
+    pub effect_seed: u64,
 }
 
+impl Default for SimpleAdjustments {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            red_gain: 1.0,
+            green_gain: 1.0,
+            blue_gain: 1.0,
+            contrast: 0.0,
+            contrast_mode: ContrastMode::default(),
+            highlights: 0.0,
+            shadows: 0.0,
+            temperature: 0.0,
+            tint: 0.0,
+            vibrance: 0.0,
+            saturation: 0.0,
+            clarity: 0.0,
+            sharpness: 0.0,
+            vignette: 0.0,
+            grain_amount: 0.0,
+            effect_seed: 0,
+        }
+    }
+}
+
+/// How the contrast amount shapes the tone response around mid-gray 0.5.
+/// Edits saved before the modes existed have no `contrastMode` and keep the
+/// linear contrast they were made with; new edits ask for `sCurve`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ContrastMode {
+    /// A straight line through mid-gray, clipping at black and white.
+    #[default]
+    Linear,
+    /// Power curves meeting at mid-gray: midtones steepen while shadows and
+    /// highlights compress towards 0 and 1 instead of clipping.
+    SCurve,
+    /// A straight midtone section between a short toe and a longer, gentler
+    /// shoulder, like a film characteristic curve. Negative amounts have no
+    /// toe or shoulder to shape and flatten linearly.
+    Filmic,
+}
+
+/// Exponents of the filmic toe and shoulder. The shoulder's lower exponent
+/// gives it the longer roll-off; it also caps the midtone slope.
+const FILMIC_TOE: f32 = 3.0;
+const FILMIC_SHOULDER: f32 = 2.0;
+
+impl ContrastMode {
+    /// Applies contrast with midtone slope `slope` (1 is identity) to an
+    /// sRGB-encoded value. The curved modes fix 0 and 1 and pass values
+    /// outside that range through unchanged.
+    pub fn apply(self, value: f32, slope: f32) -> f32 {
+        let linear = (value - 0.5_f32) * slope + 0.5_f32;
+        match self {
+            ContrastMode::Linear => linear,
+            _ if !(0.0..=1.0).contains(&value) => value,
+            ContrastMode::SCurve => {
+                let strength = slope.max(0.05);
+                if value < 0.5 {
+                    0.5 * (2.0 * value).powf(strength)
+                } else {
+                    1.0 - 0.5 * (2.0 * (1.0 - value)).powf(strength)
+                }
+            }
+            ContrastMode::Filmic if slope <= 1.0 => linear,
+            ContrastMode::Filmic => {
+                let slope = slope.min(FILMIC_SHOULDER);
+                // A power segment a * x^n meets the midtone line with equal
+                // value and slope at x = n (k - 1) / (2 k (n - 1)).
+                let joint = |n: f32| n * (slope - 1.0) / (2.0 * slope * (n - 1.0));
+                let segment = |x: f32, n: f32, end: f32| slope / (n * end.powf(n - 1.0)) * x.powf(n);
+                let (toe, shoulder) = (joint(FILMIC_TOE), joint(FILMIC_SHOULDER));
+                if value < toe {
+                    segment(value, FILMIC_TOE, toe)
+                } else if value > 1.0 - shoulder {
+                    1.0 - segment(1.0 - value, FILMIC_SHOULDER, shoulder)
+                } else {
+                    (value - 0.5) * slope + 0.5
+                }
+            }
+        }
+    }
+}
+
+/// Grain noise stream, see `rng::pixel_noise`.
+const GRAIN_STREAM: u64 = 1;
+/// Peak-to-peak grain in encoded values at `grain_amount` 1.
+const GRAIN_STRENGTH: f32 = 0.2;
+
 #[inline(always)]
 fn clamp01(value: f32) -> f32 {
     value.max(0.0).min(1.0)
@@ -26,110 +137,911 @@ pub fn parse_adjustments(json: &str) -> SimpleAdjustments {
     serde_json::from_str::<SimpleAdjustments>(json).unwrap_or_default()
 }
 
+/// Top-level adjustment keys read by the passes around `SimpleAdjustments`
+/// (geometry, lens correction, defringe, negative conversion, color grading,
+/// LUT). `parse_adjustments_checked` does not report them.
+const OTHER_PASS_KEYS: &[&str] = &[
+    "colorGrading",
+    "defringe",
+    "enableNegativeConversion",
+    "filmBaseColor",
+    "flipHorizontal",
+    "flipVertical",
+    "lensDistortionAmount",
+    "lensDistortionEnabled",
+    "lensDistortionParams",
+    "lensTcaAmount",
+    "lensTcaEnabled",
+    "lensVignetteAmount",
+    "lensVignetteEnabled",
+    "lutCube",
+    "lutIntensity",
+    "negativeBlueBalance",
+    "negativeGreenBalance",
+    "negativeRedBalance",
+    "orientationSteps",
+    "transformAspect",
+    "transformDistortion",
+    "transformHorizontal",
+    "transformRotate",
+    "transformScale",
+    "transformVertical",
+    "transformXOffset",
+    "transformYOffset",
+];
+
+/// `parse_adjustments`, plus the sorted top-level keys nothing in the
+/// pipeline reads, so a misspelled key from the frontend shows up instead of
+/// being silently ignored. Input that is not a JSON object reports no keys.
+pub fn parse_adjustments_checked(json: &str) -> (SimpleAdjustments, Vec<String>) {
+    let adjustments = parse_adjustments(json);
+    let Ok(serde_json::Value::Object(keys)) = serde_json::from_str(json) else {
+        return (adjustments, Vec::new());
+    };
+    let known = match serde_json::to_value(SimpleAdjustments::default()) {
+        Ok(serde_json::Value::Object(fields)) => fields,
+        _ => serde_json::Map::new(),
+    };
+    let mut unknown: Vec<String> = keys
+        .keys()
+        .filter(|key| !known.contains_key(*key) && !OTHER_PASS_KEYS.contains(&key.as_str()))
+        .cloned()
+        .collect();
+    unknown.sort();
+    (adjustments, unknown)
+}
+
+#[inline(always)]
+fn highlight_shadow_shift(luma: f32, adjustments: &SimpleAdjustments) -> f32 {
+    let mut shift = 0.0_f32;
+
+    if adjustments.highlights.abs() > 0.001 && luma > 0.5 {
+        let t = ((luma - 0.5_f32) * 2.0_f32).min(1.0_f32);
+        shift += adjustments.highlights * t * (1.0_f32 - luma);
+    }
+
+    if adjustments.shadows.abs() > 0.001 && luma < 0.5 {
+        let t = ((0.5_f32 - luma) * 2.0_f32).min(1.0_f32);
+        shift += adjustments.shadows * t * (0.5_f32 - luma);
+    }
+
+    shift
+}
+
+/// The tonal part of `apply_basic_adjustments` (exposure, highlights/shadows,
+/// contrast) evaluated on a neutral input, i.e. the curve a gray ramp follows.
+/// Input and output are sRGB-encoded, the domain a 1D `.cube` is applied in.
+pub fn apply_tone_curve(value: f32, adjustments: &SimpleAdjustments) -> f32 {
+    let exposed = linear_to_srgb(srgb_to_linear(value) * 2.0_f32.powf(adjustments.exposure));
+    let shifted = exposed + highlight_shadow_shift(exposed, adjustments);
+    let contrast_factor = 1.0_f32 + adjustments.contrast * 1.4_f32;
+    clamp01(adjustments.contrast_mode.apply(shifted, contrast_factor))
+}
+
+/// The f32 layout the adjustment pass runs on: `Rgba32F` when the image has
+/// alpha (which is carried through untouched), `Rgb32F` otherwise.
+pub fn to_adjustment_buffer(image: DynamicImage) -> DynamicImage {
+    match image {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => image,
+        image if image.color().has_alpha() => DynamicImage::ImageRgba32F(image.into_rgba32f()),
+        image => DynamicImage::ImageRgb32F(image.into_rgb32f()),
+    }
+}
+
 pub fn apply_basic_adjustments(image: &mut DynamicImage, adjustments: &SimpleAdjustments) {
-    let mut buffer = image.to_rgb32f();
-    let (width, height) = buffer.dimensions();
-    let data = buffer.as_mut();
+    let height = image.height();
+    apply_basic_adjustments_to_rows(image, adjustments, 0..height);
+}
 
+/// Applies the adjustments to `rows` of `image` only. Each output pixel
+/// depends on nothing but its own value and position, so processing a frame
+/// band by band matches a single pass exactly. Convert with
+/// `to_adjustment_buffer` first to avoid a conversion per band.
+///
+/// `image` is in the linear pipeline space. Exposure and vignetting scale
+/// light, so they run there; the tonal and color controls are tuned around a
+/// perceptual mid-gray of 0.5 and run on sRGB-encoded values in between.
+pub fn apply_basic_adjustments_to_rows(
+    image: &mut DynamicImage,
+    adjustments: &SimpleAdjustments,
+    rows: Range<u32>,
+) {
+    if !matches!(image, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)) {
+        *image = to_adjustment_buffer(std::mem::take(image));
+    }
+    let (width, height) = (image.width(), image.height());
+    match image {
+        DynamicImage::ImageRgba32F(buffer) => adjust_rows(
+            buffer.as_mut(),
+            width,
+            height,
+            4,
+            adjustments,
+            rows,
+            FramePlacement::whole(width, height),
+        ),
+        DynamicImage::ImageRgb32F(buffer) => adjust_rows(
+            buffer.as_mut(),
+            width,
+            height,
+            3,
+            adjustments,
+            rows,
+            FramePlacement::whole(width, height),
+        ),
+        _ => {}
+    }
+}
+
+/// Pixel positions inside the full frame, for the position-dependent
+/// controls. A buffer holding a crop of the frame sets `origin` to the crop's
+/// top-left corner.
+#[derive(Debug, Clone, Copy)]
+struct FramePlacement {
+    origin: (u32, u32),
+    frame: (u32, u32),
+}
+
+impl FramePlacement {
+    fn whole(width: u32, height: u32) -> Self {
+        Self {
+            origin: (0, 0),
+            frame: (width, height),
+        }
+    }
+}
+
+/// The adjustment pass split into stages that run one after another on each
+/// pixel. Every field belongs to exactly one stage, so a change can restart
+/// the pass at its stage from a cached input (see `AdjustmentCache`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AdjustmentStage {
+    /// Exposure, highlights/shadows and contrast. Reads linear input and
+    /// leaves sRGB-encoded values.
+    Tone,
+    /// Temperature and tint.
+    WhiteBalance,
+    /// Vibrance, saturation, clarity and sharpness.
+    Color,
+    /// Grain, vignetting, back in linear light, and the final clamp.
+    Finish,
+}
+
+impl AdjustmentStage {
+    pub const ALL: [AdjustmentStage; 4] = [
+        AdjustmentStage::Tone,
+        AdjustmentStage::WhiteBalance,
+        AdjustmentStage::Color,
+        AdjustmentStage::Finish,
+    ];
+
+    /// The earliest stage whose fields differ between `a` and `b`.
+    pub fn first_changed(a: &SimpleAdjustments, b: &SimpleAdjustments) -> Option<Self> {
+        if a.exposure != b.exposure
+            || a.red_gain != b.red_gain
+            || a.green_gain != b.green_gain
+            || a.blue_gain != b.blue_gain
+            || a.highlights != b.highlights
+            || a.shadows != b.shadows
+            || a.contrast != b.contrast
+            || a.contrast_mode != b.contrast_mode
+        {
+            Some(AdjustmentStage::Tone)
+        } else if a.temperature != b.temperature || a.tint != b.tint {
+            Some(AdjustmentStage::WhiteBalance)
+        } else if a.vibrance != b.vibrance
+            || a.saturation != b.saturation
+            || a.clarity != b.clarity
+            || a.sharpness != b.sharpness
+        {
+            Some(AdjustmentStage::Color)
+        } else if a.vignette != b.vignette
+            || a.grain_amount != b.grain_amount
+            || a.effect_seed != b.effect_seed
+        {
+            Some(AdjustmentStage::Finish)
+        } else {
+            None
+        }
+    }
+}
+
+/// Per-pass constants derived from the adjustments.
+struct StageParams<'a> {
+    adjustments: &'a SimpleAdjustments,
+    /// Exposure and the per-channel gains combined.
+    channel_mult: [f32; 3],
+    contrast_factor: f32,
+    saturation_factor: f32,
+    temperature: f32,
+    tint: f32,
+    sharpness: f32,
+    vignette_strength: f32,
+    grain: f32,
+    placement: FramePlacement,
+    inv_w: f32,
+    inv_h: f32,
+}
+
+impl<'a> StageParams<'a> {
+    fn new(adjustments: &'a SimpleAdjustments, placement: FramePlacement) -> Self {
+        let (frame_w, frame_h) = placement.frame;
+        Self {
+            adjustments,
+            channel_mult: [adjustments.red_gain, adjustments.green_gain, adjustments.blue_gain]
+                .map(|gain| gain.max(0.0) * 2.0_f32.powf(adjustments.exposure)),
+            contrast_factor: 1.0_f32 + adjustments.contrast * 1.4_f32,
+            saturation_factor: 1.0_f32 + adjustments.saturation,
+            temperature: adjustments.temperature * 0.1_f32,
+            tint: adjustments.tint * 0.1_f32,
+            sharpness: adjustments.sharpness.max(0.0),
+            vignette_strength: adjustments.vignette.clamp(-1.0, 1.0),
+            grain: adjustments.grain_amount.clamp(0.0, 1.0) * GRAIN_STRENGTH,
+            placement,
+            inv_w: 1.0_f32 / (frame_w as f32 - 1.0_f32).max(1.0_f32),
+            inv_h: 1.0_f32 / (frame_h as f32 - 1.0_f32).max(1.0_f32),
+        }
+    }
+
+    /// Runs `stage` on one pixel at buffer position (`x`, `y`).
+    fn run(&self, stage: AdjustmentStage, [r, g, b]: [f32; 3], x: u32, y: u32) -> [f32; 3] {
+        match stage {
+            AdjustmentStage::Tone => {
+                let [r_mult, g_mult, b_mult] = self.channel_mult;
+                let mut r = linear_to_srgb(r * r_mult);
+                let mut g = linear_to_srgb(g * g_mult);
+                let mut b = linear_to_srgb(b * b_mult);
+
+                let luma = luma::luma(r, g, b, LumaCoefficients::Rec709);
+                let luma_shift = highlight_shadow_shift(luma, self.adjustments);
+                r += luma_shift;
+                g += luma_shift;
+                b += luma_shift;
+
+                let mode = self.adjustments.contrast_mode;
+                [
+                    mode.apply(r, self.contrast_factor),
+                    mode.apply(g, self.contrast_factor),
+                    mode.apply(b, self.contrast_factor),
+                ]
+            }
+            AdjustmentStage::WhiteBalance => [
+                r + (self.temperature - self.tint * 0.05_f32),
+                g + self.tint * 0.1_f32,
+                b - (self.temperature - self.tint * 0.05_f32),
+            ],
+            AdjustmentStage::Color => {
+                let (mut r, mut g, mut b) = (r, g, b);
+                let vibrance = self.adjustments.vibrance;
+                let clarity = self.adjustments.clarity;
+                let luma2 = luma::luma(r, g, b, LumaCoefficients::Rec709);
+                let mut sat_scale = self.saturation_factor;
+
+                if vibrance.abs() > 0.001 {
+                    let max_c = r.max(g).max(b);
+                    let min_c = r.min(g).min(b);
+                    let sat = if max_c > 1e-6_f32 {
+                        (max_c - min_c) / max_c
+                    } else {
+                        0.0_f32
+                    };
+                    sat_scale *= 1.0_f32 + vibrance * (1.0_f32 - sat);
+                }
+
+                r = luma2 + (r - luma2) * sat_scale;
+                g = luma2 + (g - luma2) * sat_scale;
+                b = luma2 + (b - luma2) * sat_scale;
+
+                if clarity.abs() > 0.001 {
+                    let mid = 1.0_f32 - ((luma2 - 0.5_f32).abs() * 2.0_f32).min(1.0_f32);
+                    let clarity_factor = 1.0_f32 + clarity * 0.6_f32 * mid;
+                    r = (r - 0.5_f32) * clarity_factor + 0.5_f32;
+                    g = (g - 0.5_f32) * clarity_factor + 0.5_f32;
+                    b = (b - 0.5_f32) * clarity_factor + 0.5_f32;
+                }
+
+                if self.sharpness > 0.0 {
+                    let sharp_factor = 1.0_f32 + self.sharpness * 0.2_f32;
+                    r = (r - luma2) * sharp_factor + luma2;
+                    g = (g - luma2) * sharp_factor + luma2;
+                    b = (b - luma2) * sharp_factor + luma2;
+                }
+                [r, g, b]
+            }
+            AdjustmentStage::Finish => {
+                let (origin_x, origin_y) = self.placement.origin;
+                let (mut r, mut g, mut b) = (r, g, b);
+                if self.grain > 0.0 {
+                    // Monochrome grain, strongest in the midtones like film.
+                    let seed = self.adjustments.effect_seed;
+                    let noise = pixel_noise(seed, GRAIN_STREAM, origin_x + x, origin_y + y);
+                    let luma = luma::luma(r, g, b, LumaCoefficients::Rec709).clamp(0.0, 1.0);
+                    let grain = noise * self.grain * 4.0 * luma * (1.0 - luma);
+                    r += grain;
+                    g += grain;
+                    b += grain;
+                }
+
+                let mut r = srgb_to_linear(r.max(0.0));
+                let mut g = srgb_to_linear(g.max(0.0));
+                let mut b = srgb_to_linear(b.max(0.0));
+
+                if self.vignette_strength.abs() > 0.001 {
+                    let x_norm = ((origin_x + x) as f32 * self.inv_w - 0.5) * 2.0;
+                    let y_norm = ((origin_y + y) as f32 * self.inv_h - 0.5) * 2.0;
+                    let dist =
+                        ((x_norm * x_norm + y_norm * y_norm).sqrt() * 0.7071_f32).min(1.0_f32);
+                    let factor = 1.0_f32 - self.vignette_strength * dist * dist;
+                    r *= factor;
+                    g *= factor;
+                    b *= factor;
+                }
+
+                [clamp01(r), clamp01(g), clamp01(b)]
+            }
+        }
+    }
+}
+
+fn adjust_rows(
+    data: &mut [f32],
+    width: u32,
+    height: u32,
+    channels: u32,
+    adjustments: &SimpleAdjustments,
+    rows: Range<u32>,
+    placement: FramePlacement,
+) {
     if width == 0 || height == 0 {
         return;
     }
+    let params = StageParams::new(adjustments, placement);
 
-    let exposure_mult = 2.0_f32.powf(adjustments.exposure);
-    let contrast_factor = 1.0_f32 + adjustments.contrast * 1.4_f32;
-    let saturation_factor = 1.0_f32 + adjustments.saturation;
-    let vibrance = adjustments.vibrance;
-    let temperature = adjustments.temperature * 0.1_f32;
-    let tint = adjustments.tint * 0.1_f32;
-    let clarity = adjustments.clarity;
-    let sharpness = adjustments.sharpness.max(0.0);
-    let vignette = adjustments.vignette;
-
-    let inv_w = 1.0_f32 / (width as f32 - 1.0_f32).max(1.0_f32);
-    let inv_h = 1.0_f32 / (height as f32 - 1.0_f32).max(1.0_f32);
-    let vignette_strength = vignette.clamp(-1.0, 1.0);
-
-    for y in 0..height {
-        let y_norm = (y as f32 * inv_h - 0.5) * 2.0;
+    for y in rows.start..rows.end.min(height) {
         for x in 0..width {
-            let idx = ((y * width + x) * 3) as usize;
-            let mut r = data[idx] * exposure_mult;
-            let mut g = data[idx + 1] * exposure_mult;
-            let mut b = data[idx + 2] * exposure_mult;
+            let idx = ((y * width + x) * channels) as usize;
+            let pixel = AdjustmentStage::ALL
+                .iter()
+                .fold([data[idx], data[idx + 1], data[idx + 2]], |p, &stage| {
+                    params.run(stage, p, x, y)
+                });
+            data[idx..idx + 3].copy_from_slice(&pixel);
+        }
+    }
+}
+
+/// Re-runs the adjustment pass on one source frame as the adjustments change,
+/// starting from the earliest stage a change affects. The input of that stage
+/// is kept from the previous run, so nudging saturation skips exposure and
+/// white balance, and repeated nudges of one slider reuse the same input.
+/// Holds one cached stage input besides the source and the last result.
+pub struct AdjustmentCache {
+    source: DynamicImage,
+    applied: Option<SimpleAdjustments>,
+    /// Input of the stage, produced with `applied`.
+    stage_input: Option<(AdjustmentStage, Vec<[f32; 3]>)>,
+    output: Option<DynamicImage>,
+    stages_run: u64,
+}
+
+impl AdjustmentCache {
+    /// `source` is a linear pipeline image.
+    pub fn new(source: DynamicImage) -> Self {
+        Self {
+            source: to_adjustment_buffer(source),
+            applied: None,
+            stage_input: None,
+            output: None,
+            stages_run: 0,
+        }
+    }
+
+    /// Stage passes run over the whole frame so far.
+    pub fn stages_run(&self) -> u64 {
+        self.stages_run
+    }
 
-            let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
-            let mut new_luma = luma;
+    /// Bytes held by the source, the cached stage input and the last result.
+    pub fn memory_bytes(&self) -> usize {
+        self.source.as_bytes().len()
+            + self
+                .stage_input
+                .as_ref()
+                .map_or(0, |(_, pixels)| std::mem::size_of_val(pixels.as_slice()))
+            + self.output.as_ref().map_or(0, |output| output.as_bytes().len())
+    }
 
-            if adjustments.highlights.abs() > 0.001 && luma > 0.5 {
-                let t = ((luma - 0.5_f32) * 2.0_f32).min(1.0_f32);
-                new_luma += adjustments.highlights * t * (1.0_f32 - luma);
+    /// The source with `adjustments` applied; equal to a full
+    /// `apply_basic_adjustments` pass.
+    pub fn apply(&mut self, adjustments: &SimpleAdjustments) -> DynamicImage {
+        let first = match (&self.applied, &self.output) {
+            (Some(applied), Some(output)) => {
+                match AdjustmentStage::first_changed(applied, adjustments) {
+                    Some(stage) => stage,
+                    None => return output.clone(),
+                }
             }
+            _ => AdjustmentStage::Tone,
+        };
+
+        let (width, height) = (self.source.width(), self.source.height());
+        let channels = if self.source.color().has_alpha() {
+            4
+        } else {
+            3
+        };
+        let source = match &self.source {
+            DynamicImage::ImageRgba32F(buffer) => buffer.as_raw(),
+            DynamicImage::ImageRgb32F(buffer) => buffer.as_raw(),
+            _ => unreachable!("to_adjustment_buffer yields an f32 buffer"),
+        };
 
-            if adjustments.shadows.abs() > 0.001 && luma < 0.5 {
-                let t = ((0.5_f32 - luma) * 2.0_f32).min(1.0_f32);
-                new_luma += adjustments.shadows * t * (0.5_f32 - luma);
+        let (start, mut pixels) = match self.stage_input.take() {
+            Some((stage, pixels)) if stage <= first => (stage, pixels),
+            _ => (
+                AdjustmentStage::Tone,
+                source
+                    .chunks_exact(channels)
+                    .map(|p| [p[0], p[1], p[2]])
+                    .collect(),
+            ),
+        };
+
+        // The Tone input is the source itself, so cache the next one instead.
+        let cached = first.max(AdjustmentStage::WhiteBalance);
+        let params = StageParams::new(adjustments, FramePlacement::whole(width, height));
+        for stage in AdjustmentStage::ALL.into_iter().filter(|s| *s >= start) {
+            if stage == cached {
+                self.stage_input = Some((stage, pixels.clone()));
+            }
+            for (i, p) in pixels.iter_mut().enumerate() {
+                let (x, y) = (i as u32 % width, i as u32 / width);
+                *p = params.run(stage, *p, x, y);
             }
+            self.stages_run += 1;
+        }
+
+        let mut data = source.clone();
+        for (out, p) in data.chunks_exact_mut(channels).zip(&pixels) {
+            out[..3].copy_from_slice(p);
+        }
+        let output = match channels {
+            4 => DynamicImage::ImageRgba32F(
+                image::Rgba32FImage::from_raw(width, height, data).expect("source dimensions"),
+            ),
+            _ => DynamicImage::ImageRgb32F(
+                image::Rgb32FImage::from_raw(width, height, data).expect("source dimensions"),
+            ),
+        };
+        self.applied = Some(*adjustments);
+        self.output = Some(output.clone());
+        output
+    }
+}
+
+/// Applies `adjustments` to the `w` x `h` rectangle at (`x`, `y`) of an sRGB
+/// RGBA8 frame (a canvas `ImageData`) and returns the patched copy; pixels
+/// outside the rectangle and alpha are unchanged. The rectangle may hang off
+/// any edge and is clipped. Vignetting sees full-frame coordinates, so the
+/// patch matches the same pixels of a whole-frame pass.
+pub fn process_region(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    (x, y, w, h): (i64, i64, u32, u32),
+    adjustments: &SimpleAdjustments,
+) -> Result<Vec<u8>> {
+    let expected = width as usize * height as usize * 4;
+    if image_data.len() != expected {
+        return Err(anyhow!(
+            "expected {expected} bytes for a {width}x{height} RGBA buffer, got {}",
+            image_data.len()
+        ));
+    }
+
+    let mut out = image_data.to_vec();
+    let x0 = x.clamp(0, width as i64) as u32;
+    let y0 = y.clamp(0, height as i64) as u32;
+    let x1 = (x + w as i64).clamp(0, width as i64) as u32;
+    let y1 = (y + h as i64).clamp(0, height as i64) as u32;
+    if x0 >= x1 || y0 >= y1 {
+        return Ok(out);
+    }
+
+    let (region_w, region_h) = (x1 - x0, y1 - y0);
+    let row_bytes = |row: u32| {
+        let start = (row as usize * width as usize + x0 as usize) * 4;
+        start..start + region_w as usize * 4
+    };
+
+    let mut region: Vec<f32> = (y0..y1)
+        .flat_map(|row| image_data[row_bytes(row)].iter())
+        .map(|&v| v as f32 / 255.0)
+        .collect();
+    region.chunks_exact_mut(4).for_each(|p| {
+        p[..3].iter_mut().for_each(|v| *v = srgb_to_linear(*v));
+    });
+
+    adjust_rows(
+        &mut region,
+        region_w,
+        region_h,
+        4,
+        adjustments,
+        0..region_h,
+        FramePlacement {
+            origin: (x0, y0),
+            frame: (width, height),
+        },
+    );
+
+    for (row, patch) in (y0..y1).zip(region.chunks_exact(region_w as usize * 4)) {
+        for (dst, src) in out[row_bytes(row)].chunks_exact_mut(4).zip(patch.chunks_exact(4)) {
+            dst[..3].iter_mut().zip(&src[..3]).for_each(|(d, v)| {
+                *d = (linear_to_srgb(*v).clamp(0.0, 1.0) * 255.0).round() as u8;
+            });
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_contrast_mode_is_linear() {
+        assert_eq!(
+            parse_adjustments(r#"{"contrast": 0.5}"#).contrast_mode,
+            ContrastMode::Linear
+        );
+        assert_eq!(
+            parse_adjustments(r#"{"contrastMode": "sCurve"}"#).contrast_mode,
+            ContrastMode::SCurve
+        );
+        assert_eq!(
+            parse_adjustments(r#"{"contrastMode": "filmic"}"#).contrast_mode,
+            ContrastMode::Filmic
+        );
+    }
+
+    #[test]
+    fn legacy_edits_without_a_contrast_mode_render_as_before() {
+        let source = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_fn(8, 1, |x, _| {
+            image::Rgb([x as f32 / 8.0, 0.1, 0.6])
+        }));
+        let render = |json: &str| {
+            let mut image = source.clone();
+            apply_basic_adjustments(&mut image, &parse_adjustments(json));
+            image.to_rgb32f().into_raw()
+        };
+        let legacy = render(r#"{"contrast": 0.4}"#);
+        assert_eq!(legacy, render(r#"{"contrast": 0.4, "contrastMode": "linear"}"#));
+        assert_ne!(legacy, render(r#"{"contrast": 0.4, "contrastMode": "sCurve"}"#));
+
+        // The contrast line through mid-gray the pipeline always used.
+        let tone = |v: f32| apply_tone_curve(v, &parse_adjustments(r#"{"contrast": 0.4}"#));
+        assert!((tone(0.3) - (0.5 - 0.2 * 1.56)).abs() < 1e-5);
+        assert_eq!(tone(0.05), 0.0);
+    }
 
-            let luma_shift = new_luma - luma;
-            r += luma_shift;
-            g += luma_shift;
-            b += luma_shift;
+    #[test]
+    fn contrast_modes_share_mid_gray_and_curved_modes_fix_the_ends() {
+        for mode in [
+            ContrastMode::Linear,
+            ContrastMode::SCurve,
+            ContrastMode::Filmic,
+        ] {
+            assert!((mode.apply(0.5, 1.8) - 0.5).abs() < 1e-6, "{mode:?}");
+            assert!((mode.apply(0.3, 1.0) - 0.3).abs() < 1e-6, "{mode:?}");
+        }
+        for mode in [ContrastMode::SCurve, ContrastMode::Filmic] {
+            assert!(mode.apply(0.0, 1.8).abs() < 1e-6, "{mode:?}");
+            assert!((mode.apply(1.0, 1.8) - 1.0).abs() < 1e-6, "{mode:?}");
+        }
+        assert!(ContrastMode::Linear.apply(0.0, 1.8) < 0.0);
+    }
+
+    #[test]
+    fn filmic_curve_is_monotonic() {
+        let values: Vec<f32> = (0..=100)
+            .map(|i| ContrastMode::Filmic.apply(i as f32 / 100.0, 1.8))
+            .collect();
+        assert!(values.windows(2).all(|pair| pair[1] >= pair[0]));
+    }
 
-            r = (r - 0.5_f32) * contrast_factor + 0.5_f32;
-            g = (g - 0.5_f32) * contrast_factor + 0.5_f32;
-            b = (b - 0.5_f32) * contrast_factor + 0.5_f32;
+    #[test]
+    fn banded_adjustments_match_a_single_pass() {
+        let source = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_fn(8, 20, |x, y| {
+            image::Rgb([x as f32 / 8.0, y as f32 / 20.0, 0.3])
+        }));
+        let adjustments = SimpleAdjustments {
+            exposure: 0.5,
+            contrast: 0.4,
+            vignette: -0.6,
+            grain_amount: 0.3,
+            ..Default::default()
+        };
+
+        let mut single = source.clone();
+        apply_basic_adjustments(&mut single, &adjustments);
+
+        let mut banded = source;
+        for start in (0..20).step_by(7) {
+            apply_basic_adjustments_to_rows(&mut banded, &adjustments, start..start + 7);
+        }
+        assert_eq!(banded.as_bytes(), single.as_bytes());
+    }
 
-            r += temperature - tint * 0.05_f32;
-            b -= temperature - tint * 0.05_f32;
-            g += tint * 0.1_f32;
+    #[test]
+    fn adjustments_keep_the_alpha_channel() {
+        let mut image = DynamicImage::ImageRgba32F(image::Rgba32FImage::from_pixel(
+            4,
+            4,
+            image::Rgba([0.2, 0.3, 0.4, 0.5]),
+        ));
+        let adjustments = SimpleAdjustments {
+            exposure: 1.0,
+            saturation: 0.5,
+            ..Default::default()
+        };
+        apply_basic_adjustments(&mut image, &adjustments);
+        let buffer = image.as_rgba32f().expect("still RGBA");
+        assert!(buffer.pixels().all(|p| p[3] == 0.5));
+    }
 
-            let luma2 = 0.2126 * r + 0.7152 * g + 0.0722 * b;
-            let mut sat_scale = saturation_factor;
+    #[test]
+    fn region_patch_matches_the_whole_frame_pass() {
+        let (width, height) = (12, 8);
+        let frame: Vec<u8> = (0..width * height)
+            .flat_map(|i| [(i * 7 % 256) as u8, 128, (255 - i * 3 % 256) as u8, 200])
+            .collect();
+        let adjustments = SimpleAdjustments {
+            exposure: 0.5,
+            vignette: -0.8,
+            ..Default::default()
+        };
 
-            if vibrance.abs() > 0.001 {
-                let max_c = r.max(g).max(b);
-                let min_c = r.min(g).min(b);
-                let sat = if max_c > 1e-6_f32 {
-                    (max_c - min_c) / max_c
+        let whole =
+            process_region(&frame, width, height, (0, 0, width, height), &adjustments).unwrap();
+        // Hangs off the right edge, so only columns 8..12 of rows 2..5 change.
+        let patched = process_region(&frame, width, height, (8, 2, 10, 3), &adjustments).unwrap();
+        for y in 0..height {
+            for x in 0..width {
+                let i = ((y * width + x) * 4) as usize;
+                let expected = if x >= 8 && (2..5).contains(&y) {
+                    &whole
                 } else {
-                    0.0_f32
+                    &frame
                 };
-                sat_scale *= 1.0_f32 + vibrance * (1.0_f32 - sat);
+                assert_eq!(patched[i..i + 4], expected[i..i + 4], "pixel ({x}, {y})");
             }
+        }
+    }
 
-            r = luma2 + (r - luma2) * sat_scale;
-            g = luma2 + (g - luma2) * sat_scale;
-            b = luma2 + (b - luma2) * sat_scale;
+    #[test]
+    fn region_rejects_a_buffer_of_the_wrong_size() {
+        let adjustments = SimpleAdjustments::default();
+        assert!(process_region(&[0; 12], 2, 2, (0, 0, 1, 1), &adjustments).is_err());
+        let frame = vec![9; 16];
+        assert_eq!(
+            process_region(&frame, 2, 2, (-5, -5, 2, 2), &adjustments).unwrap(),
+            frame
+        );
+    }
 
-            if clarity.abs() > 0.001 {
-                let mid = 1.0_f32 - ((luma2 - 0.5_f32).abs() * 2.0_f32).min(1.0_f32);
-                let clarity_factor = 1.0_f32 + clarity * 0.6_f32 * mid;
-                r = (r - 0.5_f32) * clarity_factor + 0.5_f32;
-                g = (g - 0.5_f32) * clarity_factor + 0.5_f32;
-                b = (b - 0.5_f32) * clarity_factor + 0.5_f32;
-            }
+    #[test]
+    fn each_field_belongs_to_its_stage() {
+        let base = SimpleAdjustments::default();
+        let changed =
+            |adjustments: SimpleAdjustments| AdjustmentStage::first_changed(&base, &adjustments);
+        assert_eq!(changed(base), None);
+        assert_eq!(
+            changed(SimpleAdjustments {
+                exposure: 1.0,
+                saturation: 0.5,
+                ..base
+            }),
+            Some(AdjustmentStage::Tone)
+        );
+        assert_eq!(
+            changed(SimpleAdjustments { tint: 0.2, ..base }),
+            Some(AdjustmentStage::WhiteBalance)
+        );
+        assert_eq!(
+            changed(SimpleAdjustments {
+                saturation: 0.5,
+                ..base
+            }),
+            Some(AdjustmentStage::Color)
+        );
+        assert_eq!(
+            changed(SimpleAdjustments {
+                effect_seed: 7,
+                ..base
+            }),
+            Some(AdjustmentStage::Finish)
+        );
+    }
 
-            if sharpness > 0.0 {
-                let sharp_factor = 1.0_f32 + sharpness * 0.2_f32;
-                r = (r - luma2) * sharp_factor + luma2;
-                g = (g - luma2) * sharp_factor + luma2;
-                b = (b - luma2) * sharp_factor + luma2;
-            }
+    #[test]
+    fn cache_reruns_only_the_changed_stages() {
+        let source = DynamicImage::ImageRgba32F(image::Rgba32FImage::from_fn(6, 5, |x, y| {
+            image::Rgba([x as f32 / 6.0, y as f32 / 5.0, 0.3, 0.8])
+        }));
+        let mut adjustments = SimpleAdjustments {
+            exposure: 0.5,
+            temperature: 0.2,
+            vignette: -0.4,
+            ..Default::default()
+        };
+        let mut cache = AdjustmentCache::new(source.clone());
+        cache.apply(&adjustments);
+        assert_eq!(cache.stages_run(), 4);
 
-            if vignette_strength.abs() > 0.001 {
-                let x_norm = (x as f32 * inv_w - 0.5) * 2.0;
-                let dist = ((x_norm * x_norm + y_norm * y_norm).sqrt() * 0.7071_f32).min(1.0_f32);
-                let factor = 1.0_f32 - vignette_strength * dist * dist;
-                r *= factor;
-                g *= factor;
-                b *= factor;
-            }
+        // The first saturation change restarts from the cached white balance
+        // input; later ones from the color stage input it leaves behind.
+        adjustments.saturation = 0.6;
+        cache.apply(&adjustments);
+        assert_eq!(cache.stages_run(), 7);
+        adjustments.saturation = 0.3;
+        let cached = cache.apply(&adjustments);
+        assert_eq!(cache.stages_run(), 9);
+        let mut full = source;
+        apply_basic_adjustments(&mut full, &adjustments);
+        assert_eq!(cached.as_bytes(), full.as_bytes());
+
+        // Unchanged adjustments return the last result without a pass.
+        cache.apply(&adjustments);
+        assert_eq!(cache.stages_run(), 9);
+    }
+
+    #[test]
+    fn channel_gains_default_to_neutral_and_match_exposure() {
+        let parsed = parse_adjustments(r#"{"redGain": 1.5}"#);
+        assert_eq!(
+            [parsed.red_gain, parsed.green_gain, parsed.blue_gain],
+            [1.5, 1.0, 1.0]
+        );
+
+        let source = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_fn(4, 4, |x, y| {
+            image::Rgb([x as f32 / 8.0, y as f32 / 8.0, 0.2])
+        }));
+        let render = |adjustments: SimpleAdjustments| {
+            let mut image = source.clone();
+            apply_basic_adjustments(&mut image, &adjustments);
+            image.to_rgb32f().into_raw()
+        };
+        let gains = render(SimpleAdjustments {
+            red_gain: 2.0,
+            green_gain: 2.0,
+            blue_gain: 2.0,
+            ..Default::default()
+        });
+        let exposure = render(SimpleAdjustments {
+            exposure: 1.0,
+            ..Default::default()
+        });
+        for (a, b) in gains.iter().zip(&exposure) {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
 
-            data[idx] = clamp01(r);
-            data[idx + 1] = clamp01(g);
-            data[idx + 2] = clamp01(b);
+    #[test]
+    fn one_stop_of_exposure_doubles_linear_values() {
+        let source = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_fn(4, 1, |x, _| {
+            image::Rgb([0.05 * (x + 1) as f32, 0.02, 0.4])
+        }));
+        let mut exposed = source.clone();
+        apply_basic_adjustments(
+            &mut exposed,
+            &SimpleAdjustments {
+                exposure: 1.0,
+                ..Default::default()
+            },
+        );
+        for (before, after) in source.to_rgb32f().iter().zip(exposed.to_rgb32f().iter()) {
+            assert!((after - 2.0 * before).abs() < 1e-4, "{before} -> {after}");
         }
     }
 
-    *image = DynamicImage::ImageRgb32F(buffer);
+    #[test]
+    fn blurs_average_light_rather_than_encoded_values() {
+        use crate::core::image_utils::unsharp_mask;
+        use crate::core::working_space;
+
+        // One-pixel black and white columns, as decoded from an sRGB file.
+        let stripes = image::RgbImage::from_fn(32, 8, |x, _| image::Rgb([(x % 2) as u8 * 255; 3]));
+        let linear = working_space::from_srgb(DynamicImage::ImageRgb8(stripes));
+        // An amount of -1 leaves just the Gaussian blur.
+        let blurred = unsharp_mask(&linear, 3.0, -1.0);
+
+        let mean = blurred.to_rgb32f().get_pixel(16, 4)[0];
+        assert!((mean - 0.5).abs() < 0.02, "linear mean {mean}");
+        let encoded = working_space::to_srgb(&blurred)
+            .to_rgb32f()
+            .get_pixel(16, 4)[0];
+        assert!(encoded > 0.7, "sRGB-encoded mean {encoded}");
+    }
+
+    #[test]
+    fn lifted_shadows_from_a_deep_source_do_not_band() {
+        use crate::core::working_space;
+
+        // The darkest 328 levels of a 14-bit raw, one per column.
+        let deep = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_fn(328, 1, |x, _| {
+            image::Rgb([x as f32 / 16383.0; 3])
+        }));
+        // The same ramp squeezed through 8-bit sRGB before any adjustment.
+        let eight_bit = working_space::from_srgb(DynamicImage::ImageRgb8(
+            working_space::to_srgb(&deep).to_rgb8(),
+        ));
+        let lift = SimpleAdjustments {
+            exposure: 3.0,
+            ..Default::default()
+        };
+        let levels = |mut image: DynamicImage| {
+            apply_basic_adjustments(&mut image, &lift);
+            let mut levels: Vec<u8> = working_space::to_srgb(&image)
+                .to_rgb8()
+                .pixels()
+                .map(|p| p[0])
+                .collect();
+            levels.dedup();
+            let widest_gap = levels.windows(2).map(|w| w[1] - w[0]).max().unwrap();
+            (levels.len(), widest_gap)
+        };
+
+        let (deep_levels, deep_gap) = levels(deep);
+        let (banded_levels, banded_gap) = levels(eight_bit);
+        assert!(
+            deep_levels > 2 * banded_levels,
+            "{deep_levels} vs {banded_levels}"
+        );
+        assert!(deep_gap <= 2, "float path skips {deep_gap} codes");
+        assert!(banded_gap > deep_gap);
+    }
+
+    #[test]
+    fn a_channel_gain_only_moves_its_channel() {
+        let source =
+            DynamicImage::ImageRgb32F(image::Rgb32FImage::from_pixel(2, 2, image::Rgb([0.2; 3])));
+        let mut neutral = source.clone();
+        apply_basic_adjustments(&mut neutral, &SimpleAdjustments::default());
+        let mut warm = source;
+        let adjustments = SimpleAdjustments {
+            red_gain: 1.5,
+            ..Default::default()
+        };
+        apply_basic_adjustments(&mut warm, &adjustments);
+        let [r0, g0, b0] = neutral.to_rgb32f().get_pixel(0, 0).0;
+        let [r, g, b] = warm.to_rgb32f().get_pixel(0, 0).0;
+        assert!(r > r0 + 0.01);
+        assert!((g - g0).abs() < 1e-6 && (b - b0).abs() < 1e-6);
+        assert_eq!(
+            AdjustmentStage::first_changed(&SimpleAdjustments::default(), &adjustments),
+            Some(AdjustmentStage::Tone)
+        );
+    }
+
+    #[test]
+    fn checked_parse_reports_keys_no_pass_reads() {
+        let (adjustments, unknown) = parse_adjustments_checked(
+            r#"{"exposure": 0.5, "redGain": 1.1, "exposrue": 1, "lutIntensity": 50,
+            "defringe": {}, "zzz": null}"#,
+        );
+        assert_eq!(adjustments.exposure, 0.5);
+        assert_eq!(unknown, ["exposrue", "zzz"]);
+
+        // Keys read by the other passes are never reported.
+        let other: serde_json::Map<String, serde_json::Value> = OTHER_PASS_KEYS
+            .iter()
+            .map(|key| (key.to_string(), serde_json::Value::Null))
+            .collect();
+        let json = serde_json::Value::Object(other).to_string();
+        assert!(parse_adjustments_checked(&json).1.is_empty());
+
+        assert!(parse_adjustments_checked("not json").1.is_empty());
+        assert!(parse_adjustments_checked("[1, 2]").1.is_empty());
+    }
 }
@@ -0,0 +1,317 @@
+//! Read-only measurements on RGBA8 buffers (as handed over from a canvas
+//! `ImageData`), for loupe and eyedropper readouts in the UI.
+
+use rapidraw_core::luma::{luma, LumaCoefficients};
+use serde::Serialize;
+
+/// Statistics over a rectangle. Values are in 0..1; `pixel_count` is 0 when the
+/// rectangle lies entirely outside the image.
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RegionStats {
+    pub pixel_count: u32,
+    pub mean_luminance: f32,
+    pub min_luminance: f32,
+    pub max_luminance: f32,
+    pub mean_red: f32,
+    pub mean_green: f32,
+    pub mean_blue: f32,
+}
+
+fn buffer_height(image_data: &[u8], width: u32) -> u32 {
+    if width == 0 {
+        0
+    } else {
+        (image_data.len() / (width as usize * 4)) as u32
+    }
+}
+
+/// Measures the `w` x `h` rectangle at (`x`, `y`), clamped to the image bounds.
+pub fn measure_region(image_data: &[u8], width: u32, x: u32, y: u32, w: u32, h: u32) -> RegionStats {
+    let height = buffer_height(image_data, width);
+    let x0 = x.min(width);
+    let y0 = y.min(height);
+    let x1 = x.saturating_add(w).min(width);
+    let y1 = y.saturating_add(h).min(height);
+    if x0 >= x1 || y0 >= y1 {
+        return RegionStats::default();
+    }
+
+    let mut sums = [0.0f64; 4];
+    let mut min_luma = f32::MAX;
+    let mut max_luma = f32::MIN;
+    for row in y0..y1 {
+        let start = (row as usize * width as usize + x0 as usize) * 4;
+        let end = (row as usize * width as usize + x1 as usize) * 4;
+        for pixel in image_data[start..end].chunks_exact(4) {
+            let r = pixel[0] as f32 / 255.0;
+            let g = pixel[1] as f32 / 255.0;
+            let b = pixel[2] as f32 / 255.0;
+            let l = luma(r, g, b, LumaCoefficients::Rec709);
+            sums[0] += r as f64;
+            sums[1] += g as f64;
+            sums[2] += b as f64;
+            sums[3] += l as f64;
+            min_luma = min_luma.min(l);
+            max_luma = max_luma.max(l);
+        }
+    }
+
+    let count = (x1 - x0) * (y1 - y0);
+    let inv = 1.0 / count as f64;
+    RegionStats {
+        pixel_count: count,
+        mean_luminance: (sums[3] * inv) as f32,
+        min_luminance: min_luma,
+        max_luminance: max_luma,
+        mean_red: (sums[0] * inv) as f32,
+        mean_green: (sums[1] * inv) as f32,
+        mean_blue: (sums[2] * inv) as f32,
+    }
+}
+
+/// Bilinearly samples RGBA at fractional pixel coordinates, where integer
+/// coordinates hit pixel centers. Coordinates are clamped to the image.
+pub fn sample_pixel(image_data: &[u8], width: u32, height: u32, x: f32, y: f32) -> [f32; 4] {
+    let height = height.min(buffer_height(image_data, width));
+    if width == 0 || height == 0 {
+        return [0.0; 4];
+    }
+
+    let x = if x.is_finite() { x.clamp(0.0, (width - 1) as f32) } else { 0.0 };
+    let y = if y.is_finite() { y.clamp(0.0, (height - 1) as f32) } else { 0.0 };
+    let x0 = x.floor() as u32;
+    let y0 = y.floor() as u32;
+    let x1 = (x0 + 1).min(width - 1);
+    let y1 = (y0 + 1).min(height - 1);
+    let fx = x - x0 as f32;
+    let fy = y - y0 as f32;
+
+    let fetch = |px: u32, py: u32, c: usize| {
+        image_data[(py as usize * width as usize + px as usize) * 4 + c] as f32 / 255.0
+    };
+
+    let mut out = [0.0f32; 4];
+    for (c, value) in out.iter_mut().enumerate() {
+        let top = fetch(x0, y0, c) + (fetch(x1, y0, c) - fetch(x0, y0, c)) * fx;
+        let bottom = fetch(x0, y1, c) + (fetch(x1, y1, c) - fetch(x0, y1, c)) * fx;
+        *value = top + (bottom - top) * fy;
+    }
+    out
+}
+
+/// Side of the square blocks noise is measured in.
+const NOISE_BLOCK: usize = 8;
+/// Share of the flattest blocks the noise estimate is taken from.
+const FLAT_BLOCK_FRACTION: f32 = 0.1;
+
+/// Noise standard deviations in 0..1 units, measured in flat regions.
+/// `chroma_sigma` combines the red and blue color-difference channels
+/// (R - Y, B - Y). `block_count` is how many blocks were measured; 0 means
+/// the image had no usable flat region and every sigma is 0.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NoiseProfile {
+    pub luma_sigma: f32,
+    pub chroma_sigma: f32,
+    pub red_sigma: f32,
+    pub green_sigma: f32,
+    pub blue_sigma: f32,
+    pub block_count: u32,
+}
+
+/// Estimates per-channel noise of an RGBA8 buffer. The image is cut into
+/// `NOISE_BLOCK` squares; the ones with the lowest gradient (measured on
+/// 3x3-smoothed luma, so the noise itself barely counts) are treated as
+/// flat, and their variance around a fitted plane is averaged, so smooth
+/// gradients such as skies do not read as noise. Blocks with clipped
+/// samples are skipped since clipping hides noise.
+pub fn estimate_noise(image_data: &[u8], width: u32) -> NoiseProfile {
+    let (width, height) = (width as usize, buffer_height(image_data, width) as usize);
+    if width < NOISE_BLOCK || height < NOISE_BLOCK {
+        return NoiseProfile::default();
+    }
+
+    let rgb = |i: usize| [0, 1, 2].map(|c| image_data[i * 4 + c] as f32 / 255.0);
+    let luma_plane: Vec<f32> = (0..width * height)
+        .map(|i| {
+            let [r, g, b] = rgb(i);
+            luma(r, g, b, LumaCoefficients::Rec709)
+        })
+        .collect();
+    let smoothed = |x: usize, y: usize| {
+        let mut sum = 0.0;
+        for sy in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+            for sx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                sum += luma_plane[sy * width + sx];
+            }
+        }
+        sum
+    };
+
+    // (gradient, block origin) of every unclipped block.
+    let mut blocks = Vec::new();
+    for by in (0..=height - NOISE_BLOCK).step_by(NOISE_BLOCK) {
+        for bx in (0..=width - NOISE_BLOCK).step_by(NOISE_BLOCK) {
+            let clipped = (by..by + NOISE_BLOCK).any(|y| {
+                let row = (y * width + bx) * 4;
+                image_data[row..row + NOISE_BLOCK * 4]
+                    .chunks_exact(4)
+                    .any(|p| p[..3].iter().any(|&v| v == 0 || v == u8::MAX))
+            });
+            if clipped {
+                continue;
+            }
+            let mut gradient = 0.0;
+            for y in by..by + NOISE_BLOCK - 1 {
+                for x in bx..bx + NOISE_BLOCK - 1 {
+                    let here = smoothed(x, y);
+                    gradient += (smoothed(x + 1, y) - here).abs() + (smoothed(x, y + 1) - here).abs();
+                }
+            }
+            blocks.push((gradient, bx, by));
+        }
+    }
+    if blocks.is_empty() {
+        return NoiseProfile::default();
+    }
+    blocks.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let flat_count = ((blocks.len() as f32 * FLAT_BLOCK_FRACTION).ceil() as usize).max(1);
+
+    // Residual variance around the least-squares plane, per channel: R, G,
+    // B, Y, R - Y, B - Y. Block coordinates are centered, so the plane's
+    // slopes separate from its mean.
+    let center = (NOISE_BLOCK - 1) as f32 / 2.0;
+    let axis_energy = (0..NOISE_BLOCK)
+        .map(|i| (i as f32 - center).powi(2))
+        .sum::<f32>()
+        * NOISE_BLOCK as f32;
+    let samples = (NOISE_BLOCK * NOISE_BLOCK) as f32;
+    let mut variances = [0.0f64; 6];
+    for &(_, bx, by) in &blocks[..flat_count] {
+        let mut sum = [0.0f32; 6];
+        let mut sum_sq = [0.0f32; 6];
+        let mut sum_x = [0.0f32; 6];
+        let mut sum_y = [0.0f32; 6];
+        for y in by..by + NOISE_BLOCK {
+            for x in bx..bx + NOISE_BLOCK {
+                let i = y * width + x;
+                let [r, g, b] = rgb(i);
+                let l = luma_plane[i];
+                let values = [r, g, b, l, r - l, b - l];
+                let (dx, dy) = ((x - bx) as f32 - center, (y - by) as f32 - center);
+                for c in 0..6 {
+                    sum[c] += values[c];
+                    sum_sq[c] += values[c] * values[c];
+                    sum_x[c] += values[c] * dx;
+                    sum_y[c] += values[c] * dy;
+                }
+            }
+        }
+        for c in 0..6 {
+            let residual = sum_sq[c]
+                - sum[c] * sum[c] / samples
+                - (sum_x[c] * sum_x[c] + sum_y[c] * sum_y[c]) / axis_energy;
+            variances[c] += (residual.max(0.0) / (samples - 3.0)) as f64;
+        }
+    }
+
+    let sigma = |c: usize| (variances[c] / flat_count as f64).sqrt() as f32;
+    NoiseProfile {
+        luma_sigma: sigma(3),
+        chroma_sigma: ((variances[4] + variances[5]) / (2 * flat_count) as f64).sqrt() as f32,
+        red_sigma: sigma(0),
+        green_sigma: sigma(1),
+        blue_sigma: sigma(2),
+        block_count: flat_count as u32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 4x2 RGBA8 image: left half black, right half white.
+    fn half_white() -> Vec<u8> {
+        (0..8)
+            .flat_map(|i| {
+                let v = if i % 4 >= 2 { 255 } else { 0 };
+                [v, v, v, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn region_is_clamped_to_the_image() {
+        let stats = measure_region(&half_white(), 4, 2, 0, 10, 10);
+        assert_eq!(stats.pixel_count, 4);
+        assert!((stats.mean_luminance - 1.0).abs() < 1e-6);
+
+        let stats = measure_region(&half_white(), 4, 1, 0, 2, 2);
+        assert_eq!(stats.pixel_count, 4);
+        assert!((stats.mean_red - 0.5).abs() < 1e-6);
+        assert_eq!((stats.min_luminance, stats.max_luminance), (0.0, 1.0));
+    }
+
+    #[test]
+    fn region_outside_the_image_is_empty() {
+        assert_eq!(measure_region(&half_white(), 4, 4, 0, 2, 2).pixel_count, 0);
+        assert_eq!(measure_region(&half_white(), 4, 0, 0, 0, 2).pixel_count, 0);
+    }
+
+    #[test]
+    fn sampling_hits_pixel_centers_and_blends_between_them() {
+        let image = half_white();
+        assert_eq!(sample_pixel(&image, 4, 2, 1.0, 0.0), [0.0, 0.0, 0.0, 1.0]);
+        let between = sample_pixel(&image, 4, 2, 1.5, 0.5);
+        assert!((between[0] - 0.5).abs() < 1e-6);
+        assert_eq!(between[3], 1.0);
+    }
+
+    #[test]
+    fn sampling_outside_the_image_clamps_to_the_edge() {
+        let image = half_white();
+        assert_eq!(sample_pixel(&image, 4, 2, 99.0, -5.0), [1.0; 4]);
+        assert_eq!(
+            sample_pixel(&image, 4, 2, f32::NAN, 0.0),
+            [0.0, 0.0, 0.0, 1.0]
+        );
+        assert_eq!(sample_pixel(&[], 0, 0, 0.0, 0.0), [0.0; 4]);
+    }
+
+    /// 64x64 RGBA8 horizontal gradient with grey noise of `amplitude` levels
+    /// (uniform, so sigma is amplitude / sqrt(12)).
+    fn noisy_gradient(amplitude: f32) -> Vec<u8> {
+        (0..64u32)
+            .flat_map(|y| (0..64u32).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let base = 60.0 + x as f32 * 2.0;
+                let v =
+                    (base + crate::core::rng::pixel_noise(7, 0, x, y) * amplitude).round() as u8;
+                [v, v, v, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn noise_sigma_is_measured_around_the_gradient() {
+        let expected = 24.0 / 12f32.sqrt() / 255.0;
+        let profile = estimate_noise(&noisy_gradient(24.0), 64);
+        assert!(profile.block_count > 0);
+        for sigma in [profile.luma_sigma, profile.red_sigma, profile.blue_sigma] {
+            assert!((sigma / expected - 1.0).abs() < 0.25, "{profile:?}");
+        }
+        assert!(profile.chroma_sigma < expected * 0.1, "{profile:?}");
+
+        // The gradient alone only leaves rounding noise.
+        let clean = estimate_noise(&noisy_gradient(0.0), 64);
+        assert!(clean.luma_sigma < 0.002, "{clean:?}");
+    }
+
+    #[test]
+    fn clipped_or_tiny_images_have_no_noise_estimate() {
+        let white = vec![255u8; 64 * 64 * 4];
+        assert_eq!(estimate_noise(&white, 64), NoiseProfile::default());
+        assert_eq!(estimate_noise(&half_white(), 4), NoiseProfile::default());
+    }
+}
@@ -0,0 +1,116 @@
+//! Timing of the RAW develop pipeline, step by step, for tuning and spotting
+//! regressions on slow devices.
+//!
+//! rawler runs its steps as one call, so each step is timed by developing
+//! successively longer prefixes of the step list and taking the difference.
+//! A profile therefore costs several full develops; it is a diagnostic, not
+//! something to run per image.
+
+use crate::core::clock::now_ms;
+use anyhow::Result;
+use rawler::{
+    decoders::RawDecodeParams,
+    imgop::develop::{ProcessingStep, RawDevelop},
+    rawimage::RawImage,
+    rawsource::RawSource,
+};
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StepTiming {
+    /// rawler's name for the step, e.g. "Demosaic".
+    pub step: String,
+    pub ms: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileReport {
+    /// Parsing the container and unpacking the sensor data.
+    pub decode_ms: f64,
+    /// One entry per enabled develop step, in pipeline order.
+    pub steps: Vec<StepTiming>,
+    /// `decode_ms` plus a single develop with every step.
+    pub total_ms: f64,
+}
+
+fn timed<T>(f: impl FnOnce() -> T) -> (T, f64) {
+    let start = now_ms();
+    let value = f();
+    (value, now_ms() - start)
+}
+
+fn develop_time(raw_image: &RawImage, steps: &[ProcessingStep]) -> Result<f64> {
+    let developer = RawDevelop {
+        steps: steps.to_vec(),
+        ..RawDevelop::default()
+    };
+    let (result, ms) = timed(|| developer.develop_intermediate(raw_image));
+    result?;
+    Ok(ms)
+}
+
+/// Per-step times from the cumulative times of developing each prefix of
+/// `steps`. Clamped at zero, since timer jitter can make a cheap step look
+/// negative.
+fn step_timings(steps: &[ProcessingStep], cumulative_ms: &[f64]) -> Vec<StepTiming> {
+    let mut previous = 0.0;
+    steps
+        .iter()
+        .zip(cumulative_ms)
+        .map(|(step, &cumulative)| {
+            let ms = (cumulative - previous).max(0.0);
+            previous = cumulative;
+            StepTiming {
+                step: format!("{step:?}"),
+                ms,
+            }
+        })
+        .collect()
+}
+
+/// Decodes `file_bytes` and times each step of rawler's default develop.
+pub fn develop_profile(file_bytes: &[u8]) -> Result<ProfileReport> {
+    let (raw_image, decode_ms) = timed(|| -> Result<RawImage> {
+        let source = RawSource::new_from_slice(file_bytes);
+        let decoder = rawler::get_decoder(&source)?;
+        Ok(decoder.raw_image(&source, &RawDecodeParams::default(), false)?)
+    });
+    let raw_image = raw_image?;
+
+    let all_steps = RawDevelop::default().steps;
+    let cumulative_ms = (1..=all_steps.len())
+        .map(|end| develop_time(&raw_image, &all_steps[..end]))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(ProfileReport {
+        decode_ms,
+        steps: step_timings(&all_steps, &cumulative_ms),
+        total_ms: decode_ms + cumulative_ms.last().copied().unwrap_or(0.0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_times_are_differences_clamped_at_zero() {
+        let steps = [
+            ProcessingStep::Rescale,
+            ProcessingStep::Demosaic,
+            ProcessingStep::CropDefault,
+        ];
+        let timings = step_timings(&steps, &[2.0, 12.5, 12.25]);
+        let names: Vec<_> = timings.iter().map(|t| t.step.as_str()).collect();
+        assert_eq!(names, ["Rescale", "Demosaic", "CropDefault"]);
+        let ms: Vec<_> = timings.iter().map(|t| t.ms).collect();
+        assert_eq!(ms, [2.0, 10.5, 0.0]);
+    }
+
+    #[test]
+    fn garbage_input_is_an_error() {
+        assert!(develop_profile(b"not a raw file").is_err());
+    }
+}
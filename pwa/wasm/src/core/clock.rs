@@ -0,0 +1,16 @@
+//! Wall-clock timing for diagnostics.
+
+/// Milliseconds since an arbitrary origin. `std::time::Instant` panics on
+/// `wasm32-unknown-unknown`, so the browser clock is used there.
+#[cfg(target_arch = "wasm32")]
+pub fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_ms() -> f64 {
+    use std::sync::OnceLock;
+    use std::time::Instant;
+    static ORIGIN: OnceLock<Instant> = OnceLock::new();
+    ORIGIN.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.0
+}
@@ -0,0 +1,265 @@
+//! Named RGB color spaces and conversions between them.
+//!
+//! Each space is defined by its primaries, white point and transfer function.
+//! Conversions decode to linear light, go through XYZ (with Bradford
+//! adaptation when the white points differ) and encode again, so they are
+//! exact up to float rounding for colors inside both gamuts.
+
+#[cfg(feature = "soft-proofing")]
+pub mod proof;
+
+use crate::core::working_space::WorkingSpace;
+use image::DynamicImage;
+use rapidraw_core::srgb::{linear_to_srgb, srgb_to_linear};
+use serde::{Deserialize, Serialize};
+
+type Matrix = [[f64; 3]; 3];
+
+const D65: [f64; 2] = [0.3127, 0.3290];
+const D50: [f64; 2] = [0.3457, 0.3585];
+
+/// Encoding exponent of Adobe RGB (1998).
+const ADOBE_GAMMA: f32 = 563.0 / 256.0;
+/// ROMM/ProPhoto curve: gamma 1.8 with a linear toe below 1/512.
+const PROPHOTO_GAMMA: f32 = 1.8;
+const PROPHOTO_TOE: f32 = 1.0 / 512.0;
+
+/// Bradford cone response matrix.
+const BRADFORD: Matrix = [
+    [0.8951, 0.2664, -0.1614],
+    [-0.7502, 1.7135, 0.0367],
+    [0.0389, -0.0685, 1.0296],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ColorSpace {
+    Srgb,
+    AdobeRgb,
+    /// P3 primaries with the sRGB curve and a D65 white, as used by Apple
+    /// displays and the CSS `display-p3` space.
+    DisplayP3,
+    /// ROMM RGB, D50 white.
+    ProPhoto,
+    /// The pipeline's working space: sRGB primaries, linear light.
+    LinearRec709,
+}
+
+impl From<WorkingSpace> for ColorSpace {
+    fn from(space: WorkingSpace) -> Self {
+        match space {
+            WorkingSpace::LinearRec709 => ColorSpace::LinearRec709,
+            WorkingSpace::Srgb => ColorSpace::Srgb,
+        }
+    }
+}
+
+impl ColorSpace {
+    /// xy chromaticities of the red, green and blue primaries, then white.
+    fn chromaticities(self) -> ([[f64; 2]; 3], [f64; 2]) {
+        match self {
+            ColorSpace::Srgb | ColorSpace::LinearRec709 => {
+                ([[0.64, 0.33], [0.30, 0.60], [0.15, 0.06]], D65)
+            }
+            ColorSpace::AdobeRgb => ([[0.64, 0.33], [0.21, 0.71], [0.15, 0.06]], D65),
+            ColorSpace::DisplayP3 => ([[0.680, 0.320], [0.265, 0.690], [0.150, 0.060]], D65),
+            ColorSpace::ProPhoto => ([[0.7347, 0.2653], [0.1596, 0.8404], [0.0366, 0.0001]], D50),
+        }
+    }
+
+    /// Decodes one channel value to linear light.
+    pub fn decode(self, v: f32) -> f32 {
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => srgb_to_linear(v),
+            ColorSpace::LinearRec709 => v,
+            ColorSpace::AdobeRgb => v.max(0.0).powf(ADOBE_GAMMA),
+            ColorSpace::ProPhoto => {
+                if v < PROPHOTO_TOE * 16.0 {
+                    v / 16.0
+                } else {
+                    v.powf(PROPHOTO_GAMMA)
+                }
+            }
+        }
+    }
+
+    /// Encodes one linear channel value. Negative (out of gamut) values clip
+    /// to zero in the encoded spaces, as in `linear_to_srgb`.
+    pub fn encode(self, v: f32) -> f32 {
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => linear_to_srgb(v),
+            ColorSpace::LinearRec709 => v,
+            ColorSpace::AdobeRgb => v.max(0.0).powf(1.0 / ADOBE_GAMMA),
+            ColorSpace::ProPhoto => {
+                let v = v.max(0.0);
+                if v < PROPHOTO_TOE {
+                    v * 16.0
+                } else {
+                    v.powf(1.0 / PROPHOTO_GAMMA)
+                }
+            }
+        }
+    }
+
+    /// Linear RGB to XYZ, normalised so white has Y = 1.
+    fn rgb_to_xyz(self) -> Matrix {
+        let (primaries, white) = self.chromaticities();
+        let columns = primaries.map(xy_to_xyz);
+        let primaries_matrix: Matrix =
+            std::array::from_fn(|row| std::array::from_fn(|col| columns[col][row]));
+        let scale = mul_vector(&invert(&primaries_matrix), xy_to_xyz(white));
+        std::array::from_fn(|row| {
+            std::array::from_fn(|col| primaries_matrix[row][col] * scale[col])
+        })
+    }
+}
+
+fn xy_to_xyz([x, y]: [f64; 2]) -> [f64; 3] {
+    [x / y, 1.0, (1.0 - x - y) / y]
+}
+
+fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    std::array::from_fn(|row| {
+        std::array::from_fn(|col| (0..3).map(|k| a[row][k] * b[k][col]).sum())
+    })
+}
+
+fn mul_vector(m: &Matrix, v: [f64; 3]) -> [f64; 3] {
+    std::array::from_fn(|row| (0..3).map(|k| m[row][k] * v[k]).sum())
+}
+
+fn invert(m: &Matrix) -> Matrix {
+    let cofactor = |r: usize, c: usize| {
+        let (r0, r1) = ((r + 1) % 3, (r + 2) % 3);
+        let (c0, c1) = ((c + 1) % 3, (c + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let det: f64 = (0..3).map(|c| m[0][c] * cofactor(0, c)).sum();
+    std::array::from_fn(|row| std::array::from_fn(|col| cofactor(col, row) / det))
+}
+
+/// Bradford chromatic adaptation from one white point to another, in XYZ.
+fn adaptation(from: [f64; 2], to: [f64; 2]) -> Matrix {
+    let source = mul_vector(&BRADFORD, xy_to_xyz(from));
+    let destination = mul_vector(&BRADFORD, xy_to_xyz(to));
+    let mut gains = [[0.0; 3]; 3];
+    for i in 0..3 {
+        gains[i][i] = destination[i] / source[i];
+    }
+    mul(&invert(&BRADFORD), &mul(&gains, &BRADFORD))
+}
+
+/// Linear `from` RGB to linear `to` RGB.
+pub fn conversion_matrix(from: ColorSpace, to: ColorSpace) -> [[f32; 3]; 3] {
+    let (_, from_white) = from.chromaticities();
+    let (_, to_white) = to.chromaticities();
+    let xyz = if from_white == to_white {
+        from.rgb_to_xyz()
+    } else {
+        mul(&adaptation(from_white, to_white), &from.rgb_to_xyz())
+    };
+    let m = mul(&invert(&to.rgb_to_xyz()), &xyz);
+    m.map(|row| row.map(|v| v as f32))
+}
+
+/// Converts interleaved `channels`-wide pixels in place. Only the first three
+/// channels are touched, so alpha passes through.
+pub fn convert_pixels(data: &mut [f32], channels: usize, from: ColorSpace, to: ColorSpace) {
+    if from == to {
+        return;
+    }
+    // Spaces sharing primaries and white differ only in their curves.
+    let matrix =
+        (from.chromaticities() != to.chromaticities()).then(|| conversion_matrix(from, to));
+    for pixel in data.chunks_exact_mut(channels) {
+        let linear = [0, 1, 2].map(|c| from.decode(pixel[c]));
+        let converted = match &matrix {
+            Some(m) => m.map(|row| row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2]),
+            None => linear,
+        };
+        for (c, v) in converted.into_iter().enumerate() {
+            pixel[c] = to.encode(v);
+        }
+    }
+}
+
+/// Re-expresses `image` from one color space in another, returning an f32
+/// image (RGBA when the input has alpha).
+pub fn convert_color_space(image: &DynamicImage, from: ColorSpace, to: ColorSpace) -> DynamicImage {
+    if image.color().has_alpha() {
+        let mut buffer = image.to_rgba32f();
+        convert_pixels(&mut buffer, 4, from, to);
+        DynamicImage::ImageRgba32F(buffer)
+    } else {
+        let mut buffer = image.to_rgb32f();
+        convert_pixels(&mut buffer, 3, from, to);
+        DynamicImage::ImageRgb32F(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_matrix_matches_the_standard() {
+        let m = ColorSpace::Srgb.rgb_to_xyz();
+        let expected = [
+            [0.4124, 0.3576, 0.1805],
+            [0.2126, 0.7152, 0.0722],
+            [0.0193, 0.1192, 0.9505],
+        ];
+        for (row, expected_row) in m.iter().zip(expected) {
+            for (v, e) in row.iter().zip(expected_row) {
+                assert!((v - e).abs() < 1e-3, "{m:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn white_stays_white_across_spaces() {
+        let spaces = [
+            ColorSpace::Srgb,
+            ColorSpace::AdobeRgb,
+            ColorSpace::DisplayP3,
+            ColorSpace::ProPhoto,
+            ColorSpace::LinearRec709,
+        ];
+        for from in spaces {
+            for to in spaces {
+                let mut white = [1.0, 1.0, 1.0, 0.5];
+                convert_pixels(&mut white, 4, from, to);
+                assert!(
+                    white[..3].iter().all(|v| (v - 1.0).abs() < 1e-3),
+                    "{from:?} -> {to:?}: {white:?}"
+                );
+                assert_eq!(white[3], 0.5);
+            }
+        }
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        let original = [0.8, 0.35, 0.1, 0.2, 0.6, 0.9];
+        for space in [
+            ColorSpace::AdobeRgb,
+            ColorSpace::DisplayP3,
+            ColorSpace::ProPhoto,
+        ] {
+            let mut pixels = original;
+            convert_pixels(&mut pixels, 3, ColorSpace::Srgb, space);
+            assert_ne!(pixels, original);
+            convert_pixels(&mut pixels, 3, space, ColorSpace::Srgb);
+            for (v, o) in pixels.iter().zip(original) {
+                assert!((v - o).abs() < 1e-3, "{space:?}: {pixels:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn linear_and_encoded_srgb_differ_only_in_the_curve() {
+        let mut pixel = [0.5, 0.5, 0.5];
+        convert_pixels(&mut pixel, 3, ColorSpace::Srgb, ColorSpace::LinearRec709);
+        assert!(pixel.iter().all(|v| (v - srgb_to_linear(0.5)).abs() < 1e-6));
+    }
+}
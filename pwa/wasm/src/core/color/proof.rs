@@ -0,0 +1,221 @@
+//! ICC soft proofing: previews how an image reproduces on an output device.
+//!
+//! Built on qcms, which is pure Rust and runs in the browser but only handles
+//! RGB (matrix/shaper and LUT) destination profiles; CMYK press profiles are
+//! rejected. Photo printers driven through an RGB profile, and wide-gamut or
+//! sRGB displays, are covered.
+
+use crate::core::working_space;
+use anyhow::{anyhow, Result};
+use image::{DynamicImage, Rgba, RgbaImage};
+use qcms::{DataType, Intent, Profile, Transform};
+use serde::{Deserialize, Serialize};
+
+/// Largest 8-bit channel difference a src -> dst -> src round trip may show
+/// before a pixel counts as out of gamut. Absorbs quantisation error.
+const GAMUT_TOLERANCE: u8 = 3;
+
+/// ICC rendering intent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProofIntent {
+    #[default]
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+}
+
+impl ProofIntent {
+    fn to_qcms(self) -> Intent {
+        match self {
+            ProofIntent::Perceptual => Intent::Perceptual,
+            ProofIntent::RelativeColorimetric => Intent::RelativeColorimetric,
+            ProofIntent::Saturation => Intent::Saturation,
+            ProofIntent::AbsoluteColorimetric => Intent::AbsoluteColorimetric,
+        }
+    }
+}
+
+/// A color space to proof from or to.
+#[derive(Debug, Clone, Copy)]
+pub enum ProofProfile<'a> {
+    Srgb,
+    AdobeRgb,
+    ProPhoto,
+    /// An RGB ICC profile as file bytes.
+    Icc(&'a [u8]),
+}
+
+fn xy(x: f64, y: f64) -> qcms::CIE_xyY {
+    qcms::CIE_xyY { x, y, Y: 1.0 }
+}
+
+impl<'a> ProofProfile<'a> {
+    /// `icc` when it is non-empty, otherwise the built-in space called
+    /// `name` ("srgb", "adobeRgb" or "proPhoto").
+    pub fn from_name_or_icc(name: &str, icc: &'a [u8]) -> Result<Self> {
+        if !icc.is_empty() {
+            return Ok(ProofProfile::Icc(icc));
+        }
+        match name {
+            "srgb" => Ok(ProofProfile::Srgb),
+            "adobeRgb" => Ok(ProofProfile::AdobeRgb),
+            "proPhoto" => Ok(ProofProfile::ProPhoto),
+            _ => Err(anyhow!("Unknown proofing profile '{name}'")),
+        }
+    }
+
+    fn load(&self) -> Result<Box<Profile>> {
+        let profile = match self {
+            ProofProfile::Srgb => Some(Profile::new_sRGB()),
+            ProofProfile::AdobeRgb => Profile::new_rgb_with_gamma_set(
+                xy(0.3127, 0.3290),
+                qcms::CIE_xyYTRIPLE {
+                    red: xy(0.64, 0.33),
+                    green: xy(0.21, 0.71),
+                    blue: xy(0.15, 0.06),
+                },
+                563.0 / 256.0,
+                563.0 / 256.0,
+                563.0 / 256.0,
+            ),
+            ProofProfile::ProPhoto => Profile::new_rgb_with_gamma_set(
+                xy(0.3457, 0.3585),
+                qcms::CIE_xyYTRIPLE {
+                    red: xy(0.7347, 0.2653),
+                    green: xy(0.1596, 0.8404),
+                    blue: xy(0.0366, 0.0001),
+                },
+                1.8,
+                1.8,
+                1.8,
+            ),
+            ProofProfile::Icc(bytes) => Profile::new_from_slice(bytes, false),
+        };
+        profile.ok_or_else(|| anyhow!("Could not read the {self:?} ICC profile"))
+    }
+}
+
+fn transform(from: &Profile, to: &Profile, intent: Intent) -> Result<Transform> {
+    Transform::new(from, to, DataType::RGBA8, intent).ok_or_else(|| {
+        anyhow!("Cannot build a transform between these profiles; only RGB profiles are supported")
+    })
+}
+
+/// The image as an export would encode it: 8-bit RGBA in the pipeline's
+/// output transfer.
+fn encoded_rgba(image: &DynamicImage) -> RgbaImage {
+    working_space::to_srgb(image).to_rgba8()
+}
+
+/// Per pixel, whether the color (taken as encoded in `src`) falls outside
+/// `dst`'s gamut, judged by how far a colorimetric round trip moves it.
+pub fn out_of_gamut_mask(
+    image: &DynamicImage,
+    src: &ProofProfile,
+    dst: &ProofProfile,
+) -> Result<Vec<bool>> {
+    let (src, dst) = (src.load()?, dst.load()?);
+    let original = encoded_rgba(image);
+    let mut round_trip = original.clone();
+    transform(&src, &dst, Intent::RelativeColorimetric)?.apply(&mut round_trip);
+    transform(&dst, &src, Intent::RelativeColorimetric)?.apply(&mut round_trip);
+
+    Ok(original
+        .pixels()
+        .zip(round_trip.pixels())
+        .map(|(a, b)| (0..3).any(|c| a[c].abs_diff(b[c]) > GAMUT_TOLERANCE))
+        .collect())
+}
+
+/// Simulates `image` (its encoded values interpreted in `src`) reproduced on
+/// `dst` with `intent`, as seen on an sRGB display. With `gamut_warning`,
+/// colors `dst` cannot reach are painted that sRGB color instead. Returns a
+/// pipeline image; alpha is kept.
+pub fn soft_proof(
+    image: &DynamicImage,
+    src: &ProofProfile,
+    dst: &ProofProfile,
+    intent: ProofIntent,
+    gamut_warning: Option<[u8; 3]>,
+) -> Result<DynamicImage> {
+    let (src_profile, dst_profile) = (src.load()?, dst.load()?);
+    let display = Profile::new_sRGB();
+
+    let mut proofed = encoded_rgba(image);
+    transform(&src_profile, &dst_profile, intent.to_qcms())?.apply(&mut proofed);
+    // The device can only show what it reproduced, so map back colorimetrically.
+    transform(&dst_profile, &display, Intent::RelativeColorimetric)?.apply(&mut proofed);
+
+    if let Some([r, g, b]) = gamut_warning {
+        let mask = out_of_gamut_mask(image, src, dst)?;
+        for (pixel, _) in proofed.pixels_mut().zip(mask).filter(|(_, out)| *out) {
+            *pixel = Rgba([r, g, b, pixel[3]]);
+        }
+    }
+
+    Ok(working_space::from_srgb(DynamicImage::ImageRgba8(proofed)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_are_named_or_given_as_icc() {
+        assert!(matches!(
+            ProofProfile::from_name_or_icc("adobeRgb", &[]),
+            Ok(ProofProfile::AdobeRgb)
+        ));
+        assert!(matches!(
+            ProofProfile::from_name_or_icc("srgb", &[1, 2, 3]),
+            Ok(ProofProfile::Icc(_))
+        ));
+        assert!(ProofProfile::from_name_or_icc("cmyk", &[]).is_err());
+        assert!(ProofProfile::Icc(&[1, 2, 3]).load().is_err());
+    }
+
+    #[test]
+    fn colors_outside_the_destination_gamut_are_flagged() {
+        // Pure ProPhoto green is far outside sRGB; a mid grey is inside both.
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                Rgba([0, 255, 0, 255])
+            } else {
+                Rgba([128, 128, 128, 255])
+            }
+        }));
+        let mask = out_of_gamut_mask(&image, &ProofProfile::ProPhoto, &ProofProfile::Srgb).unwrap();
+        assert_eq!(mask, [true, false]);
+        let mask = out_of_gamut_mask(&image, &ProofProfile::Srgb, &ProofProfile::Srgb).unwrap();
+        assert_eq!(mask, [false, false]);
+    }
+
+    #[test]
+    fn gamut_warning_paints_out_of_gamut_pixels_and_keeps_alpha() {
+        let encoded = DynamicImage::ImageRgba8(RgbaImage::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                Rgba([0, 255, 0, 200])
+            } else {
+                Rgba([128, 128, 128, 100])
+            }
+        }));
+        let image = working_space::from_srgb(encoded);
+        let proofed = soft_proof(
+            &image,
+            &ProofProfile::ProPhoto,
+            &ProofProfile::Srgb,
+            ProofIntent::RelativeColorimetric,
+            Some([255, 0, 255]),
+        )
+        .unwrap();
+        let proofed = working_space::to_srgb(&proofed).to_rgba8();
+        assert_eq!(*proofed.get_pixel(0, 0), Rgba([255, 0, 255, 200]));
+        let grey = proofed.get_pixel(1, 0);
+        // ProPhoto's 1.8 gamma encodes the same grey lighter than sRGB does.
+        assert!(grey[0] > 128, "{grey:?}");
+        assert!((1..3).all(|c| grey[c].abs_diff(grey[0]) <= 2), "{grey:?}");
+        assert_eq!(grey[3], 100);
+    }
+}
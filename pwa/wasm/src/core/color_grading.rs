@@ -0,0 +1,1055 @@
+//! Creative color grading: per-hue HSL, shadow/midtone/highlight color wheels
+//! and global saturation/vibrance, applied in that order.
+//!
+//! Works on display-referred (gamma-encoded) RGB in 0..1, with Rec.709 luma
+//! deciding which tonal range a pixel belongs to.
+
+use crate::core::adjustments::{apply_tone_curve, SimpleAdjustments};
+use crate::core::working_space::{
+    convert_buffer, convert_rgba_buffer, WorkingSpace, PIPELINE_SPACE,
+};
+use anyhow::{anyhow, Result};
+use image::{imageops, DynamicImage, RgbaImage};
+use rapidraw_core::luma::{luma, LumaCoefficients};
+use rapidraw_core::lut::{parse_cube, sample_lut_tetrahedral, Lut3d, MAX_LUT_SIZE, MIN_LUT_SIZE};
+use serde::{Deserialize, Serialize};
+
+/// A single color wheel. `x`/`y` is the puck position on the unit disk (angle is
+/// hue, distance from center is saturation); `intensity` scales the effect.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorWheel {
+    pub x: f32,
+    pub y: f32,
+    pub intensity: f32,
+}
+
+/// Per-band HSL adjustment. `hue` is in degrees, `saturation` and `luminance`
+/// in -100..100.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HslAdjustment {
+    pub hue: f32,
+    pub saturation: f32,
+    pub luminance: f32,
+}
+
+impl HslAdjustment {
+    fn is_identity(&self) -> bool {
+        self.hue == 0.0 && self.saturation == 0.0 && self.luminance == 0.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HslAdjustments {
+    pub reds: HslAdjustment,
+    pub oranges: HslAdjustment,
+    pub yellows: HslAdjustment,
+    pub greens: HslAdjustment,
+    pub aquas: HslAdjustment,
+    pub blues: HslAdjustment,
+    pub purples: HslAdjustment,
+    pub magentas: HslAdjustment,
+}
+
+/// Band centers in degrees, in the same order as `HslAdjustments::bands`.
+pub const HUE_BAND_CENTERS: [f32; 8] = [0.0, 30.0, 60.0, 120.0, 180.0, 240.0, 270.0, 300.0];
+
+impl HslAdjustments {
+    pub fn bands(&self) -> [HslAdjustment; 8] {
+        [
+            self.reds,
+            self.oranges,
+            self.yellows,
+            self.greens,
+            self.aquas,
+            self.blues,
+            self.purples,
+            self.magentas,
+        ]
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.bands().iter().all(HslAdjustment::is_identity)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ColorGradingSettings {
+    pub hsl: HslAdjustments,
+    pub shadows: ColorWheel,
+    pub midtones: ColorWheel,
+    pub highlights: ColorWheel,
+    /// -1 (grayscale) to +1 (double saturation).
+    pub global_saturation: f32,
+    /// Like saturation, but weighted towards less saturated colors.
+    pub global_vibrance: f32,
+}
+
+pub fn parse_color_grading_settings(json: &str) -> ColorGradingSettings {
+    serde_json::from_str::<ColorGradingSettings>(json).unwrap_or_default()
+}
+
+/// Reads the `colorGrading` object embedded in an adjustments JSON document.
+pub fn color_grading_from_adjustments(adjustments_json: &str) -> ColorGradingSettings {
+    serde_json::from_str::<serde_json::Value>(adjustments_json)
+        .ok()
+        .and_then(|v| v.get("colorGrading").cloned())
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+impl ColorGradingSettings {
+    pub fn is_identity(&self) -> bool {
+        self.hsl.is_identity()
+            && self.global_saturation.abs() < 1e-6
+            && self.global_vibrance.abs() < 1e-6
+            && [self.shadows, self.midtones, self.highlights]
+                .iter()
+                .all(|w| w.intensity.abs() < 1e-6 || (w.x == 0.0 && w.y == 0.0))
+    }
+}
+
+/// Checks every field against its documented range, naming the first one out
+/// of range (NaN counts as out of range).
+pub fn validate_color_grading_settings(settings: &ColorGradingSettings) -> Result<()> {
+    let check = |name: &str, value: f32, min: f32, max: f32| {
+        if (min..=max).contains(&value) {
+            Ok(())
+        } else {
+            Err(anyhow!("{name} must be within {min}..={max}, got {value}"))
+        }
+    };
+
+    const BAND_NAMES: [&str; 8] = [
+        "reds", "oranges", "yellows", "greens", "aquas", "blues", "purples", "magentas",
+    ];
+    for (name, band) in BAND_NAMES.iter().zip(settings.hsl.bands()) {
+        check(&format!("hsl.{name}.hue"), band.hue, -180.0, 180.0)?;
+        check(&format!("hsl.{name}.saturation"), band.saturation, -100.0, 100.0)?;
+        check(&format!("hsl.{name}.luminance"), band.luminance, -100.0, 100.0)?;
+    }
+
+    for (name, wheel) in [
+        ("shadows", settings.shadows),
+        ("midtones", settings.midtones),
+        ("highlights", settings.highlights),
+    ] {
+        // A little slack for pucks placed on the rim from polar coordinates.
+        let radius = (wheel.x * wheel.x + wheel.y * wheel.y).sqrt();
+        check(&format!("{name} puck radius"), radius, 0.0, 1.0 + 1e-4)?;
+        check(&format!("{name}.intensity"), wheel.intensity, 0.0, 1.0)?;
+    }
+
+    check("globalSaturation", settings.global_saturation, -1.0, 1.0)?;
+    check("globalVibrance", settings.global_vibrance, -1.0, 1.0)
+}
+
+const MAX_LIFT: f32 = 0.25;
+const MAX_GAMMA: f32 = 0.5;
+const MAX_GAIN: f32 = 0.5;
+
+#[inline(always)]
+fn hue_to_rgb(hue: f32) -> [f32; 3] {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let channel = |n: f32| {
+        let k = (n + h) % 6.0;
+        1.0 - (k.min(4.0 - k).clamp(0.0, 1.0))
+    };
+    [channel(5.0), channel(3.0), channel(1.0)]
+}
+
+impl ColorWheel {
+    /// Zero-luma RGB offset for this wheel, i.e. a direction in the chroma plane
+    /// whose length is saturation times intensity.
+    pub fn chroma_offset(&self) -> [f32; 3] {
+        let saturation = (self.x * self.x + self.y * self.y).sqrt().min(1.0);
+        let magnitude = saturation * self.intensity;
+        if magnitude.abs() < 1e-6 {
+            return [0.0; 3];
+        }
+        let hue = self.y.atan2(self.x) / std::f32::consts::TAU;
+        let [r, g, b] = hue_to_rgb(hue);
+        let l = luma(r, g, b, LumaCoefficients::Rec709);
+        [(r - l) * magnitude, (g - l) * magnitude, (b - l) * magnitude]
+    }
+}
+
+// The shadow and highlight ramps overlap the midtones so zone transitions are
+// spread out; the midtone weight is the remainder, so the three always sum to 1.
+const SHADOW_RAMP_END: f32 = 0.55;
+const HIGHLIGHT_RAMP_START: f32 = 0.45;
+
+#[inline(always)]
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+pub fn calculate_shadow_weight(luma: f32) -> f32 {
+    1.0 - smoothstep(0.0, SHADOW_RAMP_END, luma)
+}
+
+pub fn calculate_highlight_weight(luma: f32) -> f32 {
+    smoothstep(HIGHLIGHT_RAMP_START, 1.0, luma)
+}
+
+pub fn calculate_midtone_weight(luma: f32) -> f32 {
+    (1.0 - calculate_shadow_weight(luma) - calculate_highlight_weight(luma)).max(0.0)
+}
+
+/// Applies the three wheels as lift (shadows), gamma (midtones) and gain
+/// (highlights), each restricted to its tonal range.
+pub fn apply_color_wheel_point(rgb: [f32; 3], settings: &ColorGradingSettings) -> [f32; 3] {
+    let lift = settings.shadows.chroma_offset();
+    let gamma = settings.midtones.chroma_offset();
+    let gain = settings.highlights.chroma_offset();
+
+    let l = luma(rgb[0], rgb[1], rgb[2], LumaCoefficients::Rec709).clamp(0.0, 1.0);
+    let w_shadow = calculate_shadow_weight(l);
+    let w_mid = calculate_midtone_weight(l);
+    let w_high = calculate_highlight_weight(l);
+
+    let mut out = rgb;
+    for c in 0..3 {
+        let v = rgb[c].max(0.0);
+        let lifted = v + lift[c] * MAX_LIFT * (1.0 - v);
+        let gamma_exp = 1.0 / (1.0 + gamma[c] * MAX_GAMMA).max(0.1);
+        let gammaed = v.powf(gamma_exp);
+        let gained = v * (1.0 + gain[c] * MAX_GAIN);
+
+        out[c] = rgb[c]
+            + w_shadow * (lifted - v)
+            + w_mid * (gammaed - v)
+            + w_high * (gained - v);
+    }
+    out
+}
+
+pub fn rgb_to_hsl(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb;
+    let max_c = r.max(g).max(b);
+    let min_c = r.min(g).min(b);
+    let l = (max_c + min_c) * 0.5;
+    let delta = max_c - min_c;
+    if delta < 1e-6 {
+        return [0.0, 0.0, l];
+    }
+    let s = delta / (1.0 - (2.0 * l - 1.0).abs()).max(1e-6);
+    let h = if max_c == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max_c == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+    [h * 60.0, s.min(1.0), l]
+}
+
+pub fn hsl_to_rgb(hsl: [f32; 3]) -> [f32; 3] {
+    let [h, s, l] = hsl;
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let hp = h.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (hp % 2.0 - 1.0).abs());
+    let (r, g, b) = match hp as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c * 0.5;
+    [r + m, g + m, b + m]
+}
+
+/// The two bands (see `HUE_BAND_CENTERS`) bracketing `hue` degrees, with weights
+/// summing to 1. Weights follow a smoothstep between band centers so an
+/// adjustment to one band fades into its neighbour instead of cutting off.
+pub fn get_color_range(hue: f32) -> [(usize, f32); 2] {
+    let hue = hue.rem_euclid(360.0);
+    let count = HUE_BAND_CENTERS.len();
+    for (i, &start) in HUE_BAND_CENTERS.iter().enumerate() {
+        let next = (i + 1) % count;
+        let end = if next == 0 { 360.0 } else { HUE_BAND_CENTERS[next] };
+        if hue >= start && hue < end {
+            let t = (hue - start) / (end - start);
+            let w = t * t * (3.0 - 2.0 * t);
+            return [(i, 1.0 - w), (next, w)];
+        }
+    }
+    [(0, 1.0), (1, 0.0)]
+}
+
+fn blend_band_adjustments(hsl: &HslAdjustments, hue: f32) -> HslAdjustment {
+    let bands = hsl.bands();
+    get_color_range(hue)
+        .iter()
+        .fold(HslAdjustment::default(), |acc, &(band, weight)| HslAdjustment {
+            hue: acc.hue + bands[band].hue * weight,
+            saturation: acc.saturation + bands[band].saturation * weight,
+            luminance: acc.luminance + bands[band].luminance * weight,
+        })
+}
+
+/// Hue shifts additively; saturation and lightness scale multiplicatively so
+/// boosts roll off towards the limits instead of clipping, and -100 saturation
+/// fully desaturates.
+pub fn apply_hsl_adjustments(rgb: [f32; 3], hsl: &HslAdjustments) -> [f32; 3] {
+    let [h, s, l] = rgb_to_hsl(rgb);
+    if s < 1e-6 {
+        return rgb;
+    }
+    let adjustment = blend_band_adjustments(hsl, h);
+    if adjustment.is_identity() {
+        return rgb;
+    }
+
+    let new_h = h + adjustment.hue;
+    let new_s = (s * (1.0 + adjustment.saturation / 100.0)).clamp(0.0, 1.0);
+    let lum = adjustment.luminance / 100.0;
+    let new_l = if lum >= 0.0 {
+        l + (1.0 - l) * lum * s
+    } else {
+        l * (1.0 + lum * s)
+    };
+    hsl_to_rgb([new_h, new_s, new_l.clamp(0.0, 1.0)])
+}
+
+/// Global saturation and vibrance, using the same vibrance falloff as
+/// `adjustments::apply_basic_adjustments`.
+pub fn apply_global_saturation(rgb: [f32; 3], saturation: f32, vibrance: f32) -> [f32; 3] {
+    let [r, g, b] = rgb;
+    let l = luma(r, g, b, LumaCoefficients::Rec709);
+    let mut sat_scale = 1.0 + saturation;
+
+    if vibrance.abs() > 0.001 {
+        let max_c = r.max(g).max(b);
+        let min_c = r.min(g).min(b);
+        let sat = if max_c > 1e-6 {
+            (max_c - min_c) / max_c
+        } else {
+            0.0
+        };
+        sat_scale *= 1.0 + vibrance * (1.0 - sat);
+    }
+
+    let sat_scale = sat_scale.max(0.0);
+    [
+        l + (r - l) * sat_scale,
+        l + (g - l) * sat_scale,
+        l + (b - l) * sat_scale,
+    ]
+}
+
+pub fn apply_color_grading_to_rgb(rgb: [f32; 3], settings: &ColorGradingSettings) -> [f32; 3] {
+    let graded = apply_hsl_adjustments(rgb, &settings.hsl);
+    let graded = apply_color_wheel_point(graded, settings);
+    let graded =
+        apply_global_saturation(graded, settings.global_saturation, settings.global_vibrance);
+    [
+        graded[0].clamp(0.0, 1.0),
+        graded[1].clamp(0.0, 1.0),
+        graded[2].clamp(0.0, 1.0),
+    ]
+}
+
+/// Runs `map` over every pixel of a linear pipeline image, with the RGB
+/// values sRGB-encoded for the call and decoded back afterwards. Alpha passes
+/// through.
+fn map_encoded_pixels(image: &mut DynamicImage, map: impl Fn([f32; 3]) -> [f32; 3]) {
+    let apply = |data: &mut [f32], channels: usize| {
+        data.chunks_exact_mut(channels).for_each(|p| {
+            let mapped = map([p[0], p[1], p[2]]);
+            p[..3].copy_from_slice(&mapped);
+        });
+    };
+    if image.color().has_alpha() {
+        let mut buffer = image.to_rgba32f();
+        convert_rgba_buffer(&mut buffer, PIPELINE_SPACE, WorkingSpace::Srgb);
+        apply(buffer.as_mut(), 4);
+        convert_rgba_buffer(&mut buffer, WorkingSpace::Srgb, PIPELINE_SPACE);
+        *image = DynamicImage::ImageRgba32F(buffer);
+    } else {
+        let mut buffer = image.to_rgb32f();
+        convert_buffer(&mut buffer, PIPELINE_SPACE, WorkingSpace::Srgb);
+        apply(buffer.as_mut(), 3);
+        convert_buffer(&mut buffer, WorkingSpace::Srgb, PIPELINE_SPACE);
+        *image = DynamicImage::ImageRgb32F(buffer);
+    }
+}
+
+/// Grades a linear pipeline image. The wheels and HSL bands are defined on
+/// sRGB-encoded values (as the generated LUTs are), so each pixel is encoded
+/// for grading and decoded back. Alpha passes through.
+pub fn apply_color_grading_to_image(image: &mut DynamicImage, settings: &ColorGradingSettings) {
+    if settings.is_identity() {
+        return;
+    }
+    map_encoded_pixels(image, |rgb| apply_color_grading_to_rgb(rgb, settings));
+}
+
+/// The creative LUT of the adjustments JSON: `.cube` text in `lutCube` and
+/// its strength in `lutIntensity` (0-100, default 100, as on desktop).
+/// `None` when there is no LUT, it does not parse, or the strength is zero.
+pub fn lut_from_adjustments(adjustments_json: &str) -> Option<(Lut3d, f32)> {
+    let adjustments: serde_json::Value = serde_json::from_str(adjustments_json).ok()?;
+    let lut = parse_cube(adjustments.get("lutCube")?.as_str()?).ok()?;
+    let intensity = adjustments["lutIntensity"].as_f64().unwrap_or(100.0) as f32 / 100.0;
+    (intensity > 0.0).then_some((lut, intensity.min(1.0)))
+}
+
+/// Runs a linear pipeline image through a `.cube` LUT on sRGB-encoded
+/// values, blended with the input by `intensity` (0..1). This is the last
+/// color step, after grading, matching the desktop shader.
+pub fn apply_lut_to_image(image: &mut DynamicImage, lut: &Lut3d, intensity: f32) {
+    if intensity <= 0.0 {
+        return;
+    }
+    map_encoded_pixels(image, |rgb| lut.apply(rgb, intensity.min(1.0)));
+}
+
+/// Grades a canvas `ImageData` buffer (sRGB RGBA8) with HSL, wheels and
+/// globals in one pass over the pixels. Settings are validated first, so
+/// nothing is touched when they are out of range.
+pub fn apply_color_grading_rgba8(
+    image_data: &[u8],
+    settings: &ColorGradingSettings,
+) -> Result<Vec<u8>> {
+    validate_color_grading_settings(settings)?;
+    if !image_data.len().is_multiple_of(4) {
+        return Err(anyhow!(
+            "RGBA buffer length {} is not a multiple of 4",
+            image_data.len()
+        ));
+    }
+    let mut out = image_data.to_vec();
+    if settings.is_identity() {
+        return Ok(out);
+    }
+    for p in out.chunks_exact_mut(4) {
+        let rgb = [0, 1, 2].map(|c| p[c] as f32 / 255.0);
+        let graded = apply_color_grading_to_rgb(rgb, settings);
+        for c in 0..3 {
+            p[c] = (graded[c] * 255.0).round() as u8;
+        }
+    }
+    Ok(out)
+}
+
+/// Rebuilds each RGB channel of an RGBA8 buffer from the source channel named
+/// in `mapping` (`[2, 1, 0]` swaps red and blue for false-color infrared).
+/// Channels may repeat; alpha is kept.
+pub fn swap_channels(image_data: &[u8], mapping: &[u32]) -> Result<Vec<u8>> {
+    let &[r, g, b] = mapping else {
+        return Err(anyhow!(
+            "channel mapping needs 3 entries, got {}",
+            mapping.len()
+        ));
+    };
+    let sources = [r, g, b].map(|c| c as usize);
+    if sources.iter().any(|&c| c > 2) {
+        return Err(anyhow!(
+            "channel mapping {mapping:?} must only name channels 0-2"
+        ));
+    }
+    if !image_data.len().is_multiple_of(4) {
+        return Err(anyhow!(
+            "RGBA buffer length {} is not a multiple of 4",
+            image_data.len()
+        ));
+    }
+
+    let mut out = image_data.to_vec();
+    if sources == [0, 1, 2] {
+        return Ok(out);
+    }
+    for (dst, src) in out.chunks_exact_mut(4).zip(image_data.chunks_exact(4)) {
+        for (d, &s) in dst.iter_mut().zip(&sources) {
+            *d = src[s];
+        }
+    }
+    Ok(out)
+}
+
+/// Bakes the full grading pipeline into a `size`^3 RGB lattice, red varying
+/// fastest (the `.cube` ordering).
+pub fn generate_lut(settings: &ColorGradingSettings, size: usize) -> Vec<f32> {
+    let size = size.clamp(MIN_LUT_SIZE, MAX_LUT_SIZE);
+    let scale = 1.0 / (size - 1) as f32;
+    let mut data = Vec::with_capacity(size * size * size * 3);
+    for b in 0..size {
+        for g in 0..size {
+            for r in 0..size {
+                let rgb = [r as f32 * scale, g as f32 * scale, b as f32 * scale];
+                data.extend_from_slice(&apply_color_grading_to_rgb(rgb, settings));
+            }
+        }
+    }
+    data
+}
+
+/// Serializes a 3D LUT (as produced by `generate_lut`) to `.cube` text.
+///
+/// NaN/Inf entries are always rejected. Values outside the declared 0..1
+/// domain are clamped when `clamp_domain` is set and rejected otherwise, so the
+/// file stays readable by other tools.
+pub fn export_cube_lut(data: &[f32], size: usize, title: &str, clamp_domain: bool) -> Result<String> {
+    if !(MIN_LUT_SIZE..=MAX_LUT_SIZE).contains(&size) {
+        return Err(anyhow!(
+            "LUT size {size} is outside the supported range {MIN_LUT_SIZE}..={MAX_LUT_SIZE}"
+        ));
+    }
+    let expected = size * size * size * 3;
+    if data.len() != expected {
+        return Err(anyhow!(
+            "LUT data has {} values, expected {expected} for size {size}",
+            data.len()
+        ));
+    }
+
+    let mut out = String::with_capacity(expected * 10 + 128);
+    if !title.is_empty() {
+        out.push_str(&format!("TITLE \"{}\"\n", title.replace('"', "'")));
+    }
+    out.push_str(&format!("LUT_3D_SIZE {size}\n"));
+    out.push_str("DOMAIN_MIN 0.0 0.0 0.0\n");
+    out.push_str("DOMAIN_MAX 1.0 1.0 1.0\n");
+
+    for (entry, rgb) in data.chunks_exact(3).enumerate() {
+        let mut values = [0.0f32; 3];
+        for (c, &v) in rgb.iter().enumerate() {
+            if !v.is_finite() {
+                return Err(anyhow!(
+                    "LUT entry {entry} (r={}, g={}, b={}) has a non-finite value: {v}",
+                    entry % size,
+                    (entry / size) % size,
+                    entry / (size * size)
+                ));
+            }
+            if !(0.0..=1.0).contains(&v) && !clamp_domain {
+                return Err(anyhow!(
+                    "LUT entry {entry} value {v} is outside the declared domain 0..1"
+                ));
+            }
+            values[c] = v.clamp(0.0, 1.0);
+        }
+        out.push_str(&format!("{:.6} {:.6} {:.6}\n", values[0], values[1], values[2]));
+    }
+    Ok(out)
+}
+
+pub const MIN_LUT_1D_SIZE: usize = 2;
+pub const MAX_LUT_1D_SIZE: usize = 65536;
+
+/// Bakes the tone portion of the basic adjustments (exposure, highlights,
+/// shadows, contrast) into a `size`-entry curve. One value per node; the curve
+/// applies equally to all three channels.
+pub fn generate_1d_lut(adjustments: &SimpleAdjustments, size: usize) -> Result<Vec<f32>> {
+    if !(MIN_LUT_1D_SIZE..=MAX_LUT_1D_SIZE).contains(&size) {
+        return Err(anyhow!(
+            "1D LUT size {size} is outside the supported range {MIN_LUT_1D_SIZE}..={MAX_LUT_1D_SIZE}"
+        ));
+    }
+    let scale = 1.0 / (size - 1) as f32;
+    Ok((0..size)
+        .map(|i| apply_tone_curve(i as f32 * scale, adjustments))
+        .collect())
+}
+
+/// Serializes a 1D curve (as produced by `generate_1d_lut`) to `.cube` text.
+///
+/// Rejects non-finite and out-of-domain values, and curves that decrease
+/// anywhere: an inverting tone curve is almost always an extreme setting
+/// rather than something a colorist wants baked into a file.
+pub fn export_cube_1d(curve: &[f32], title: &str) -> Result<String> {
+    let size = curve.len();
+    if !(MIN_LUT_1D_SIZE..=MAX_LUT_1D_SIZE).contains(&size) {
+        return Err(anyhow!(
+            "1D LUT size {size} is outside the supported range {MIN_LUT_1D_SIZE}..={MAX_LUT_1D_SIZE}"
+        ));
+    }
+
+    for (i, &v) in curve.iter().enumerate() {
+        if !v.is_finite() {
+            return Err(anyhow!("1D LUT node {i} has a non-finite value: {v}"));
+        }
+        if !(0.0..=1.0).contains(&v) {
+            return Err(anyhow!(
+                "1D LUT node {i} value {v} is outside the declared domain 0..1"
+            ));
+        }
+    }
+    if let Some(i) = curve.windows(2).position(|w| w[1] < w[0] - 1e-6) {
+        return Err(anyhow!(
+            "1D LUT is not monotonic: node {} ({}) is below node {i} ({})",
+            i + 1,
+            curve[i + 1],
+            curve[i]
+        ));
+    }
+
+    let mut out = String::with_capacity(size * 30 + 128);
+    if !title.is_empty() {
+        out.push_str(&format!("TITLE \"{}\"\n", title.replace('"', "'")));
+    }
+    out.push_str(&format!("LUT_1D_SIZE {size}\n"));
+    out.push_str("DOMAIN_MIN 0.0 0.0 0.0\n");
+    out.push_str("DOMAIN_MAX 1.0 1.0 1.0\n");
+    for &v in curve {
+        out.push_str(&format!("{v:.6} {v:.6} {v:.6}\n"));
+    }
+    Ok(out)
+}
+
+/// Dimensions of an image fitted inside a `max_edge` square, never upscaled.
+pub fn thumbnail_dimensions(width: u32, height: u32, max_edge: u32) -> (u32, u32) {
+    let long_edge = width.max(height);
+    if max_edge == 0 || long_edge <= max_edge {
+        return (width, height);
+    }
+    let ratio = max_edge as f32 / long_edge as f32;
+    (
+        ((width as f32 * ratio).round() as u32).max(1),
+        ((height as f32 * ratio).round() as u32).max(1),
+    )
+}
+
+/// Downscales an RGBA8 image to fit `max_edge` and runs it through a 3D LUT
+/// of the given `resolution`, for cheap LUT browsing previews. Alpha is
+/// passed through. Returns the RGBA8 thumbnail buffer; its size is given by
+/// `thumbnail_dimensions`.
+pub fn apply_lut_to_thumbnail(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    lut_data: &[f32],
+    resolution: usize,
+    max_edge: u32,
+) -> Result<Vec<u8>> {
+    if !(MIN_LUT_SIZE..=MAX_LUT_SIZE).contains(&resolution) {
+        return Err(anyhow!(
+            "LUT size {resolution} is outside the supported range {MIN_LUT_SIZE}..={MAX_LUT_SIZE}"
+        ));
+    }
+    if lut_data.len() != resolution * resolution * resolution * 3 {
+        return Err(anyhow!(
+            "LUT data has {} values, expected {} for size {resolution}",
+            lut_data.len(),
+            resolution * resolution * resolution * 3
+        ));
+    }
+    let image = RgbaImage::from_raw(width, height, image_data.to_vec()).ok_or_else(|| {
+        anyhow!(
+            "image buffer has {} bytes, expected {} for {width}x{height} RGBA",
+            image_data.len(),
+            width as usize * height as usize * 4
+        )
+    })?;
+
+    let (thumb_w, thumb_h) = thumbnail_dimensions(width, height, max_edge);
+    let mut thumbnail = if (thumb_w, thumb_h) == (width, height) {
+        image
+    } else {
+        imageops::thumbnail(&image, thumb_w, thumb_h)
+    };
+
+    thumbnail.pixels_mut().for_each(|p| {
+        let rgb = [
+            p[0] as f32 / 255.0,
+            p[1] as f32 / 255.0,
+            p[2] as f32 / 255.0,
+        ];
+        let mapped = sample_lut_tetrahedral(lut_data, resolution, rgb);
+        for c in 0..3 {
+            p[c] = (mapped[c].clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    });
+
+    Ok(thumbnail.into_raw())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rapidraw_core::srgb::{linear_to_srgb, srgb_to_linear};
+
+    #[test]
+    fn band_center_gets_its_band_only() {
+        for (i, &center) in HUE_BAND_CENTERS.iter().enumerate() {
+            let [(band, weight), (_, other)] = get_color_range(center);
+            assert_eq!((band, weight, other), (i, 1.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn weights_sum_to_one_and_wrap_past_the_last_band() {
+        for hue in [-30.0, 15.0, 90.0, 299.0, 330.0, 359.9, 725.0] {
+            let [(_, a), (_, b)] = get_color_range(hue);
+            assert!((a + b - 1.0).abs() < 1e-6, "hue {hue}");
+        }
+        let [(last, _), (first, _)] = get_color_range(330.0);
+        assert_eq!((last, first), (HUE_BAND_CENTERS.len() - 1, 0));
+    }
+
+    #[test]
+    fn swap_channels_remaps_rgb_and_keeps_alpha() {
+        let pixels = [10, 20, 30, 255, 1, 2, 3, 128];
+        assert_eq!(
+            swap_channels(&pixels, &[2, 1, 0]).unwrap(),
+            [30, 20, 10, 255, 3, 2, 1, 128]
+        );
+        assert_eq!(
+            swap_channels(&pixels, &[0, 0, 0]).unwrap(),
+            [10, 10, 10, 255, 1, 1, 1, 128]
+        );
+    }
+
+    #[test]
+    fn swap_channels_rejects_bad_input() {
+        assert!(swap_channels(&[0; 8], &[0, 1]).is_err());
+        assert!(swap_channels(&[0; 8], &[0, 1, 3]).is_err());
+        assert!(swap_channels(&[0; 7], &[2, 1, 0]).is_err());
+    }
+
+    fn blue_wheel() -> ColorWheel {
+        let angle = 240.0f32.to_radians();
+        ColorWheel {
+            x: angle.cos(),
+            y: angle.sin(),
+            intensity: 1.0,
+        }
+    }
+
+    #[test]
+    fn lift_tints_shadows_and_leaves_white_alone() {
+        let settings = ColorGradingSettings {
+            shadows: blue_wheel(),
+            ..Default::default()
+        };
+        let [r, g, b] = apply_color_wheel_point([0.02; 3], &settings);
+        assert!(b > 0.02 && r < 0.02 && g < 0.02, "{:?}", [r, g, b]);
+        assert_eq!(apply_color_wheel_point([1.0; 3], &settings), [1.0; 3]);
+    }
+
+    #[test]
+    fn gain_tints_highlights_and_leaves_black_alone() {
+        let settings = ColorGradingSettings {
+            highlights: blue_wheel(),
+            ..Default::default()
+        };
+        let [r, g, b] = apply_color_wheel_point([0.9; 3], &settings);
+        assert!(b > 0.9 && r < 0.9 && g < 0.9, "{:?}", [r, g, b]);
+        assert_eq!(apply_color_wheel_point([0.0; 3], &settings), [0.0; 3]);
+    }
+
+    #[test]
+    fn centred_or_zero_intensity_wheels_are_identity() {
+        let settings = ColorGradingSettings {
+            midtones: ColorWheel {
+                intensity: 0.0,
+                ..blue_wheel()
+            },
+            shadows: ColorWheel {
+                x: 0.0,
+                y: 0.0,
+                intensity: 1.0,
+            },
+            ..Default::default()
+        };
+        assert!(settings.is_identity());
+        assert_eq!(
+            apply_color_wheel_point([0.3, 0.5, 0.7], &settings),
+            [0.3, 0.5, 0.7]
+        );
+    }
+
+    #[test]
+    fn tonal_weights_sum_to_one_and_change_smoothly() {
+        let mut previous: Option<[f32; 3]> = None;
+        for i in 0..=1000 {
+            let luma = i as f32 / 1000.0;
+            let weights = [
+                calculate_shadow_weight(luma),
+                calculate_midtone_weight(luma),
+                calculate_highlight_weight(luma),
+            ];
+            assert!(
+                (weights.iter().sum::<f32>() - 1.0).abs() < 1e-5,
+                "luma {luma}"
+            );
+            if let Some(previous) = previous {
+                for (w, p) in weights.iter().zip(previous) {
+                    assert!((w - p).abs() < 0.005, "jump at luma {luma}");
+                }
+            }
+            previous = Some(weights);
+        }
+        assert_eq!(calculate_shadow_weight(0.0), 1.0);
+        assert_eq!(calculate_highlight_weight(1.0), 1.0);
+    }
+
+    fn spread(rgb: [f32; 3]) -> f32 {
+        rgb.iter().cloned().fold(f32::MIN, f32::max) - rgb.iter().cloned().fold(f32::MAX, f32::min)
+    }
+
+    #[test]
+    fn global_saturation_is_applied_by_color_grading() {
+        let settings = ColorGradingSettings {
+            global_saturation: -1.0,
+            ..Default::default()
+        };
+        assert!(!settings.is_identity());
+        assert!(spread(apply_color_grading_to_rgb([0.8, 0.4, 0.2], &settings)) < 1e-6);
+    }
+
+    #[test]
+    fn vibrance_boosts_muted_colors_more_than_saturated_ones() {
+        let settings = ColorGradingSettings {
+            global_vibrance: 0.5,
+            ..Default::default()
+        };
+        let (muted, vivid) = ([0.5, 0.45, 0.4], [0.9, 0.1, 0.1]);
+        let muted_gain = spread(apply_color_grading_to_rgb(muted, &settings)) / spread(muted);
+        let vivid_gain = spread(apply_color_grading_to_rgb(vivid, &settings)) / spread(vivid);
+        assert!(
+            muted_gain > vivid_gain && vivid_gain >= 1.0,
+            "{muted_gain} {vivid_gain}"
+        );
+    }
+
+    fn reds(saturation: f32) -> HslAdjustments {
+        HslAdjustments {
+            reds: HslAdjustment {
+                saturation,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hsl_saturation_scales_instead_of_adding() {
+        let red = [0.6, 0.4, 0.4];
+        let [_, s, l] = rgb_to_hsl(red);
+        let [_, boosted, boosted_l] = rgb_to_hsl(apply_hsl_adjustments(red, &reds(50.0)));
+        assert!((boosted - s * 1.5).abs() < 1e-4, "{boosted} vs {s}");
+        assert!((boosted_l - l).abs() < 1e-5);
+
+        let muted = apply_hsl_adjustments(red, &reds(-100.0));
+        assert!(spread(muted) < 1e-5, "{muted:?}");
+    }
+
+    #[test]
+    fn hsl_saturation_boost_stops_at_full_saturation() {
+        let [_, s, _] = rgb_to_hsl(apply_hsl_adjustments([0.9, 0.1, 0.1], &reds(100.0)));
+        assert!((s - 1.0).abs() < 1e-5);
+        assert_eq!(apply_hsl_adjustments([0.5; 3], &reds(100.0)), [0.5; 3]);
+    }
+
+    #[test]
+    fn cube_export_rejects_non_finite_values() {
+        let mut data = generate_lut(&ColorGradingSettings::default(), 2);
+        data[4] = f32::NAN;
+        let err = export_cube_lut(&data, 2, "", true).unwrap_err().to_string();
+        assert!(
+            err.contains("entry 1") && err.contains("non-finite"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn cube_export_clamps_or_rejects_out_of_domain_values() {
+        let mut data = generate_lut(&ColorGradingSettings::default(), 2);
+        data[0] = -0.25;
+        assert!(export_cube_lut(&data, 2, "", false).is_err());
+        let cube = export_cube_lut(&data, 2, "My \"look\"", true).unwrap();
+        assert!(cube.starts_with("TITLE \"My 'look'\"\nLUT_3D_SIZE 2\n"));
+        assert!(cube.contains("\n0.000000 0.000000 0.000000\n"));
+        assert_eq!(cube.lines().count(), 4 + 8);
+    }
+
+    #[test]
+    fn cube_export_checks_size_and_length() {
+        let data = generate_lut(&ColorGradingSettings::default(), 2);
+        assert!(export_cube_lut(&data, 3, "", true).is_err());
+        assert!(export_cube_lut(&data[..21], 2, "", true).is_err());
+        assert!(export_cube_lut(&[], 0, "", true).is_err());
+    }
+
+    #[test]
+    fn neutral_tone_curve_exports_as_identity() {
+        let curve = generate_1d_lut(&SimpleAdjustments::default(), 5).unwrap();
+        for (i, v) in curve.iter().enumerate() {
+            assert!((v - i as f32 / 4.0).abs() < 1e-5, "node {i}: {v}");
+        }
+        let cube = export_cube_1d(&curve, "neutral").unwrap();
+        assert!(cube.starts_with("TITLE \"neutral\"\nLUT_1D_SIZE 5\n"));
+        assert!(cube.ends_with("1.000000 1.000000 1.000000\n"));
+    }
+
+    #[test]
+    fn tone_curve_follows_exposure() {
+        let brighter = SimpleAdjustments {
+            exposure: 1.0,
+            ..Default::default()
+        };
+        let curve = generate_1d_lut(&brighter, 17).unwrap();
+        assert!(curve[8] > 0.5);
+        assert!(export_cube_1d(&curve, "").is_ok());
+    }
+
+    #[test]
+    fn one_dimensional_export_rejects_bad_curves() {
+        assert!(generate_1d_lut(&SimpleAdjustments::default(), 1).is_err());
+        assert!(export_cube_1d(&[0.0], "").is_err());
+        assert!(export_cube_1d(&[0.0, f32::INFINITY], "").is_err());
+        assert!(export_cube_1d(&[0.0, 1.5], "").is_err());
+        let err = export_cube_1d(&[0.0, 0.6, 0.4, 1.0], "")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("not monotonic"), "{err}");
+    }
+
+    #[test]
+    fn thumbnails_fit_the_edge_without_upscaling() {
+        assert_eq!(thumbnail_dimensions(4000, 3000, 256), (256, 192));
+        assert_eq!(thumbnail_dimensions(3000, 4000, 256), (192, 256));
+        assert_eq!(thumbnail_dimensions(100, 50, 256), (100, 50));
+        assert_eq!(thumbnail_dimensions(100, 50, 0), (100, 50));
+        assert_eq!(thumbnail_dimensions(5000, 1, 100), (100, 1));
+    }
+
+    #[test]
+    fn lut_thumbnail_maps_colors_and_keeps_alpha() {
+        // Inverting 2-point LUT.
+        let lut: Vec<f32> = generate_lut(&ColorGradingSettings::default(), 2)
+            .iter()
+            .map(|v| 1.0 - v)
+            .collect();
+        let image = [255, 0, 64, 200].repeat(8 * 4);
+        let thumbnail = apply_lut_to_thumbnail(&image, 8, 4, &lut, 2, 4).unwrap();
+        assert_eq!(thumbnail.len(), 4 * 2 * 4);
+        for pixel in thumbnail.chunks_exact(4) {
+            assert_eq!(pixel, [0, 255, 191, 200]);
+        }
+    }
+
+    #[test]
+    fn lut_thumbnail_checks_its_inputs() {
+        let lut = generate_lut(&ColorGradingSettings::default(), 2);
+        assert!(apply_lut_to_thumbnail(&[0; 16], 2, 2, &lut, 3, 8).is_err());
+        assert!(apply_lut_to_thumbnail(&[0; 16], 2, 2, &lut[..3], 2, 8).is_err());
+        assert!(apply_lut_to_thumbnail(&[0; 15], 2, 2, &lut, 2, 8).is_err());
+    }
+
+    #[test]
+    fn validation_names_the_first_field_out_of_range() {
+        assert!(validate_color_grading_settings(&ColorGradingSettings::default()).is_ok());
+
+        let mut settings = ColorGradingSettings::default();
+        settings.hsl.blues.saturation = 150.0;
+        let err = validate_color_grading_settings(&settings).unwrap_err();
+        assert!(err.to_string().starts_with("hsl.blues.saturation"), "{err}");
+
+        let settings = ColorGradingSettings {
+            midtones: ColorWheel {
+                x: 0.9,
+                y: 0.9,
+                intensity: 0.5,
+            },
+            ..Default::default()
+        };
+        let err = validate_color_grading_settings(&settings).unwrap_err();
+        assert!(err.to_string().starts_with("midtones puck radius"), "{err}");
+
+        let settings = ColorGradingSettings {
+            global_vibrance: f32::NAN,
+            ..Default::default()
+        };
+        let err = validate_color_grading_settings(&settings).unwrap_err();
+        assert!(err.to_string().starts_with("globalVibrance"), "{err}");
+
+        // A puck placed on the rim from polar coordinates is accepted.
+        let settings = ColorGradingSettings {
+            shadows: blue_wheel(),
+            ..Default::default()
+        };
+        assert!(validate_color_grading_settings(&settings).is_ok());
+    }
+
+    #[test]
+    fn rgba8_grading_keeps_alpha_and_checks_its_inputs() {
+        let data = [200, 80, 40, 90, 60, 120, 30, 255];
+        let settings = ColorGradingSettings {
+            global_saturation: -1.0,
+            ..Default::default()
+        };
+        let graded = apply_color_grading_rgba8(&data, &settings).unwrap();
+        assert_eq!((graded[3], graded[7]), (90, 255));
+        assert!(
+            graded[..3].iter().all(|&c| c.abs_diff(graded[0]) <= 1),
+            "{graded:?}"
+        );
+
+        assert_eq!(
+            apply_color_grading_rgba8(&data, &ColorGradingSettings::default()).unwrap(),
+            data
+        );
+        assert!(apply_color_grading_rgba8(&data[..7], &settings).is_err());
+        let out_of_range = ColorGradingSettings {
+            global_saturation: 2.0,
+            ..Default::default()
+        };
+        assert!(apply_color_grading_rgba8(&data, &out_of_range).is_err());
+    }
+
+    #[test]
+    fn lut_comes_from_the_adjustments_with_its_intensity() {
+        let cube = "LUT_3D_SIZE 2\n0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n";
+        let json = serde_json::json!({ "lutCube": cube, "lutIntensity": 40 }).to_string();
+        let (lut, intensity) = lut_from_adjustments(&json).unwrap();
+        assert_eq!(lut.size, 2);
+        assert!((intensity - 0.4).abs() < 1e-6);
+
+        let full = serde_json::json!({ "lutCube": cube }).to_string();
+        assert_eq!(lut_from_adjustments(&full).unwrap().1, 1.0);
+        let off = serde_json::json!({ "lutCube": cube, "lutIntensity": 0 }).to_string();
+        assert!(lut_from_adjustments(&off).is_none());
+        assert!(lut_from_adjustments(r#"{"lutCube": "LUT_3D_SIZE 2"}"#).is_none());
+        assert!(lut_from_adjustments("{}").is_none());
+    }
+
+    #[test]
+    fn shared_cube_lut_gives_the_desktop_pixels() {
+        // The desktop `lut_processing` test checks the same file against the
+        // same encoded pixels.
+        let cube = include_str!("../../../../crates/rapidraw-core/testdata/domain.cube");
+        let json = serde_json::json!({ "lutCube": cube }).to_string();
+        let (lut, intensity) = lut_from_adjustments(&json).unwrap();
+        let cases = [
+            ([0.0, 0.0, 0.0], [0.05, 0.0, 0.0]),
+            ([0.05, 0.5, 0.9], [0.1, 0.475, 0.2545]),
+            ([0.5, 0.25, 0.75], [0.525, 0.2475, 0.2121]),
+            ([0.8, 0.9, 0.1], [0.89, 0.8725, 0.0283]),
+            ([1.0, 1.0, 1.0], [1.0, 0.97, 0.2828]),
+        ];
+        let mut image = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_fn(5, 1, |x, _| {
+            image::Rgb(cases[x as usize].0.map(srgb_to_linear))
+        }));
+        apply_lut_to_image(&mut image, &lut, intensity);
+
+        let out = image.to_rgb32f();
+        for (pixel, (input, expected)) in out.pixels().zip(cases) {
+            let encoded = pixel.0.map(linear_to_srgb);
+            for c in 0..3 {
+                assert!(
+                    (encoded[c] - expected[c]).abs() < 1e-3,
+                    "{input:?} -> {encoded:?}"
+                );
+            }
+        }
+    }
+}
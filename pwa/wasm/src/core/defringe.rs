@@ -0,0 +1,283 @@
+//! Defringe: removes the purple and green fringes that longitudinal
+//! chromatic aberration leaves along high-contrast edges. Unlike lateral (TCA)
+//! correction it does not shift channels; it desaturates pixels whose hue
+//! falls in a fringe band and that sit next to a strong luminance edge, so
+//! the same hues away from edges keep their color.
+
+use crate::core::adjustments::to_adjustment_buffer;
+use image::DynamicImage;
+use rapidraw_core::luma::{self, LumaCoefficients};
+use rapidraw_core::srgb::linear_to_srgb;
+use serde::Deserialize;
+
+/// Pixels within this distance of an edge count as next to it.
+const EDGE_RADIUS: usize = 2;
+/// Local luminance range (sRGB-encoded) where fringe removal starts and where
+/// it reaches full strength.
+const EDGE_LOW: f32 = 0.08;
+const EDGE_HIGH: f32 = 0.25;
+/// Degrees over which a hue band fades in and out.
+const HUE_FEATHER: f32 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DefringeSettings {
+    /// 0 (off) to 1: how much of a purple fringe's color is removed.
+    pub purple_amount: f32,
+    /// Purple fringe hues as [start, end] degrees; start > end wraps through
+    /// red.
+    pub purple_hue: [f32; 2],
+    /// 0 (off) to 1: how much of a green fringe's color is removed.
+    pub green_amount: f32,
+    /// Green fringe hues as [start, end] degrees.
+    pub green_hue: [f32; 2],
+}
+
+impl Default for DefringeSettings {
+    fn default() -> Self {
+        Self {
+            purple_amount: 0.0,
+            purple_hue: [260.0, 330.0],
+            green_amount: 0.0,
+            green_hue: [75.0, 165.0],
+        }
+    }
+}
+
+impl DefringeSettings {
+    pub fn is_identity(&self) -> bool {
+        self.purple_amount <= 0.0 && self.green_amount <= 0.0
+    }
+}
+
+/// Reads the `defringe` object of the adjustments JSON.
+pub fn defringe_from_adjustments(adjustments_json: &str) -> DefringeSettings {
+    serde_json::from_str::<serde_json::Value>(adjustments_json)
+        .ok()
+        .and_then(|v| v.get("defringe").cloned())
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+/// HSV hue in degrees, `None` for neutral pixels.
+fn hue_degrees([r, g, b]: [f32; 3]) -> Option<f32> {
+    let max = r.max(g).max(b);
+    let chroma = max - r.min(g).min(b);
+    if chroma <= 1e-6 {
+        return None;
+    }
+    let hue = if max == r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    Some(hue * 60.0)
+}
+
+/// 1 inside the `[start, end]` band, fading to 0 over `HUE_FEATHER` degrees
+/// outside it.
+fn hue_band_weight(hue: f32, [start, end]: [f32; 2]) -> f32 {
+    let width = (end - start).rem_euclid(360.0);
+    let offset = (hue - start).rem_euclid(360.0);
+    if offset <= width {
+        return 1.0;
+    }
+    let distance = (offset - width).min(360.0 - offset);
+    (1.0 - distance / HUE_FEATHER).max(0.0)
+}
+
+fn smoothstep(low: f32, high: f32, x: f32) -> f32 {
+    let t = ((x - low) / (high - low)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Max minus min of `values` over a `(2 * EDGE_RADIUS + 1)` square window.
+fn local_range(values: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let window = |line: &[f32], i: usize| {
+        let lo = i.saturating_sub(EDGE_RADIUS);
+        let hi = (i + EDGE_RADIUS).min(line.len() - 1);
+        line[lo..=hi]
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), &v| {
+                (min.min(v), max.max(v))
+            })
+    };
+
+    let mut row_min = vec![0.0; values.len()];
+    let mut row_max = vec![0.0; values.len()];
+    for y in 0..height {
+        let line = &values[y * width..(y + 1) * width];
+        for x in 0..width {
+            (row_min[y * width + x], row_max[y * width + x]) = window(line, x);
+        }
+    }
+
+    let mut range = vec![0.0; values.len()];
+    let mut column_min = vec![0.0; height];
+    let mut column_max = vec![0.0; height];
+    for x in 0..width {
+        for y in 0..height {
+            column_min[y] = row_min[y * width + x];
+            column_max[y] = row_max[y * width + x];
+        }
+        for y in 0..height {
+            let (min, _) = window(&column_min, y);
+            let (_, max) = window(&column_max, y);
+            range[y * width + x] = max - min;
+        }
+    }
+    range
+}
+
+/// Desaturates fringe-colored pixels next to high-contrast edges, keeping
+/// their luminance. `image` is in the linear pipeline space; alpha is kept.
+pub fn apply_defringe(image: &mut DynamicImage, settings: &DefringeSettings) {
+    if settings.is_identity() || image.width() == 0 || image.height() == 0 {
+        return;
+    }
+    *image = to_adjustment_buffer(std::mem::take(image));
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let channels = if image.color().has_alpha() { 4 } else { 3 };
+    let data: &mut [f32] = match image {
+        DynamicImage::ImageRgba32F(buffer) => buffer.as_mut(),
+        DynamicImage::ImageRgb32F(buffer) => buffer.as_mut(),
+        _ => return,
+    };
+
+    let pixel = |data: &[f32], i: usize| {
+        [
+            data[i * channels],
+            data[i * channels + 1],
+            data[i * channels + 2],
+        ]
+    };
+    let encoded_luma: Vec<f32> = (0..width * height)
+        .map(|i| {
+            let [r, g, b] = pixel(data, i);
+            linear_to_srgb(luma::luma(r, g, b, LumaCoefficients::Rec709).max(0.0))
+        })
+        .collect();
+    let edges = local_range(&encoded_luma, width, height);
+
+    let purple_amount = settings.purple_amount.clamp(0.0, 1.0);
+    let green_amount = settings.green_amount.clamp(0.0, 1.0);
+    for i in 0..width * height {
+        let edge = smoothstep(EDGE_LOW, EDGE_HIGH, edges[i]);
+        if edge <= 0.0 {
+            continue;
+        }
+        let rgb = pixel(data, i);
+        let Some(hue) = hue_degrees(rgb.map(|c| linear_to_srgb(c.max(0.0)))) else {
+            continue;
+        };
+        let band = (purple_amount * hue_band_weight(hue, settings.purple_hue))
+            .max(green_amount * hue_band_weight(hue, settings.green_hue));
+        let keep = 1.0 - edge * band;
+        if keep >= 1.0 {
+            continue;
+        }
+        let [r, g, b] = rgb;
+        let y = luma::luma(r, g, b, LumaCoefficients::Rec709);
+        for (c, value) in rgb.into_iter().enumerate() {
+            data[i * channels + c] = y + (value - y) * keep;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use image::{Rgba, Rgba32FImage};
+
+    const PURPLE: [f32; 4] = [0.3, 0.05, 0.4, 1.0];
+
+    /// Black left half, white right half, with a purple fringe column on the
+    /// dark side of the edge.
+    fn fringed_edge() -> DynamicImage {
+        DynamicImage::ImageRgba32F(Rgba32FImage::from_fn(12, 4, |x, _| match x {
+            0..5 => Rgba([0.0, 0.0, 0.0, 1.0]),
+            5 => Rgba(PURPLE),
+            _ => Rgba([1.0, 1.0, 1.0, 1.0]),
+        }))
+    }
+
+    fn purple_only(amount: f32) -> DefringeSettings {
+        DefringeSettings {
+            purple_amount: amount,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hue_of_primaries_and_neutrals() {
+        assert_eq!(hue_degrees([1.0, 0.0, 0.0]), Some(0.0));
+        assert_eq!(hue_degrees([0.0, 1.0, 0.0]), Some(120.0));
+        assert_eq!(hue_degrees([0.0, 0.0, 1.0]), Some(240.0));
+        assert_eq!(hue_degrees([1.0, 0.0, 1.0]), Some(300.0));
+        assert_eq!(hue_degrees([0.4, 0.4, 0.4]), None);
+    }
+
+    #[test]
+    fn hue_band_feathers_and_wraps() {
+        assert_eq!(hue_band_weight(300.0, [260.0, 330.0]), 1.0);
+        assert!((hue_band_weight(335.0, [260.0, 330.0]) - 0.5).abs() < 1e-4);
+        assert_eq!(hue_band_weight(200.0, [260.0, 330.0]), 0.0);
+        // start > end wraps through red.
+        assert_eq!(hue_band_weight(10.0, [340.0, 20.0]), 1.0);
+        assert_eq!(hue_band_weight(180.0, [340.0, 20.0]), 0.0);
+    }
+
+    #[test]
+    fn local_range_only_sees_nearby_pixels() {
+        let mut values = vec![0.0; 10];
+        values[0] = 1.0;
+        let range = local_range(&values, 10, 1);
+        assert_eq!(&range[..=EDGE_RADIUS], &[1.0; EDGE_RADIUS + 1]);
+        assert!(range[EDGE_RADIUS + 1..].iter().all(|&r| r == 0.0));
+    }
+
+    #[test]
+    fn fringe_next_to_an_edge_is_desaturated() {
+        let mut image = fringed_edge();
+        apply_defringe(&mut image, &purple_only(1.0));
+        let pixel = image.to_rgba32f().get_pixel(5, 1).0;
+        let y = luma::luma(PURPLE[0], PURPLE[1], PURPLE[2], LumaCoefficients::Rec709);
+        for c in &pixel[..3] {
+            assert!((c - y).abs() < 1e-5, "{pixel:?}");
+        }
+        assert_eq!(pixel[3], 1.0);
+    }
+
+    #[test]
+    fn flat_fringe_hues_and_identity_settings_are_kept() {
+        let mut flat = DynamicImage::ImageRgba32F(Rgba32FImage::from_pixel(8, 8, Rgba(PURPLE)));
+        apply_defringe(&mut flat, &purple_only(1.0));
+        assert_eq!(flat.to_rgba32f().get_pixel(4, 4).0, PURPLE);
+
+        let mut image = fringed_edge();
+        apply_defringe(&mut image, &DefringeSettings::default());
+        assert_eq!(image.to_rgba32f().get_pixel(5, 1).0, PURPLE);
+
+        // A green amount leaves purple fringes alone.
+        let mut image = fringed_edge();
+        let green = DefringeSettings {
+            green_amount: 1.0,
+            ..Default::default()
+        };
+        apply_defringe(&mut image, &green);
+        assert_eq!(image.to_rgba32f().get_pixel(5, 1).0, PURPLE);
+    }
+
+    #[test]
+    fn settings_are_read_from_the_adjustments() {
+        let settings =
+            defringe_from_adjustments(r#"{"exposure":1,"defringe":{"purpleAmount":0.5}}"#);
+        assert_eq!(settings.purple_amount, 0.5);
+        assert_eq!(settings.purple_hue, [260.0, 330.0]);
+        assert!(!settings.is_identity());
+        assert!(defringe_from_adjustments("not json").is_identity());
+    }
+}
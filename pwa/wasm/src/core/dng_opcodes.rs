@@ -0,0 +1,433 @@
+//! DNG opcode lists: per-file corrections a DNG asks the raw converter to
+//! apply while developing.
+//!
+//! Only the two common in phone and converted DNGs are handled: GainMap
+//! (lens shading / vignetting, usually in OpcodeList2 on the CFA data) and
+//! WarpRectilinear (distortion and lateral CA, in OpcodeList3 on the
+//! demosaiced image). Other opcodes are skipped; the spec lets readers do
+//! that for any opcode flagged optional, and the rest are rare.
+
+use rawler::formats::tiff::{GenericTiffReader, Value, IFD};
+use rawler::rawimage::{RawImage, RawImageData};
+use std::io::Cursor;
+
+const TAG_SUB_IFDS: u16 = 0x014A;
+/// Applied after linearisation, before demosaic.
+const TAG_OPCODE_LIST_2: u16 = 0xC741;
+/// Applied after demosaic.
+const TAG_OPCODE_LIST_3: u16 = 0xC74E;
+
+const OPCODE_WARP_RECTILINEAR: u32 = 1;
+const OPCODE_GAIN_MAP: u32 = 9;
+
+/// Big-endian reader over an opcode's parameter block.
+struct Params<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Params<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes = self.data.get(self.pos..self.pos + N)?.try_into().ok()?;
+        self.pos += N;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_be_bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.take().map(f32::from_be_bytes)
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        self.take().map(f64::from_be_bytes)
+    }
+}
+
+/// A grid of gains multiplied into the pixels of `area`, bilinearly
+/// interpolated between map points.
+#[derive(Debug, Clone)]
+pub struct GainMap {
+    /// Top, left, bottom, right in stage-image pixels.
+    pub area: [u32; 4],
+    pub plane: u32,
+    pub planes: u32,
+    pub row_pitch: u32,
+    pub col_pitch: u32,
+    points_v: u32,
+    points_h: u32,
+    /// Spacing and origin of the map points, relative to the image size.
+    spacing: (f64, f64),
+    origin: (f64, f64),
+    map_planes: u32,
+    gains: Vec<f32>,
+}
+
+impl GainMap {
+    fn parse(p: &mut Params) -> Option<Self> {
+        let area = [p.u32()?, p.u32()?, p.u32()?, p.u32()?];
+        let (plane, planes, row_pitch, col_pitch) = (p.u32()?, p.u32()?, p.u32()?, p.u32()?);
+        let (points_v, points_h) = (p.u32()?, p.u32()?);
+        let spacing = (p.f64()?, p.f64()?);
+        let origin = (p.f64()?, p.f64()?);
+        let map_planes = p.u32()?;
+        let count = points_v as usize * points_h as usize * map_planes as usize;
+        if count == 0 || row_pitch == 0 || col_pitch == 0 {
+            return None;
+        }
+        let gains = (0..count).map(|_| p.f32()).collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            area,
+            plane,
+            planes,
+            row_pitch,
+            col_pitch,
+            points_v,
+            points_h,
+            spacing,
+            origin,
+            map_planes,
+            gains,
+        })
+    }
+
+    /// Gain at relative position (`v`, `h`) for map plane `plane`; positions
+    /// outside the map take the nearest edge value.
+    fn gain(&self, v: f64, h: f64, plane: u32) -> f32 {
+        let index = |pos: f64, origin: f64, spacing: f64, points: u32| {
+            let t = if spacing > 0.0 {
+                ((pos - origin) / spacing).clamp(0.0, (points - 1) as f64)
+            } else {
+                0.0
+            };
+            let i0 = (t.floor() as u32).min(points - 1);
+            (i0, (i0 + 1).min(points - 1), (t - i0 as f64) as f32)
+        };
+        let (r0, r1, fr) = index(v, self.origin.0, self.spacing.0, self.points_v);
+        let (c0, c1, fc) = index(h, self.origin.1, self.spacing.1, self.points_h);
+        let plane = plane.min(self.map_planes - 1);
+        let at = |r: u32, c: u32| {
+            self.gains[((r * self.points_h + c) * self.map_planes + plane) as usize]
+        };
+        let top = at(r0, c0) + (at(r0, c1) - at(r0, c0)) * fc;
+        let bottom = at(r1, c0) + (at(r1, c1) - at(r1, c0)) * fc;
+        top + (bottom - top) * fr
+    }
+
+    /// Calls `apply(row, col, plane, gain)` for every sample the map covers
+    /// in a `width` x `height` stage image with `cpp` planes.
+    fn for_each_sample(
+        &self,
+        width: usize,
+        height: usize,
+        cpp: usize,
+        mut apply: impl FnMut(usize, usize, usize, f32),
+    ) {
+        let [top, left, bottom, right] = self.area.map(|v| v as usize);
+        let (bottom, right) = (bottom.min(height), right.min(width));
+        let planes = self.plane as usize..(self.plane + self.planes) as usize;
+        for row in (top..bottom).step_by(self.row_pitch as usize) {
+            let v = (row as f64 + 0.5) / height as f64;
+            for col in (left..right).step_by(self.col_pitch as usize) {
+                let h = (col as f64 + 0.5) / width as f64;
+                for plane in planes.clone().filter(|&p| p < cpp) {
+                    apply(
+                        row,
+                        col,
+                        plane,
+                        self.gain(v, h, (plane - planes.start) as u32),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Applies the map to undemosaiced sensor data. The stage image is the
+    /// active area; gains scale the signal above black.
+    pub fn apply_to_raw(&self, raw: &mut RawImage) {
+        let (ax, ay, width, height) = match raw.active_area {
+            Some(area) => (area.p.x, area.p.y, area.d.w, area.d.h),
+            None => (0, 0, raw.width, raw.height),
+        };
+        let levels = &raw.blacklevel.levels;
+        let black = if levels.is_empty() {
+            0.0
+        } else {
+            levels.iter().map(|level| level.as_f32()).sum::<f32>() / levels.len() as f32
+        };
+        let (stride, cpp) = (raw.width, raw.cpp);
+        let index =
+            |row: usize, col: usize, plane: usize| ((row + ay) * stride + col + ax) * cpp + plane;
+        match &mut raw.data {
+            RawImageData::Integer(data) => {
+                self.for_each_sample(width, height, cpp, |row, col, plane, gain| {
+                    if let Some(v) = data.get_mut(index(row, col, plane)) {
+                        let scaled = (*v as f32 - black) * gain + black;
+                        *v = scaled.round().clamp(0.0, u16::MAX as f32) as u16;
+                    }
+                });
+            }
+            RawImageData::Float(data) => {
+                self.for_each_sample(width, height, cpp, |row, col, plane, gain| {
+                    if let Some(v) = data.get_mut(index(row, col, plane)) {
+                        *v = (*v - black) * gain + black;
+                    }
+                });
+            }
+        }
+    }
+
+    /// Applies the map to a demosaiced RGB image.
+    pub fn apply_to_rgb(&self, pixels: &mut [[f32; 3]], width: usize, height: usize) {
+        self.for_each_sample(width, height, 3, |row, col, plane, gain| {
+            pixels[row * width + col][plane] *= gain;
+        });
+    }
+}
+
+/// Radial and tangential distortion correction with one coefficient set per
+/// plane (or one for all), so lateral CA can be fixed as well.
+#[derive(Debug, Clone)]
+pub struct WarpRectilinear {
+    /// kr0..kr3, kt0, kt1 per plane.
+    coefficients: Vec<[f64; 6]>,
+    /// Optical centre, relative to the image size.
+    center: (f64, f64),
+}
+
+impl WarpRectilinear {
+    fn parse(p: &mut Params) -> Option<Self> {
+        let planes = p.u32()?;
+        if planes == 0 || planes > 4 {
+            return None;
+        }
+        let coefficients = (0..planes)
+            .map(|_| Some([p.f64()?, p.f64()?, p.f64()?, p.f64()?, p.f64()?, p.f64()?]))
+            .collect::<Option<Vec<_>>>()?;
+        let center = (p.f64()?, p.f64()?);
+        Some(Self {
+            coefficients,
+            center,
+        })
+    }
+
+    /// Resamples `pixels` in place: each output pixel reads the source
+    /// position the warp maps it to, bilinearly, per plane.
+    pub fn apply(&self, pixels: &mut [[f32; 3]], width: usize, height: usize) {
+        if width < 2 || height < 2 {
+            return;
+        }
+        let source = pixels.to_vec();
+        let (cx, cy) = (
+            self.center.0 * (width - 1) as f64,
+            self.center.1 * (height - 1) as f64,
+        );
+        // Normalised so the farthest corner lies at radius 1.
+        let max_x = cx.max((width - 1) as f64 - cx);
+        let max_y = cy.max((height - 1) as f64 - cy);
+        let m = (max_x * max_x + max_y * max_y).sqrt().max(1.0);
+
+        let sample = |x: f64, y: f64, plane: usize| -> f32 {
+            let x = x.clamp(0.0, (width - 1) as f64);
+            let y = y.clamp(0.0, (height - 1) as f64);
+            let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+            let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+            let (fx, fy) = ((x - x0 as f64) as f32, (y - y0 as f64) as f32);
+            let at = |px: usize, py: usize| source[py * width + px][plane];
+            let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * fx;
+            let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * fx;
+            top + (bottom - top) * fy
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                let dx = (x as f64 - cx) / m;
+                let dy = (y as f64 - cy) / m;
+                let r2 = dx * dx + dy * dy;
+                let out = &mut pixels[y * width + x];
+                for (plane, value) in out.iter_mut().enumerate() {
+                    let [kr0, kr1, kr2, kr3, kt0, kt1] =
+                        self.coefficients[plane.min(self.coefficients.len() - 1)];
+                    let radial = kr0 + r2 * (kr1 + r2 * (kr2 + r2 * kr3));
+                    let sx = dx * radial + kt0 * 2.0 * dx * dy + kt1 * (r2 + 2.0 * dx * dx);
+                    let sy = dy * radial + kt1 * 2.0 * dx * dy + kt0 * (r2 + 2.0 * dy * dy);
+                    *value = sample(cx + sx * m, cy + sy * m, plane);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Opcode {
+    GainMap(GainMap),
+    WarpRectilinear(WarpRectilinear),
+}
+
+/// Parses a serialised opcode list, keeping the opcodes this module
+/// implements and skipping the rest.
+pub fn parse_opcode_list(data: &[u8]) -> Vec<Opcode> {
+    let mut header = Params { data, pos: 0 };
+    let Some(count) = header.u32() else {
+        return Vec::new();
+    };
+    let mut opcodes = Vec::new();
+    for _ in 0..count {
+        let (Some(id), Some(_version), Some(_flags), Some(size)) =
+            (header.u32(), header.u32(), header.u32(), header.u32())
+        else {
+            break;
+        };
+        let Some(body) = data.get(header.pos..header.pos + size as usize) else {
+            break;
+        };
+        header.pos += size as usize;
+        let mut params = Params { data: body, pos: 0 };
+        let opcode = match id {
+            OPCODE_GAIN_MAP => GainMap::parse(&mut params).map(Opcode::GainMap),
+            OPCODE_WARP_RECTILINEAR => {
+                WarpRectilinear::parse(&mut params).map(Opcode::WarpRectilinear)
+            }
+            _ => None,
+        };
+        opcodes.extend(opcode);
+    }
+    opcodes
+}
+
+/// The supported opcodes of OpcodeList2 and OpcodeList3.
+#[derive(Debug, Clone, Default)]
+pub struct DngOpcodes {
+    pub before_demosaic: Vec<Opcode>,
+    pub after_demosaic: Vec<Opcode>,
+}
+
+fn find_list(ifd: &IFD, tag: u16) -> Option<Vec<u8>> {
+    if let Some(Value::Undefined(bytes) | Value::Byte(bytes)) =
+        ifd.entries().get(&tag).map(|entry| &entry.value)
+    {
+        return Some(bytes.clone());
+    }
+    ifd.sub_ifds()
+        .values()
+        .flatten()
+        .find_map(|sub| find_list(sub, tag))
+}
+
+/// Reads the opcode lists of a DNG. Empty for other formats.
+pub fn read_dng_opcodes(bytes: &[u8]) -> DngOpcodes {
+    let Ok(reader) = GenericTiffReader::new(&mut Cursor::new(bytes), 0, 0, None, &[TAG_SUB_IFDS])
+    else {
+        return DngOpcodes::default();
+    };
+    let list = |tag| {
+        reader
+            .chains()
+            .iter()
+            .find_map(|ifd| find_list(ifd, tag))
+            .map(|data| parse_opcode_list(&data))
+            .unwrap_or_default()
+    };
+    DngOpcodes {
+        before_demosaic: list(TAG_OPCODE_LIST_2),
+        after_demosaic: list(TAG_OPCODE_LIST_3),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opcode(id: u32, params: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for value in [id, 0x0103_0000, 0, params.len() as u32] {
+            bytes.extend(value.to_be_bytes());
+        }
+        bytes.extend(params);
+        bytes
+    }
+
+    fn opcode_list(opcodes: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = (opcodes.len() as u32).to_be_bytes().to_vec();
+        opcodes.iter().for_each(|op| bytes.extend(op));
+        bytes
+    }
+
+    fn only_opcode(list: &[u8]) -> Opcode {
+        let mut opcodes = parse_opcode_list(list);
+        assert_eq!(opcodes.len(), 1);
+        opcodes.remove(0)
+    }
+
+    /// A 2x2 gain map over a 4x4 image: `left` on the left column of map
+    /// points, `right` on the right one, for all three planes.
+    fn gain_map_params(left: f32, right: f32) -> Vec<u8> {
+        let mut p = Vec::new();
+        for value in [0u32, 0, 4, 4, 0, 3, 1, 1, 2, 2] {
+            p.extend(value.to_be_bytes());
+        }
+        for value in [1.0f64, 1.0, 0.0, 0.0] {
+            p.extend(value.to_be_bytes());
+        }
+        p.extend(1u32.to_be_bytes());
+        for gain in [left, right, left, right] {
+            p.extend(gain.to_be_bytes());
+        }
+        p
+    }
+
+    #[test]
+    fn unknown_opcodes_are_skipped() {
+        let list = opcode_list(&[
+            opcode(99, &[1, 2, 3]),
+            opcode(OPCODE_GAIN_MAP, &gain_map_params(2.0, 2.0)),
+        ]);
+        let opcodes = parse_opcode_list(&list);
+        assert_eq!(opcodes.len(), 1);
+        assert!(matches!(opcodes[0], Opcode::GainMap(_)));
+    }
+
+    #[test]
+    fn gain_map_scales_every_plane() {
+        let list = opcode_list(&[opcode(OPCODE_GAIN_MAP, &gain_map_params(2.0, 2.0))]);
+        let Opcode::GainMap(map) = only_opcode(&list) else {
+            panic!("expected a gain map");
+        };
+        let mut pixels = vec![[0.25f32, 0.5, 0.125]; 16];
+        map.apply_to_rgb(&mut pixels, 4, 4);
+        assert!(pixels.iter().all(|p| *p == [0.5, 1.0, 0.25]));
+    }
+
+    #[test]
+    fn gain_map_interpolates_between_points() {
+        let list = opcode_list(&[opcode(OPCODE_GAIN_MAP, &gain_map_params(1.0, 3.0))]);
+        let Opcode::GainMap(map) = only_opcode(&list) else {
+            panic!("expected a gain map");
+        };
+        let mut pixels = vec![[1.0f32; 3]; 16];
+        map.apply_to_rgb(&mut pixels, 4, 4);
+        let row: Vec<f32> = pixels[..4].iter().map(|p| p[0]).collect();
+        assert!(row.windows(2).all(|pair| pair[0] < pair[1]), "{row:?}");
+        assert!(row.iter().all(|&g| (1.0..=3.0).contains(&g)));
+    }
+
+    #[test]
+    fn identity_warp_leaves_pixels_unchanged() {
+        let mut params = 1u32.to_be_bytes().to_vec();
+        for value in [1.0f64, 0.0, 0.0, 0.0, 0.0, 0.0, 0.5, 0.5] {
+            params.extend(value.to_be_bytes());
+        }
+        let list = opcode_list(&[opcode(OPCODE_WARP_RECTILINEAR, &params)]);
+        let Opcode::WarpRectilinear(warp) = only_opcode(&list) else {
+            panic!("expected a warp");
+        };
+        let original: Vec<[f32; 3]> = (0..20).map(|i| [i as f32, 0.0, 1.0]).collect();
+        let mut pixels = original.clone();
+        warp.apply(&mut pixels, 5, 4);
+        for (a, b) in pixels.iter().zip(&original) {
+            assert!((a[0] - b[0]).abs() < 1e-4);
+        }
+    }
+}
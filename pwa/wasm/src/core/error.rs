@@ -0,0 +1,147 @@
+use serde::Serialize;
+use std::fmt;
+use wasm_bindgen::JsValue;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessingErrorKind {
+    UnsupportedFormat,
+    CorruptData,
+    DecodeUnsupported,
+    FeatureDisabled,
+    OutOfMemory,
+    TooLarge,
+    MetadataError,
+    /// Caller-supplied settings outside their documented ranges.
+    InvalidSettings,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ProcessingError {
+    pub kind: ProcessingErrorKind,
+    pub message: String,
+}
+
+impl ProcessingError {
+    pub fn new(kind: ProcessingErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn unsupported_format(message: impl Into<String>) -> Self {
+        Self::new(ProcessingErrorKind::UnsupportedFormat, message)
+    }
+
+    pub fn corrupt_data(message: impl Into<String>) -> Self {
+        Self::new(ProcessingErrorKind::CorruptData, message)
+    }
+
+    pub fn decode_unsupported(message: impl Into<String>) -> Self {
+        Self::new(ProcessingErrorKind::DecodeUnsupported, message)
+    }
+
+    pub fn feature_disabled(message: impl Into<String>) -> Self {
+        Self::new(ProcessingErrorKind::FeatureDisabled, message)
+    }
+
+    pub fn out_of_memory(message: impl Into<String>) -> Self {
+        Self::new(ProcessingErrorKind::OutOfMemory, message)
+    }
+
+    pub fn too_large(message: impl Into<String>) -> Self {
+        Self::new(ProcessingErrorKind::TooLarge, message)
+    }
+
+    pub fn metadata(message: impl Into<String>) -> Self {
+        Self::new(ProcessingErrorKind::MetadataError, message)
+    }
+
+    pub fn invalid_settings(message: impl Into<String>) -> Self {
+        Self::new(ProcessingErrorKind::InvalidSettings, message)
+    }
+
+    /// Classifies a decoder/encoder failure by looking for a `ProcessingError` or
+    /// an `image` crate error in the chain; anything unrecognised is treated as
+    /// corrupt input.
+    #[cfg(feature = "image-decoding")]
+    pub fn from_decode_error(context: &str, err: &anyhow::Error) -> Self {
+        let message = format!("{context}: {err}");
+        let kind = err
+            .chain()
+            .find_map(|cause| {
+                cause
+                    .downcast_ref::<ProcessingError>()
+                    .map(|e| e.kind)
+                    .or_else(|| cause.downcast_ref::<image::ImageError>().map(classify_image_error))
+            })
+            .unwrap_or(ProcessingErrorKind::CorruptData);
+        Self::new(kind, message)
+    }
+}
+
+#[cfg(feature = "image-decoding")]
+pub fn classify_image_error(err: &image::ImageError) -> ProcessingErrorKind {
+    match err {
+        image::ImageError::Unsupported(_) => ProcessingErrorKind::UnsupportedFormat,
+        image::ImageError::Limits(_) => ProcessingErrorKind::OutOfMemory,
+        _ => ProcessingErrorKind::CorruptData,
+    }
+}
+
+impl fmt::Display for ProcessingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.message)
+    }
+}
+
+impl std::error::Error for ProcessingError {}
+
+impl From<ProcessingError> for JsValue {
+    fn from(err: ProcessingError) -> Self {
+        serde_wasm_bindgen::to_value(&err).unwrap_or_else(|_| JsValue::from_str(&err.to_string()))
+    }
+}
+
+#[cfg(all(test, feature = "image-decoding"))]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn decode_errors_are_classified_through_the_chain() {
+        let truncated = image::load_from_memory(b"\x89PNG\r\n\x1a\n").unwrap_err();
+        let err = anyhow::Error::new(truncated).context("decoding");
+        let classified = ProcessingError::from_decode_error("image decode failed", &err);
+        assert_eq!(classified.kind, ProcessingErrorKind::CorruptData);
+        assert!(
+            classified
+                .message
+                .starts_with("image decode failed: decoding")
+        );
+
+        let too_large: anyhow::Result<()> =
+            Err(ProcessingError::too_large("over the pixel limit").into());
+        let err = too_large.context("loading").unwrap_err();
+        assert_eq!(
+            ProcessingError::from_decode_error("load", &err).kind,
+            ProcessingErrorKind::TooLarge
+        );
+    }
+
+    #[test]
+    fn unknown_formats_and_limits_map_to_their_kinds() {
+        let unknown = image::load_from_memory(b"not an image").unwrap_err();
+        assert_eq!(
+            classify_image_error(&unknown),
+            ProcessingErrorKind::UnsupportedFormat
+        );
+        let limits = image::ImageError::Limits(image::error::LimitError::from_kind(
+            image::error::LimitErrorKind::InsufficientMemory,
+        ));
+        assert_eq!(
+            classify_image_error(&limits),
+            ProcessingErrorKind::OutOfMemory
+        );
+    }
+}
@@ -0,0 +1,703 @@
+use crate::core::image_utils::{resize_f32_image, unsharp_mask};
+use crate::core::working_space::to_srgb;
+use anyhow::{anyhow, Result};
+use image::{DynamicImage, GenericImageView, RgbImage, RgbaImage};
+use rapidraw_core::luma::{luma, LumaCoefficients};
+use serde::Deserialize;
+use std::io::Cursor;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ResizeMode {
+    LongEdge,
+    Width,
+    Height,
+    Percent,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResizeSpec {
+    pub mode: ResizeMode,
+    pub value: f32,
+    #[serde(default)]
+    pub allow_upscale: bool,
+}
+
+impl ResizeSpec {
+    /// Output size for a `width` x `height` source, preserving aspect ratio.
+    pub fn target_dimensions(&self, width: u32, height: u32) -> (u32, u32) {
+        if width == 0 || height == 0 || self.value <= 0.0 {
+            return (width, height);
+        }
+        let (w, h) = (width as f32, height as f32);
+        let mut scale = match self.mode {
+            ResizeMode::LongEdge => self.value / w.max(h),
+            ResizeMode::Width => self.value / w,
+            ResizeMode::Height => self.value / h,
+            ResizeMode::Percent => self.value / 100.0,
+        };
+        if !self.allow_upscale {
+            scale = scale.min(1.0);
+        }
+        (
+            ((w * scale).round() as u32).max(1),
+            ((h * scale).round() as u32).max(1),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SharpenTarget {
+    ForScreen,
+    ForPrint,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputSharpen {
+    pub amount: f32,
+    pub target: SharpenTarget,
+}
+
+impl OutputSharpen {
+    /// Radius grows with the output size; print viewing distances call for a
+    /// wider halo than screens.
+    fn sigma_for(&self, long_edge: u32) -> f32 {
+        let base = match self.target {
+            SharpenTarget::ForScreen => 0.6,
+            SharpenTarget::ForPrint => 1.0,
+        };
+        (base * long_edge as f32 / 2048.0).clamp(0.3, 2.5)
+    }
+
+    /// Scales the user amount by how much the image was shrunk: no reduction
+    /// gets half strength, a 4x (or larger) reduction the full amount.
+    fn strength_for(&self, reduction: f32) -> f32 {
+        let reduction_weight = (reduction.max(1.0).log2() / 2.0).min(1.0);
+        self.amount.max(0.0) * (0.5 + 0.5 * reduction_weight)
+    }
+}
+
+pub fn apply_output_sharpening(
+    image: DynamicImage,
+    sharpen: &OutputSharpen,
+    source_long_edge: u32,
+) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let long_edge = width.max(height);
+    if long_edge == 0 {
+        return image;
+    }
+    let reduction = source_long_edge as f32 / long_edge as f32;
+    let strength = sharpen.strength_for(reduction);
+    unsharp_mask(&image, sharpen.sigma_for(long_edge), strength)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    #[default]
+    Jpeg,
+    Png,
+}
+
+impl ExportFormat {
+    /// The name used in export options JSON.
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportFormat::Jpeg => "jpeg",
+            ExportFormat::Png => "png",
+        }
+    }
+}
+
+/// How float values are rounded to 8 bits. Without dithering, smooth
+/// gradients (skies, vignettes) quantise into visible bands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Dither {
+    #[default]
+    None,
+    /// 8x8 Bayer threshold matrix: a fixed, fine pattern; fast and stable
+    /// under recompression.
+    Ordered,
+    /// Floyd-Steinberg error diffusion: no visible pattern, slightly noisier.
+    FloydSteinberg,
+}
+
+/// What happens to colors the output space cannot hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GamutMapping {
+    /// Clamp each channel on its own; saturated areas can flatten into
+    /// patches and shift hue.
+    #[default]
+    Clip,
+    /// Pull chroma in towards gray at constant luminance, with a soft knee
+    /// so in-gamut colors near the edge are only slightly desaturated.
+    Compress,
+}
+
+/// Fraction of the distance to the gamut boundary left untouched by
+/// `GamutMapping::Compress`.
+const GAMUT_KNEE: f32 = 0.8;
+
+/// Compresses one linear pipeline (Rec.709) pixel into 0..1. Hue and
+/// luminance are kept by scaling the pixel's offset from gray; the knee curve
+/// approaches the boundary asymptotically, so distinct out-of-gamut colors
+/// stay distinct instead of clipping to the same edge.
+fn compress_gamut_pixel(rgb: [f32; 3]) -> [f32; 3] {
+    let y = luma(rgb[0], rgb[1], rgb[2], LumaCoefficients::Rec709).clamp(0.0, 1.0);
+    let chroma = rgb.map(|c| c - y);
+    // How far along the chroma direction the pixel sits, where 1 is the
+    // boundary of the 0..1 cube.
+    let distance = chroma
+        .iter()
+        .map(|&c| {
+            if c > 0.0 {
+                c / (1.0 - y).max(1e-6)
+            } else if c < 0.0 {
+                -c / y.max(1e-6)
+            } else {
+                0.0
+            }
+        })
+        .fold(0.0f32, f32::max);
+    if distance <= GAMUT_KNEE {
+        return rgb;
+    }
+    let excess = (distance - GAMUT_KNEE) / (1.0 - GAMUT_KNEE);
+    let compressed = GAMUT_KNEE + (1.0 - GAMUT_KNEE) * excess / (1.0 + excess);
+    let scale = compressed / distance;
+    chroma.map(|c| (y + c * scale).clamp(0.0, 1.0))
+}
+
+/// Applies `GamutMapping::Compress` to a linear pipeline image; alpha is
+/// kept.
+pub fn compress_gamut(image: &DynamicImage) -> DynamicImage {
+    if image.color().has_alpha() {
+        let mut buffer = image.to_rgba32f();
+        for p in buffer.pixels_mut() {
+            let [r, g, b] = compress_gamut_pixel([p[0], p[1], p[2]]);
+            p.0[..3].copy_from_slice(&[r, g, b]);
+        }
+        DynamicImage::ImageRgba32F(buffer)
+    } else {
+        let mut buffer = image.to_rgb32f();
+        for p in buffer.pixels_mut() {
+            p.0 = compress_gamut_pixel(p.0);
+        }
+        DynamicImage::ImageRgb32F(buffer)
+    }
+}
+
+/// JPEG chroma subsampling, as in the desktop export. `Auto` keeps full chroma
+/// resolution at high quality settings and uses 4:2:0 below that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum ChromaSubsampling {
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "4:4:4")]
+    Yuv444,
+    #[serde(rename = "4:2:2")]
+    Yuv422,
+    #[serde(rename = "4:2:0")]
+    Yuv420,
+}
+
+const AUTO_FULL_CHROMA_QUALITY: u8 = 90;
+
+impl ChromaSubsampling {
+    fn sampling_factor(self, quality: u8) -> jpeg_encoder::SamplingFactor {
+        match self {
+            ChromaSubsampling::Auto if quality >= AUTO_FULL_CHROMA_QUALITY => {
+                jpeg_encoder::SamplingFactor::R_4_4_4
+            }
+            ChromaSubsampling::Auto | ChromaSubsampling::Yuv420 => {
+                jpeg_encoder::SamplingFactor::R_4_2_0
+            }
+            ChromaSubsampling::Yuv422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Yuv444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub quality: u8,
+    pub resize: Option<ResizeSpec>,
+    pub output_sharpening: Option<OutputSharpen>,
+    /// Applies to the 8-bit JPEG path; PNG is written at 16 bits.
+    pub dither: Dither,
+    pub gamut_mapping: GamutMapping,
+    pub chroma_subsampling: ChromaSubsampling,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Jpeg,
+            quality: 90,
+            resize: None,
+            output_sharpening: None,
+            dither: Dither::None,
+            gamut_mapping: GamutMapping::Clip,
+            chroma_subsampling: ChromaSubsampling::Auto,
+        }
+    }
+}
+
+pub fn parse_export_options(json: &str) -> ExportOptions {
+    serde_json::from_str::<ExportOptions>(json).unwrap_or_default()
+}
+
+pub fn apply_resize(image: DynamicImage, spec: &ResizeSpec) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let (new_w, new_h) = spec.target_dimensions(width, height);
+    if new_w == width && new_h == height {
+        return image;
+    }
+    resize_f32_image(&image, new_w, new_h)
+}
+
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Quantises an sRGB-encoded image to 8-bit RGB with `dither`. Dithering runs
+/// on encoded values, after the linear-to-sRGB conversion, so the noise is
+/// spread evenly over the output code values.
+pub fn quantize_rgb8(encoded: &DynamicImage, dither: Dither) -> RgbImage {
+    if dither == Dither::None {
+        return encoded.to_rgb8();
+    }
+    let source = encoded.to_rgb32f();
+    let (width, height) = source.dimensions();
+    let mut out = RgbImage::new(width, height);
+    let to_u8 = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+
+    match dither {
+        Dither::None => unreachable!(),
+        Dither::Ordered => {
+            for (x, y, p) in out.enumerate_pixels_mut() {
+                let threshold =
+                    (BAYER_8X8[(y % 8) as usize][(x % 8) as usize] as f32 + 0.5) / 64.0 - 0.5;
+                let s = source.get_pixel(x, y);
+                p.0 = [0, 1, 2].map(|c| to_u8(s[c] * 255.0 + threshold));
+            }
+        }
+        Dither::FloydSteinberg => {
+            let w = width as usize;
+            // Error carried into the current and the next row.
+            let mut current = vec![[0.0f32; 3]; w + 2];
+            let mut next = vec![[0.0f32; 3]; w + 2];
+            for y in 0..height {
+                for x in 0..w {
+                    let s = source.get_pixel(x as u32, y);
+                    let mut quantised = [0u8; 3];
+                    for c in 0..3 {
+                        let wanted = s[c] * 255.0 + current[x + 1][c];
+                        quantised[c] = to_u8(wanted);
+                        let error = wanted - quantised[c] as f32;
+                        current[x + 2][c] += error * 7.0 / 16.0;
+                        next[x][c] += error * 3.0 / 16.0;
+                        next[x + 1][c] += error * 5.0 / 16.0;
+                        next[x + 2][c] += error / 16.0;
+                    }
+                    out.put_pixel(x as u32, y, image::Rgb(quantised));
+                }
+                std::mem::swap(&mut current, &mut next);
+                next.iter_mut().for_each(|e| *e = [0.0; 3]);
+            }
+        }
+    }
+    out
+}
+
+static PREVIEW_DITHER: AtomicBool = AtomicBool::new(true);
+
+/// Whether `preview_rgba8` dithers. On by default; hosts that compare preview
+/// pixels exactly can turn it off.
+pub fn set_preview_dither(enabled: bool) {
+    PREVIEW_DITHER.store(enabled, Ordering::Relaxed);
+}
+
+/// 8-bit sRGB of a pipeline image for on-screen previews, alpha kept. Deep
+/// sources are ordered-dithered on the way down so smooth gradients do not
+/// band; exports keep their own path and bit depth.
+pub fn preview_rgba8(image: &DynamicImage) -> RgbaImage {
+    let encoded = to_srgb(image);
+    let mut rgba = encoded.to_rgba8();
+    let deep = encoded.color().bytes_per_pixel() > encoded.color().channel_count();
+    if deep && PREVIEW_DITHER.load(Ordering::Relaxed) {
+        let rgb = quantize_rgb8(&encoded, Dither::Ordered);
+        for (out, dithered) in rgba.pixels_mut().zip(rgb.pixels()) {
+            out.0[..3].copy_from_slice(&dithered.0);
+        }
+    }
+    rgba
+}
+
+/// Encodes a pipeline image as sRGB. PNG keeps the alpha channel; JPEG has
+/// none and drops it.
+pub fn encode_image(image: &DynamicImage, options: &ExportOptions) -> Result<Vec<u8>> {
+    let image = match options.gamut_mapping {
+        GamutMapping::Clip => to_srgb(image),
+        GamutMapping::Compress => to_srgb(&compress_gamut(image)),
+    };
+
+    let mut bytes = Vec::new();
+    let mut cursor = Cursor::new(&mut bytes);
+    match options.format {
+        ExportFormat::Jpeg => {
+            let rgb = quantize_rgb8(&image, options.dither);
+            let (width, height) = match (u16::try_from(rgb.width()), u16::try_from(rgb.height())) {
+                (Ok(w), Ok(h)) => (w, h),
+                _ => {
+                    return Err(anyhow!(
+                        "{}x{} exceeds the JPEG size limit of 65535 pixels",
+                        rgb.width(),
+                        rgb.height()
+                    ));
+                }
+            };
+            let quality = options.quality.clamp(1, 100);
+            let mut encoder = jpeg_encoder::Encoder::new(&mut cursor, quality);
+            encoder.set_sampling_factor(options.chroma_subsampling.sampling_factor(quality));
+            encoder
+                .encode(rgb.as_raw(), width, height, jpeg_encoder::ColorType::Rgb)
+                .map_err(|err| anyhow!("jpeg encode failed: {err}"))?;
+        }
+        ExportFormat::Png => {
+            let png = if image.color().has_alpha() {
+                DynamicImage::ImageRgba16(image.to_rgba16())
+            } else {
+                DynamicImage::ImageRgb16(image.to_rgb16())
+            };
+            png.write_to(&mut cursor, image::ImageFormat::Png)
+                .map_err(|err| anyhow!("png encode failed: {err}"))?;
+        }
+    }
+    Ok(bytes)
+}
+
+/// An encoded export with a description of what was written, so callers can
+/// show the output size without decoding it again.
+#[derive(Debug, Clone)]
+pub struct ExportResult {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub format: ExportFormat,
+    pub byte_len: usize,
+    /// Whether source metadata (EXIF/XMP) was embedded. This encoder writes
+    /// none, so it is always false for now.
+    pub has_metadata: bool,
+}
+
+pub fn export_image(image: DynamicImage, options: &ExportOptions) -> Result<ExportResult> {
+    let (source_w, source_h) = image.dimensions();
+    let image = match &options.resize {
+        Some(spec) => apply_resize(image, spec),
+        None => image,
+    };
+    let image = match &options.output_sharpening {
+        Some(sharpen) => apply_output_sharpening(image, sharpen, source_w.max(source_h)),
+        None => image,
+    };
+    let data = encode_image(&image, options)?;
+    Ok(ExportResult {
+        width: image.width(),
+        height: image.height(),
+        format: options.format,
+        byte_len: data.len(),
+        has_metadata: false,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rapidraw_core::srgb::srgb_to_linear;
+
+    fn spec(mode: ResizeMode, value: f32, allow_upscale: bool) -> ResizeSpec {
+        ResizeSpec {
+            mode,
+            value,
+            allow_upscale,
+        }
+    }
+
+    #[test]
+    fn resize_modes_keep_the_aspect_ratio() {
+        assert_eq!(
+            spec(ResizeMode::LongEdge, 1500.0, false).target_dimensions(6000, 4000),
+            (1500, 1000)
+        );
+        assert_eq!(
+            spec(ResizeMode::LongEdge, 1500.0, false).target_dimensions(4000, 6000),
+            (1000, 1500)
+        );
+        assert_eq!(
+            spec(ResizeMode::Width, 300.0, false).target_dimensions(600, 400),
+            (300, 200)
+        );
+        assert_eq!(
+            spec(ResizeMode::Height, 100.0, false).target_dimensions(600, 400),
+            (150, 100)
+        );
+        assert_eq!(
+            spec(ResizeMode::Percent, 25.0, false).target_dimensions(600, 400),
+            (150, 100)
+        );
+    }
+
+    #[test]
+    fn resize_only_upscales_when_allowed() {
+        assert_eq!(
+            spec(ResizeMode::Percent, 200.0, false).target_dimensions(600, 400),
+            (600, 400)
+        );
+        assert_eq!(
+            spec(ResizeMode::Percent, 200.0, true).target_dimensions(600, 400),
+            (1200, 800)
+        );
+        assert_eq!(
+            spec(ResizeMode::Width, 0.0, true).target_dimensions(600, 400),
+            (600, 400)
+        );
+        assert_eq!(
+            spec(ResizeMode::Percent, 0.01, false).target_dimensions(600, 400),
+            (1, 1)
+        );
+    }
+
+    #[test]
+    fn export_options_parse_a_resize() {
+        let options = parse_export_options(
+            r#"{"format": "png", "resize": {"mode": "longEdge", "value": 64}}"#,
+        );
+        assert_eq!(options.format, ExportFormat::Png);
+        assert_eq!(options.quality, 90);
+        let resized = apply_resize(DynamicImage::new_rgb32f(128, 32), &options.resize.unwrap());
+        assert_eq!(resized.dimensions(), (64, 16));
+    }
+
+    #[test]
+    fn sharpening_strength_grows_with_the_reduction() {
+        let sharpen = OutputSharpen {
+            amount: 1.0,
+            target: SharpenTarget::ForScreen,
+        };
+        assert!((sharpen.strength_for(1.0) - 0.5).abs() < 1e-6);
+        assert!((sharpen.strength_for(2.0) - 0.75).abs() < 1e-6);
+        assert!((sharpen.strength_for(4.0) - 1.0).abs() < 1e-6);
+        assert!((sharpen.strength_for(16.0) - 1.0).abs() < 1e-6);
+        assert!((sharpen.strength_for(0.5) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn print_sharpening_uses_a_wider_radius() {
+        let screen = OutputSharpen {
+            amount: 1.0,
+            target: SharpenTarget::ForScreen,
+        };
+        let print = OutputSharpen {
+            target: SharpenTarget::ForPrint,
+            ..screen
+        };
+        assert!(print.sigma_for(4096) > screen.sigma_for(4096));
+        assert_eq!(screen.sigma_for(16), 0.3);
+        assert_eq!(print.sigma_for(100_000), 2.5);
+    }
+
+    #[test]
+    fn output_sharpening_steepens_an_edge() {
+        let edge = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_fn(32, 8, |x, _| {
+            image::Rgb([if x < 16 { 0.3 } else { 0.6 }; 3])
+        }));
+        let sharpen = OutputSharpen {
+            amount: 1.0,
+            target: SharpenTarget::ForScreen,
+        };
+        let sharpened = apply_output_sharpening(edge, &sharpen, 128).to_rgb32f();
+        assert!(sharpened.get_pixel(15, 4)[0] < 0.3);
+        assert!(sharpened.get_pixel(16, 4)[0] > 0.6);
+        assert!((sharpened.get_pixel(2, 4)[0] - 0.3).abs() < 1e-4);
+    }
+
+    #[test]
+    fn png_export_keeps_alpha_and_jpeg_drops_it() {
+        let image = DynamicImage::ImageRgba32F(image::Rgba32FImage::from_pixel(
+            2,
+            2,
+            image::Rgba([0.5, 0.5, 0.5, 0.25]),
+        ));
+        let png_options = ExportOptions {
+            format: ExportFormat::Png,
+            ..Default::default()
+        };
+        let png = image::load_from_memory(&encode_image(&image, &png_options).unwrap()).unwrap();
+        assert!(png.color().has_alpha());
+        assert_eq!(
+            png.to_rgba16().get_pixel(0, 0)[3],
+            (0.25 * 65535.0f32).round() as u16
+        );
+
+        let jpeg =
+            image::load_from_memory(&encode_image(&image, &ExportOptions::default()).unwrap())
+                .unwrap();
+        assert!(!jpeg.color().has_alpha());
+    }
+
+    #[test]
+    fn dithering_keeps_the_mean_between_code_values() {
+        let flat = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_pixel(
+            16,
+            16,
+            image::Rgb([100.25 / 255.0; 3]),
+        ));
+        let mean = |image: &RgbImage| {
+            image.pixels().map(|p| p[0] as f32).sum::<f32>() / image.pixels().len() as f32
+        };
+        assert_eq!(mean(&quantize_rgb8(&flat, Dither::None)), 100.0);
+        for dither in [Dither::Ordered, Dither::FloydSteinberg] {
+            let dithered = quantize_rgb8(&flat, dither);
+            assert!((mean(&dithered) - 100.25).abs() < 0.05, "{dither:?}");
+            assert!(dithered.pixels().all(|p| p[0] == 100 || p[0] == 101));
+        }
+    }
+
+    #[test]
+    fn exact_code_values_are_not_dithered() {
+        let image = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_fn(9, 9, |x, y| {
+            image::Rgb([x as f32 * 20.0 / 255.0, y as f32 * 20.0 / 255.0, 1.0])
+        }));
+        let plain = quantize_rgb8(&image, Dither::None);
+        for dither in [Dither::Ordered, Dither::FloydSteinberg] {
+            assert_eq!(quantize_rgb8(&image, dither), plain, "{dither:?}");
+        }
+    }
+
+    #[test]
+    fn deep_previews_are_dithered_unless_turned_off() {
+        // A shallow ramp that rounds to a handful of flat bands.
+        let deep = DynamicImage::ImageRgba32F(image::Rgba32FImage::from_fn(32, 8, |x, _| {
+            let v = srgb_to_linear(0.5 + x as f32 / 32.0 / 255.0 * 4.0);
+            image::Rgba([v, v, v, 0.5])
+        }));
+        let plain = to_srgb(&deep).to_rgba8();
+
+        set_preview_dither(false);
+        let undithered = preview_rgba8(&deep);
+        set_preview_dither(true);
+        let dithered = preview_rgba8(&deep);
+
+        assert_eq!(undithered, plain);
+        assert_ne!(dithered, plain);
+        assert!(dithered.pixels().all(|p| p[3] == 128));
+    }
+
+    #[test]
+    fn gamut_compression_keeps_luminance_and_hue() {
+        // In gamut and well inside the knee: untouched.
+        assert_eq!(compress_gamut_pixel([0.4, 0.5, 0.45]), [0.4, 0.5, 0.45]);
+
+        let over = [1.6, 0.3, -0.1];
+        let out = compress_gamut_pixel(over);
+        assert!(out.iter().all(|c| (0.0..=1.0).contains(c)), "{out:?}");
+        let y = |p: [f32; 3]| luma(p[0], p[1], p[2], LumaCoefficients::Rec709);
+        assert!((y(out) - y(over)).abs() < 1e-4);
+        // The offset from gray only shrinks, so the channel order is kept.
+        assert!(out[0] > out[1] && out[1] > out[2]);
+    }
+
+    #[test]
+    fn distinct_out_of_gamut_colors_stay_distinct() {
+        let a = compress_gamut_pixel([1.5, 0.2, 0.2]);
+        let b = compress_gamut_pixel([2.0, 0.1, 0.1]);
+        assert_ne!(a, b);
+        assert!(a[0] < 1.0 && b[0] < 1.0);
+    }
+
+    #[test]
+    fn compress_gamut_keeps_alpha() {
+        let image = DynamicImage::ImageRgba32F(image::Rgba32FImage::from_pixel(
+            2,
+            2,
+            image::Rgba([1.5, 0.2, 0.2, 0.25]),
+        ));
+        let DynamicImage::ImageRgba32F(out) = compress_gamut(&image) else {
+            panic!("expected an RGBA image");
+        };
+        assert!(out.pixels().all(|p| p[3] == 0.25 && p[0] <= 1.0));
+    }
+
+    #[test]
+    fn export_result_describes_the_written_file() {
+        let image = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_fn(60, 40, |x, _| {
+            image::Rgb([x as f32 / 60.0, 0.4, 0.2])
+        }));
+        let options = ExportOptions {
+            format: ExportFormat::Png,
+            resize: Some(spec(ResizeMode::LongEdge, 30.0, false)),
+            ..Default::default()
+        };
+        let result = export_image(image.clone(), &options).unwrap();
+        assert_eq!((result.width, result.height), (30, 20));
+        assert_eq!(result.format, ExportFormat::Png);
+        assert_eq!(result.byte_len, result.data.len());
+        assert!(!result.has_metadata);
+        let decoded = image::load_from_memory(&result.data).unwrap();
+        assert_eq!(decoded.dimensions(), (30, 20));
+
+        let jpeg = export_image(image, &ExportOptions::default()).unwrap();
+        assert_eq!(
+            (jpeg.width, jpeg.height, jpeg.format),
+            (60, 40, ExportFormat::Jpeg)
+        );
+        assert_eq!(&jpeg.data[..2], &[0xFF, 0xD8]);
+        assert_eq!(jpeg.format.name(), "jpeg");
+    }
+
+    /// The packed horizontal/vertical sampling factors of each component in
+    /// the baseline SOF segment.
+    fn sof_sampling_factors(jpeg: &[u8]) -> Vec<u8> {
+        let mut at = 2;
+        while jpeg[at + 1] != 0xC0 {
+            at += 2 + u16::from_be_bytes([jpeg[at + 2], jpeg[at + 3]]) as usize;
+        }
+        let components = jpeg[at + 9] as usize;
+        (0..components).map(|i| jpeg[at + 11 + 3 * i]).collect()
+    }
+
+    #[test]
+    fn jpeg_chroma_subsampling_matches_the_desktop_export() {
+        let image = DynamicImage::ImageRgb32F(image::Rgb32FImage::from_fn(16, 16, |x, y| {
+            image::Rgb([x as f32 / 16.0, y as f32 / 16.0, 0.3])
+        }));
+        let factors = |json: &str| {
+            sof_sampling_factors(&encode_image(&image, &parse_export_options(json)).unwrap())
+        };
+        assert_eq!(factors(r#"{"quality": 95}"#), [0x11, 0x11, 0x11]);
+        assert_eq!(factors(r#"{"quality": 80}"#), [0x22, 0x11, 0x11]);
+        assert_eq!(
+            factors(r#"{"quality": 80, "chromaSubsampling": "4:4:4"}"#),
+            [0x11, 0x11, 0x11]
+        );
+    }
+}
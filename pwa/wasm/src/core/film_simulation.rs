@@ -0,0 +1,279 @@
+//! Fujifilm film simulations: names as the camera reports them, and rough
+//! color-grading equivalents for starting an edit close to the in-camera
+//! look.
+//!
+//! The looks are approximations built from the grading controls, not
+//! measured profiles. They are baked into LUTs with
+//! `color_grading::generate_lut`, so they go through the same LUT path as
+//! user presets.
+
+use crate::core::color_grading::{
+    generate_lut, ColorGradingSettings, ColorWheel, HslAdjustment, HslAdjustments,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilmSimulation {
+    #[default]
+    Provia,
+    Velvia,
+    Astia,
+    ClassicChrome,
+    ProNegHi,
+    ProNegStd,
+    ClassicNeg,
+    Eterna,
+    EternaBleachBypass,
+    NostalgicNeg,
+    RealaAce,
+    Acros,
+    Monochrome,
+    Sepia,
+}
+
+const ALL: [FilmSimulation; 14] = [
+    FilmSimulation::Provia,
+    FilmSimulation::Velvia,
+    FilmSimulation::Astia,
+    FilmSimulation::ClassicChrome,
+    FilmSimulation::ProNegHi,
+    FilmSimulation::ProNegStd,
+    FilmSimulation::ClassicNeg,
+    FilmSimulation::Eterna,
+    FilmSimulation::EternaBleachBypass,
+    FilmSimulation::NostalgicNeg,
+    FilmSimulation::RealaAce,
+    FilmSimulation::Acros,
+    FilmSimulation::Monochrome,
+    FilmSimulation::Sepia,
+];
+
+/// A wheel puck at `hue` degrees and `radius` from the centre.
+fn wheel(hue: f32, radius: f32, intensity: f32) -> ColorWheel {
+    let (sin, cos) = hue.to_radians().sin_cos();
+    ColorWheel {
+        x: cos * radius,
+        y: sin * radius,
+        intensity,
+    }
+}
+
+fn saturation(saturation: f32) -> HslAdjustment {
+    HslAdjustment {
+        saturation,
+        ..Default::default()
+    }
+}
+
+impl FilmSimulation {
+    /// The name Fujifilm uses for the simulation.
+    pub fn name(self) -> &'static str {
+        match self {
+            FilmSimulation::Provia => "Provia/Standard",
+            FilmSimulation::Velvia => "Velvia",
+            FilmSimulation::Astia => "Astia",
+            FilmSimulation::ClassicChrome => "Classic Chrome",
+            FilmSimulation::ProNegHi => "Pro Neg. Hi",
+            FilmSimulation::ProNegStd => "Pro Neg. Std",
+            FilmSimulation::ClassicNeg => "Classic Neg.",
+            FilmSimulation::Eterna => "Eterna",
+            FilmSimulation::EternaBleachBypass => "Eterna Bleach Bypass",
+            FilmSimulation::NostalgicNeg => "Nostalgic Neg.",
+            FilmSimulation::RealaAce => "Reala Ace",
+            FilmSimulation::Acros => "Acros",
+            FilmSimulation::Monochrome => "Monochrome",
+            FilmSimulation::Sepia => "Sepia",
+        }
+    }
+
+    /// Looks a simulation up by `name` (case-insensitive); unknown names
+    /// fall back to Provia/Standard.
+    pub fn from_name(name: &str) -> Self {
+        let name = name.trim();
+        ALL.into_iter()
+            .find(|sim| sim.name().eq_ignore_ascii_case(name))
+            .unwrap_or_default()
+    }
+
+    /// Decodes the Fujifilm MakerNote FilmMode (0x1401) and Saturation
+    /// (0x1003) values. Monochrome simulations are recorded as a saturation
+    /// setting rather than a film mode. Unknown codes fall back to
+    /// Provia/Standard.
+    #[cfg(feature = "raw-processing")]
+    pub fn from_fuji_codes(film_mode: Option<u16>, saturation: Option<u16>) -> Self {
+        match saturation {
+            Some(0x300..=0x303) => return FilmSimulation::Monochrome,
+            Some(0x310) => return FilmSimulation::Sepia,
+            Some(0x500..=0x503) => return FilmSimulation::Acros,
+            _ => {}
+        }
+        match film_mode {
+            Some(0x120) => FilmSimulation::Astia,
+            Some(0x200) | Some(0x400) => FilmSimulation::Velvia,
+            Some(0x500) => FilmSimulation::ProNegStd,
+            Some(0x501) => FilmSimulation::ProNegHi,
+            Some(0x600) => FilmSimulation::ClassicChrome,
+            Some(0x700) => FilmSimulation::Eterna,
+            Some(0x800) => FilmSimulation::ClassicNeg,
+            Some(0x900) => FilmSimulation::EternaBleachBypass,
+            Some(0xA00) => FilmSimulation::NostalgicNeg,
+            Some(0xB00) => FilmSimulation::RealaAce,
+            _ => FilmSimulation::Provia,
+        }
+    }
+
+    /// Grading settings approximating the simulation's color rendering.
+    pub fn grading(self) -> ColorGradingSettings {
+        match self {
+            FilmSimulation::Provia => ColorGradingSettings::default(),
+            FilmSimulation::Velvia => ColorGradingSettings {
+                hsl: HslAdjustments {
+                    greens: saturation(10.0),
+                    blues: saturation(10.0),
+                    ..Default::default()
+                },
+                global_saturation: 0.3,
+                global_vibrance: 0.1,
+                ..Default::default()
+            },
+            FilmSimulation::Astia => ColorGradingSettings {
+                hsl: HslAdjustments {
+                    oranges: HslAdjustment {
+                        saturation: -5.0,
+                        luminance: 5.0,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                global_saturation: 0.1,
+                ..Default::default()
+            },
+            FilmSimulation::ClassicChrome => ColorGradingSettings {
+                hsl: HslAdjustments {
+                    reds: saturation(-10.0),
+                    blues: HslAdjustment {
+                        hue: -5.0,
+                        saturation: -20.0,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                shadows: wheel(200.0, 0.5, 0.15),
+                global_saturation: -0.25,
+                ..Default::default()
+            },
+            FilmSimulation::ProNegHi => ColorGradingSettings {
+                global_saturation: 0.05,
+                ..Default::default()
+            },
+            FilmSimulation::ProNegStd => ColorGradingSettings {
+                hsl: HslAdjustments {
+                    oranges: saturation(-5.0),
+                    ..Default::default()
+                },
+                global_saturation: -0.1,
+                ..Default::default()
+            },
+            FilmSimulation::ClassicNeg => ColorGradingSettings {
+                hsl: HslAdjustments {
+                    greens: HslAdjustment {
+                        hue: 10.0,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                shadows: wheel(190.0, 0.6, 0.2),
+                highlights: wheel(40.0, 0.5, 0.15),
+                global_saturation: -0.1,
+                ..Default::default()
+            },
+            FilmSimulation::Eterna => ColorGradingSettings {
+                global_saturation: -0.35,
+                ..Default::default()
+            },
+            FilmSimulation::EternaBleachBypass => ColorGradingSettings {
+                global_saturation: -0.6,
+                ..Default::default()
+            },
+            FilmSimulation::NostalgicNeg => ColorGradingSettings {
+                highlights: wheel(40.0, 0.6, 0.25),
+                global_saturation: -0.05,
+                ..Default::default()
+            },
+            FilmSimulation::RealaAce => ColorGradingSettings {
+                global_vibrance: 0.1,
+                ..Default::default()
+            },
+            FilmSimulation::Acros | FilmSimulation::Monochrome => ColorGradingSettings {
+                global_saturation: -1.0,
+                ..Default::default()
+            },
+            FilmSimulation::Sepia => ColorGradingSettings {
+                midtones: wheel(35.0, 0.6, 0.4),
+                global_saturation: -1.0,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// The approximating look baked into a `size`^3 LUT, laid out as
+    /// `color_grading::generate_lut` produces.
+    pub fn lut(self, size: usize) -> Vec<f32> {
+        generate_lut(&self.grading(), size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip_and_unknown_names_fall_back() {
+        for sim in ALL {
+            assert_eq!(FilmSimulation::from_name(sim.name()), sim);
+        }
+        assert_eq!(
+            FilmSimulation::from_name(" classic chrome "),
+            FilmSimulation::ClassicChrome
+        );
+        assert_eq!(
+            FilmSimulation::from_name("Kodachrome"),
+            FilmSimulation::Provia
+        );
+    }
+
+    #[cfg(feature = "raw-processing")]
+    #[test]
+    fn monochrome_saturation_codes_override_the_film_mode() {
+        assert_eq!(
+            FilmSimulation::from_fuji_codes(Some(0x600), None),
+            FilmSimulation::ClassicChrome
+        );
+        assert_eq!(
+            FilmSimulation::from_fuji_codes(Some(0x600), Some(0x501)),
+            FilmSimulation::Acros
+        );
+        assert_eq!(
+            FilmSimulation::from_fuji_codes(None, Some(0x310)),
+            FilmSimulation::Sepia
+        );
+        assert_eq!(
+            FilmSimulation::from_fuji_codes(Some(0x1234), None),
+            FilmSimulation::Provia
+        );
+    }
+
+    #[test]
+    fn every_look_is_valid_grading() {
+        for sim in ALL {
+            crate::core::color_grading::validate_color_grading_settings(&sim.grading())
+                .unwrap_or_else(|err| panic!("{}: {err}", sim.name()));
+        }
+        // Black and white looks bake to a neutral LUT.
+        let lut = FilmSimulation::Acros.lut(9);
+        assert_eq!(lut.len(), 9 * 9 * 9 * 3);
+        assert!(
+            lut.chunks_exact(3)
+                .all(|p| (p[0] - p[1]).abs() < 1e-4 && (p[1] - p[2]).abs() < 1e-4)
+        );
+    }
+}
@@ -68,7 +68,7 @@ pub const RAW_EXTENSIONS: &[(&str, &str)] = &[
 ]; // Tell me if your's is missing.
 
 pub const NON_RAW_EXTENSIONS: &[&str] = &[
-    "jpg", "jpeg", "png", "gif", "bmp", "tiff", "tif", "exr", "qoi",
+    "jpg", "jpeg", "png", "gif", "bmp", "tiff", "tif", "exr", "qoi", "webp",
 ];
 
 pub fn is_raw_file(path: &str) -> bool {
@@ -101,3 +101,277 @@ pub fn is_supported_image_file(path: &str) -> bool {
         false
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectedFormat {
+    /// Plain TIFF container. Most RAW formats (NEF, ARW, DNG, PEF, ...) share this
+    /// header, so it can only be told apart from a RAW by attempting a decode.
+    Tiff,
+    Cr2,
+    Cr3,
+    Raf,
+    Orf,
+    Rw2,
+    Png,
+    Jpeg,
+    Gif,
+    Bmp,
+    WebP,
+    Exr,
+    Qoi,
+    /// HEIF/HEIC container. Recognised so it is not mistaken for something
+    /// else, but there is no decoder for it.
+    Heic,
+    Unknown,
+}
+
+impl DetectedFormat {
+    /// True for signatures that only RAW files carry.
+    pub fn is_raw(self) -> bool {
+        matches!(
+            self,
+            DetectedFormat::Cr2
+                | DetectedFormat::Cr3
+                | DetectedFormat::Raf
+                | DetectedFormat::Orf
+                | DetectedFormat::Rw2
+        )
+    }
+
+    /// True when the container may hold a RAW but the header alone can't confirm it.
+    pub fn may_be_raw(self) -> bool {
+        self.is_raw() || self == DetectedFormat::Tiff
+    }
+
+    /// True when a loader in this crate can decode the format.
+    pub fn is_decodable(self) -> bool {
+        !matches!(self, DetectedFormat::Heic | DetectedFormat::Unknown)
+    }
+}
+
+const CR3_BRANDS: &[&[u8; 4]] = &[b"crx "];
+const HEIC_BRANDS: &[&[u8; 4]] = &[b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx"];
+
+fn has_iso_bmff_brand(data: &[u8], brands: &[&[u8; 4]]) -> bool {
+    if data.len() < 12 || &data[4..8] != b"ftyp" {
+        return false;
+    }
+    let box_len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let end = box_len.clamp(12, data.len());
+    // Major brand at 8..12, minor version at 12..16, compatible brands after that.
+    std::iter::once(&data[8..12])
+        .chain(data[16.min(end)..end].chunks_exact(4))
+        .any(|brand| brands.iter().any(|b| brand == &b[..]))
+}
+
+/// Identifies the container from its leading bytes, independent of the file name.
+pub fn detect_format(data: &[u8]) -> DetectedFormat {
+    let starts = |sig: &[u8]| data.starts_with(sig);
+
+    if starts(b"II*\0") || starts(b"MM\0*") {
+        if data.len() >= 10 && &data[8..10] == b"CR" {
+            return DetectedFormat::Cr2;
+        }
+        return DetectedFormat::Tiff;
+    }
+    if starts(b"IIRO") || starts(b"IIRS") || starts(b"MMOR") {
+        return DetectedFormat::Orf;
+    }
+    if starts(b"IIU\0") {
+        return DetectedFormat::Rw2;
+    }
+    if starts(b"FUJIFILMCCD-RAW") {
+        return DetectedFormat::Raf;
+    }
+    if has_iso_bmff_brand(data, CR3_BRANDS) {
+        return DetectedFormat::Cr3;
+    }
+    if has_iso_bmff_brand(data, HEIC_BRANDS) {
+        return DetectedFormat::Heic;
+    }
+    if starts(&[0xFF, 0xD8, 0xFF]) {
+        return DetectedFormat::Jpeg;
+    }
+    if starts(b"\x89PNG\r\n\x1a\n") {
+        return DetectedFormat::Png;
+    }
+    if starts(b"GIF87a") || starts(b"GIF89a") {
+        return DetectedFormat::Gif;
+    }
+    if starts(b"BM") {
+        return DetectedFormat::Bmp;
+    }
+    if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        return DetectedFormat::WebP;
+    }
+    if starts(&[0x76, 0x2F, 0x31, 0x01]) {
+        return DetectedFormat::Exr;
+    }
+    if starts(b"qoif") {
+        return DetectedFormat::Qoi;
+    }
+    DetectedFormat::Unknown
+}
+
+/// Content-based fallback for files without a usable extension.
+pub fn is_supported_image_bytes(data: &[u8]) -> bool {
+    detect_format(data).is_decodable()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeRoute {
+    Raw,
+    /// Try the RAW decoder first and fall back to the regular image loader.
+    MaybeRaw,
+    NonRaw,
+}
+
+/// Picks a decoder from the extension, consulting the file signature when the
+/// extension is missing, ambiguous (`.raw`) or contradicted by the content.
+pub fn decode_route(data: &[u8], path: &str) -> DecodeRoute {
+    let detected = detect_format(data);
+    let ext = std::path::Path::new(path)
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_lowercase());
+
+    match ext.as_deref() {
+        Some(ext) if ext != "raw" => {
+            // A RAW extension only loses to content the header identifies as
+            // something else, e.g. a JPEG export saved under the RAW's name.
+            let content_is_not_raw = detected.is_decodable() && !detected.may_be_raw();
+            if detected.is_raw() || (is_raw_file(path) && !content_is_not_raw) {
+                DecodeRoute::Raw
+            } else {
+                DecodeRoute::NonRaw
+            }
+        }
+        ext => {
+            if detected.is_raw() {
+                DecodeRoute::Raw
+            } else if detected.may_be_raw() || ext.is_some() {
+                DecodeRoute::MaybeRaw
+            } else {
+                DecodeRoute::NonRaw
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webp_is_accepted_by_extension() {
+        assert!(is_supported_image_file("photo.webp"));
+        assert!(is_supported_image_file("PHOTO.WEBP"));
+        assert!(!is_raw_file("photo.webp"));
+    }
+
+    #[test]
+    fn formats_without_a_decoder_are_rejected() {
+        for path in ["a.avif", "a.heic", "a.heif", "a.jxl"] {
+            assert!(!is_supported_image_file(path), "{path}");
+        }
+    }
+
+    #[test]
+    fn content_is_sniffed_without_an_extension() {
+        assert_eq!(
+            detect_format(&[0xFF, 0xD8, 0xFF, 0xE0]),
+            DetectedFormat::Jpeg
+        );
+        assert_eq!(detect_format(b"\x89PNG\r\n\x1a\n\0\0"), DetectedFormat::Png);
+        assert_eq!(detect_format(b"RIFF\0\0\0\0WEBPVP8 "), DetectedFormat::WebP);
+        assert_eq!(
+            detect_format(b"II*\0\x10\0\0\0CR\x02\0"),
+            DetectedFormat::Cr2
+        );
+        assert_eq!(
+            detect_format(b"\0\0\0\x18ftypcrx \0\0\0\x01crx "),
+            DetectedFormat::Cr3
+        );
+        assert!(is_supported_image_bytes(b"RIFF\0\0\0\0WEBPVP8 "));
+    }
+
+    #[test]
+    fn heic_and_avif_content_is_not_claimed() {
+        assert!(!is_supported_image_bytes(
+            b"\0\0\0\x18ftypheic\0\0\0\0mif1heic"
+        ));
+        assert!(!is_supported_image_bytes(
+            b"\0\0\0\x1cftypavif\0\0\0\0avifmif1"
+        ));
+        assert_eq!(decode_route(b"", "photo.webp"), DecodeRoute::NonRaw);
+    }
+
+    #[test]
+    fn raw_signatures_are_recognised() {
+        assert_eq!(detect_format(b"IIRO\x08\0\0\0"), DetectedFormat::Orf);
+        assert_eq!(detect_format(b"IIU\0\x08\0\0\0"), DetectedFormat::Rw2);
+        assert_eq!(detect_format(b"FUJIFILMCCD-RAW 0201"), DetectedFormat::Raf);
+        assert_eq!(detect_format(b"II*\0\x08\0\0\0\0\0"), DetectedFormat::Tiff);
+        assert!(DetectedFormat::Tiff.may_be_raw() && !DetectedFormat::Tiff.is_raw());
+        assert_eq!(detect_format(b"hello"), DetectedFormat::Unknown);
+    }
+
+    #[test]
+    fn header_bytes_map_to_formats() {
+        let table: &[(&[u8], DetectedFormat)] = &[
+            (b"MM\0*\0\0\0\x08", DetectedFormat::Tiff),
+            (b"II*\0\x10\0\0\0CR\x02\0", DetectedFormat::Cr2),
+            (b"\0\0\0\x18ftypcrx \0\0\0\x01crx ", DetectedFormat::Cr3),
+            (b"FUJIFILMCCD-RAW 0201", DetectedFormat::Raf),
+            (b"\x89PNG\r\n\x1a\n", DetectedFormat::Png),
+            (b"\xFF\xD8\xFF\xE1", DetectedFormat::Jpeg),
+            (b"\x76\x2F\x31\x01\x02\0\0\0", DetectedFormat::Exr),
+            (b"qoif\0\0\0\x10", DetectedFormat::Qoi),
+            (b"\0\0\0\x18ftypheic\0\0\0\0mif1heic", DetectedFormat::Heic),
+            (b"\0\0\0\x18ftypmif1\0\0\0\0mif1heix", DetectedFormat::Heic),
+        ];
+        for (header, expected) in table {
+            assert_eq!(detect_format(header), *expected, "{header:?}");
+        }
+        assert!(!DetectedFormat::Heic.is_decodable());
+    }
+
+    #[test]
+    fn content_overrides_a_misleading_extension() {
+        let cr2 = b"II*\0\x10\0\0\0CR\x02\0";
+        assert_eq!(decode_route(cr2, "renamed.jpg"), DecodeRoute::Raw);
+        assert_eq!(
+            decode_route(b"\xFF\xD8\xFF\xE0", "photo.nef"),
+            DecodeRoute::NonRaw
+        );
+        assert_eq!(
+            decode_route(b"II*\0\x08\0\0\0", "photo.nef"),
+            DecodeRoute::Raw
+        );
+        assert_eq!(decode_route(b"\0\0\0\0", "photo.nef"), DecodeRoute::Raw);
+        assert_eq!(
+            decode_route(b"\xFF\xD8\xFF\xE0", "photo.jpg"),
+            DecodeRoute::NonRaw
+        );
+    }
+
+    #[test]
+    fn ambiguous_or_missing_extensions_use_the_signature() {
+        assert_eq!(
+            decode_route(b"\xFF\xD8\xFF\xE0", "IMG_0001.RAW"),
+            DecodeRoute::MaybeRaw
+        );
+        assert_eq!(
+            decode_route(b"IIU\0\x08\0\0\0", "IMG_0001.RAW"),
+            DecodeRoute::Raw
+        );
+        assert_eq!(
+            decode_route(b"II*\0\x08\0\0\0", "download"),
+            DecodeRoute::MaybeRaw
+        );
+        assert_eq!(
+            decode_route(b"\x89PNG\r\n\x1a\n", "download"),
+            DecodeRoute::NonRaw
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use crate::core::metadata::Crop;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -26,6 +27,10 @@ pub struct GeometryParams {
     pub vig_k1: f32,
     pub vig_k2: f32,
     pub vig_k3: f32,
+    /// Mirror flips, applied after the warp and coarse 90° rotation and
+    /// before the crop (same order as the desktop pipeline).
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
 }
 
 impl Default for GeometryParams {
@@ -54,6 +59,8 @@ impl Default for GeometryParams {
             vig_k1: 0.0,
             vig_k2: 0.0,
             vig_k3: 0.0,
+            flip_horizontal: false,
+            flip_vertical: false,
         }
     }
 }
@@ -107,5 +114,296 @@ pub fn get_geometry_params_from_json(adjustments: &Value) -> GeometryParams {
         vig_k3: lens_params
             .and_then(|p| p.get("vig_k3").and_then(|k| k.as_f64()))
             .unwrap_or(0.0) as f32,
+        flip_horizontal: adjustments["flipHorizontal"].as_bool().unwrap_or(false),
+        flip_vertical: adjustments["flipVertical"].as_bool().unwrap_or(false),
+    }
+}
+
+/// Crop tool aspect presets, named width:height as usually written.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AspectPreset {
+    #[serde(rename = "original")]
+    Original,
+    #[serde(rename = "1:1")]
+    Square,
+    #[serde(rename = "4:5")]
+    FourFive,
+    #[serde(rename = "3:2")]
+    ThreeTwo,
+    #[serde(rename = "16:9")]
+    SixteenNine,
+}
+
+/// Which way round a preset is laid on the image.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PresetOrientation {
+    /// Match the image: 3:2 on a portrait image becomes 2:3. Square images
+    /// take the preset as written.
+    #[default]
+    Auto,
+    Landscape,
+    Portrait,
+}
+
+impl AspectPreset {
+    /// Width over height as written, or `None` for the image's own ratio.
+    fn ratio(self) -> Option<f64> {
+        match self {
+            AspectPreset::Original => None,
+            AspectPreset::Square => Some(1.0),
+            AspectPreset::FourFive => Some(4.0 / 5.0),
+            AspectPreset::ThreeTwo => Some(3.0 / 2.0),
+            AspectPreset::SixteenNine => Some(16.0 / 9.0),
+        }
+    }
+}
+
+/// The largest centred crop of a `width` x `height` image with the preset's
+/// aspect ratio, in pixels.
+pub fn aspect_crop(
+    width: u32,
+    height: u32,
+    preset: AspectPreset,
+    orientation: PresetOrientation,
+) -> Crop {
+    let (w, h) = (width as f64, height as f64);
+    let Some(ratio) = preset.ratio() else {
+        return Crop {
+            x: 0.0,
+            y: 0.0,
+            width: w,
+            height: h,
+        };
+    };
+    let wants_landscape = match orientation {
+        PresetOrientation::Auto if w > h => true,
+        PresetOrientation::Auto if w < h => false,
+        PresetOrientation::Auto => ratio >= 1.0,
+        PresetOrientation::Landscape => true,
+        PresetOrientation::Portrait => false,
+    };
+    let ratio = if wants_landscape == (ratio >= 1.0) {
+        ratio
+    } else {
+        1.0 / ratio
+    };
+
+    let (crop_w, crop_h) = if w / h > ratio {
+        (h * ratio, h)
+    } else {
+        (w, w / ratio)
+    };
+    Crop {
+        x: (w - crop_w) / 2.0,
+        y: (h - crop_h) / 2.0,
+        width: crop_w,
+        height: crop_h,
+    }
+}
+
+/// Longest edge `auto_straighten` analyses at; larger images are downscaled.
+#[cfg(feature = "image-decoding")]
+const STRAIGHTEN_ANALYSIS_EDGE: u32 = 512;
+/// Steepest line `auto_straighten` considers a horizon, in degrees.
+#[cfg(feature = "image-decoding")]
+const STRAIGHTEN_MAX_ANGLE: f32 = 20.0;
+/// Angular resolution of the Hough search, in degrees.
+#[cfg(feature = "image-decoding")]
+const STRAIGHTEN_ANGLE_STEP: f32 = 0.1;
+
+/// Suggested `transformRotate` (degrees, clockwise positive) that levels the
+/// dominant near-horizontal line, found with a Hough transform over luma
+/// edges. Returns 0.0 when no line within ±20° stands out, so the result can be
+/// applied unconditionally.
+#[cfg(feature = "image-decoding")]
+pub fn auto_straighten(image: &image::DynamicImage) -> f32 {
+    use rapidraw_core::luma::{luma, LumaCoefficients};
+
+    let (w, h) = (image.width(), image.height());
+    if w < 8 || h < 8 {
+        return 0.0;
+    }
+    let scale = (STRAIGHTEN_ANALYSIS_EDGE as f32 / w.max(h) as f32).min(1.0);
+    let (aw, ah) = (
+        ((w as f32 * scale).round() as u32).max(8),
+        ((h as f32 * scale).round() as u32).max(8),
+    );
+    let small = image
+        .resize_exact(aw, ah, image::imageops::FilterType::Triangle)
+        .into_rgb32f();
+    let (aw, ah) = (aw as usize, ah as usize);
+    let plane: Vec<f32> = small
+        .pixels()
+        .map(|p| luma(p[0], p[1], p[2], LumaCoefficients::Rec709))
+        .collect();
+
+    // Sobel gradients; only edges whose gradient points mostly up or down
+    // belong to near-horizontal lines.
+    let mut edges = Vec::new();
+    let mut max_magnitude = 0.0f32;
+    for y in 1..ah - 1 {
+        for x in 1..aw - 1 {
+            let at = |dx: isize, dy: isize| {
+                plane[(y as isize + dy) as usize * aw + (x as isize + dx) as usize]
+            };
+            let gx =
+                at(1, -1) + 2.0 * at(1, 0) + at(1, 1) - at(-1, -1) - 2.0 * at(-1, 0) - at(-1, 1);
+            let gy =
+                at(-1, 1) + 2.0 * at(0, 1) + at(1, 1) - at(-1, -1) - 2.0 * at(0, -1) - at(1, -1);
+            if gy.abs() < gx.abs() * 2.0 {
+                continue;
+            }
+            let magnitude = (gx * gx + gy * gy).sqrt();
+            max_magnitude = max_magnitude.max(magnitude);
+            edges.push((x as f32, y as f32, magnitude));
+        }
+    }
+    if max_magnitude <= 0.0 {
+        return 0.0;
+    }
+    let threshold = max_magnitude * 0.2;
+    edges.retain(|e| e.2 >= threshold);
+
+    let angle_bins = (2.0 * STRAIGHTEN_MAX_ANGLE / STRAIGHTEN_ANGLE_STEP).round() as usize + 1;
+    let diagonal = ((aw * aw + ah * ah) as f32).sqrt().ceil() as usize;
+    let rho_bins = 2 * diagonal + 1;
+    let mut accumulator = vec![0.0f32; angle_bins * rho_bins];
+    let trig: Vec<(f32, f32)> = (0..angle_bins)
+        .map(|i| {
+            (-STRAIGHTEN_MAX_ANGLE + i as f32 * STRAIGHTEN_ANGLE_STEP)
+                .to_radians()
+                .sin_cos()
+        })
+        .collect();
+    for &(x, y, magnitude) in &edges {
+        for (i, (sin, cos)) in trig.iter().enumerate() {
+            let rho = -x * sin + y * cos;
+            let bin = (rho.round() as isize + diagonal as isize) as usize;
+            accumulator[i * rho_bins + bin] += magnitude;
+        }
+    }
+
+    let Some((peak, votes)) = accumulator
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+    else {
+        return 0.0;
+    };
+    // A line has to span about a quarter of the frame to count as a horizon.
+    if *votes < threshold * aw as f32 * 0.25 {
+        return 0.0;
+    }
+    let line_angle = -STRAIGHTEN_MAX_ANGLE + (peak / rho_bins) as f32 * STRAIGHTEN_ANGLE_STEP;
+    // Image y points down, so a line rising to the right has a negative angle
+    // and needs a clockwise (positive) rotation.
+    -line_angle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flips_are_read_from_the_adjustments() {
+        let params = get_geometry_params_from_json(&serde_json::json!({
+            "flipHorizontal": true,
+            "transformRotate": 2.5,
+        }));
+        assert!(params.flip_horizontal && !params.flip_vertical);
+        assert_eq!(params.rotate, 2.5);
+
+        let defaults = get_geometry_params_from_json(&Value::Null);
+        assert!(!defaults.flip_horizontal && !defaults.flip_vertical);
+        assert_eq!(defaults.scale, 100.0);
+    }
+
+    #[cfg(feature = "image-decoding")]
+    #[test]
+    fn auto_straighten_levels_a_tilted_horizon() {
+        // Dark sky over bright ground, the horizon falling 5° to the right.
+        let tilt = 5.0f32.to_radians().tan();
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(240, 160, |x, y| {
+            if (y as f32) < 80.0 + (x as f32 - 120.0) * tilt {
+                image::Rgb([40, 60, 90])
+            } else {
+                image::Rgb([200, 190, 150])
+            }
+        }));
+        let rotate = auto_straighten(&image);
+        assert!((rotate + 5.0).abs() <= 0.3, "{rotate}");
+
+        let flat = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            240,
+            160,
+            image::Rgb([128, 128, 128]),
+        ));
+        assert_eq!(auto_straighten(&flat), 0.0);
+    }
+
+    fn crop_box(crop: Crop) -> [f64; 4] {
+        [crop.x, crop.y, crop.width, crop.height].map(|v| (v * 100.0).round() / 100.0)
+    }
+
+    #[test]
+    fn aspect_presets_follow_the_image_orientation() {
+        let auto = PresetOrientation::Auto;
+        assert_eq!(
+            crop_box(aspect_crop(6000, 4000, AspectPreset::ThreeTwo, auto)),
+            [0.0, 0.0, 6000.0, 4000.0]
+        );
+        assert_eq!(
+            crop_box(aspect_crop(6000, 4000, AspectPreset::SixteenNine, auto)),
+            [0.0, 312.5, 6000.0, 3375.0]
+        );
+        // 4:5 is written portrait, so a landscape image gets 5:4.
+        assert_eq!(
+            crop_box(aspect_crop(6000, 4000, AspectPreset::FourFive, auto)),
+            [500.0, 0.0, 5000.0, 4000.0]
+        );
+        assert_eq!(
+            crop_box(aspect_crop(4000, 6000, AspectPreset::ThreeTwo, auto)),
+            [0.0, 0.0, 4000.0, 6000.0]
+        );
+        assert_eq!(
+            crop_box(aspect_crop(1000, 1000, AspectPreset::FourFive, auto)),
+            [100.0, 0.0, 800.0, 1000.0]
+        );
+    }
+
+    #[test]
+    fn aspect_presets_can_be_forced_either_way() {
+        assert_eq!(
+            crop_box(aspect_crop(
+                6000,
+                4000,
+                AspectPreset::ThreeTwo,
+                PresetOrientation::Portrait
+            )),
+            [1666.67, 0.0, 2666.67, 4000.0]
+        );
+        assert_eq!(
+            crop_box(aspect_crop(
+                1000,
+                1000,
+                AspectPreset::FourFive,
+                PresetOrientation::Landscape
+            )),
+            [0.0, 100.0, 1000.0, 800.0]
+        );
+        assert_eq!(
+            crop_box(aspect_crop(
+                300,
+                200,
+                AspectPreset::Original,
+                PresetOrientation::Portrait
+            )),
+            [0.0, 0.0, 300.0, 200.0]
+        );
+        assert_eq!(
+            serde_json::from_str::<AspectPreset>("\"16:9\"").unwrap(),
+            AspectPreset::SixteenNine
+        );
     }
 }
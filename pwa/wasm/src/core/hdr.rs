@@ -0,0 +1,558 @@
+//! Merging of bracketed exposures into one scene-linear float image.
+//!
+//! Frames are decoded into the pipeline space, optionally aligned by whole
+//! pixel translation, scaled to a common exposure using the EXIF shutter,
+//! aperture and ISO, and averaged with weights that favour well exposed,
+//! unclipped samples. The result keeps values above 1.0; `tone_map` brings
+//! it back into display range.
+
+use crate::core::image_loader::load_frame_from_bytes;
+use anyhow::{anyhow, bail, Context, Result};
+use exif::{In, Reader as ExifReader, Tag, Value};
+use image::{DynamicImage, Rgb32FImage};
+use rapidraw_core::luma::{luma, LumaCoefficients};
+use rapidraw_core::srgb::linear_to_srgb;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+/// Linear value treated as clipped; such samples only count when every frame
+/// is clipped.
+const CLIP_LEVEL: f32 = 0.98;
+/// Largest translation searched by `align_translation`, in pixels.
+const MAX_ALIGN_SHIFT: i32 = 64;
+/// Samples this close to the median are left out of the alignment bitmaps,
+/// where noise would flip them from frame to frame.
+const MTB_EXCLUSION: f32 = 4.0 / 255.0;
+
+/// Mid-grey that the contrast knob pivots around, in linear light.
+const TONE_MAP_PIVOT: f32 = 0.18;
+/// Linear white point of the filmic curve.
+const FILMIC_WHITE: f32 = 11.2;
+
+/// Capture settings that set how much light reached the sensor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExposureSettings {
+    pub exposure_time: f32,
+    pub f_number: f32,
+    pub iso: f32,
+}
+
+impl ExposureSettings {
+    /// Light gathered relative to 1 s at f/1 and ISO 100.
+    pub fn relative_exposure(&self) -> f32 {
+        self.exposure_time * (self.iso / 100.0) / (self.f_number * self.f_number)
+    }
+}
+
+/// Reads exposure time, f-number and ISO from EXIF. Missing aperture or ISO
+/// fall back to f/1 and ISO 100, which is right for brackets that only vary
+/// the shutter.
+pub fn read_exposure_settings(bytes: &[u8]) -> Option<ExposureSettings> {
+    let exif = ExifReader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()?;
+    let number = |tag: Tag| -> Option<f32> {
+        let field = exif.get_field(tag, In::PRIMARY)?;
+        let value = match &field.value {
+            Value::Rational(v) => v.first().map(|r| r.to_f64())?,
+            Value::SRational(v) => v.first().map(|r| r.to_f64())?,
+            _ => field.value.get_uint(0)? as f64,
+        };
+        (value.is_finite() && value > 0.0).then_some(value as f32)
+    };
+
+    Some(ExposureSettings {
+        exposure_time: number(Tag::ExposureTime)?,
+        f_number: number(Tag::FNumber).unwrap_or(1.0),
+        iso: number(Tag::PhotographicSensitivity).unwrap_or(100.0),
+    })
+}
+
+/// One decoded bracket frame.
+pub struct HdrFrame {
+    pub image: Rgb32FImage,
+    /// Relative exposure; only ratios between frames matter.
+    pub exposure: f32,
+}
+
+/// Decodes `images` (matched by index with `paths`) and merges them. Output
+/// is scaled to the exposure of the first frame.
+pub fn merge_hdr(images: Vec<&[u8]>, paths: Vec<&str>, align: bool) -> Result<DynamicImage> {
+    if images.len() != paths.len() {
+        bail!(
+            "Got {} images but {} paths for the HDR merge",
+            images.len(),
+            paths.len()
+        );
+    }
+    if images.len() < 2 {
+        bail!("HDR merge needs at least two exposures");
+    }
+
+    let decoded = images
+        .iter()
+        .zip(&paths)
+        .map(|(bytes, path)| {
+            // The merge recovers highlights from the shorter frames, so RAW
+            // highlights are left uncompressed.
+            load_frame_from_bytes(bytes, path, f32::MAX)
+                .map(|image| (image.into_rgb32f(), read_exposure_settings(bytes)))
+                .with_context(|| format!("Failed to decode bracket frame '{path}'"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (width, height) = decoded[0].0.dimensions();
+    if let Some((image, _)) = decoded
+        .iter()
+        .find(|(i, _)| i.dimensions() != (width, height))
+    {
+        bail!(
+            "Bracket frames differ in size ({}x{} vs {}x{})",
+            image.width(),
+            image.height(),
+            width,
+            height
+        );
+    }
+
+    // Without complete EXIF the ratios are measured from the pixels instead.
+    let from_exif: Option<Vec<f32>> = decoded
+        .iter()
+        .map(|(_, settings)| settings.map(|s| s.relative_exposure()))
+        .collect();
+    let exposures = match from_exif {
+        Some(exposures) => exposures,
+        None => estimate_exposures(&decoded.iter().map(|(i, _)| i).collect::<Vec<_>>()),
+    };
+
+    let mut frames: Vec<HdrFrame> = decoded
+        .into_iter()
+        .zip(exposures)
+        .map(|((image, _), exposure)| HdrFrame { image, exposure })
+        .collect();
+
+    if align {
+        let reference = luma_plane(&frames[0].image);
+        for frame in frames.iter_mut().skip(1) {
+            let (dx, dy) = align_translation(&reference, &luma_plane(&frame.image), width, height);
+            if (dx, dy) != (0, 0) {
+                frame.image = translate(&frame.image, dx, dy);
+            }
+        }
+    }
+
+    merge_exposures(&frames)
+}
+
+/// Weight of a linear sample: a bell around mid-grey in the encoded domain,
+/// zero once clipped.
+fn exposure_weight(value: f32) -> f32 {
+    if value >= CLIP_LEVEL {
+        return 0.0;
+    }
+    let encoded = linear_to_srgb(value.max(0.0)) - 0.5;
+    (-12.5 * encoded * encoded).exp()
+}
+
+/// Weighted average of the exposure-normalised frames, scaled to the first
+/// frame's exposure. Pixels clipped everywhere come from the shortest frame.
+pub fn merge_exposures(frames: &[HdrFrame]) -> Result<DynamicImage> {
+    let first = frames
+        .first()
+        .ok_or_else(|| anyhow!("No frames to merge"))?;
+    let (width, height) = first.image.dimensions();
+    if frames
+        .iter()
+        .any(|f| !(f.exposure.is_finite() && f.exposure > 0.0))
+    {
+        bail!("Every frame needs a positive exposure");
+    }
+
+    let scales: Vec<f32> = frames.iter().map(|f| first.exposure / f.exposure).collect();
+    let shortest = frames
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.exposure.total_cmp(&b.1.exposure))
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+
+    let mut merged = Rgb32FImage::new(width, height);
+    for (i, out) in merged.as_mut().chunks_exact_mut(3).enumerate() {
+        let mut sum = [0.0f32; 3];
+        let mut w_sum = 0.0;
+        for (frame, scale) in frames.iter().zip(&scales) {
+            let p = &frame.image.as_raw()[i * 3..i * 3 + 3];
+            let weight = exposure_weight(p[0].max(p[1]).max(p[2]));
+            if weight > 0.0 {
+                sum.iter_mut()
+                    .zip(p)
+                    .for_each(|(acc, v)| *acc += v * scale * weight);
+                w_sum += weight;
+            }
+        }
+
+        if w_sum > 1e-6 {
+            out.iter_mut().zip(&sum).for_each(|(o, s)| *o = s / w_sum);
+        } else {
+            let p = &frames[shortest].image.as_raw()[i * 3..i * 3 + 3];
+            out.iter_mut()
+                .zip(p)
+                .for_each(|(o, v)| *o = v * scales[shortest]);
+        }
+    }
+
+    Ok(DynamicImage::ImageRgb32F(merged))
+}
+
+/// Relative exposures from the pixels: the median ratio against the first
+/// frame over samples unclipped and above the noise floor in both.
+fn estimate_exposures(images: &[&Rgb32FImage]) -> Vec<f32> {
+    let reference = luma_plane(images[0]);
+    images
+        .iter()
+        .map(|image| {
+            let plane = luma_plane(image);
+            let mut ratios: Vec<f32> = reference
+                .iter()
+                .zip(&plane)
+                .filter(|&(&r, &v)| r > 0.02 && v > 0.02 && r < CLIP_LEVEL && v < CLIP_LEVEL)
+                .map(|(r, v)| v / r)
+                .collect();
+            if ratios.is_empty() {
+                return 1.0;
+            }
+            let mid = ratios.len() / 2;
+            *ratios.select_nth_unstable_by(mid, f32::total_cmp).1
+        })
+        .collect()
+}
+
+fn luma_plane(image: &Rgb32FImage) -> Vec<f32> {
+    image
+        .pixels()
+        .map(|p| luma(p[0], p[1], p[2], LumaCoefficients::Rec709))
+        .collect()
+}
+
+/// Whole-pixel offset that best lines `moving` up with `reference`, found by
+/// comparing median threshold bitmaps over an image pyramid. The bitmaps do
+/// not depend on exposure, so frames of different brightness compare directly.
+pub fn align_translation(reference: &[f32], moving: &[f32], width: u32, height: u32) -> (i32, i32) {
+    let mut levels = vec![(
+        reference.to_vec(),
+        moving.to_vec(),
+        width as usize,
+        height as usize,
+    )];
+    while levels.len() < 6 {
+        let (r, m, w, h) = levels.last().unwrap();
+        if (*w).min(*h) < 64 || 1 << levels.len() > MAX_ALIGN_SHIFT {
+            break;
+        }
+        let (nw, nh) = (w / 2, h / 2);
+        levels.push((halve(r, *w, nw, nh), halve(m, *w, nw, nh), nw, nh));
+    }
+
+    let (mut dx, mut dy) = (0i32, 0i32);
+    for (r, m, w, h) in levels.iter().rev() {
+        dx *= 2;
+        dy *= 2;
+        let (r_bits, r_mask) = threshold_bitmap(r);
+        let (m_bits, m_mask) = threshold_bitmap(m);
+
+        let mut best = (f64::MAX, dx, dy);
+        for sy in -1..=1 {
+            for sx in -1..=1 {
+                let (cx, cy) = (dx + sx, dy + sy);
+                let cost = bitmap_difference(&r_bits, &r_mask, &m_bits, &m_mask, *w, *h, cx, cy);
+                if cost < best.0 {
+                    best = (cost, cx, cy);
+                }
+            }
+        }
+        (dx, dy) = (best.1, best.2);
+    }
+    (dx, dy)
+}
+
+fn halve(plane: &[f32], width: usize, nw: usize, nh: usize) -> Vec<f32> {
+    let mut out = Vec::with_capacity(nw * nh);
+    for y in 0..nh {
+        for x in 0..nw {
+            let i = 2 * y * width + 2 * x;
+            out.push((plane[i] + plane[i + 1] + plane[i + width] + plane[i + width + 1]) * 0.25);
+        }
+    }
+    out
+}
+
+/// Above-median bits plus a mask of samples far enough from the median to
+/// trust, both in the encoded domain.
+fn threshold_bitmap(plane: &[f32]) -> (Vec<bool>, Vec<bool>) {
+    let encoded: Vec<f32> = plane.iter().map(|v| linear_to_srgb(v.max(0.0))).collect();
+    let mut sorted = encoded.clone();
+    let mid = sorted.len() / 2;
+    let median = *sorted.select_nth_unstable_by(mid, f32::total_cmp).1;
+    let bits = encoded.iter().map(|&v| v > median).collect();
+    let mask = encoded
+        .iter()
+        .map(|&v| (v - median).abs() > MTB_EXCLUSION)
+        .collect();
+    (bits, mask)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn bitmap_difference(
+    r_bits: &[bool],
+    r_mask: &[bool],
+    m_bits: &[bool],
+    m_mask: &[bool],
+    width: usize,
+    height: usize,
+    dx: i32,
+    dy: i32,
+) -> f64 {
+    let (mut mismatched, mut compared) = (0u64, 0u64);
+    for y in 0..height {
+        let sy = y as i32 + dy;
+        if sy < 0 || sy >= height as i32 {
+            continue;
+        }
+        for x in 0..width {
+            let sx = x as i32 + dx;
+            if sx < 0 || sx >= width as i32 {
+                continue;
+            }
+            let r = y * width + x;
+            let m = sy as usize * width + sx as usize;
+            if r_mask[r] && m_mask[m] {
+                compared += 1;
+                mismatched += (r_bits[r] != m_bits[m]) as u64;
+            }
+        }
+    }
+    // A fraction, so larger shifts do not win just by overlapping less.
+    if compared == 0 {
+        1.0
+    } else {
+        mismatched as f64 / compared as f64
+    }
+}
+
+/// Samples `image` at `(x + dx, y + dy)`, repeating edge pixels.
+fn translate(image: &Rgb32FImage, dx: i32, dy: i32) -> Rgb32FImage {
+    let (width, height) = image.dimensions();
+    Rgb32FImage::from_fn(width, height, |x, y| {
+        let sx = (x as i32 + dx).clamp(0, width as i32 - 1) as u32;
+        let sy = (y as i32 + dy).clamp(0, height as i32 - 1) as u32;
+        *image.get_pixel(sx, sy)
+    })
+}
+
+/// Curve used by `tone_map` to squeeze scene-linear values into 0..1.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ToneMapOperator {
+    /// `x / (1 + x)`: gentle, never fully reaches white.
+    #[default]
+    Reinhard,
+    /// Narkowicz's fit of the ACES reference rendering transform.
+    Aces,
+    /// Hable's filmic curve with a toe, shoulder and white point.
+    Filmic,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ToneMapSettings {
+    pub operator: ToneMapOperator,
+    /// Stops applied before the curve.
+    pub exposure: f32,
+    /// -1..1; steepens (positive) or flattens the log-domain slope around
+    /// mid-grey before the curve.
+    pub contrast: f32,
+}
+
+pub fn parse_tone_map_settings(json: &str) -> ToneMapSettings {
+    serde_json::from_str::<ToneMapSettings>(json).unwrap_or_default()
+}
+
+fn aces(x: f32) -> f32 {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+fn hable(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+impl ToneMapOperator {
+    pub fn apply(self, x: f32) -> f32 {
+        let x = x.max(0.0);
+        let mapped = match self {
+            ToneMapOperator::Reinhard => x / (1.0 + x),
+            ToneMapOperator::Aces => aces(x),
+            ToneMapOperator::Filmic => hable(2.0 * x) / hable(FILMIC_WHITE),
+        };
+        mapped.clamp(0.0, 1.0)
+    }
+}
+
+/// Maps a merged scene-linear image into 0..1 linear display range, channel
+/// by channel. Alpha is carried over untouched.
+pub fn tone_map(image: &DynamicImage, settings: &ToneMapSettings) -> DynamicImage {
+    let gain = 2f32.powf(settings.exposure);
+    let slope = 1.0 + settings.contrast.clamp(-1.0, 1.0);
+    let map = |v: f32| {
+        let v = (v * gain).max(0.0);
+        let v = if slope != 1.0 && v > 0.0 {
+            TONE_MAP_PIVOT * (v / TONE_MAP_PIVOT).powf(slope)
+        } else {
+            v
+        };
+        settings.operator.apply(v)
+    };
+
+    if image.color().has_alpha() {
+        let mut buffer = image.to_rgba32f();
+        buffer.pixels_mut().for_each(|p| {
+            p.0[..3].iter_mut().for_each(|v| *v = map(*v));
+        });
+        DynamicImage::ImageRgba32F(buffer)
+    } else {
+        let mut buffer = image.to_rgb32f();
+        buffer.iter_mut().for_each(|v| *v = map(*v));
+        DynamicImage::ImageRgb32F(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame of `scene` values as a sensor with `exposure` records them.
+    fn bracket(scene: &[f32], exposure: f32) -> HdrFrame {
+        let data = scene
+            .iter()
+            .flat_map(|&v| [(v * exposure).min(1.0); 3])
+            .collect();
+        HdrFrame {
+            image: Rgb32FImage::from_raw(scene.len() as u32, 1, data).unwrap(),
+            exposure,
+        }
+    }
+
+    #[test]
+    fn relative_exposure_combines_shutter_aperture_and_iso() {
+        let settings = ExposureSettings {
+            exposure_time: 0.01,
+            f_number: 2.0,
+            iso: 400.0,
+        };
+        assert!((settings.relative_exposure() - 0.01).abs() < 1e-7);
+    }
+
+    #[test]
+    fn merge_recovers_values_clipped_in_the_long_frame() {
+        let scene = [0.05, 0.1, 0.5, 0.9];
+        let merged = merge_exposures(&[bracket(&scene, 1.0), bracket(&scene, 4.0)]).unwrap();
+        let merged = merged.as_rgb32f().unwrap();
+        for (x, &expected) in scene.iter().enumerate() {
+            let value = merged.get_pixel(x as u32, 0)[0];
+            assert!((value - expected).abs() < 1e-5, "{value} vs {expected}");
+        }
+    }
+
+    #[test]
+    fn merge_rejects_frames_without_an_exposure() {
+        assert!(merge_exposures(&[]).is_err());
+        assert!(merge_exposures(&[bracket(&[0.5], 1.0), bracket(&[0.5], 0.0)]).is_err());
+    }
+
+    #[test]
+    fn alignment_undoes_a_translation() {
+        let (width, height) = (128u32, 96u32);
+        let texture = |x: i32, y: i32| {
+            let (x, y) = (x.clamp(0, width as i32 - 1), y.clamp(0, height as i32 - 1));
+            // Blocks of pseudo-random brightness, so only the true offset
+            // lines up.
+            let cell = (x / 4 * 7919 + y / 4 * 104_729) as u32;
+            0.05 + 0.9 * (cell.wrapping_mul(2_654_435_761) >> 8) as f32 / (1 << 24) as f32
+        };
+        let plane = |shift_x: i32, shift_y: i32| -> Vec<f32> {
+            (0..height as i32)
+                .flat_map(|y| (0..width as i32).map(move |x| texture(x - shift_x, y - shift_y)))
+                .collect()
+        };
+        let reference = plane(0, 0);
+        let moving = plane(3, -2);
+        assert_eq!(
+            align_translation(&reference, &moving, width, height),
+            (3, -2)
+        );
+    }
+
+    #[test]
+    fn operators_map_black_to_black_and_stay_monotonic() {
+        for operator in [
+            ToneMapOperator::Reinhard,
+            ToneMapOperator::Aces,
+            ToneMapOperator::Filmic,
+        ] {
+            assert!(operator.apply(0.0).abs() < 1e-3, "{operator:?}");
+            let mut previous = 0.0;
+            for step in 1..=200 {
+                let value = operator.apply(step as f32 * 0.1);
+                assert!(value >= previous && value <= 1.0, "{operator:?} at {step}");
+                previous = value;
+            }
+        }
+        assert!((ToneMapOperator::Reinhard.apply(1.0) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn tone_map_settings_parse_with_defaults() {
+        let settings = parse_tone_map_settings(r#"{"operator": "filmic", "exposure": 1.5}"#);
+        assert_eq!(settings.operator, ToneMapOperator::Filmic);
+        assert_eq!((settings.exposure, settings.contrast), (1.5, 0.0));
+        assert_eq!(
+            parse_tone_map_settings("not json"),
+            ToneMapSettings::default()
+        );
+    }
+
+    #[test]
+    fn tone_map_keeps_alpha_and_mid_grey_pivot() {
+        let image = DynamicImage::ImageRgba32F(image::Rgba32FImage::from_pixel(
+            1,
+            1,
+            image::Rgba([TONE_MAP_PIVOT, 4.0, 0.0, 0.4]),
+        ));
+        let flat = ToneMapSettings {
+            contrast: -0.5,
+            ..Default::default()
+        };
+        let steep = ToneMapSettings {
+            contrast: 0.5,
+            ..Default::default()
+        };
+        let [grey_a, bright_a, _, alpha] = tone_map(&image, &flat)
+            .as_rgba32f()
+            .unwrap()
+            .get_pixel(0, 0)
+            .0;
+        let [grey_b, bright_b, ..] = tone_map(&image, &steep)
+            .as_rgba32f()
+            .unwrap()
+            .get_pixel(0, 0)
+            .0;
+        assert_eq!(alpha, 0.4);
+        assert!((grey_a - grey_b).abs() < 1e-5);
+        assert!(bright_b > bright_a);
+    }
+}
@@ -1,10 +1,184 @@
-use anyhow::{anyhow, Context, Result};
+use crate::core::error::ProcessingError;
+use crate::core::formats::{decode_route, detect_format, DecodeRoute, DetectedFormat};
+use crate::core::working_space::{self, WorkingSpace, PIPELINE_SPACE};
+use anyhow::{Context, Result};
 use exif::{Reader as ExifReader, Tag};
 use exr::image::pixel_vec::PixelVec;
 use exr::prelude::*;
-use image::{DynamicImage, ImageReader};
-use qoi::Channels;
+use image::{DynamicImage, ImageReader, RgbImage};
+use qoi::{Channels, ColorSpace};
 use std::io::Cursor;
+use std::sync::RwLock;
+use zune_jpeg::zune_core::bytestream::ZCursor;
+use zune_jpeg::zune_core::colorspace::ColorSpace as JpegColorSpace;
+use zune_jpeg::zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+/// Decode budget. The defaults keep a decoded image (plus its f32 working
+/// copy) within what a phone browser tab can allocate; trusted desktop hosts
+/// can raise them with `set_image_limits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageLimits {
+    pub max_pixels: u64,
+    pub max_alloc_bytes: u64,
+}
+
+impl ImageLimits {
+    pub const MOBILE: ImageLimits = ImageLimits {
+        max_pixels: 64_000_000,
+        max_alloc_bytes: 512 * 1024 * 1024,
+    };
+
+    fn check_dimensions(&self, width: u32, height: u32) -> Result<()> {
+        let pixels = width as u64 * height as u64;
+        if pixels > self.max_pixels {
+            return Err(ProcessingError::too_large(format!(
+                "{width}x{height} ({pixels} pixels) exceeds the decode limit of {} pixels",
+                self.max_pixels
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    fn to_image_limits(self) -> image::Limits {
+        let mut limits = image::Limits::default();
+        limits.max_alloc = Some(self.max_alloc_bytes);
+        limits
+    }
+}
+
+impl Default for ImageLimits {
+    fn default() -> Self {
+        Self::MOBILE
+    }
+}
+
+static IMAGE_LIMITS: RwLock<ImageLimits> = RwLock::new(ImageLimits::MOBILE);
+
+pub fn image_limits() -> ImageLimits {
+    IMAGE_LIMITS.read().map(|l| *l).unwrap_or_default()
+}
+
+pub fn set_image_limits(limits: ImageLimits) {
+    if let Ok(mut current) = IMAGE_LIMITS.write() {
+        *current = limits;
+    }
+}
+
+/// How a four-component JPEG stores its inks, read from the SOF and Adobe
+/// APP14 segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CmykJpegLayout {
+    /// Adobe transform 2: C, M and Y are carried as YCbCr.
+    ycck: bool,
+    /// Adobe writers store `255 - ink`; other writers store the ink itself.
+    inverted: bool,
+}
+
+/// Walks the JPEG header up to the first scan and reports the ink layout if
+/// the frame has four components (CMYK or YCCK).
+fn detect_cmyk_jpeg(bytes: &[u8]) -> Option<CmykJpegLayout> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+
+    let mut pos = 2;
+    let mut components = None;
+    let mut adobe_transform = None;
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xFF {
+            return None;
+        }
+        let marker = bytes[pos + 1];
+        match marker {
+            // Fill byte before a marker.
+            0xFF => {
+                pos += 1;
+                continue;
+            }
+            // Markers without a length field.
+            0x01 | 0xD0..=0xD7 => {
+                pos += 2;
+                continue;
+            }
+            // Start of scan or end of image: the header is over.
+            0xDA | 0xD9 => break,
+            _ => {}
+        }
+
+        let len = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let data = bytes.get(pos + 4..pos + 2 + len)?;
+        match marker {
+            0xEE if data.len() >= 12 && data.starts_with(b"Adobe") => {
+                adobe_transform = Some(data[11]);
+            }
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                components = data.get(5).copied();
+            }
+            _ => {}
+        }
+        pos += 2 + len;
+    }
+
+    (components == Some(4)).then(|| CmykJpegLayout {
+        ycck: adobe_transform == Some(2),
+        inverted: adobe_transform.is_some(),
+    })
+}
+
+/// Decodes the raw ink channels and converts them to RGB here rather than
+/// trusting the decoder's conversion, which assumes Adobe-inverted inks and
+/// turns plain CMYK files into negatives. No ICC profile is applied; the
+/// result is a naive conversion treated as sRGB.
+fn decode_cmyk_jpeg(bytes: &[u8], layout: CmykJpegLayout) -> Result<DynamicImage> {
+    let colorspace = if layout.ycck {
+        JpegColorSpace::YCCK
+    } else {
+        JpegColorSpace::CMYK
+    };
+    let options = DecoderOptions::default().jpeg_set_out_colorspace(colorspace);
+    let mut decoder = JpegDecoder::new_with_options(ZCursor::new(bytes), options);
+    let samples = decoder
+        .decode()
+        .map_err(|e| anyhow::anyhow!("Failed to decode CMYK JPEG: {e}"))?;
+    let (width, height) = decoder
+        .dimensions()
+        .context("CMYK JPEG has no frame header")?;
+
+    let rgb = samples
+        .chunks_exact(4)
+        .flat_map(|p| cmyk_to_rgb(p, layout))
+        .collect();
+    RgbImage::from_raw(width as u32, height as u32, rgb)
+        .map(DynamicImage::ImageRgb8)
+        .context("CMYK JPEG sample count does not match its dimensions")
+}
+
+fn cmyk_to_rgb(pixel: &[u8], layout: CmykJpegLayout) -> [u8; 3] {
+    let [c, m, y] = if layout.ycck {
+        // libjpeg's YCCK -> CMYK: decode YCbCr as if it were RGB, then invert.
+        let (luma, cb, cr) = (
+            pixel[0] as f32,
+            pixel[1] as f32 - 128.0,
+            pixel[2] as f32 - 128.0,
+        );
+        [
+            luma + 1.402 * cr,
+            luma - 0.344136 * cb - 0.714136 * cr,
+            luma + 1.772 * cb,
+        ]
+        .map(|v| 255 - v.round().clamp(0.0, 255.0) as u8)
+    } else {
+        [pixel[0], pixel[1], pixel[2]]
+    };
+    let k = pixel[3];
+
+    // Stored values as "paper left over" in 0..=255 before multiplying.
+    let paper = |ink: u8| if layout.inverted { ink } else { 255 - ink } as u32;
+    let key = paper(k);
+    [c, m, y].map(|ink| ((paper(ink) * key + 127) / 255) as u8)
+}
 
 fn apply_exif_orientation(image: DynamicImage, orientation: u16) -> DynamicImage {
     match orientation {
@@ -19,7 +193,13 @@ fn apply_exif_orientation(image: DynamicImage, orientation: u16) -> DynamicImage
     }
 }
 
-fn load_exr_from_bytes(bytes: &[u8]) -> Result<DynamicImage> {
+fn load_exr_from_bytes(bytes: &[u8], limits: &ImageLimits) -> Result<DynamicImage> {
+    let meta = MetaData::read_from_buffered(Cursor::new(bytes), false)
+        .context("Failed to read EXR header")?;
+    for header in meta.headers.iter() {
+        limits.check_dimensions(header.layer_size.x() as u32, header.layer_size.y() as u32)?;
+    }
+
     let cursor = Cursor::new(bytes);
     let buffered_reader = std::io::BufReader::new(cursor);
 
@@ -53,67 +233,263 @@ fn load_exr_from_bytes(bytes: &[u8]) -> Result<DynamicImage> {
     Ok(DynamicImage::ImageRgb32F(rgb_image))
 }
 
-fn load_qoi_from_bytes(bytes: &[u8]) -> Result<DynamicImage> {
+fn load_qoi_from_bytes(bytes: &[u8], limits: &ImageLimits) -> Result<DynamicImage> {
+    let header = qoi::decode_header(bytes).context("Failed to read QOI header")?;
+    limits.check_dimensions(header.width, header.height)?;
     let (qoi_header, qoi_image) =
         qoi::decode_to_vec(bytes).context("Failed to decode QOI image")?;
 
-    match qoi_header.channels {
+    let image = match qoi_header.channels {
         Channels::Rgb => {
             let img_buffer =
                 image::RgbImage::from_raw(qoi_header.width, qoi_header.height, qoi_image)
                     .context("Failed to create RGB image from QOI data")?;
-            Ok(DynamicImage::ImageRgb8(img_buffer))
+            DynamicImage::ImageRgb8(img_buffer)
         }
         Channels::Rgba => {
             let img_buffer =
                 image::RgbaImage::from_raw(qoi_header.width, qoi_header.height, qoi_image)
                     .context("Failed to create RGBA image from QOI data")?;
-            Ok(DynamicImage::ImageRgba8(img_buffer))
+            DynamicImage::ImageRgba8(img_buffer)
         }
-    }
+    };
+
+    let encoded_as = match qoi_header.colorspace {
+        ColorSpace::Linear => WorkingSpace::LinearRec709,
+        ColorSpace::Srgb => WorkingSpace::Srgb,
+    };
+    Ok(working_space::convert(image, encoded_as, PIPELINE_SPACE))
+}
+
+/// The EXIF orientation value (1-8) a non-RAW file records, if any.
+pub fn read_exif_orientation(bytes: &[u8]) -> Option<u16> {
+    let exif = ExifReader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()?;
+    exif.get_field(Tag::Orientation, exif::In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+        .map(|orientation| orientation as u16)
 }
 
-pub fn load_image_with_orientation(bytes: &[u8]) -> Result<DynamicImage> {
+/// Decodes `bytes`, baking in the EXIF orientation when `auto_orient` is set.
+/// Otherwise the pixels are returned as stored, for files that were already
+/// rotated upright; `read_exif_orientation` still reports the tag.
+pub fn load_image_with_orientation(
+    bytes: &[u8],
+    limits: &ImageLimits,
+    auto_orient: bool,
+) -> Result<DynamicImage> {
     let cursor = Cursor::new(bytes);
     let mut reader = ImageReader::new(cursor.clone())
         .with_guessed_format()
         .context("Failed to guess image format")?;
 
-    reader.no_limits();
+    // Check the header-declared size first so a tiny file claiming huge
+    // dimensions is rejected before any allocation.
+    let (width, height) = ImageReader::new(cursor.clone())
+        .with_guessed_format()
+        .context("Failed to guess image format")?
+        .into_dimensions()
+        .context("Failed to read image dimensions")?;
+    limits.check_dimensions(width, height)?;
+    reader.limits(limits.to_image_limits());
 
-    let image = reader.decode().context("Failed to decode image")?;
+    let image = match detect_cmyk_jpeg(bytes) {
+        Some(layout) => decode_cmyk_jpeg(bytes, layout)?,
+        None => reader.decode().context("Failed to decode image")?,
+    };
 
-    let oriented_image = {
-        let exif_reader = ExifReader::new();
-        if let Ok(exif) = exif_reader.read_from_container(&mut cursor.clone()) {
-            if let Some(orientation) = exif
-                .get_field(Tag::Orientation, exif::In::PRIMARY)
-                .and_then(|f| f.value.get_uint(0))
-            {
-                apply_exif_orientation(image, orientation as u16)
-            } else {
-                image
-            }
-        } else {
-            image
-        }
+    let oriented_image = match read_exif_orientation(bytes) {
+        Some(orientation) if auto_orient => apply_exif_orientation(image, orientation),
+        _ => image,
     };
 
-    Ok(DynamicImage::ImageRgb32F(oriented_image.to_rgb32f()))
+    Ok(working_space::from_srgb(oriented_image))
 }
 
 pub fn load_non_raw_image_from_bytes(bytes: &[u8], path_for_ext_check: &str) -> Result<DynamicImage> {
+    load_non_raw_image_from_bytes_with_limits(bytes, path_for_ext_check, &image_limits(), true)
+}
+
+/// Decodes a non-RAW file into the pipeline working space. EXR is already
+/// scene-linear; everything else is treated as sRGB-encoded unless the format
+/// says otherwise. `auto_orient` is as in `load_image_with_orientation`.
+pub fn load_non_raw_image_from_bytes_with_limits(
+    bytes: &[u8],
+    path_for_ext_check: &str,
+    limits: &ImageLimits,
+    auto_orient: bool,
+) -> Result<DynamicImage> {
     let path = std::path::Path::new(path_for_ext_check);
     let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
+    let detected = if ext.is_empty() {
+        detect_format(bytes)
+    } else {
+        DetectedFormat::Unknown
+    };
+
+    if ext.eq_ignore_ascii_case("exr") || detected == DetectedFormat::Exr {
+        return load_exr_from_bytes(bytes, limits);
+    }
+
+    if ext.eq_ignore_ascii_case("qoi") || detected == DetectedFormat::Qoi {
+        return load_qoi_from_bytes(bytes, limits);
+    }
+
+    load_image_with_orientation(bytes, limits, auto_orient)
+        .with_context(|| format!("Failed to load image '{}'", path_for_ext_check))
+}
+
+/// RAW highlight compression for merges that do not take one from the caller,
+/// matching the app's default setting.
+pub const DEFAULT_HIGHLIGHT_COMPRESSION: f32 = 2.5;
+
+/// Decodes one input of a multi-image merge (HDR, focus stack, panorama), RAW
+/// or not, into the pipeline working space. RAWs are developed at full quality.
+pub fn load_frame_from_bytes(
+    bytes: &[u8],
+    path: &str,
+    highlight_compression: f32,
+) -> Result<DynamicImage> {
+    match decode_route(bytes, path) {
+        DecodeRoute::NonRaw => load_non_raw_image_from_bytes(bytes, path),
+        DecodeRoute::Raw => develop_frame(bytes, highlight_compression),
+        DecodeRoute::MaybeRaw => develop_frame(bytes, highlight_compression)
+            .or_else(|_| load_non_raw_image_from_bytes(bytes, path)),
+    }
+}
+
+#[cfg(feature = "raw-processing")]
+fn develop_frame(bytes: &[u8], highlight_compression: f32) -> Result<DynamicImage> {
+    crate::core::raw_processing::develop_raw_image(bytes, false, highlight_compression, None)
+}
+
+#[cfg(not(feature = "raw-processing"))]
+fn develop_frame(_bytes: &[u8], _highlight_compression: f32) -> Result<DynamicImage> {
+    Err(ProcessingError::feature_disabled("RAW decoding is not enabled in this build.").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exr_bytes(width: usize, height: usize) -> Vec<u8> {
+        let image = Image::from_channels(
+            (width, height),
+            SpecificChannels::rgba(|_| (0.5f32, 0.25f32, 0.125f32, 1.0f32)),
+        );
+        let mut bytes = Vec::new();
+        image.write().to_buffered(Cursor::new(&mut bytes)).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn exr_above_the_pixel_limit_is_rejected_before_decoding() {
+        let limits = ImageLimits {
+            max_pixels: 100,
+            ..ImageLimits::MOBILE
+        };
+        let error = load_exr_from_bytes(&exr_bytes(20, 10), &limits).unwrap_err();
+        assert!(error.to_string().contains("exceeds the decode limit"));
+    }
 
-    if ext.eq_ignore_ascii_case("exr") {
-        return load_exr_from_bytes(bytes);
+    #[test]
+    fn exr_within_the_limit_decodes() {
+        let image = load_exr_from_bytes(&exr_bytes(8, 4), &ImageLimits::MOBILE).unwrap();
+        assert_eq!((image.width(), image.height()), (8, 4));
     }
 
-    if ext.eq_ignore_ascii_case("qoi") {
-        return load_qoi_from_bytes(bytes);
+    /// SOI, an optional Adobe APP14 segment with `transform`, a baseline SOF
+    /// with `components`, then SOS.
+    fn jpeg_header(components: u8, adobe_transform: Option<u8>) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xD8];
+        if let Some(transform) = adobe_transform {
+            bytes.extend_from_slice(&[0xFF, 0xEE, 0x00, 0x0E]);
+            bytes.extend_from_slice(b"Adobe");
+            bytes.extend_from_slice(&[0x00, 0x64, 0x00, 0x00, 0x00, 0x00, transform]);
+        }
+        let sof_len = 8 + 3 * components as u16;
+        bytes.extend_from_slice(&[0xFF, 0xC0]);
+        bytes.extend_from_slice(&sof_len.to_be_bytes());
+        bytes.extend_from_slice(&[8, 0, 16, 0, 16, components]);
+        for id in 1..=components {
+            bytes.extend_from_slice(&[id, 0x11, 0]);
+        }
+        bytes.extend_from_slice(&[0xFF, 0xDA]);
+        bytes
+    }
+
+    #[test]
+    fn four_component_jpegs_are_detected_with_their_ink_layout() {
+        assert_eq!(detect_cmyk_jpeg(&jpeg_header(3, None)), None);
+        assert_eq!(
+            detect_cmyk_jpeg(&jpeg_header(4, None)),
+            Some(CmykJpegLayout {
+                ycck: false,
+                inverted: false
+            })
+        );
+        assert_eq!(
+            detect_cmyk_jpeg(&jpeg_header(4, Some(0))),
+            Some(CmykJpegLayout {
+                ycck: false,
+                inverted: true
+            })
+        );
+        assert_eq!(
+            detect_cmyk_jpeg(&jpeg_header(4, Some(2))),
+            Some(CmykJpegLayout {
+                ycck: true,
+                inverted: true
+            })
+        );
+    }
+
+    #[test]
+    fn plain_and_adobe_inverted_inks_give_the_same_color() {
+        let plain = CmykJpegLayout {
+            ycck: false,
+            inverted: false,
+        };
+        let adobe = CmykJpegLayout {
+            ycck: false,
+            inverted: true,
+        };
+        // Full cyan on white paper.
+        assert_eq!(cmyk_to_rgb(&[255, 0, 0, 0], plain), [0, 255, 255]);
+        assert_eq!(cmyk_to_rgb(&[0, 255, 255, 255], adobe), [0, 255, 255]);
+        // Full black ink wins over everything else.
+        assert_eq!(cmyk_to_rgb(&[0, 0, 0, 255], plain), [0, 0, 0]);
     }
 
-    load_image_with_orientation(bytes)
-        .map_err(|err| anyhow!("Failed to load image '{}': {err}", path_for_ext_check))
+    /// A 4x2 JPEG whose EXIF block records `orientation`.
+    fn jpeg_with_orientation(orientation: u16) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(4, 2))
+            .write_to(&mut Cursor::new(&mut encoded), image::ImageFormat::Jpeg)
+            .unwrap();
+        let mut tiff = b"II*\x00\x08\x00\x00\x00\x01\x00".to_vec();
+        tiff.extend_from_slice(&[0x12, 0x01, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00]);
+        tiff.extend_from_slice(&orientation.to_le_bytes());
+        tiff.extend_from_slice(&[0; 6]);
+        let mut app1 = vec![0xFF, 0xE1];
+        app1.extend_from_slice(&(2 + 6 + tiff.len() as u16).to_be_bytes());
+        app1.extend_from_slice(b"Exif\x00\x00");
+        app1.extend_from_slice(&tiff);
+        [&encoded[..2], &app1, &encoded[2..]].concat()
+    }
+
+    #[test]
+    fn orientation_is_applied_only_when_asked() {
+        let bytes = jpeg_with_orientation(6);
+        assert_eq!(read_exif_orientation(&bytes), Some(6));
+
+        let limits = ImageLimits::MOBILE;
+        let oriented = load_image_with_orientation(&bytes, &limits, true).unwrap();
+        assert_eq!((oriented.width(), oriented.height()), (2, 4));
+        let stored = load_image_with_orientation(&bytes, &limits, false).unwrap();
+        assert_eq!((stored.width(), stored.height()), (4, 2));
+
+        assert_eq!(read_exif_orientation(&jpeg_header(3, None)), None);
+    }
 }
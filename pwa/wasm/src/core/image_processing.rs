@@ -1,7 +1,18 @@
-use image::DynamicImage;
-use rayon::prelude::*;
+#[cfg(feature = "raw-processing")]
+use crate::core::image_utils::joint_bilateral_filter;
+#[cfg(feature = "raw-processing")]
+use crate::core::working_space::WorkingSpace;
+use image::{DynamicImage, Rgba, Rgba32FImage};
+#[cfg(feature = "raw-processing")]
+use rapidraw_core::luma::{luma, LumaCoefficients};
+#[cfg(feature = "raw-processing")]
+use rapidraw_core::srgb::{linear_to_srgb, srgb_to_linear};
+#[cfg(feature = "raw-processing")]
 use rawler::decoders::Orientation;
+#[cfg(feature = "raw-processing")]
+use rayon::prelude::*;
 
+#[cfg(feature = "raw-processing")]
 pub fn apply_orientation(image: DynamicImage, orientation: Orientation) -> DynamicImage {
     match orientation {
         Orientation::Normal | Orientation::Unknown => image,
@@ -24,14 +35,119 @@ pub fn apply_coarse_rotation(image: DynamicImage, orientation_steps: u8) -> Dyna
     }
 }
 
+pub fn apply_flip(image: DynamicImage, horizontal: bool, vertical: bool) -> DynamicImage {
+    let mut img = image;
+    if horizontal {
+        img = img.fliph();
+    }
+    if vertical {
+        img = img.flipv();
+    }
+    img
+}
+
+/// Zoom toward the centre (at most 1) at which a `width` x `height` frame,
+/// rotated by `sin_t`/`cos_t`, samples only inside a source of the same size.
+/// Distances are between pixel centres, the span bilinear sampling can reach.
+fn rotation_fill_scale(width: u32, height: u32, sin_t: f32, cos_t: f32) -> f32 {
+    let (span_w, span_h) = ((width.max(2) - 1) as f32, (height.max(2) - 1) as f32);
+    let (sin_t, cos_t) = (sin_t.abs(), cos_t.abs());
+    let scale = (span_w / (span_w * cos_t + span_h * sin_t))
+        .min(span_h / (span_w * sin_t + span_h * cos_t));
+    // Pull in by a hair so rounding cannot reach past the outermost pixels.
+    (scale * (1.0 - 1e-4)).min(1.0)
+}
+
+/// Rotates by an arbitrary angle (clockwise for positive `degrees`, matching
+/// `transformRotate`) using bilinear inverse mapping. The canvas grows to
+/// contain the whole rotated image, with `fill` in the uncovered corners,
+/// unless `crop_to_original` is set. Then it keeps the source dimensions and
+/// the rotated image is zoomed in just enough to cover it, as the desktop
+/// fills the frame after lens correction, so no corner is left empty.
+pub fn rotate_arbitrary(
+    image: &DynamicImage,
+    degrees: f32,
+    fill: [f32; 4],
+    crop_to_original: bool,
+) -> DynamicImage {
+    if degrees.rem_euclid(360.0) == 0.0 {
+        return image.clone();
+    }
+
+    let src = image.to_rgba32f();
+    let (w, h) = src.dimensions();
+    let (sin_t, cos_t) = degrees.to_radians().sin_cos();
+
+    let (out_w, out_h) = if crop_to_original {
+        (w, h)
+    } else {
+        let (wf, hf) = (w as f32, h as f32);
+        (
+            ((wf * cos_t.abs() + hf * sin_t.abs()).round() as u32).max(1),
+            ((wf * sin_t.abs() + hf * cos_t.abs()).round() as u32).max(1),
+        )
+    };
+
+    let zoom = if crop_to_original {
+        rotation_fill_scale(w, h, sin_t, cos_t)
+    } else {
+        1.0
+    };
+    let (sin_t, cos_t) = (sin_t * zoom, cos_t * zoom);
+
+    let src_cx = w as f32 * 0.5;
+    let src_cy = h as f32 * 0.5;
+    let dst_cx = out_w as f32 * 0.5;
+    let dst_cy = out_h as f32 * 0.5;
+
+    let fetch = |x: i64, y: i64| -> [f32; 4] {
+        if x < 0 || y < 0 || x >= w as i64 || y >= h as i64 {
+            fill
+        } else {
+            src.get_pixel(x as u32, y as u32).0
+        }
+    };
+
+    let mut out = Rgba32FImage::new(out_w, out_h);
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        let dx = x as f32 + 0.5 - dst_cx;
+        let dy = y as f32 + 0.5 - dst_cy;
+        let sx = cos_t * dx + sin_t * dy + src_cx - 0.5;
+        let sy = -sin_t * dx + cos_t * dy + src_cy - 0.5;
+
+        let x0 = sx.floor();
+        let y0 = sy.floor();
+        let fx = sx - x0;
+        let fy = sy - y0;
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let p00 = fetch(x0, y0);
+        let p10 = fetch(x0 + 1, y0);
+        let p01 = fetch(x0, y0 + 1);
+        let p11 = fetch(x0 + 1, y0 + 1);
+
+        let mut value = [0.0f32; 4];
+        for c in 0..4 {
+            let top = p00[c] + (p10[c] - p00[c]) * fx;
+            let bottom = p01[c] + (p11[c] - p01[c]) * fx;
+            value[c] = top + (bottom - top) * fy;
+        }
+        *pixel = Rgba(value);
+    }
+
+    DynamicImage::ImageRgba32F(out)
+}
+
+#[cfg(feature = "raw-processing")]
 #[inline(always)]
 fn rgb_to_yc_only(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
-    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let y = luma(r, g, b, LumaCoefficients::Rec601);
     let cb = -0.168736 * r - 0.331264 * g + 0.5 * b;
     let cr = 0.5 * r - 0.418688 * g - 0.081312 * b;
     (y, cb, cr)
 }
 
+#[cfg(feature = "raw-processing")]
 #[inline(always)]
 fn yc_to_rgb(y: f32, cb: f32, cr: f32) -> (f32, f32, f32) {
     let r = y + 1.402 * cr;
@@ -40,105 +156,196 @@ fn yc_to_rgb(y: f32, cb: f32, cr: f32) -> (f32, f32, f32) {
     (r, g, b)
 }
 
-pub fn remove_raw_artifacts_and_enhance(image: &mut DynamicImage) {
+#[cfg(feature = "raw-processing")]
+/// Edge-aware chroma smoothing. The luma weights and range sigma are tuned for
+/// gamma-encoded data, so linear input is encoded for filtering and decoded back.
+pub fn remove_raw_artifacts_and_enhance(image: &mut DynamicImage, working_space: WorkingSpace) {
     let mut buffer = image.to_rgb32f();
     let w = buffer.width() as usize;
     let h = buffer.height() as usize;
+    let is_linear = working_space == WorkingSpace::LinearRec709;
 
-    let mut ycbcr_buffer = vec![0.0f32; w * h * 3];
+    if is_linear {
+        buffer
+            .as_mut()
+            .par_iter_mut()
+            .for_each(|v| *v = linear_to_srgb(*v));
+    }
 
     let src = buffer.as_raw();
+    let mut luma_buffer = vec![0.0f32; w * h];
+    let mut chroma_buffer = vec![0.0f32; w * h * 2];
 
-    ycbcr_buffer
-        .par_chunks_mut(3)
+    luma_buffer
+        .par_iter_mut()
+        .zip(chroma_buffer.par_chunks_mut(2))
         .zip(src.par_chunks(3))
-        .for_each(|(dest, pixel)| {
+        .for_each(|((luma_out, chroma_out), pixel)| {
             let (y, cb, cr) = rgb_to_yc_only(pixel[0], pixel[1], pixel[2]);
-            dest[0] = y;
-            dest[1] = cb;
-            dest[2] = cr;
+            *luma_out = y;
+            chroma_out[0] = cb;
+            chroma_out[1] = cr;
         });
 
-    const BASE_INV_SIGMA: f32 = 14.0;
-    const OFFSETS: [isize; 3] = [-5, -1, 3];
-    const OFFSET_SQUARES: [f32; 3] = [25.0, 1.0, 9.0];
+    // Chroma follows luma edges: a range sigma of 1/14 in gamma-encoded luma,
+    // taps spread over about five pixels.
+    const SPATIAL_SIGMA: f32 = 5.0;
+    const RANGE_SIGMA: f32 = 1.0 / 14.0;
+    let filtered = joint_bilateral_filter(
+        &luma_buffer,
+        &chroma_buffer,
+        2,
+        w,
+        h,
+        SPATIAL_SIGMA,
+        RANGE_SIGMA,
+    );
 
     buffer
         .par_chunks_mut(w * 3)
         .enumerate()
         .for_each(|(y, row)| {
-            let row_offset = y * w;
-            let h_isize = h as isize;
-            let w_isize = w as isize;
-            let y_isize = y as isize;
-
             for x in 0..w {
-                let center_idx = (row_offset + x) * 3;
+                let idx = y * w + x;
+                let cy = luma_buffer[idx];
+                let ccb = chroma_buffer[idx * 2];
+                let ccr = chroma_buffer[idx * 2 + 1];
+                let filtered_cb = filtered[idx * 2];
+                let filtered_cr = filtered[idx * 2 + 1];
 
-                let cy = ycbcr_buffer[center_idx];
-                let ccb = ycbcr_buffer[center_idx + 1];
-                let ccr = ycbcr_buffer[center_idx + 2];
-
-                let mut cb_sum = 0.0;
-                let mut cr_sum = 0.0;
-                let mut w_sum = 0.0;
+                // Never let smoothing raise saturation.
+                let orig_mag_sq = ccb * ccb + ccr * ccr;
+                let filt_mag_sq = filtered_cb * filtered_cb + filtered_cr * filtered_cr;
+                let (out_cb, out_cr) = if filt_mag_sq > orig_mag_sq && orig_mag_sq > 1e-12 {
+                    let scale = (orig_mag_sq / filt_mag_sq).sqrt();
+                    (filtered_cb * scale, filtered_cr * scale)
+                } else {
+                    (filtered_cb, filtered_cr)
+                };
 
-                for (ki, &ky) in OFFSETS.iter().enumerate() {
-                    let sy = y_isize + ky as isize;
-                    if sy < 0 || sy >= h_isize {
-                        continue;
-                    }
+                let (r, g, b) = yc_to_rgb(cy, out_cb, out_cr);
+                let out_idx = x * 3;
+                if is_linear {
+                    row[out_idx] = srgb_to_linear(r);
+                    row[out_idx + 1] = srgb_to_linear(g);
+                    row[out_idx + 2] = srgb_to_linear(b);
+                } else {
+                    row[out_idx] = r;
+                    row[out_idx + 1] = g;
+                    row[out_idx + 2] = b;
+                }
+            }
+        });
 
-                    let neighbor_row_idx = (sy as usize) * w;
-                    let ky_sq_div_50 = OFFSET_SQUARES[ki] * 0.02;
+    *image = DynamicImage::ImageRgb32F(buffer);
+}
 
-                    for (kj, &kx) in OFFSETS.iter().enumerate() {
-                        let sx = (x as isize) + kx as isize;
-                        if sx < 0 || sx >= w_isize {
-                            continue;
-                        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                        let neighbor_idx = (neighbor_row_idx + sx as usize) * 3;
+    fn opaque(width: u32, height: u32) -> DynamicImage {
+        DynamicImage::ImageRgba32F(Rgba32FImage::from_pixel(
+            width,
+            height,
+            Rgba([0.5, 0.4, 0.3, 1.0]),
+        ))
+    }
 
-                        let neighbor_y = ycbcr_buffer[neighbor_idx];
-                        let y_diff = (cy - neighbor_y).abs();
+    #[test]
+    fn straightening_leaves_no_empty_corners() {
+        for degrees in [-12.5, 3.0, 45.0, 170.0] {
+            let rotated = rotate_arbitrary(&opaque(120, 80), degrees, [0.0; 4], true).to_rgba32f();
+            assert_eq!(rotated.dimensions(), (120, 80));
+            for (x, y, pixel) in rotated.enumerate_pixels() {
+                assert!(
+                    (pixel[3] - 1.0).abs() < 1e-3,
+                    "alpha {} at ({x}, {y}) for {degrees}°",
+                    pixel[3]
+                );
+            }
+        }
+    }
 
-                        let val = y_diff * BASE_INV_SIGMA;
-                        let spatial_penalty = OFFSET_SQUARES[kj] * 0.02 + ky_sq_div_50;
+    #[test]
+    fn fill_scale_is_one_for_quarter_turns_of_a_square() {
+        let (sin_t, cos_t) = 90.0f32.to_radians().sin_cos();
+        assert!(rotation_fill_scale(64, 64, sin_t, cos_t) > 0.999);
+        let (sin_t, cos_t) = 10.0f32.to_radians().sin_cos();
+        assert!(rotation_fill_scale(64, 64, sin_t, cos_t) < 0.9);
+    }
 
-                        let weight = 1.0 / (1.0 + val * val + spatial_penalty);
+    #[test]
+    fn expanded_canvas_keeps_the_fill_in_the_corners() {
+        let rotated = rotate_arbitrary(&opaque(100, 100), 30.0, [0.0; 4], false).to_rgba32f();
+        assert!(rotated.width() > 100 && rotated.height() > 100);
+        assert_eq!(rotated.get_pixel(0, 0)[3], 0.0);
+        let (cx, cy) = (rotated.width() / 2, rotated.height() / 2);
+        assert!((rotated.get_pixel(cx, cy)[3] - 1.0).abs() < 1e-6);
+    }
 
-                        cb_sum += ycbcr_buffer[neighbor_idx + 1] * weight;
-                        cr_sum += ycbcr_buffer[neighbor_idx + 2] * weight;
-                        w_sum += weight;
-                    }
-                }
+    #[cfg(feature = "raw-processing")]
+    fn stripes(values: [[f32; 3]; 2]) -> DynamicImage {
+        DynamicImage::ImageRgb32F(image::Rgb32FImage::from_fn(24, 24, |x, _| {
+            image::Rgb(values[(x / 6 % 2) as usize])
+        }))
+    }
 
-                let (out_cb, out_cr) = if w_sum > 1e-4 {
-                    let inv_w_sum = 1.0 / w_sum;
-                    let filtered_cb = cb_sum * inv_w_sum;
-                    let filtered_cr = cr_sum * inv_w_sum;
+    #[cfg(feature = "raw-processing")]
+    #[test]
+    fn artifact_removal_keeps_flat_linear_areas() {
+        let mut image = stripes([[0.18, 0.18, 0.18]; 2]);
+        remove_raw_artifacts_and_enhance(&mut image, WorkingSpace::LinearRec709);
+        for pixel in image.to_rgb32f().pixels() {
+            for c in 0..3 {
+                assert!((pixel[c] - 0.18).abs() < 1e-4, "{pixel:?}");
+            }
+        }
+    }
 
-                    let orig_mag_sq = ccb * ccb + ccr * ccr;
-                    let filt_mag_sq = filtered_cb * filtered_cb + filtered_cr * filtered_cr;
+    #[cfg(feature = "raw-processing")]
+    #[test]
+    fn artifact_removal_matches_across_working_spaces() {
+        let linear = [[0.05, 0.2, 0.6], [0.4, 0.3, 0.1]];
+        let encoded = linear.map(|rgb| rgb.map(linear_to_srgb));
 
-                    if filt_mag_sq > orig_mag_sq && orig_mag_sq > 1e-12 {
-                        let scale = (orig_mag_sq / filt_mag_sq).sqrt();
-                        (filtered_cb * scale, filtered_cr * scale)
-                    } else {
-                        (filtered_cb, filtered_cr)
-                    }
-                } else {
-                    (ccb, ccr)
-                };
+        let mut from_linear = stripes(linear);
+        remove_raw_artifacts_and_enhance(&mut from_linear, WorkingSpace::LinearRec709);
+        let mut from_encoded = stripes(encoded);
+        remove_raw_artifacts_and_enhance(&mut from_encoded, WorkingSpace::Srgb);
 
-                let (r, g, b) = yc_to_rgb(cy, out_cb, out_cr);
-                let out_idx = x * 3;
-                row[out_idx] = r;
-                row[out_idx + 1] = g;
-                row[out_idx + 2] = b;
+        let (from_linear, from_encoded) = (from_linear.to_rgb32f(), from_encoded.to_rgb32f());
+        for (a, b) in from_linear.pixels().zip(from_encoded.pixels()) {
+            for c in 0..3 {
+                assert!((a[c] - srgb_to_linear(b[c])).abs() < 1e-4, "{a:?} vs {b:?}");
             }
-        });
+        }
+    }
 
-    *image = DynamicImage::ImageRgb32F(buffer);
+    fn corners(image: &DynamicImage) -> [u8; 4] {
+        let luma = image.to_luma8();
+        let (w, h) = luma.dimensions();
+        [(0, 0), (w - 1, 0), (0, h - 1), (w - 1, h - 1)].map(|(x, y)| luma.get_pixel(x, y)[0])
+    }
+
+    #[test]
+    fn flips_mirror_the_requested_axes() {
+        let image = DynamicImage::ImageLuma8(image::GrayImage::from_fn(3, 2, |x, y| {
+            image::Luma([(10 * x + 100 * y) as u8])
+        }));
+        assert_eq!(corners(&image), [0, 20, 100, 120]);
+        assert_eq!(
+            corners(&apply_flip(image.clone(), true, false)),
+            [20, 0, 120, 100]
+        );
+        assert_eq!(
+            corners(&apply_flip(image.clone(), false, true)),
+            [100, 120, 0, 20]
+        );
+        assert_eq!(
+            corners(&apply_flip(image.clone(), true, true)),
+            [120, 100, 20, 0]
+        );
+        assert_eq!(corners(&apply_flip(image, false, false)), [0, 20, 100, 120]);
+    }
 }
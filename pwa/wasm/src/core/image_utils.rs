@@ -1,5 +1,76 @@
-use image::{DynamicImage, GenericImageView, Rgb32FImage};
+use anyhow::{bail, Result};
+use image::{imageops, DynamicImage, GenericImageView, Rgb32FImage, Rgba32FImage};
+#[cfg(feature = "raw-processing")]
+use rayon::prelude::*;
+use serde::Serialize;
 
+/// How far two images are apart, per RGB channel.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffStats {
+    pub max_delta: [f32; 3],
+    pub mean_delta: [f32; 3],
+    /// Peak signal-to-noise ratio in dB over all three channels, with a peak
+    /// of 1.0; infinite for identical images.
+    pub psnr: f32,
+}
+
+fn check_same_size(a: &DynamicImage, b: &DynamicImage) -> Result<()> {
+    if a.dimensions() != b.dimensions() {
+        bail!(
+            "images differ in size: {}x{} vs {}x{}",
+            a.width(),
+            a.height(),
+            b.width(),
+            b.height()
+        );
+    }
+    Ok(())
+}
+
+/// Compares the RGB channels of two same-sized images as stored, in 0..1:
+/// pipeline images compare in linear light, 8-bit sRGB ones in encoded
+/// values. Alpha is ignored.
+pub fn image_diff(a: &DynamicImage, b: &DynamicImage) -> Result<DiffStats> {
+    check_same_size(a, b)?;
+    let (a, b) = (a.to_rgb32f(), b.to_rgb32f());
+    let mut max_delta = [0.0f32; 3];
+    let mut sums = [0.0f64; 3];
+    let mut squared_sum = 0.0f64;
+    for (pa, pb) in a.pixels().zip(b.pixels()) {
+        for c in 0..3 {
+            let delta = (pa[c] - pb[c]).abs();
+            max_delta[c] = max_delta[c].max(delta);
+            sums[c] += delta as f64;
+            squared_sum += (delta as f64).powi(2);
+        }
+    }
+    let count = (a.width() as f64 * a.height() as f64).max(1.0);
+    let mse = squared_sum / (count * 3.0);
+    Ok(DiffStats {
+        max_delta,
+        mean_delta: sums.map(|sum| (sum / count) as f32),
+        psnr: if mse > 0.0 {
+            (-10.0 * mse.log10()) as f32
+        } else {
+            f32::INFINITY
+        },
+    })
+}
+
+/// Per-channel absolute difference of two same-sized images, multiplied by
+/// `gain` so small changes show up in a before/after difference view.
+pub fn difference_image(a: &DynamicImage, b: &DynamicImage, gain: f32) -> Result<Rgb32FImage> {
+    check_same_size(a, b)?;
+    let (a, b) = (a.to_rgb32f(), b.to_rgb32f());
+    Ok(Rgb32FImage::from_fn(a.width(), a.height(), |x, y| {
+        let (pa, pb) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        image::Rgb(std::array::from_fn(|c| (pa[c] - pb[c]).abs() * gain))
+    }))
+}
+
+/// Box-filter downscale to fit `nwidth` x `nheight`. Alpha, when present, is
+/// averaged like the color channels.
 pub fn downscale_f32_image(image: &DynamicImage, nwidth: u32, nheight: u32) -> DynamicImage {
     let (width, height) = image.dimensions();
     if nwidth == 0 || nheight == 0 {
@@ -17,8 +88,26 @@ pub fn downscale_f32_image(image: &DynamicImage, nwidth: u32, nheight: u32) -> D
         return image.clone();
     }
 
-    let img = image.to_rgb32f();
-    let mut out = Rgb32FImage::new(new_w, new_h);
+    if image.color().has_alpha() {
+        let img = image.to_rgba32f();
+        let out = box_downscale(img.as_raw(), width, height, 4, new_w, new_h);
+        DynamicImage::ImageRgba32F(Rgba32FImage::from_raw(new_w, new_h, out).unwrap())
+    } else {
+        let img = image.to_rgb32f();
+        let out = box_downscale(img.as_raw(), width, height, 3, new_w, new_h);
+        DynamicImage::ImageRgb32F(Rgb32FImage::from_raw(new_w, new_h, out).unwrap())
+    }
+}
+
+fn box_downscale(
+    src: &[f32],
+    width: u32,
+    height: u32,
+    channels: usize,
+    new_w: u32,
+    new_h: u32,
+) -> Vec<f32> {
+    let mut out = vec![0.0f32; new_w as usize * new_h as usize * channels];
 
     let x_ratio = width as f32 / new_w as f32;
     let y_ratio = height as f32 / new_h as f32;
@@ -30,30 +119,260 @@ pub fn downscale_f32_image(image: &DynamicImage, nwidth: u32, nheight: u32) -> D
             let x_end = ((x_out + 1) as f32 * x_ratio).ceil() as u32;
             let y_end = ((y_out + 1) as f32 * y_ratio).ceil() as u32;
 
-            let mut r_sum = 0.0;
-            let mut g_sum = 0.0;
-            let mut b_sum = 0.0;
+            let mut sums = [0.0f32; 4];
             let mut count = 0.0;
 
             for y_in in y_start..y_end.min(height) {
                 for x_in in x_start..x_end.min(width) {
-                    let pixel = img.get_pixel(x_in, y_in);
-                    r_sum += pixel[0];
-                    g_sum += pixel[1];
-                    b_sum += pixel[2];
+                    let idx = (y_in as usize * width as usize + x_in as usize) * channels;
+                    for c in 0..channels {
+                        sums[c] += src[idx + c];
+                    }
                     count += 1.0;
                 }
             }
 
             if count > 0.0 {
-                out.put_pixel(
-                    x_out,
-                    y_out,
-                    image::Rgb([r_sum / count, g_sum / count, b_sum / count]),
-                );
+                let out_idx = (y_out as usize * new_w as usize + x_out as usize) * channels;
+                for c in 0..channels {
+                    out[out_idx + c] = sums[c] / count;
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Lanczos resample to exactly `nwidth` x `nheight`. Expects pipeline
+/// (linear) input, so downscaled highlights and fine detail keep their
+/// brightness.
+pub fn resize_f32_image(image: &DynamicImage, nwidth: u32, nheight: u32) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    if nwidth == 0 || nheight == 0 || (nwidth == width && nheight == height) {
+        return image.clone();
+    }
+
+    if image.color().has_alpha() {
+        let rgba = image.to_rgba32f();
+        let resized = imageops::resize(&rgba, nwidth, nheight, imageops::FilterType::Lanczos3);
+        return DynamicImage::ImageRgba32F(resized);
+    }
+
+    let linear = image.to_rgb32f();
+    let resized = imageops::resize(&linear, nwidth, nheight, imageops::FilterType::Lanczos3);
+
+    DynamicImage::ImageRgb32F(resized)
+}
+
+/// Classic unsharp mask: adds `amount` times the difference between the image
+/// and its Gaussian blur of the given `sigma`. The blur runs on the linear
+/// pipeline buffer, so halos around bright edges keep the right brightness.
+pub fn unsharp_mask(image: &DynamicImage, sigma: f32, amount: f32) -> DynamicImage {
+    if sigma <= 0.0 || amount.abs() < 1e-4 {
+        return image.clone();
+    }
+    let mut sharpened = image.to_rgb32f();
+    let blurred = imageops::blur(&sharpened, sigma);
+    sharpened
+        .iter_mut()
+        .zip(blurred.iter())
+        .for_each(|(v, b)| *v = (*v + (*v - b) * amount).clamp(0.0, 1.0));
+
+    if !image.color().has_alpha() {
+        return DynamicImage::ImageRgb32F(sharpened);
+    }
+    // Alpha is left as it was; only color is sharpened.
+    let mut rgba = image.to_rgba32f();
+    rgba.pixels_mut()
+        .zip(sharpened.pixels())
+        .for_each(|(out, rgb)| out.0[..3].copy_from_slice(&rgb.0));
+    DynamicImage::ImageRgba32F(rgba)
+}
+
+/// Tap offsets for the bilateral filters: a 3x3 grid of the pixel itself and
+/// neighbours one `spatial_sigma` away each way. Nine taps keep the filter
+/// affordable on phones; the grid is symmetric so smoothing never shifts
+/// features towards one side.
+fn bilateral_offsets(spatial_sigma: f32) -> [isize; 3] {
+    let s = spatial_sigma.max(1.0).round() as isize;
+    [-s, 0, s]
+}
+
+/// Edge-preserving smoothing of a single-channel `width` x `height` buffer.
+/// See `joint_bilateral_filter` for the weighting.
+pub fn bilateral_filter(
+    buffer: &[f32],
+    width: usize,
+    height: usize,
+    spatial_sigma: f32,
+    range_sigma: f32,
+) -> Vec<f32> {
+    joint_bilateral_filter(buffer, buffer, 1, width, height, spatial_sigma, range_sigma)
+}
+
+/// Smooths the interleaved `channels`-wide `values` with weights taken from
+/// the single-channel `guide`, so edges in the guide are kept in every channel
+/// (e.g. chroma filtered along luma edges). Weights fall off as
+/// `1 / (1 + (dv / range_sigma)^2 + d^2 / (2 spatial_sigma^2))`, a heavier-tailed
+/// form of the Gaussian bilateral that needs no `exp`.
+pub fn joint_bilateral_filter(
+    guide: &[f32],
+    values: &[f32],
+    channels: usize,
+    width: usize,
+    height: usize,
+    spatial_sigma: f32,
+    range_sigma: f32,
+) -> Vec<f32> {
+    let mut out = vec![0.0f32; width * height * channels];
+    if width == 0 || height == 0 || channels == 0 {
+        return out;
+    }
+
+    let offsets = bilateral_offsets(spatial_sigma);
+    let inv_range = 1.0 / range_sigma.max(1e-6);
+    let inv_two_spatial_sq = 1.0 / (2.0 * spatial_sigma.max(1.0).powi(2));
+
+    let filter_row = |(y, row): (usize, &mut [f32])| {
+        let mut sums = vec![0.0f32; channels];
+        for x in 0..width {
+            let center = guide[y * width + x];
+            sums.iter_mut().for_each(|v| *v = 0.0);
+            let mut w_sum = 0.0;
+
+            for &ky in &offsets {
+                let sy = y as isize + ky;
+                if sy < 0 || sy >= height as isize {
+                    continue;
+                }
+                for &kx in &offsets {
+                    let sx = x as isize + kx;
+                    if sx < 0 || sx >= width as isize {
+                        continue;
+                    }
+                    let neighbor = sy as usize * width + sx as usize;
+                    let range = (center - guide[neighbor]).abs() * inv_range;
+                    let spatial = (kx * kx + ky * ky) as f32 * inv_two_spatial_sq;
+                    let weight = 1.0 / (1.0 + range * range + spatial);
+
+                    let src = &values[neighbor * channels..(neighbor + 1) * channels];
+                    sums.iter_mut()
+                        .zip(src)
+                        .for_each(|(acc, v)| *acc += v * weight);
+                    w_sum += weight;
+                }
+            }
+
+            let dst = &mut row[x * channels..(x + 1) * channels];
+            if w_sum > 1e-4 {
+                dst.iter_mut()
+                    .zip(&sums)
+                    .for_each(|(d, acc)| *d = acc / w_sum);
+            } else {
+                let center_idx = (y * width + x) * channels;
+                dst.copy_from_slice(&values[center_idx..center_idx + channels]);
             }
         }
+    };
+
+    #[cfg(feature = "raw-processing")]
+    out.par_chunks_mut(width * channels)
+        .enumerate()
+        .for_each(filter_row);
+    #[cfg(not(feature = "raw-processing"))]
+    out.chunks_mut(width * channels)
+        .enumerate()
+        .for_each(filter_row);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bilateral_taps_are_symmetric() {
+        for sigma in [0.5, 1.0, 2.4, 6.0] {
+            let [a, b, c] = bilateral_offsets(sigma);
+            assert_eq!((a, b), (-c, 0));
+        }
+    }
+
+    #[test]
+    fn bilateral_filter_does_not_shift_a_ramp() {
+        let (width, height) = (16, 4);
+        let ramp: Vec<f32> = (0..width * height)
+            .map(|i| (i % width) as f32 / width as f32)
+            .collect();
+        let out = bilateral_filter(&ramp, width, height, 2.0, 10.0);
+        for y in 0..height {
+            for x in 2..width - 2 {
+                let i = y * width + x;
+                assert!((out[i] - ramp[i]).abs() < 1e-5, "({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn bilateral_filter_keeps_a_hard_edge() {
+        let (width, height) = (8, 8);
+        let step: Vec<f32> = (0..width * height)
+            .map(|i| if i % width < 4 { 0.0 } else { 1.0 })
+            .collect();
+        let out = bilateral_filter(&step, width, height, 1.0, 0.01);
+        for (o, s) in out.iter().zip(&step) {
+            assert!((o - s).abs() < 0.01);
+        }
     }
 
-    DynamicImage::ImageRgb32F(out)
+    #[test]
+    fn downscale_averages_alpha_like_color() {
+        let image = DynamicImage::ImageRgba32F(Rgba32FImage::from_fn(2, 2, |x, _| {
+            image::Rgba([1.0, 1.0, 1.0, x as f32])
+        }));
+        let small = downscale_f32_image(&image, 1, 1);
+        let pixel = small.as_rgba32f().expect("still RGBA").get_pixel(0, 0);
+        assert_eq!(pixel[3], 0.5);
+    }
+
+    #[test]
+    fn identical_images_have_no_difference() {
+        let image = DynamicImage::ImageRgb32F(Rgb32FImage::from_fn(4, 3, |x, y| {
+            image::Rgb([x as f32 / 4.0, y as f32 / 3.0, 0.5])
+        }));
+        let stats = image_diff(&image, &image).unwrap();
+        assert_eq!(stats.max_delta, [0.0; 3]);
+        assert_eq!(stats.mean_delta, [0.0; 3]);
+        assert_eq!(stats.psnr, f32::INFINITY);
+    }
+
+    #[test]
+    fn diff_reports_per_channel_deltas_and_psnr() {
+        let a = DynamicImage::ImageRgb32F(Rgb32FImage::from_pixel(2, 2, image::Rgb([0.5; 3])));
+        let b = DynamicImage::ImageRgb32F(Rgb32FImage::from_fn(2, 2, |x, _| {
+            image::Rgb([0.5 + 0.1 * x as f32, 0.5, 0.4])
+        }));
+        let stats = image_diff(&a, &b).unwrap();
+        assert!((stats.max_delta[0] - 0.1).abs() < 1e-6);
+        assert!((stats.mean_delta[0] - 0.05).abs() < 1e-6);
+        assert_eq!(stats.max_delta[1], 0.0);
+        assert!((stats.mean_delta[2] - 0.1).abs() < 1e-6);
+        // Half the red samples and all blue ones are 0.1 off: MSE 0.01 / 2.
+        assert!((stats.psnr - 23.0103).abs() < 1e-3, "{}", stats.psnr);
+
+        let diff = difference_image(&a, &b, 10.0).unwrap();
+        let [r, g, bl] = diff.get_pixel(1, 0).0;
+        assert!((r - 1.0).abs() < 1e-5 && g == 0.0 && (bl - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn diff_of_different_sizes_fails() {
+        let a = DynamicImage::ImageRgb32F(Rgb32FImage::new(2, 2));
+        let b = DynamicImage::ImageRgb32F(Rgb32FImage::new(2, 3));
+        let err = image_diff(&a, &b).unwrap_err();
+        assert_eq!(err.to_string(), "images differ in size: 2x2 vs 2x3");
+        assert!(difference_image(&a, &b, 1.0).is_err());
+    }
 }
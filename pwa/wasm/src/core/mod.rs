@@ -1,17 +1,52 @@
+pub mod analysis;
+pub mod error;
 pub mod formats;
 pub mod geometry;
 pub mod metadata;
+pub mod rng;
 #[cfg(feature = "image-decoding")]
 pub mod adjustments;
 #[cfg(feature = "image-decoding")]
+pub mod clock;
+#[cfg(feature = "image-decoding")]
+pub mod color;
+#[cfg(feature = "image-decoding")]
+pub mod color_grading;
+#[cfg(feature = "image-decoding")]
+pub mod defringe;
+#[cfg(feature = "image-decoding")]
+pub mod export;
+#[cfg(feature = "image-decoding")]
+pub mod film_simulation;
+#[cfg(feature = "image-decoding")]
+pub mod hdr;
+#[cfg(feature = "image-decoding")]
+pub mod image_processing;
+#[cfg(feature = "image-decoding")]
 pub mod image_utils;
 #[cfg(feature = "image-decoding")]
 pub mod image_loader;
 #[cfg(feature = "image-decoding")]
+pub mod negative;
+#[cfg(feature = "image-decoding")]
 pub mod non_raw_metadata;
+#[cfg(feature = "image-decoding")]
+pub mod pano;
+#[cfg(feature = "image-decoding")]
+pub mod placeholder;
+#[cfg(feature = "image-decoding")]
+pub mod retouch;
+#[cfg(feature = "image-decoding")]
+pub mod stack;
+#[cfg(feature = "image-decoding")]
+pub mod working_space;
 #[cfg(feature = "raw-processing")]
-pub mod image_processing;
+pub mod dng_opcodes;
 #[cfg(feature = "raw-processing")]
 pub mod raw_processing;
 #[cfg(feature = "raw-processing")]
 pub mod raw_metadata;
+#[cfg(feature = "pixel-shift")]
+pub mod pixel_shift;
+#[cfg(feature = "bench")]
+pub mod bench;
@@ -0,0 +1,173 @@
+//! Color negative conversion: turns a scanned or camera-copied negative into
+//! a positive. The orange film base (the mask every color negative carries)
+//! is divided out so it maps to black, the film's density is turned back into
+//! scene exposure, and per-channel balance multipliers correct the remaining
+//! cast.
+
+use crate::core::adjustments::to_adjustment_buffer;
+use image::DynamicImage;
+use rapidraw_core::srgb::srgb_to_linear;
+
+/// The film base desktop falls back to when none was picked.
+const DEFAULT_FILM_BASE: &str = "#ff8800";
+/// Film base values are kept above this so dividing by them stays finite.
+const MIN_FILM_BASE: f32 = 1e-4;
+/// Contrast (gamma) of negative film: density above the base grows by this
+/// much per decade of exposure.
+const FILM_GAMMA: f32 = 0.6;
+/// Density above the base that maps to white. Denser areas clip.
+const MAX_DENSITY: f32 = 1.8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NegativeConversion {
+    /// Linear RGB of unexposed film (the frame border or the gap between
+    /// frames).
+    pub film_base: [f32; 3],
+    /// Per-channel multipliers applied to the positive; 1 leaves a channel
+    /// unchanged.
+    pub balance: [f32; 3],
+}
+
+/// Parses `#rrggbb` into linear RGB.
+fn hex_to_linear(hex: &str) -> Option<[f32; 3]> {
+    let digits = hex.strip_prefix('#').filter(|d| d.len() == 6)?;
+    let mut rgb = [0.0; 3];
+    for (c, value) in rgb.iter_mut().enumerate() {
+        let byte = u8::from_str_radix(digits.get(c * 2..c * 2 + 2)?, 16).ok()?;
+        *value = srgb_to_linear(byte as f32 / 255.0);
+    }
+    Some(rgb)
+}
+
+/// Reads the negative conversion of the adjustments JSON, using the same keys
+/// as desktop: `enableNegativeConversion`, `filmBaseColor` (`#rrggbb`) and
+/// `negativeRedBalance`/`negativeGreenBalance`/`negativeBlueBalance` (offsets
+/// from 1). `None` when conversion is off.
+pub fn negative_from_adjustments(adjustments_json: &str) -> Option<NegativeConversion> {
+    let adjustments: serde_json::Value = serde_json::from_str(adjustments_json).ok()?;
+    if !adjustments["enableNegativeConversion"]
+        .as_bool()
+        .unwrap_or(false)
+    {
+        return None;
+    }
+    let film_base = adjustments["filmBaseColor"]
+        .as_str()
+        .and_then(hex_to_linear)
+        .or_else(|| hex_to_linear(DEFAULT_FILM_BASE))?;
+    let balance = [
+        "negativeRedBalance",
+        "negativeGreenBalance",
+        "negativeBlueBalance",
+    ]
+    .map(|key| 1.0 + adjustments[key].as_f64().unwrap_or(0.0) as f32);
+    Some(NegativeConversion { film_base, balance })
+}
+
+/// Positive value, 0 at the base and 1 at `MAX_DENSITY`, for a channel with
+/// `transmittance` relative to the film base. Density is `-log10` of the
+/// transmittance and exposure is `10^(density / FILM_GAMMA)`, so the result is
+/// `transmittance^(-1 / FILM_GAMMA)` rescaled: linear in scene exposure, which
+/// a straight `1 - transmittance` is not.
+fn transmittance_to_positive(transmittance: f32) -> f32 {
+    let min_transmittance = 10f32.powf(-MAX_DENSITY);
+    let exponent = -1.0 / FILM_GAMMA;
+    let exposure = transmittance.clamp(min_transmittance, 1.0).powf(exponent);
+    (exposure - 1.0) / (min_transmittance.powf(exponent) - 1.0)
+}
+
+/// Converts a negative in the linear pipeline space to a positive: each
+/// channel is divided by the film base (giving the film's transmittance, 1 at
+/// the base), mapped from density back to exposure (see
+/// `transmittance_to_positive`) and scaled by `balance`. Alpha is kept.
+pub fn invert_negative(image: &mut DynamicImage, film_base: [f32; 3], balance: [f32; 3]) {
+    *image = to_adjustment_buffer(std::mem::take(image));
+    let channels = if image.color().has_alpha() { 4 } else { 3 };
+    let data: &mut [f32] = match image {
+        DynamicImage::ImageRgba32F(buffer) => buffer.as_mut(),
+        DynamicImage::ImageRgb32F(buffer) => buffer.as_mut(),
+        _ => return,
+    };
+
+    let base = film_base.map(|c| c.max(MIN_FILM_BASE));
+    for pixel in data.chunks_exact_mut(channels) {
+        for c in 0..3 {
+            let positive = transmittance_to_positive(pixel[c] / base[c]);
+            pixel[c] = positive * balance[c].max(0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, Rgba32FImage};
+
+    #[test]
+    fn film_base_is_black_and_max_density_is_white() {
+        assert_eq!(transmittance_to_positive(1.0), 0.0);
+        assert_eq!(transmittance_to_positive(1.5), 0.0);
+        assert!((transmittance_to_positive(10f32.powf(-MAX_DENSITY)) - 1.0).abs() < 1e-5);
+        assert!((transmittance_to_positive(1e-6) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn one_stop_more_exposure_doubles_the_exposure_term() {
+        let scale = 10f32.powf(MAX_DENSITY / FILM_GAMMA) - 1.0;
+        let exposure = |t: f32| transmittance_to_positive(t) * scale + 1.0;
+        let stop_denser = 10f32.powf(-FILM_GAMMA * 2f32.log10());
+        for t in [0.8, 0.3, 0.1] {
+            let ratio = exposure(t * stop_denser) / exposure(t);
+            assert!((ratio - 2.0).abs() < 1e-3, "ratio {ratio} at {t}");
+        }
+    }
+
+    #[test]
+    fn invert_negative_divides_out_the_base_and_applies_balance() {
+        let base = [0.8, 0.4, 0.2];
+        let pixels = [Rgba([0.8, 0.4, 0.2, 1.0]), Rgba([0.08, 0.04, 0.02, 0.5])];
+        let mut image =
+            DynamicImage::ImageRgba32F(Rgba32FImage::from_fn(2, 1, |x, _| pixels[x as usize]));
+
+        invert_negative(&mut image, base, [1.0, 0.5, 2.0]);
+
+        let out = image.to_rgba32f();
+        assert_eq!(out.get_pixel(0, 0).0, [0.0, 0.0, 0.0, 1.0]);
+        let dense = out.get_pixel(1, 0);
+        let expected = transmittance_to_positive(0.1);
+        assert!((dense[0] - expected).abs() < 1e-5);
+        assert!((dense[1] - expected * 0.5).abs() < 1e-5);
+        assert!((dense[2] - expected * 2.0).abs() < 1e-5);
+        assert_eq!(dense[3], 0.5);
+    }
+
+    #[test]
+    fn conversion_is_read_with_the_desktop_keys() {
+        assert!(negative_from_adjustments(r##"{"filmBaseColor": "#ffffff"}"##).is_none());
+
+        let conversion = negative_from_adjustments(
+            r##"{"enableNegativeConversion": true, "filmBaseColor": "#ffffff",
+            "negativeRedBalance": 0.2, "negativeBlueBalance": -0.5}"##,
+        )
+        .unwrap();
+        assert_eq!(conversion.film_base, [1.0; 3]);
+        assert!((conversion.balance[0] - 1.2).abs() < 1e-6);
+        assert_eq!(conversion.balance[1..], [1.0, 0.5]);
+
+        // A missing or malformed base falls back to the desktop default orange.
+        let fallback = hex_to_linear(DEFAULT_FILM_BASE).unwrap();
+        for base in [
+            serde_json::json!(""),
+            serde_json::json!("#ff88"),
+            serde_json::json!("#gg8800"),
+            serde_json::Value::Null,
+        ] {
+            let json =
+                serde_json::json!({ "enableNegativeConversion": true, "filmBaseColor": base });
+            let conversion = negative_from_adjustments(&json.to_string()).unwrap();
+            assert_eq!(conversion.film_base, fallback);
+        }
+        assert_eq!(fallback[0], 1.0);
+        assert_eq!(fallback[2], 0.0);
+    }
+}
@@ -0,0 +1,364 @@
+//! Two-frame panorama stitching.
+//!
+//! The right frame is placed against the left by a horizontal overlap, a
+//! vertical offset and a small rotation, found by normalised cross-correlation
+//! of luma, and the overlap is feather-blended. There is no projection model:
+//! this suits shots panned on a level tripod with modest lens distortion.
+
+use crate::core::image_loader::{load_frame_from_bytes, DEFAULT_HIGHLIGHT_COMPRESSION};
+use anyhow::{bail, Context, Result};
+use image::{DynamicImage, Rgb32FImage, Rgba, Rgba32FImage};
+use rapidraw_core::luma::{luma, LumaCoefficients};
+use rapidraw_core::srgb::linear_to_srgb;
+
+/// Height the coarse search runs at.
+const SEARCH_HEIGHT: u32 = 128;
+/// Rotations tried for the right frame, in degrees.
+const SEARCH_ANGLES: [f32; 7] = [-1.5, -1.0, -0.5, 0.0, 0.5, 1.0, 1.5];
+/// Overlap searched when no hint is given, as fractions of the narrower frame.
+const DEFAULT_OVERLAP_RANGE: (f32, f32) = (0.05, 0.7);
+/// Largest vertical offset searched, as a fraction of the frame height.
+const MAX_VERTICAL_SHIFT: f32 = 0.05;
+/// Fewest overlapping samples a candidate needs to be scored.
+const MIN_SAMPLES: usize = 64;
+
+/// Where the right frame sits relative to the left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PairPlacement {
+    /// Columns shared by the two frames.
+    pub overlap: u32,
+    /// Rows the right frame is shifted down by (negative: up).
+    pub vertical_offset: i32,
+    /// Rotation of the right frame about its centre, in degrees.
+    pub angle: f32,
+}
+
+/// Decodes and stitches a left/right pair. `overlap_hint` is the expected
+/// overlap as a fraction of the left frame's width; pass 0 to search a wide
+/// range.
+pub fn stitch_pair(left: &[u8], right: &[u8], overlap_hint: f32) -> Result<DynamicImage> {
+    let decode = |bytes: &[u8], side: &str| {
+        load_frame_from_bytes(bytes, "", DEFAULT_HIGHLIGHT_COMPRESSION)
+            .map(DynamicImage::into_rgb32f)
+            .with_context(|| format!("Failed to decode the {side} panorama frame"))
+    };
+    let left = decode(left, "left")?;
+    let right = decode(right, "right")?;
+    let placement = find_placement(&left, &right, overlap_hint)?;
+    Ok(compose(&left, &right, &placement))
+}
+
+/// Single-channel float plane with bilinear sampling.
+struct Plane {
+    data: Vec<f32>,
+    width: u32,
+    height: u32,
+}
+
+impl Plane {
+    /// Encoded luma of `image`, box-downscaled by `factor`.
+    fn luma(image: &Rgb32FImage, factor: u32) -> Plane {
+        let (width, height) = (image.width() / factor, image.height() / factor);
+        let mut data = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = 0.0;
+                for sy in 0..factor {
+                    for sx in 0..factor {
+                        let p = image.get_pixel(x * factor + sx, y * factor + sy);
+                        sum += luma(p[0], p[1], p[2], LumaCoefficients::Rec709);
+                    }
+                }
+                data.push(linear_to_srgb((sum / (factor * factor) as f32).max(0.0)));
+            }
+        }
+        Plane {
+            data,
+            width,
+            height,
+        }
+    }
+
+    /// This plane rotated by `angle` degrees about its centre; samples that
+    /// fall outside are NaN.
+    fn rotated(&self, angle: f32) -> Plane {
+        if angle == 0.0 {
+            return Plane {
+                data: self.data.clone(),
+                width: self.width,
+                height: self.height,
+            };
+        }
+        let mut data = Vec::with_capacity(self.data.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (sx, sy) = rotate_back(x as f32, y as f32, self.width, self.height, angle);
+                data.push(
+                    bilinear(&self.data, self.width, self.height, 1, sx, sy)
+                        .map_or(f32::NAN, |v| v[0]),
+                );
+            }
+        }
+        Plane {
+            data,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+/// Source position in an unrotated `width` x `height` frame for output
+/// position `(x, y)` of the frame rotated by `angle` degrees about its centre.
+fn rotate_back(x: f32, y: f32, width: u32, height: u32, angle: f32) -> (f32, f32) {
+    let (sin, cos) = (-angle.to_radians()).sin_cos();
+    let (cx, cy) = (width as f32 * 0.5, height as f32 * 0.5);
+    let (dx, dy) = (x + 0.5 - cx, y + 0.5 - cy);
+    (
+        cx + dx * cos - dy * sin - 0.5,
+        cy + dx * sin + dy * cos - 0.5,
+    )
+}
+
+/// Bilinear sample of an interleaved `channels`-wide buffer, or `None` off
+/// the edge.
+fn bilinear(
+    data: &[f32],
+    width: u32,
+    height: u32,
+    channels: usize,
+    x: f32,
+    y: f32,
+) -> Option<[f32; 3]> {
+    if x < 0.0 || y < 0.0 || x > (width - 1) as f32 || y > (height - 1) as f32 {
+        return None;
+    }
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let x1 = (x0 + 1).min(width as usize - 1);
+    let y1 = (y0 + 1).min(height as usize - 1);
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let w = width as usize;
+    let mut out = [0.0; 3];
+    for (c, o) in out.iter_mut().enumerate().take(channels) {
+        let at = |px: usize, py: usize| data[(py * w + px) * channels + c];
+        let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * fx;
+        let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * fx;
+        *o = top + (bottom - top) * fy;
+    }
+    Some(out)
+}
+
+/// Normalised cross-correlation between the last `overlap` columns of `left`
+/// and the first of `right` shifted down by `dy`, sampling every `step`th
+/// pixel. `None` when too few samples overlap.
+fn correlation(left: &Plane, right: &Plane, overlap: u32, dy: i32, step: usize) -> Option<f32> {
+    let x0 = left.width.checked_sub(overlap)?;
+    let (mut sa, mut sb, mut saa, mut sbb, mut sab, mut n) = (0.0f64, 0.0, 0.0, 0.0, 0.0, 0usize);
+    for y in (0..left.height).step_by(step) {
+        let ry = y as i32 - dy;
+        if ry < 0 || ry >= right.height as i32 {
+            continue;
+        }
+        for x in (x0..left.width).step_by(step) {
+            let rx = x - x0;
+            if rx >= right.width {
+                break;
+            }
+            let a = left.data[(y * left.width + x) as usize] as f64;
+            let b = right.data[(ry as u32 * right.width + rx) as usize] as f64;
+            if b.is_nan() {
+                continue;
+            }
+            sa += a;
+            sb += b;
+            saa += a * a;
+            sbb += b * b;
+            sab += a * b;
+            n += 1;
+        }
+    }
+    if n < MIN_SAMPLES {
+        return None;
+    }
+    let n = n as f64;
+    let variance = (n * saa - sa * sa) * (n * sbb - sb * sb);
+    if variance <= 1e-12 {
+        return None;
+    }
+    Some(((n * sab - sa * sb) / variance.sqrt()) as f32)
+}
+
+/// Best overlap, vertical offset and angle: a coarse search over all three on
+/// downscaled luma, then the offsets refined at full resolution.
+pub fn find_placement(
+    left: &Rgb32FImage,
+    right: &Rgb32FImage,
+    overlap_hint: f32,
+) -> Result<PairPlacement> {
+    let narrow = left.width().min(right.width());
+    let (lo, hi) = if overlap_hint > 0.0 && overlap_hint < 1.0 {
+        (overlap_hint * 0.5, (overlap_hint * 1.5).min(0.95))
+    } else {
+        DEFAULT_OVERLAP_RANGE
+    };
+    let overlap_range = ((narrow as f32 * lo) as u32).max(1)..=(narrow as f32 * hi) as u32;
+
+    let factor = (left.height().max(right.height()) / SEARCH_HEIGHT).max(1);
+    let small_left = Plane::luma(left, factor);
+    let small_right = Plane::luma(right, factor);
+    let max_dy = ((left.height() as f32 * MAX_VERTICAL_SHIFT) as i32 / factor as i32).max(1);
+
+    let mut best: Option<(f32, PairPlacement)> = None;
+    for angle in SEARCH_ANGLES {
+        let rotated = small_right.rotated(angle);
+        for overlap in (overlap_range.start() / factor)..=(overlap_range.end() / factor) {
+            for dy in -max_dy..=max_dy {
+                let Some(score) = correlation(&small_left, &rotated, overlap, dy, 1) else {
+                    continue;
+                };
+                if best.is_none_or(|(s, _)| score > s) {
+                    best = Some((
+                        score,
+                        PairPlacement {
+                            overlap,
+                            vertical_offset: dy,
+                            angle,
+                        },
+                    ));
+                }
+            }
+        }
+    }
+    let Some((_, coarse)) = best else {
+        bail!("Could not find an overlap between the panorama frames");
+    };
+
+    if factor == 1 {
+        return Ok(coarse);
+    }
+
+    let full_left = Plane::luma(left, 1);
+    let full_right = Plane::luma(right, 1).rotated(coarse.angle);
+    let radius = factor as i32;
+    let mut refined = (f32::MIN, coarse);
+    for d_overlap in -radius..=radius {
+        let overlap = coarse.overlap as i32 * factor as i32 + d_overlap;
+        if overlap <= 0 {
+            continue;
+        }
+        for d_dy in -radius..=radius {
+            let dy = coarse.vertical_offset * factor as i32 + d_dy;
+            let score = correlation(&full_left, &full_right, overlap as u32, dy, factor as usize);
+            if let Some(score) = score.filter(|s| *s > refined.0) {
+                refined = (
+                    score,
+                    PairPlacement {
+                        overlap: overlap as u32,
+                        vertical_offset: dy,
+                        angle: coarse.angle,
+                    },
+                );
+            }
+        }
+    }
+    Ok(refined.1)
+}
+
+/// Lays both frames onto one canvas, blending linearly across the overlap.
+/// Canvas areas neither frame covers are transparent.
+pub fn compose(left: &Rgb32FImage, right: &Rgb32FImage, placement: &PairPlacement) -> DynamicImage {
+    let right_x = left.width() as i32 - placement.overlap as i32;
+    let top = placement.vertical_offset.min(0);
+    let bottom = (left.height() as i32).max(right.height() as i32 + placement.vertical_offset);
+    let width = (right_x + right.width() as i32).max(left.width() as i32) as u32;
+    let height = (bottom - top) as u32;
+
+    let sample_left = |x: i32, y: i32| -> Option<[f32; 3]> {
+        let ly = y + top;
+        (x < left.width() as i32 && ly >= 0 && ly < left.height() as i32)
+            .then(|| left.get_pixel(x as u32, ly as u32).0)
+    };
+    let sample_right = |x: i32, y: i32| -> Option<[f32; 3]> {
+        let (rx, ry) = (x - right_x, y + top - placement.vertical_offset);
+        let (sx, sy) = rotate_back(
+            rx as f32,
+            ry as f32,
+            right.width(),
+            right.height(),
+            placement.angle,
+        );
+        bilinear(right.as_raw(), right.width(), right.height(), 3, sx, sy)
+    };
+
+    let feather = placement.overlap.max(1) as f32;
+    let canvas = Rgba32FImage::from_fn(width, height, |x, y| {
+        let (x, y) = (x as i32, y as i32);
+        match (sample_left(x, y), sample_right(x, y)) {
+            (Some(l), Some(r)) => {
+                let t = ((x - right_x) as f32 / feather).clamp(0.0, 1.0);
+                Rgba([
+                    l[0] + (r[0] - l[0]) * t,
+                    l[1] + (r[1] - l[1]) * t,
+                    l[2] + (r[2] - l[2]) * t,
+                    1.0,
+                ])
+            }
+            (Some(p), None) | (None, Some(p)) => Rgba([p[0], p[1], p[2], 1.0]),
+            (None, None) => Rgba([0.0; 4]),
+        }
+    });
+    DynamicImage::ImageRgba32F(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Columns `x0..x1` of a 200x60 scene of pseudo-random 4x4 blocks.
+    fn scene_crop(x0: u32, x1: u32) -> Rgb32FImage {
+        Rgb32FImage::from_fn(x1 - x0, 60, |x, y| {
+            let cell = ((x + x0) / 4 * 7919 + y / 4 * 104_729).wrapping_mul(2_654_435_761);
+            image::Rgb([0.05 + 0.9 * (cell >> 8) as f32 / (1 << 24) as f32; 3])
+        })
+    }
+
+    #[test]
+    fn placement_is_found_and_composed_back_into_the_scene() {
+        let (left, right) = (scene_crop(0, 120), scene_crop(80, 200));
+        let placement = find_placement(&left, &right, 0.0).unwrap();
+        assert_eq!(
+            placement,
+            PairPlacement {
+                overlap: 40,
+                vertical_offset: 0,
+                angle: 0.0,
+            }
+        );
+
+        let stitched = compose(&left, &right, &placement);
+        let stitched = stitched.as_rgba32f().unwrap();
+        assert_eq!(stitched.dimensions(), (200, 60));
+        let scene = scene_crop(0, 200);
+        for (x, y) in [(10, 5), (100, 30), (190, 55)] {
+            let (got, want) = (stitched.get_pixel(x, y), scene.get_pixel(x, y));
+            assert!(
+                (got[0] - want[0]).abs() < 1e-4 && got[3] == 1.0,
+                "({x}, {y})"
+            );
+        }
+    }
+
+    #[test]
+    fn vertical_offset_leaves_uncovered_canvas_transparent() {
+        let frame = scene_crop(0, 20);
+        let placement = PairPlacement {
+            overlap: 5,
+            vertical_offset: 3,
+            angle: 0.0,
+        };
+        let stitched = compose(&frame, &frame, &placement);
+        let stitched = stitched.as_rgba32f().unwrap();
+        assert_eq!(stitched.dimensions(), (35, 63));
+        assert_eq!(stitched.get_pixel(30, 0)[3], 0.0);
+        assert_eq!(stitched.get_pixel(2, 62)[3], 0.0);
+    }
+}
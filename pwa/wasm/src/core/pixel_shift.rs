@@ -0,0 +1,251 @@
+//! Pixel-shift composites: several RAWs of a static scene, each taken with the
+//! sensor moved by one photosite, combined so every pixel has a measured red,
+//! green and blue value instead of demosaiced estimates.
+//!
+//! Supported pattern: four frames from a 2x2 Bayer sensor, in the order of
+//! `FOUR_SHOT_OFFSETS` (no shift, one right, one right and down, one down),
+//! which is the common four-shot sequence. Frames must come from the same
+//! camera with identical sensor geometry. The shifts are whole photosites
+//! and known in advance, so alignment is a fixed offset per frame; subject
+//! or camera motion between frames is not compensated and shows as
+//! color-fringed ghosts.
+
+use crate::core::image_processing::apply_orientation;
+use crate::core::raw_processing::read_orientation;
+use anyhow::{anyhow, bail, Result};
+use image::{DynamicImage, ImageBuffer, Rgba};
+use rapidraw_core::highlight::{compress_highlights, HIGHLIGHT_KNEE};
+use rapidraw_core::raw_sanity::sanitize_levels;
+use rawler::{
+    decoders::RawDecodeParams,
+    formats::tiff::Rational,
+    imgop::develop::{Intermediate, ProcessingStep, RawDevelop},
+    rawimage::{BlackLevel, RawImage, RawImageData, RawPhotometricInterpretation, WhiteLevel},
+    rawsource::RawSource,
+};
+
+/// Sensor offset of each frame relative to the first, in photosites
+/// (x, y): frame pixel (x, y) sees the scene point at (x + dx, y + dy).
+pub const FOUR_SHOT_OFFSETS: [(usize, usize); 4] = [(0, 0), (1, 0), (1, 1), (0, 1)];
+
+/// Per-color black level (R, G, B) of a Bayer frame, averaging the CFA
+/// positions of each color.
+fn bayer_black_levels(raw_image: &RawImage, color_at: impl Fn(usize, usize) -> usize) -> [f32; 3] {
+    let levels = &raw_image.blacklevel.levels;
+    if levels.is_empty() {
+        return [0.0; 3];
+    }
+    let mut sums = [0.0f32; 3];
+    let mut counts = [0u32; 3];
+    for row in 0..2 {
+        for col in 0..2 {
+            let level = levels[(row * 2 + col) % levels.len()].as_f32();
+            let color = color_at(row, col);
+            sums[color] += level;
+            counts[color] += 1;
+        }
+    }
+    std::array::from_fn(|c| sums[c] / counts[c].max(1) as f32)
+}
+
+/// Gathers, for every scene point, the sample each shifted frame (see
+/// `FOUR_SHOT_OFFSETS`) took of it and averages them per color, black
+/// subtracted. Returns `width` x `height` RGB samples; greens arrive twice.
+fn combine_shifted_frames(
+    frames: &[&RawImageData],
+    width: usize,
+    height: usize,
+    color_at: impl Fn(usize, usize) -> usize,
+    black: [f32; 3],
+) -> Vec<u16> {
+    // Frame photosite that saw scene point `p`. A shifted frame has no data
+    // for the first row/column, so there the photosite one CFA period in is
+    // used: same color, offset by one photosite.
+    let seen_at = |p: usize, d: usize, len: usize| {
+        if p >= d {
+            p - d
+        } else {
+            (p + 2 - d).min(len - 1)
+        }
+    };
+
+    let mut sums = vec![[0.0f32; 3]; width * height];
+    let mut counts = vec![[0u8; 3]; width * height];
+    for (data, &(dx, dy)) in frames.iter().zip(FOUR_SHOT_OFFSETS.iter()) {
+        let sample = |index: usize| -> f32 {
+            match data {
+                RawImageData::Integer(data) => data[index] as f32,
+                RawImageData::Float(data) => data[index],
+            }
+        };
+        for y in 0..height {
+            let sy = seen_at(y, dy, height);
+            for x in 0..width {
+                let sx = seen_at(x, dx, width);
+                let color = color_at(sy, sx);
+                let out = y * width + x;
+                sums[out][color] += sample(sy * width + sx) - black[color];
+                counts[out][color] += 1;
+            }
+        }
+    }
+
+    sums.iter()
+        .zip(&counts)
+        .flat_map(|(sum, count)| {
+            [0, 1, 2].map(|c| {
+                (sum[c] / count[c].max(1) as f32)
+                    .round()
+                    .clamp(0.0, u16::MAX as f32) as u16
+            })
+        })
+        .collect()
+}
+
+/// Combines four pixel-shifted Bayer RAWs (see `FOUR_SHOT_OFFSETS`) into a
+/// full-color image in the pipeline space. `highlight_compression` is as in
+/// `raw_processing::develop_raw_image`.
+pub fn develop_pixel_shift(frames: &[&[u8]], highlight_compression: f32) -> Result<DynamicImage> {
+    if frames.len() != FOUR_SHOT_OFFSETS.len() {
+        bail!(
+            "pixel shift needs {} frames, got {}",
+            FOUR_SHOT_OFFSETS.len(),
+            frames.len()
+        );
+    }
+
+    let mut raws = Vec::with_capacity(frames.len());
+    let mut orientation = None;
+    for bytes in frames {
+        let source = RawSource::new_from_slice(bytes);
+        let decoder = rawler::get_decoder(&source)?;
+        orientation.get_or_insert(read_orientation(decoder.as_ref(), &source)?);
+        let mut raw_image = decoder.raw_image(&source, &RawDecodeParams::default(), false)?;
+        sanitize_levels(&mut raw_image);
+        raws.push(raw_image);
+    }
+    let first = &raws[0];
+    let RawPhotometricInterpretation::Cfa(config) = &first.photometric else {
+        bail!("pixel shift needs CFA (Bayer) frames");
+    };
+    let cfa = &config.cfa;
+    if cfa.width != 2 || cfa.height != 2 || first.cpp != 1 {
+        bail!(
+            "pixel shift supports 2x2 Bayer sensors only, not {}",
+            cfa.name
+        );
+    }
+    if raws
+        .iter()
+        .any(|raw| raw.width != first.width || raw.height != first.height || raw.cpp != first.cpp)
+    {
+        bail!("pixel shift frames have different sensor sizes");
+    }
+
+    let (width, height) = (first.width, first.height);
+    let color_at = |row: usize, col: usize| cfa.color_at(row, col).min(2);
+    let black = bayer_black_levels(first, color_at);
+    let white = first
+        .whitelevel
+        .0
+        .first()
+        .copied()
+        .unwrap_or(u16::MAX as u32) as f32;
+
+    let frame_data: Vec<&RawImageData> = raws.iter().map(|raw| &raw.data).collect();
+    let data = combine_shifted_frames(&frame_data, width, height, color_at, black);
+
+    // Black is already subtracted; the white level is lifted out of the way
+    // and applied as a rescale afterwards so highlights keep their headroom,
+    // as `develop_raw_image` does.
+    let mut combined = first.clone();
+    combined.cpp = 3;
+    combined.photometric = RawPhotometricInterpretation::LinearRaw;
+    combined.data = RawImageData::Integer(data);
+    combined.blacklevel = BlackLevel {
+        levels: vec![Rational { n: 0, d: 1 }; 3],
+        cpp: 3,
+        width: 1,
+        height: 1,
+    };
+    combined.whitelevel = WhiteLevel(vec![u32::MAX; 3]);
+    let average_black = black.iter().sum::<f32>() / 3.0;
+    let rescale_factor = u32::MAX as f32 / (white - average_black).max(1.0);
+
+    let mut developer = RawDevelop::default();
+    developer
+        .steps
+        .retain(|&step| step != ProcessingStep::Demosaic && step != ProcessingStep::SRgb);
+    let Intermediate::ThreeColor(pixels) = developer.develop_intermediate(&combined)? else {
+        return Err(anyhow!("pixel shift develop did not produce RGB"));
+    };
+
+    let (out_width, out_height) = (pixels.width as u32, pixels.height as u32);
+    let buffer = ImageBuffer::<Rgba<f32>, _>::from_fn(out_width, out_height, |x, y| {
+        let p = pixels.data[(y * out_width + x) as usize];
+        let rgb = p.map(|c| (c * rescale_factor).max(0.0));
+        let [r, g, b] = compress_highlights(rgb, HIGHLIGHT_KNEE, highlight_compression);
+        Rgba([r, g, b, 1.0])
+    });
+
+    Ok(apply_orientation(
+        DynamicImage::ImageRgba32F(buffer),
+        orientation.unwrap_or(rawler::decoders::Orientation::Normal),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RGGB: red at (0, 0), blue at (1, 1), green elsewhere.
+    fn rggb(row: usize, col: usize) -> usize {
+        match (row % 2, col % 2) {
+            (0, 0) => 0,
+            (1, 1) => 2,
+            _ => 1,
+        }
+    }
+
+    #[test]
+    fn shifted_frames_give_every_pixel_all_three_colors() {
+        let (width, height) = (6, 4);
+        let scene = |x: usize, y: usize, color: usize| (100 * (color + 1) + 10 * y + x) as u16;
+        let black = [64.0, 60.0, 64.0];
+        let frames: Vec<RawImageData> = FOUR_SHOT_OFFSETS
+            .iter()
+            .map(|&(dx, dy)| {
+                let data = (0..width * height)
+                    .map(|i| {
+                        let (x, y) = (i % width, i / width);
+                        let color = rggb(y, x);
+                        scene(x + dx, y + dy, color) + black[color] as u16
+                    })
+                    .collect();
+                RawImageData::Integer(data)
+            })
+            .collect();
+        let frame_refs: Vec<&RawImageData> = frames.iter().collect();
+
+        let combined = combine_shifted_frames(&frame_refs, width, height, rggb, black);
+        assert_eq!(combined.len(), width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let rgb = &combined[(y * width + x) * 3..][..3];
+                // The first row and column borrow a photosite one step in.
+                if x > 0 && y > 0 {
+                    assert_eq!(rgb, [scene(x, y, 0), scene(x, y, 1), scene(x, y, 2)]);
+                } else {
+                    assert!(rgb.iter().all(|&c| c > 0), "({x}, {y}): {rgb:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pixel_shift_needs_four_frames() {
+        let frame: &[u8] = b"not a raw";
+        let err = develop_pixel_shift(&[frame, frame], 0.0).unwrap_err();
+        assert!(err.to_string().contains("needs 4 frames"), "{err}");
+    }
+}
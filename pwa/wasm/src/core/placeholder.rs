@@ -0,0 +1,66 @@
+//! Stand-in image for RAW files that cannot be decoded in this build.
+//!
+//! The output depends only on the file contents, so the same file always gets
+//! the same placeholder. Callers must surface `is_placeholder` to the user;
+//! nothing here resembles the real photo.
+
+use crate::core::working_space;
+use image::{DynamicImage, Rgb, RgbImage};
+
+pub const PLACEHOLDER_WIDTH: u32 = 480;
+pub const PLACEHOLDER_HEIGHT: u32 = 320;
+
+/// FNV-1a; only used to seed the placeholder, not for identity.
+fn content_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+struct XorShift64(u64);
+
+impl XorShift64 {
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+}
+
+/// A neutral diagonal gradient with low-amplitude seeded noise.
+pub fn placeholder_image(data: &[u8]) -> DynamicImage {
+    let mut rng = XorShift64(content_hash(data) | 1);
+    let span = (PLACEHOLDER_WIDTH + PLACEHOLDER_HEIGHT) as f32;
+    let image = RgbImage::from_fn(PLACEHOLDER_WIDTH, PLACEHOLDER_HEIGHT, |x, y| {
+        let base = 64.0 + 96.0 * (x + y) as f32 / span;
+        let noise = (rng.next() % 17) as f32 - 8.0;
+        let v = (base + noise).clamp(0.0, 255.0) as u8;
+        Rgb([v, v, v])
+    });
+    working_space::from_srgb(DynamicImage::ImageRgb8(image))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholder_depends_only_on_the_file_contents() {
+        let first = placeholder_image(b"not a raw file");
+        assert_eq!(
+            (first.width(), first.height()),
+            (PLACEHOLDER_WIDTH, PLACEHOLDER_HEIGHT)
+        );
+        assert_eq!(
+            first.as_bytes(),
+            placeholder_image(b"not a raw file").as_bytes()
+        );
+        assert_ne!(
+            first.as_bytes(),
+            placeholder_image(b"another raw file").as_bytes()
+        );
+    }
+}
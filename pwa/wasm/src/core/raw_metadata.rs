@@ -1,6 +1,34 @@
-use anyhow::Result;
+use crate::core::film_simulation::FilmSimulation;
+use anyhow::{Context, Result};
+use rawler::cfa::CFA;
+use rawler::formats::tiff::{GenericTiffReader, Value, IFD};
+use rawler::rawimage::RawPhotometricInterpretation;
 use rawler::rawsource::RawSource;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+
+const TAG_SUB_IFDS: u16 = 0x014A;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_INTEROP_IFD: u16 = 0xA005;
+const TAG_MAKER_NOTE: u16 = 0x927C;
+/// DNG ProfileToneCurve: (input, output) pairs, both 0..1.
+const TAG_PROFILE_TONE_CURVE: u16 = 0xC6FC;
+/// DNG BaselineExposure: EV the camera's rendering adds on top of the
+/// metered raw data.
+const TAG_BASELINE_EXPOSURE: u16 = 0xC62A;
+/// DNG DefaultScale: horizontal and vertical scale that make the pixels
+/// square.
+const TAG_DEFAULT_SCALE: u16 = 0xC61E;
+
+/// Fujifilm MakerNote header: the maker name, then the IFD offset (always
+/// 12) relative to the start of the note.
+const FUJI_MAKER_NOTE_HEADER: &[u8] = b"FUJIFILM\x0c\x00\x00\x00";
+const FUJI_TAG_SATURATION: u16 = 0x1003;
+const FUJI_TAG_FILM_MODE: u16 = 0x1401;
+
+/// Array values (tone curves, maker blobs) are cut to this many items.
+const MAX_VALUE_ITEMS: usize = 64;
 
 fn normalize_date_string(value: String) -> String {
     let clean = value.replace('"', "").trim().to_string();
@@ -40,9 +68,24 @@ pub fn extract_raw_metadata(bytes: &[u8]) -> Result<HashMap<String, String>> {
         }
     };
 
+    if metadata.make.to_ascii_uppercase().contains("FUJIFILM") {
+        insert_if_present("FilmSimulation", read_fuji_film_simulation(bytes).name().to_string());
+    }
+
     insert_if_present("Make", metadata.make);
     insert_if_present("Model", metadata.model);
 
+    // Only the header is needed for the CFA layout; skip the pixel data.
+    let photometric = decoder
+        .raw_image(&raw_source, &Default::default(), true)
+        .ok()
+        .map(|raw_image| raw_image.photometric);
+    if let Some(RawPhotometricInterpretation::Cfa(config)) = photometric {
+        for (key, value) in cfa_entries(&config.cfa) {
+            insert_if_present(key, value);
+        }
+    }
+
     if let Some(v) = exif.artist { insert_if_present("Artist", v); }
     if let Some(v) = exif.copyright { insert_if_present("Copyright", v); }
     if let Some(v) = exif.owner_name { insert_if_present("OwnerName", v); }
@@ -186,3 +229,301 @@ pub fn extract_raw_metadata(bytes: &[u8]) -> Result<HashMap<String, String>> {
 
     Ok(map)
 }
+
+/// Metadata entries describing the color filter layout. X-Trans patterns
+/// are named by size, as their 36 letters mean little to a reader.
+fn cfa_entries(cfa: &CFA) -> [(&'static str, String); 4] {
+    let is_xtrans = cfa.width == 6 && cfa.height == 6;
+    let pattern = if is_xtrans {
+        "X-Trans 6x6".to_string()
+    } else {
+        cfa.name.clone()
+    };
+    [
+        ("CFAPattern", pattern),
+        ("CFAWidth", cfa.width.to_string()),
+        ("CFAHeight", cfa.height.to_string()),
+        ("IsXTrans", is_xtrans.to_string()),
+    ]
+}
+
+/// Reads SHORT values of `tags` from the Fujifilm MakerNote IFD. The note
+/// sits in the Exif block of the embedded JPEG, which rawler does not parse,
+/// so it is found by its header. The IFD is always little-endian.
+fn read_fuji_maker_note_shorts<const N: usize>(
+    bytes: &[u8],
+    tags: [u16; N],
+) -> [Option<u16>; N] {
+    let mut values = [None; N];
+    let Some(start) = bytes
+        .windows(FUJI_MAKER_NOTE_HEADER.len())
+        .position(|window| window == FUJI_MAKER_NOTE_HEADER)
+    else {
+        return values;
+    };
+    let note = &bytes[start..];
+    let u16_at = |pos: usize| note.get(pos..pos + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let Some(count) = u16_at(FUJI_MAKER_NOTE_HEADER.len()) else {
+        return values;
+    };
+    for entry in 0..count as usize {
+        let pos = FUJI_MAKER_NOTE_HEADER.len() + 2 + entry * 12;
+        let (Some(tag), Some(kind)) = (u16_at(pos), u16_at(pos + 2)) else {
+            break;
+        };
+        // Type 3 is SHORT; a single one is stored inline in the value field.
+        match tags.iter().position(|&t| t == tag) {
+            Some(slot) if kind == 3 => values[slot] = u16_at(pos + 8),
+            _ => {}
+        }
+    }
+    values
+}
+
+/// The film simulation a Fujifilm RAW was shot with, Provia/Standard when
+/// the MakerNote does not say.
+fn read_fuji_film_simulation(bytes: &[u8]) -> FilmSimulation {
+    let [film_mode, saturation] =
+        read_fuji_maker_note_shorts(bytes, [FUJI_TAG_FILM_MODE, FUJI_TAG_SATURATION]);
+    FilmSimulation::from_fuji_codes(film_mode, saturation)
+}
+
+fn sub_ifd_name(tag: u16) -> String {
+    match tag {
+        TAG_SUB_IFDS => "SubIFD".to_string(),
+        TAG_EXIF_IFD => "Exif".to_string(),
+        TAG_GPS_IFD => "GPS".to_string(),
+        TAG_INTEROP_IFD => "Interop".to_string(),
+        TAG_MAKER_NOTE => "MakerNote".to_string(),
+        other => format!("0x{other:04X}"),
+    }
+}
+
+fn dump_ifd(ifd: &IFD, path: &str, map: &mut BTreeMap<String, String>) {
+    for (tag, entry) in ifd.entries() {
+        map.insert(
+            format!("{path}.0x{tag:04X}"),
+            entry.value.visual_rep(MAX_VALUE_ITEMS),
+        );
+    }
+    for (tag, sub_ifds) in ifd.sub_ifds() {
+        let name = sub_ifd_name(*tag);
+        for (index, sub_ifd) in sub_ifds.iter().enumerate() {
+            let sub_path = if sub_ifds.len() > 1 {
+                format!("{path}.{name}{index}")
+            } else {
+                format!("{path}.{name}")
+            };
+            dump_ifd(sub_ifd, &sub_path, map);
+        }
+    }
+}
+
+/// Every entry of every IFD reachable from the TIFF header, keyed as
+/// `<ifd path>.<tag id>` (e.g. `IFD0.Exif.0x829A`). Follows the IFD chain,
+/// SubIFDs, Exif/GPS/Interop pointers and the MakerNote when rawler can parse
+/// the vendor's layout. Values are rawler's textual form, untranslated.
+///
+/// For advanced inspection only; the UI uses `extract_raw_metadata`.
+/// Containers that are not TIFF-based (CR3, RAF headers) are rejected.
+pub fn extract_raw_metadata_full(bytes: &[u8]) -> Result<BTreeMap<String, String>> {
+    let mut map = BTreeMap::new();
+    let reader = GenericTiffReader::new(
+        &mut Cursor::new(bytes),
+        0,
+        0,
+        None,
+        &[TAG_SUB_IFDS, TAG_EXIF_IFD, TAG_GPS_IFD, TAG_INTEROP_IFD, TAG_MAKER_NOTE],
+    )
+    .context("file is not TIFF-based")?;
+
+    for (index, ifd) in reader.chains().iter().enumerate() {
+        dump_ifd(ifd, &format!("IFD{index}"), &mut map);
+    }
+    Ok(map)
+}
+
+/// (input, output) pairs from a ProfileToneCurve value: an even number of
+/// floats, at least two points.
+fn tone_curve_points(value: &Value) -> Option<Vec<(f32, f32)>> {
+    let values: Vec<f32> = match value {
+        Value::Float(v) => v.clone(),
+        Value::Double(v) => v.iter().map(|&x| x as f32).collect(),
+        _ => return None,
+    };
+    (values.len() >= 4 && values.len().is_multiple_of(2))
+        .then(|| values.chunks_exact(2).map(|p| (p[0], p[1])).collect())
+}
+
+fn find_tone_curve(ifd: &IFD) -> Option<Vec<(f32, f32)>> {
+    if let Some(points) = ifd
+        .entries()
+        .get(&TAG_PROFILE_TONE_CURVE)
+        .and_then(|entry| tone_curve_points(&entry.value))
+    {
+        return Some(points);
+    }
+    ifd.sub_ifds().values().flatten().find_map(find_tone_curve)
+}
+
+/// The camera's default tone curve from a DNG's ProfileToneCurve tag, as
+/// (input, output) pairs. `None` for other formats and DNGs without one.
+pub fn read_profile_tone_curve(bytes: &[u8]) -> Option<Vec<(f32, f32)>> {
+    let reader =
+        GenericTiffReader::new(&mut Cursor::new(bytes), 0, 0, None, &[TAG_SUB_IFDS]).ok()?;
+    reader.chains().iter().find_map(find_tone_curve)
+}
+
+/// EV from a BaselineExposure value: its first SRATIONAL, if the
+/// denominator is not zero.
+fn baseline_exposure_ev(value: &Value) -> Option<f32> {
+    match value {
+        Value::SRational(v) => v
+            .first()
+            .filter(|r| r.d != 0)
+            .map(|r| r.n as f32 / r.d as f32),
+        _ => None,
+    }
+}
+
+fn find_baseline_exposure(ifd: &IFD) -> Option<f32> {
+    if let Some(ev) = ifd
+        .entries()
+        .get(&TAG_BASELINE_EXPOSURE)
+        .and_then(|entry| baseline_exposure_ev(&entry.value))
+    {
+        return Some(ev);
+    }
+    ifd.sub_ifds().values().flatten().find_map(find_baseline_exposure)
+}
+
+/// A DNG's BaselineExposure in EV. `None` for other formats and DNGs
+/// without one.
+pub fn read_baseline_exposure(bytes: &[u8]) -> Option<f32> {
+    let reader =
+        GenericTiffReader::new(&mut Cursor::new(bytes), 0, 0, None, &[TAG_SUB_IFDS]).ok()?;
+    reader.chains().iter().find_map(find_baseline_exposure)
+}
+
+fn find_default_scale(ifd: &IFD) -> Option<[f32; 2]> {
+    if let Some(entry) = ifd.entries().get(&TAG_DEFAULT_SCALE)
+        && let Value::Rational(v) = &entry.value
+        && let [h, v, ..] = v.as_slice()
+        && h.d != 0
+        && v.d != 0
+        && h.n != 0
+        && v.n != 0
+    {
+        return Some([h.n as f32 / h.d as f32, v.n as f32 / v.d as f32]);
+    }
+    ifd.sub_ifds().values().flatten().find_map(find_default_scale)
+}
+
+/// A DNG's DefaultScale as [horizontal, vertical]. `None` for other formats
+/// and DNGs without one.
+pub fn read_default_scale(bytes: &[u8]) -> Option<[f32; 2]> {
+    let reader =
+        GenericTiffReader::new(&mut Cursor::new(bytes), 0, 0, None, &[TAG_SUB_IFDS]).ok()?;
+    reader.chains().iter().find_map(find_default_scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sub_ifds_are_named_by_their_pointer_tag() {
+        assert_eq!(sub_ifd_name(TAG_EXIF_IFD), "Exif");
+        assert_eq!(sub_ifd_name(TAG_MAKER_NOTE), "MakerNote");
+        assert_eq!(sub_ifd_name(0xC634), "0xC634");
+    }
+
+    #[test]
+    fn full_dump_rejects_containers_that_are_not_tiff() {
+        let err = extract_raw_metadata_full(b"ftypcrx not a tiff header").unwrap_err();
+        assert!(err.to_string().contains("not TIFF-based"));
+    }
+
+    #[test]
+    fn bayer_patterns_are_named_and_x_trans_is_flagged() {
+        let bayer = cfa_entries(&CFA::new("RGGB"));
+        assert_eq!(bayer[0], ("CFAPattern", "RGGB".to_string()));
+        assert_eq!(bayer[3], ("IsXTrans", "false".to_string()));
+
+        let xtrans = cfa_entries(&CFA::new("GGRGGBGGBGGRBRGRBGGGBGGRGGRGGBRBGBRG"));
+        assert_eq!(xtrans[0], ("CFAPattern", "X-Trans 6x6".to_string()));
+        assert_eq!(xtrans[1], ("CFAWidth", "6".to_string()));
+        assert_eq!(xtrans[3], ("IsXTrans", "true".to_string()));
+    }
+
+    #[test]
+    fn tone_curves_need_whole_points() {
+        assert_eq!(
+            tone_curve_points(&Value::Float(vec![0.0, 0.0, 0.5, 0.6, 1.0, 1.0])),
+            Some(vec![(0.0, 0.0), (0.5, 0.6), (1.0, 1.0)])
+        );
+        assert_eq!(
+            tone_curve_points(&Value::Double(vec![0.0, 0.0, 1.0, 0.9])),
+            Some(vec![(0.0, 0.0), (1.0, 0.9)])
+        );
+        assert_eq!(tone_curve_points(&Value::Float(vec![0.0, 0.0, 1.0])), None);
+        assert_eq!(tone_curve_points(&Value::Float(vec![0.0, 0.0])), None);
+        assert_eq!(tone_curve_points(&Value::Short(vec![0, 0, 1, 1])), None);
+        assert_eq!(read_profile_tone_curve(b"not a tiff"), None);
+    }
+
+    fn fuji_maker_note(entries: &[(u16, u16, u16)]) -> Vec<u8> {
+        let mut bytes = b"JPEG junk before the note".to_vec();
+        bytes.extend_from_slice(FUJI_MAKER_NOTE_HEADER);
+        bytes.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for &(tag, kind, value) in entries {
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&kind.to_le_bytes());
+            bytes.extend_from_slice(&1u32.to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn film_simulation_is_read_from_the_fuji_maker_note() {
+        let bytes = fuji_maker_note(&[
+            (0x1000, 3, 1),
+            (FUJI_TAG_SATURATION, 3, 0),
+            (FUJI_TAG_FILM_MODE, 3, 0x800),
+        ]);
+        assert_eq!(
+            read_fuji_film_simulation(&bytes),
+            FilmSimulation::ClassicNeg
+        );
+
+        // Only SHORT values count, and a truncated note stops the scan.
+        let bytes = fuji_maker_note(&[(FUJI_TAG_FILM_MODE, 4, 0x800)]);
+        assert_eq!(read_fuji_film_simulation(&bytes), FilmSimulation::Provia);
+        let mut bytes = fuji_maker_note(&[(FUJI_TAG_FILM_MODE, 3, 0x600)]);
+        bytes.truncate(bytes.len() - 6);
+        assert_eq!(
+            read_fuji_maker_note_shorts(&bytes, [FUJI_TAG_FILM_MODE]),
+            [None]
+        );
+        assert_eq!(
+            read_fuji_film_simulation(b"no maker note"),
+            FilmSimulation::Provia
+        );
+    }
+
+    #[test]
+    fn baseline_exposure_is_the_first_srational() {
+        use rawler::formats::tiff::SRational;
+        let value = Value::SRational(vec![SRational { n: -1, d: 2 }, SRational { n: 3, d: 1 }]);
+        assert_eq!(baseline_exposure_ev(&value), Some(-0.5));
+        assert_eq!(
+            baseline_exposure_ev(&Value::SRational(vec![SRational { n: 1, d: 0 }])),
+            None
+        );
+        assert_eq!(baseline_exposure_ev(&Value::SRational(vec![])), None);
+        assert_eq!(baseline_exposure_ev(&Value::Float(vec![0.5])), None);
+        assert_eq!(read_baseline_exposure(b"not a tiff"), None);
+    }
+}
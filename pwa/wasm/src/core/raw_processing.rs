@@ -1,38 +1,430 @@
+use crate::core::adjustments::SimpleAdjustments;
+use crate::core::dng_opcodes::{read_dng_opcodes, Opcode};
 use crate::core::image_processing::apply_orientation;
+use crate::core::image_utils::resize_f32_image;
+use crate::core::raw_metadata::{
+    read_baseline_exposure, read_default_scale, read_profile_tone_curve,
+};
+use crate::core::working_space;
 use anyhow::{anyhow, Result};
-use image::{DynamicImage, ImageBuffer, Rgba};
+use image::{imageops, DynamicImage, ImageBuffer, Luma, Rgba};
+use rapidraw_core::highlight::rescale_and_compress;
+use rapidraw_core::raw_sanity::{default_crop_in_active_area, sanitize_levels};
 use rawler::{
     decoders::{Orientation, RawDecodeParams},
-    imgop::develop::{DemosaicAlgorithm, Intermediate, ProcessingStep, RawDevelop},
-    rawimage::RawImage,
+    imgop::{
+        develop::{DemosaicAlgorithm, Intermediate, ProcessingStep, RawDevelop},
+        Rect,
+    },
+    pixarray::Color2D,
+    rawimage::{RawImage, RawImageData, RawPhotometricInterpretation},
     rawsource::RawSource,
 };
+use serde::{Deserialize, Serialize};
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc,
 };
 
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct SensorRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl From<Rect> for SensorRect {
+    fn from(rect: Rect) -> Self {
+        Self {
+            x: rect.p.x,
+            y: rect.p.y,
+            width: rect.d.w,
+            height: rect.d.h,
+        }
+    }
+}
+
+/// Sensor-level facts about a RAW file, in raw (unrotated) pixel coordinates.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawDevelopInfo {
+    pub raw_width: usize,
+    pub raw_height: usize,
+    pub active_area: Option<SensorRect>,
+    pub default_crop: Option<SensorRect>,
+    /// e.g. "RGGB"; `None` for linear/monochrome data without a CFA.
+    pub cfa_pattern: Option<String>,
+    /// EXIF orientation value (1-8).
+    pub orientation: u16,
+    /// Only known when the sensor data was actually decoded.
+    pub clipping: Option<SensorClipping>,
+    /// Set when the file's white or black level was unusable and a default
+    /// was developed with instead; says what was replaced.
+    pub level_fallback: Option<String>,
+}
+
+/// Fraction of samples at the white level above which a capture counts as
+/// clipped; below it the hits are assumed to be hot pixels.
+const CLIPPED_FRACTION_THRESHOLD: f64 = 1e-4;
+
+/// Clipping at the sensor, before any develop step. Unlike the display
+/// clipping mask, highlights flagged here cannot be recovered by the tone curve.
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct SensorClipping {
+    pub white_level: u32,
+    pub black_level: f32,
+    /// log2 of the range between black and white level: the most the
+    /// encoding can hold, before noise takes its share.
+    pub dynamic_range_stops: f32,
+    pub clipped_samples: u64,
+    pub clipped_fraction: f64,
+    pub highlights_clipped: bool,
+}
+
+impl SensorClipping {
+    fn new(white_level: u32, black_level: f32, clipped_samples: usize, total_samples: usize) -> Self {
+        let clipped_fraction = if total_samples == 0 {
+            0.0
+        } else {
+            clipped_samples as f64 / total_samples as f64
+        };
+        Self {
+            white_level,
+            black_level,
+            dynamic_range_stops: (white_level as f32 - black_level).max(1.0).log2(),
+            clipped_samples: clipped_samples as u64,
+            clipped_fraction,
+            highlights_clipped: clipped_fraction > CLIPPED_FRACTION_THRESHOLD,
+        }
+    }
+}
+
+/// Counts raw samples at or above the white level. Uses the lowest per-channel
+/// white level, so a channel that saturates early is never missed.
+pub fn sensor_clipping(raw_image: &RawImage) -> SensorClipping {
+    let white_level = raw_image
+        .whitelevel
+        .0
+        .iter()
+        .copied()
+        .min()
+        .unwrap_or(u16::MAX as u32);
+    let levels = &raw_image.blacklevel.levels;
+    let black_level = if levels.is_empty() {
+        0.0
+    } else {
+        levels.iter().map(|level| level.as_f32()).sum::<f32>() / levels.len() as f32
+    };
+
+    let (clipped_samples, total_samples) = match &raw_image.data {
+        RawImageData::Integer(data) => (
+            data.iter().filter(|&&v| v as u32 >= white_level).count(),
+            data.len(),
+        ),
+        RawImageData::Float(data) => (
+            data.iter().filter(|&&v| v >= white_level as f32).count(),
+            data.len(),
+        ),
+    };
+    SensorClipping::new(white_level, black_level, clipped_samples, total_samples)
+}
+
+fn raw_develop_info(raw_image: &RawImage, orientation: Orientation) -> RawDevelopInfo {
+    let cfa_pattern = match &raw_image.photometric {
+        RawPhotometricInterpretation::Cfa(config) => Some(config.cfa.name.clone()),
+        _ => None,
+    };
+    RawDevelopInfo {
+        raw_width: raw_image.width,
+        raw_height: raw_image.height,
+        active_area: raw_image.active_area.map(SensorRect::from),
+        default_crop: raw_image.crop_area.map(SensorRect::from),
+        cfa_pattern,
+        orientation: orientation.to_u16(),
+        clipping: None,
+        level_fallback: None,
+    }
+}
+
+/// Single-channel data without a color filter (Leica Monochrom, astro
+/// cameras) has no color to balance or calibrate.
+fn is_monochrome(cpp: usize, photometric: &RawPhotometricInterpretation) -> bool {
+    cpp == 1 && !matches!(photometric, RawPhotometricInterpretation::Cfa(_))
+}
+
+/// Filter letter for each CFA color index, read from the pattern name (e.g.
+/// "RGBE", "CYGM"). Defaults to R, G, B, E when there is no CFA.
+fn cfa_channel_labels(raw_image: &RawImage) -> [char; 4] {
+    let mut labels = ['R', 'G', 'B', 'E'];
+    if let RawPhotometricInterpretation::Cfa(config) = &raw_image.photometric {
+        let cfa = &config.cfa;
+        let name: Vec<char> = cfa.name.chars().collect();
+        for row in 0..cfa.height {
+            for col in 0..cfa.width {
+                let color = cfa.color_at(row, col);
+                if let (Some(&letter), true) = (name.get(row * cfa.width + col), color < 4) {
+                    labels[color] = letter.to_ascii_uppercase();
+                }
+            }
+        }
+    }
+    labels
+}
+
+/// Maps a white-balanced four-color pixel to RGB. Complementary filters are
+/// treated as pairwise averages (C = (G+B)/2, Y = (R+G)/2, M = (R+B)/2), so a
+/// neutral input, where all four channels are equal, stays neutral. A fourth
+/// green-like channel (RGBE emerald) is averaged into green.
+fn four_color_to_rgb(p: [f32; 4], labels: &[char; 4]) -> [f32; 3] {
+    let channel = |letter: char| labels.iter().position(|&l| l == letter).map(|i| p[i]);
+
+    if let (Some(c), Some(y), Some(m)) = (channel('C'), channel('Y'), channel('M')) {
+        let r = y + m - c;
+        let b = c + m - y;
+        let g_estimate = y + c - m;
+        let g = channel('G').map_or(g_estimate, |g| (g + g_estimate) * 0.5);
+        return [r, g, b];
+    }
+
+    if let (Some(r), Some(g), Some(b)) = (channel('R'), channel('G'), channel('B')) {
+        let g = channel('E').map_or(g, |e| (g + e) * 0.5);
+        return [r, g, b];
+    }
+
+    [p[0], p[1], p[2]]
+}
+
+/// How the outermost demosaiced pixels are treated. Interpolation has too
+/// few neighbours there, so they tend to show colored fringes; the fast
+/// paths are worst off.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DemosaicBorder {
+    /// Repeat the nearest trusted row/column outwards.
+    #[default]
+    Clamp,
+    /// Reflect the trusted pixels across the first trusted row/column, which
+    /// keeps texture running to the edge.
+    Mirror,
+    /// Drop the border; the image shrinks by twice the border width.
+    Crop,
+}
+
+/// Optional develop behaviour beyond the demosaic speed and highlight
+/// compression.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DevelopOptions {
+    pub border: DemosaicBorder,
+    /// Apply the tone curve the file carries (DNG ProfileToneCurve), so the
+    /// result starts closer to the camera's rendering. Files without one
+    /// develop as usual. The desktop app has no such option, so only with
+    /// this off do both develop the same pixels.
+    pub apply_camera_curve: bool,
+    /// Apply the GainMap (lens shading) and WarpRectilinear (distortion)
+    /// opcodes a DNG carries. Other files develop as usual.
+    pub apply_dng_opcodes: bool,
+    /// Return the image as the sensor recorded it instead of applying the
+    /// EXIF orientation; `RawDevelopInfo::orientation` still reports it.
+    pub keep_sensor_orientation: bool,
+}
+
+/// Piecewise-linear tone curve over linear values in 0..1.
+struct ToneCurve {
+    points: Vec<(f32, f32)>,
+}
+
+impl ToneCurve {
+    fn new(mut points: Vec<(f32, f32)>) -> Option<Self> {
+        points.retain(|(x, y)| x.is_finite() && y.is_finite());
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        (points.len() >= 2).then_some(Self { points })
+    }
+
+    /// Values past the last point scale with its output, so highlight
+    /// headroom is kept.
+    fn eval(&self, x: f32) -> f32 {
+        let (first, last) = (self.points[0], self.points[self.points.len() - 1]);
+        if x <= first.0 {
+            return first.1;
+        }
+        if x >= last.0 {
+            return last.1 * x / last.0.max(1e-6);
+        }
+        let i = self.points.partition_point(|p| p.0 <= x);
+        let (x0, y0) = self.points[i - 1];
+        let (x1, y1) = self.points[i];
+        y0 + (y1 - y0) * (x - x0) / (x1 - x0).max(1e-9)
+    }
+}
+
+/// Pixels on each side the policy applies to.
+fn demosaic_border_width(fast_demosaic: bool) -> usize {
+    if fast_demosaic {
+        1
+    } else {
+        2
+    }
+}
+
+/// Applies `policy` to the outer `border` pixels of a row-major buffer and
+/// returns its new dimensions. Buffers too small to keep an interior are
+/// left alone.
+fn apply_demosaic_border<T: Copy>(
+    data: &mut Vec<T>,
+    width: usize,
+    height: usize,
+    border: usize,
+    policy: DemosaicBorder,
+) -> (usize, usize) {
+    if border == 0 || width <= 4 * border || height <= 4 * border {
+        return (width, height);
+    }
+    let source = |i: usize, len: usize| -> usize {
+        let last = len - 1 - border;
+        match policy {
+            DemosaicBorder::Clamp => i.clamp(border, last),
+            DemosaicBorder::Mirror if i < border => 2 * border - i,
+            DemosaicBorder::Mirror if i > last => 2 * last - i,
+            _ => i,
+        }
+    };
+
+    if policy == DemosaicBorder::Crop {
+        let (new_w, new_h) = (width - 2 * border, height - 2 * border);
+        let mut cropped = Vec::with_capacity(new_w * new_h);
+        for y in border..height - border {
+            cropped.extend_from_slice(&data[y * width + border..y * width + width - border]);
+        }
+        *data = cropped;
+        return (new_w, new_h);
+    }
+
+    for y in 0..height {
+        let sy = source(y, height);
+        let edge_row = sy != y;
+        for x in 0..width {
+            let sx = source(x, width);
+            if edge_row || sx != x {
+                data[y * width + x] = data[sy * width + sx];
+            }
+        }
+    }
+    (width, height)
+}
+
+/// The EXIF orientation the file records, `Normal` when it has none.
+pub fn read_orientation(
+    decoder: &dyn rawler::decoders::Decoder,
+    source: &RawSource,
+) -> Result<Orientation> {
+    let metadata = decoder.raw_metadata(source, &RawDecodeParams::default())?;
+    Ok(metadata
+        .exif
+        .orientation
+        .map(Orientation::from_u16)
+        .unwrap_or(Orientation::Normal))
+}
+
+/// Whether rawler has a decoder for `file_bytes`. Only the decoder lookup
+/// runs, not the decode, so this is cheap enough to call per file before
+/// offering a develop.
+pub fn can_decode_raw(file_bytes: &[u8]) -> bool {
+    rawler::get_decoder(&RawSource::new_from_slice(file_bytes)).is_ok()
+}
+
+/// Adjustments for starting an edit from the camera's as-shot rendering
+/// rather than from all-zero sliders: the develop already applies the
+/// as-shot white balance, so temperature and tint stay neutral, and exposure
+/// takes the DNG BaselineExposure the camera renders with (0 for other
+/// files). The ProfileToneCurve is a develop step
+/// (`DevelopOptions::apply_camera_curve`), not an adjustment.
+pub fn reset_to_camera_baseline(file_bytes: &[u8]) -> SimpleAdjustments {
+    SimpleAdjustments {
+        exposure: read_baseline_exposure(file_bytes).unwrap_or(0.0),
+        ..Default::default()
+    }
+}
+
+/// The embedded camera preview (falling back to the smaller thumbnail) whose
+/// long edge is at least `min_edge`, with the file's orientation applied and
+/// converted to the pipeline space. `None` when the file carries nothing
+/// large enough.
+pub fn extract_embedded_preview(file_bytes: &[u8], min_edge: u32) -> Result<Option<DynamicImage>> {
+    let source = RawSource::new_from_slice(file_bytes);
+    let decoder = rawler::get_decoder(&source)?;
+    let params = RawDecodeParams::default();
+    let orientation = read_orientation(decoder.as_ref(), &source)?;
+
+    let candidates = [
+        decoder.preview_image(&source, &params),
+        decoder.thumbnail_image(&source, &params),
+    ];
+    let preview = candidates
+        .into_iter()
+        .filter_map(|candidate| candidate.ok().flatten())
+        .find(|image| image.width().max(image.height()) >= min_edge);
+    Ok(preview.map(|image| apply_orientation(working_space::from_srgb(image), orientation)))
+}
+
 pub fn develop_raw_image(
     file_bytes: &[u8],
     fast_demosaic: bool,
     highlight_compression: f32,
     cancel_token: Option<(Arc<AtomicUsize>, usize)>,
 ) -> Result<DynamicImage> {
-    let (developed_image, orientation) = develop_internal(
+    develop_raw_with_info(
+        file_bytes,
+        fast_demosaic,
+        highlight_compression,
+        DevelopOptions::default(),
+        cancel_token,
+    )
+    .map(|(image, _)| image)
+}
+
+/// Like `develop_raw_image`, but with explicit `options`, and also reports
+/// the sensor geometry the image was developed from and how much of it
+/// clipped.
+pub fn develop_raw_with_info(
+    file_bytes: &[u8],
+    fast_demosaic: bool,
+    highlight_compression: f32,
+    options: DevelopOptions,
+    cancel_token: Option<(Arc<AtomicUsize>, usize)>,
+) -> Result<(DynamicImage, RawDevelopInfo)> {
+    let (developed_image, orientation, info) = develop_internal(
         file_bytes,
         fast_demosaic,
         highlight_compression,
+        options,
         cancel_token,
     )?;
-    Ok(apply_orientation(developed_image, orientation))
+    if options.keep_sensor_orientation {
+        return Ok((developed_image, info));
+    }
+    Ok((apply_orientation(developed_image, orientation), info))
+}
+
+/// Reads `RawDevelopInfo` without demosaicing. The pixel data is not decoded,
+/// so `clipping` is `None`.
+pub fn read_raw_develop_info(file_bytes: &[u8]) -> Result<RawDevelopInfo> {
+    let source = RawSource::new_from_slice(file_bytes);
+    let decoder = rawler::get_decoder(&source)?;
+    let raw_image = decoder.raw_image(&source, &RawDecodeParams::default(), true)?;
+    let orientation = read_orientation(decoder.as_ref(), &source)?;
+    Ok(raw_develop_info(&raw_image, orientation))
 }
 
 fn develop_internal(
     file_bytes: &[u8],
     fast_demosaic: bool,
     highlight_compression: f32,
+    options: DevelopOptions,
     cancel_token: Option<(Arc<AtomicUsize>, usize)>,
-) -> Result<(DynamicImage, Orientation)> {
+) -> Result<(DynamicImage, Orientation, RawDevelopInfo)> {
     let check_cancel = || -> Result<()> {
         if let Some((tracker, generation)) = &cancel_token {
             if tracker.load(Ordering::SeqCst) != *generation {
@@ -51,12 +443,10 @@ fn develop_internal(
     let mut raw_image: RawImage =
         decoder.raw_image(&source, &RawDecodeParams::default(), false)?;
 
-    let metadata = decoder.raw_metadata(&source, &RawDecodeParams::default())?;
-    let orientation = metadata
-        .exif
-        .orientation
-        .map(Orientation::from_u16)
-        .unwrap_or(Orientation::Normal);
+    let orientation = read_orientation(decoder.as_ref(), &source)?;
+    let mut info = raw_develop_info(&raw_image, orientation);
+    info.level_fallback = sanitize_levels(&mut raw_image);
+    info.clipping = Some(sensor_clipping(&raw_image));
 
     let original_white_level = raw_image
         .whitelevel
@@ -76,19 +466,101 @@ fn develop_internal(
         *level = u32::MAX;
     }
 
+    let is_monochrome = is_monochrome(raw_image.cpp, &raw_image.photometric);
+
     let mut developer = RawDevelop::default();
     if fast_demosaic {
         developer.demosaic_algorithm = DemosaicAlgorithm::Speed;
     }
     developer.steps.retain(|&step| step != ProcessingStep::SRgb);
+    if is_monochrome {
+        developer.steps.retain(|&step| {
+            step != ProcessingStep::WhiteBalance && step != ProcessingStep::Calibrate
+        });
+    }
+    if !default_crop_in_active_area(&raw_image) {
+        developer.steps.retain(|&step| step != ProcessingStep::CropDefault);
+    }
+
+    let opcodes = if options.apply_dng_opcodes {
+        read_dng_opcodes(file_bytes)
+    } else {
+        Default::default()
+    };
+    for opcode in &opcodes.before_demosaic {
+        if let Opcode::GainMap(map) = opcode {
+            map.apply_to_raw(&mut raw_image);
+        }
+    }
+    // OpcodeList3 works on the uncropped active area, so the default crop
+    // has to wait until it has run. Only RGB results get the list.
+    let crop_after_opcodes = !is_monochrome
+        && !opcodes.after_demosaic.is_empty()
+        && developer.steps.contains(&ProcessingStep::CropDefault);
+    if crop_after_opcodes {
+        developer.steps.retain(|&step| step != ProcessingStep::CropDefault);
+    }
 
     check_cancel()?;
-    let mut developed_intermediate = developer.develop_intermediate(&raw_image)?;
+    let mut developed_intermediate = match developer.develop_intermediate(&raw_image)? {
+        Intermediate::FourColor(pixels) => {
+            let labels = cfa_channel_labels(&raw_image);
+            let data = pixels
+                .data
+                .iter()
+                .map(|p| four_color_to_rgb(*p, &labels))
+                .collect();
+            Intermediate::ThreeColor(Color2D::new_with(data, pixels.width, pixels.height))
+        }
+        other => other,
+    };
+
+    if let Intermediate::ThreeColor(pixels) = &mut developed_intermediate {
+        if !opcodes.after_demosaic.is_empty() {
+            check_cancel()?;
+            let (w, h) = (pixels.width, pixels.height);
+            for opcode in &opcodes.after_demosaic {
+                match opcode {
+                    Opcode::GainMap(map) => map.apply_to_rgb(&mut pixels.data, w, h),
+                    Opcode::WarpRectilinear(warp) => warp.apply(&mut pixels.data, w, h),
+                }
+            }
+        }
+        if crop_after_opcodes && let Some(crop) = raw_image.crop_area {
+            let (ax, ay) = raw_image.active_area.map_or((0, 0), |area| (area.p.x, area.p.y));
+            let (x, y) = (crop.p.x - ax, crop.p.y - ay);
+            let (w, h) = (crop.d.w.min(pixels.width - x), crop.d.h.min(pixels.height - y));
+            let mut cropped = Vec::with_capacity(w * h);
+            for row in y..y + h {
+                let start = row * pixels.width + x;
+                cropped.extend_from_slice(&pixels.data[start..start + w]);
+            }
+            *pixels = Color2D::new_with(cropped, w, h);
+        }
+    }
+
+    let border_width = demosaic_border_width(fast_demosaic);
+    match &mut developed_intermediate {
+        Intermediate::Monochrome(pixels) => {
+            let (w, h) = (pixels.width, pixels.height);
+            (pixels.width, pixels.height) =
+                apply_demosaic_border(&mut pixels.data, w, h, border_width, options.border);
+        }
+        Intermediate::ThreeColor(pixels) => {
+            let (w, h) = (pixels.width, pixels.height);
+            (pixels.width, pixels.height) =
+                apply_demosaic_border(&mut pixels.data, w, h, border_width, options.border);
+        }
+        Intermediate::FourColor(_) => {}
+    }
 
     let denominator = (original_white_level - original_black_level).max(1.0);
     let rescale_factor = (headroom_white_level - original_black_level) / denominator;
-
-    let safe_highlight_compression = highlight_compression.max(1.01);
+    let camera_curve = if options.apply_camera_curve {
+        read_profile_tone_curve(file_bytes).and_then(ToneCurve::new)
+    } else {
+        None
+    };
 
     check_cancel()?;
 
@@ -96,47 +568,17 @@ fn develop_internal(
         Intermediate::Monochrome(pixels) => {
             pixels.data.iter_mut().for_each(|p| {
                 let linear_val = *p * rescale_factor;
-                *p = linear_val;
+                *p = match &camera_curve {
+                    Some(curve) => curve.eval(linear_val),
+                    None => linear_val,
+                };
             });
         }
         Intermediate::ThreeColor(pixels) => {
-            pixels.data.iter_mut().for_each(|p| {
-                let r = (p[0] * rescale_factor).max(0.0);
-                let g = (p[1] * rescale_factor).max(0.0);
-                let b = (p[2] * rescale_factor).max(0.0);
-
-                let max_c = r.max(g).max(b);
-
-                let (final_r, final_g, final_b) = if max_c > 1.0 {
-                    let min_c = r.min(g).min(b);
-                    let compression_factor = (1.0
-                        - (max_c - 1.0) / (safe_highlight_compression - 1.0))
-                        .max(0.0)
-                        .min(1.0);
-                    let compressed_r = min_c + (r - min_c) * compression_factor;
-                    let compressed_g = min_c + (g - min_c) * compression_factor;
-                    let compressed_b = min_c + (b - min_c) * compression_factor;
-                    let compressed_max =
-                        compressed_r.max(compressed_g).max(compressed_b);
-
-                    if compressed_max > 1e-6 {
-                        let rescale = max_c / compressed_max;
-                        (
-                            compressed_r * rescale,
-                            compressed_g * rescale,
-                            compressed_b * rescale,
-                        )
-                    } else {
-                        (max_c, max_c, max_c)
-                    }
-                } else {
-                    (r, g, b)
-                };
-
-                p[0] = final_r;
-                p[1] = final_g;
-                p[2] = final_b;
-            });
+            rescale_and_compress(&mut pixels.data, rescale_factor, highlight_compression);
+            if let Some(curve) = &camera_curve {
+                pixels.data.iter_mut().for_each(|p| *p = p.map(|c| curve.eval(c)));
+            }
         }
         Intermediate::FourColor(pixels) => {
             pixels.data.iter_mut().for_each(|p| {
@@ -164,11 +606,11 @@ fn develop_internal(
             DynamicImage::ImageRgba32F(buffer)
         }
         Intermediate::Monochrome(pixels) => {
-            let buffer = ImageBuffer::<Rgba<f32>, _>::from_fn(width, height, |x, y| {
+            let buffer = ImageBuffer::<Luma<u16>, _>::from_fn(width, height, |x, y| {
                 let p = pixels.data[(y * width + x) as usize];
-                Rgba([p, p, p, 1.0])
+                Luma([(p.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16])
             });
-            DynamicImage::ImageRgba32F(buffer)
+            DynamicImage::ImageLuma16(buffer)
         }
         _ => {
             return Err(anyhow!(
@@ -176,6 +618,238 @@ fn develop_internal(
             ));
         }
     };
+    let dynamic_image = match read_default_scale(file_bytes) {
+        Some(scale) => apply_pixel_aspect(dynamic_image, scale),
+        None => dynamic_image,
+    };
 
-    Ok((dynamic_image, orientation))
+    Ok((dynamic_image, orientation, info))
+}
+
+/// Resamples a develop from a sensor with non-square pixels (DNG
+/// DefaultScale other than 1:1) so its pixels come out square. The shorter
+/// axis is stretched rather than the longer one shrunk, so no detail is
+/// dropped; a uniform DefaultScale only sets a rendering size and is ignored.
+fn apply_pixel_aspect(image: DynamicImage, [scale_x, scale_y]: [f32; 2]) -> DynamicImage {
+    let base = scale_x.min(scale_y);
+    if !base.is_finite() || base <= 0.0 || (scale_x / scale_y - 1.0).abs() < 1e-3 {
+        return image;
+    }
+    let width = (image.width() as f32 * scale_x / base).round() as u32;
+    let height = (image.height() as f32 * scale_y / base).round() as u32;
+    match image {
+        // Kept single-channel, so monochrome develops stay recognisable.
+        DynamicImage::ImageLuma16(buffer) => DynamicImage::ImageLuma16(imageops::resize(
+            &buffer,
+            width,
+            height,
+            imageops::FilterType::Lanczos3,
+        )),
+        other => resize_f32_image(&other, width, height),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rawler::imgop::{Dim2, Point};
+
+    #[test]
+    fn sensor_rect_keeps_the_origin_and_size() {
+        let rect = SensorRect::from(Rect {
+            p: Point { x: 12, y: 8 },
+            d: Dim2 { w: 6000, h: 4000 },
+        });
+        assert_eq!(
+            (rect.x, rect.y, rect.width, rect.height),
+            (12, 8, 6000, 4000)
+        );
+    }
+
+    #[test]
+    fn develop_info_serializes_in_camel_case() {
+        let info = RawDevelopInfo {
+            raw_width: 6048,
+            raw_height: 4024,
+            active_area: None,
+            default_crop: Some(SensorRect {
+                x: 24,
+                y: 12,
+                width: 6000,
+                height: 4000,
+            }),
+            cfa_pattern: Some("RGGB".to_string()),
+            orientation: 6,
+            clipping: None,
+            level_fallback: None,
+        };
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["rawWidth"], 6048);
+        assert_eq!(json["defaultCrop"]["width"], 6000);
+        assert_eq!(json["cfaPattern"], "RGGB");
+        assert!(json["activeArea"].is_null());
+    }
+
+    #[test]
+    fn only_single_channel_data_without_a_cfa_is_monochrome() {
+        assert!(is_monochrome(1, &RawPhotometricInterpretation::BlackIsZero));
+        assert!(is_monochrome(1, &RawPhotometricInterpretation::LinearRaw));
+        assert!(!is_monochrome(3, &RawPhotometricInterpretation::LinearRaw));
+    }
+
+    #[test]
+    fn neutral_four_color_pixels_stay_neutral() {
+        for labels in [['C', 'Y', 'G', 'M'], ['R', 'G', 'B', 'E']] {
+            let [r, g, b] = four_color_to_rgb([0.4; 4], &labels);
+            assert!((r - 0.4).abs() < 1e-6 && (g - 0.4).abs() < 1e-6 && (b - 0.4).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn complementary_filters_are_unmixed() {
+        // Pure red: yellow and magenta see it, cyan and green do not.
+        let [r, g, b] = four_color_to_rgb([0.0, 0.5, 0.0, 0.5], &['C', 'Y', 'G', 'M']);
+        assert!((r - 1.0).abs() < 1e-6);
+        assert!(g.abs() < 1e-6 && b.abs() < 1e-6);
+    }
+
+    #[test]
+    fn emerald_is_averaged_into_green() {
+        let rgb = four_color_to_rgb([0.2, 0.4, 0.6, 0.8], &['R', 'G', 'B', 'E']);
+        assert_eq!(rgb, [0.2, 0.6, 0.6]);
+    }
+
+    #[test]
+    fn a_few_hot_pixels_do_not_count_as_clipping() {
+        let hot = SensorClipping::new(16383, 0.0, 5, 1_000_000);
+        assert!(!hot.highlights_clipped);
+        let blown = SensorClipping::new(16383, 0.0, 5_000, 1_000_000);
+        assert!(blown.highlights_clipped);
+        assert!((blown.clipped_fraction - 0.005).abs() < 1e-12);
+        assert!(!SensorClipping::new(16383, 0.0, 0, 0).highlights_clipped);
+    }
+
+    #[test]
+    fn dynamic_range_spans_black_to_white() {
+        let clipping = SensorClipping::new(16384, 0.0, 0, 1);
+        assert!((clipping.dynamic_range_stops - 14.0).abs() < 1e-4);
+        let clipping = SensorClipping::new(4096 + 1024, 1024.0, 0, 1);
+        assert!((clipping.dynamic_range_stops - 12.0).abs() < 1e-4);
+    }
+
+    fn ramp(width: usize, height: usize) -> Vec<usize> {
+        (0..width * height)
+            .map(|i| (i / width) * 100 + i % width)
+            .collect()
+    }
+
+    #[test]
+    fn clamped_border_repeats_the_nearest_trusted_pixel() {
+        let mut data = ramp(10, 10);
+        let size = apply_demosaic_border(&mut data, 10, 10, 2, DemosaicBorder::Clamp);
+        assert_eq!(size, (10, 10));
+        assert_eq!(
+            &data[..10],
+            &[202, 202, 202, 203, 204, 205, 206, 207, 207, 207]
+        );
+        assert_eq!(data[55], 505);
+        assert_eq!(data[99], 707);
+    }
+
+    #[test]
+    fn mirrored_border_reflects_the_interior() {
+        let mut data = ramp(10, 10);
+        apply_demosaic_border(&mut data, 10, 10, 2, DemosaicBorder::Mirror);
+        assert_eq!(
+            &data[..10],
+            &[404, 403, 402, 403, 404, 405, 406, 407, 406, 405]
+        );
+        assert_eq!(data[9 * 10], 504);
+    }
+
+    #[test]
+    fn cropped_border_shrinks_the_buffer() {
+        let mut data = ramp(10, 9);
+        let size = apply_demosaic_border(&mut data, 10, 9, 1, DemosaicBorder::Crop);
+        assert_eq!(size, (8, 7));
+        assert_eq!(data.len(), 8 * 7);
+        assert_eq!((data[0], data[55]), (101, 708));
+    }
+
+    #[test]
+    fn tiny_buffers_and_zero_borders_are_left_alone() {
+        let original = ramp(8, 8);
+        let mut data = original.clone();
+        assert_eq!(
+            apply_demosaic_border(&mut data, 8, 8, 2, DemosaicBorder::Crop),
+            (8, 8)
+        );
+        assert_eq!(
+            apply_demosaic_border(&mut data, 8, 8, 0, DemosaicBorder::Clamp),
+            (8, 8)
+        );
+        assert_eq!(data, original);
+        assert_eq!(demosaic_border_width(true), 1);
+        assert_eq!(demosaic_border_width(false), 2);
+    }
+
+    #[test]
+    fn tone_curve_interpolates_and_keeps_headroom() {
+        let curve =
+            ToneCurve::new(vec![(1.0, 0.9), (0.0, 0.1), (0.5, 0.7), (f32::NAN, 0.0)]).unwrap();
+        assert_eq!(curve.eval(-1.0), 0.1);
+        assert!((curve.eval(0.25) - 0.4).abs() < 1e-6);
+        assert!((curve.eval(0.75) - 0.8).abs() < 1e-6);
+        // Past the last point the output scales with the input.
+        assert!((curve.eval(2.0) - 1.8).abs() < 1e-6);
+        assert!(ToneCurve::new(vec![(0.0, 0.0), (f32::INFINITY, 1.0)]).is_none());
+    }
+
+    #[test]
+    fn garbage_and_empty_input_cannot_be_decoded() {
+        assert!(!can_decode_raw(&[]));
+        assert!(!can_decode_raw(b"II*\x00 truncated tiff"));
+        assert!(!can_decode_raw(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10]));
+    }
+
+    #[test]
+    fn camera_baseline_of_a_non_dng_is_neutral() {
+        let baseline = reset_to_camera_baseline(b"not a raw file");
+        assert_eq!(baseline.exposure, 0.0);
+        assert_eq!(
+            [baseline.red_gain, baseline.green_gain, baseline.blue_gain],
+            [1.0; 3]
+        );
+        assert_eq!((baseline.temperature, baseline.tint), (0.0, 0.0));
+    }
+
+    #[test]
+    fn non_square_pixels_stretch_the_shorter_axis() {
+        let color = DynamicImage::ImageRgba32F(image::Rgba32FImage::from_pixel(
+            6,
+            4,
+            Rgba([0.25, 0.5, 0.75, 1.0]),
+        ));
+        let wide = apply_pixel_aspect(color.clone(), [1.0, 0.5]);
+        assert_eq!((wide.width(), wide.height()), (12, 4));
+        let pixel = wide.to_rgba32f().get_pixel(5, 2).0;
+        assert!((pixel[1] - 0.5).abs() < 1e-4, "{pixel:?}");
+
+        let tall = apply_pixel_aspect(color, [1.0, 1.5]);
+        assert_eq!((tall.width(), tall.height()), (6, 6));
+
+        let mono = DynamicImage::ImageLuma16(ImageBuffer::from_pixel(4, 4, Luma([1000u16])));
+        let mono = apply_pixel_aspect(mono, [2.0, 1.0]);
+        assert!(matches!(&mono, DynamicImage::ImageLuma16(_)));
+        assert_eq!((mono.width(), mono.height()), (8, 4));
+    }
+
+    #[test]
+    fn uniform_or_unusable_scales_are_ignored() {
+        let image = DynamicImage::ImageRgba32F(image::Rgba32FImage::new(6, 4));
+        for scale in [[1.0, 1.0], [2.0, 2.0], [0.0, 1.0], [f32::NAN, 1.0]] {
+            let out = apply_pixel_aspect(image.clone(), scale);
+            assert_eq!((out.width(), out.height()), (6, 4), "{scale:?}");
+        }
+    }
 }
@@ -0,0 +1,278 @@
+//! Local retouching tools working on sRGB RGBA8 frames (a canvas `ImageData`),
+//! each returning a patched copy of the buffer.
+
+use anyhow::{anyhow, Result};
+
+/// Red-to-green/blue ratio where a pixel starts counting as red eye, and where
+/// it is fully corrected. Skin sits around 1.3-1.6, flash-lit pupils well
+/// above 2.
+const RED_RATIO_START: f32 = 1.8;
+const RED_RATIO_FULL: f32 = 2.5;
+/// Pupils darker than this red level are left alone; they are already dark.
+const MIN_RED: f32 = 0.15;
+/// Brightness kept by corrected pupils, relative to their green/blue level.
+const PUPIL_DARKENING: f32 = 0.8;
+/// Over-relaxation factor and convergence threshold of the healing solve.
+const SOR_OMEGA: f32 = 1.9;
+const SOR_TOLERANCE: f32 = 1e-4;
+
+fn check_rgba_len(image_data: &[u8], width: u32, height: u32) -> Result<()> {
+    let expected = width as usize * height as usize * 4;
+    if image_data.len() != expected {
+        return Err(anyhow!(
+            "expected {expected} bytes for a {width}x{height} RGBA buffer, got {}",
+            image_data.len()
+        ));
+    }
+    Ok(())
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Neutralises red pupils inside the circle at (`x`, `y`). Only strongly red
+/// pixels are touched, so catchlights, iris and skin keep their color; the
+/// correction fades out over the outer fifth of `radius`. Alpha is unchanged.
+pub fn remove_red_eye(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    x: f32,
+    y: f32,
+    radius: f32,
+) -> Result<Vec<u8>> {
+    check_rgba_len(image_data, width, height)?;
+    let mut out = image_data.to_vec();
+    if radius <= 0.0 {
+        return Ok(out);
+    }
+
+    let x0 = (x - radius).floor().clamp(0.0, width as f32) as u32;
+    let x1 = (x + radius).ceil().clamp(0.0, width as f32) as u32;
+    let y0 = (y - radius).floor().clamp(0.0, height as f32) as u32;
+    let y1 = (y + radius).ceil().clamp(0.0, height as f32) as u32;
+
+    for py in y0..y1 {
+        for px in x0..x1 {
+            let dx = px as f32 + 0.5 - x;
+            let dy = py as f32 + 0.5 - y;
+            let falloff = smoothstep(radius, radius * 0.8, (dx * dx + dy * dy).sqrt());
+            if falloff <= 0.0 {
+                continue;
+            }
+
+            let idx = (py as usize * width as usize + px as usize) * 4;
+            let [r, g, b] = [0, 1, 2].map(|c| out[idx + c] as f32 / 255.0);
+            if r < MIN_RED {
+                continue;
+            }
+            let green_blue = (g + b) * 0.5;
+            let ratio = r / green_blue.max(1e-3);
+            let amount = smoothstep(RED_RATIO_START, RED_RATIO_FULL, ratio) * falloff;
+            if amount <= 0.0 {
+                continue;
+            }
+
+            let neutral = green_blue * PUPIL_DARKENING;
+            let corrected = [
+                r + (neutral - r) * amount,
+                g + (neutral - g) * amount,
+                b + (neutral - b) * amount,
+            ];
+            for (c, v) in corrected.into_iter().enumerate() {
+                out[idx + c] = (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Covers the `w` x `h` rectangle at (`x`, `y`) with texture cloned from
+/// `source_offset` pixels away, blended Poisson-style: the clone keeps the
+/// source's gradients while its colour and brightness are bent to meet the
+/// target's surroundings, so flat skin or sky heals without a visible patch.
+/// `feather` (pixels) additionally fades the result in from the rectangle's
+/// edge. The rectangle is clipped to the frame; the source must lie inside it.
+pub fn heal_spot(
+    image_data: &[u8],
+    width: u32,
+    height: u32,
+    (x, y, w, h): (i64, i64, u32, u32),
+    (offset_x, offset_y): (i64, i64),
+    feather: f32,
+) -> Result<Vec<u8>> {
+    check_rgba_len(image_data, width, height)?;
+    let mut out = image_data.to_vec();
+
+    let x0 = x.clamp(0, width as i64);
+    let y0 = y.clamp(0, height as i64);
+    let x1 = (x + w as i64).clamp(0, width as i64);
+    let y1 = (y + h as i64).clamp(0, height as i64);
+    let (rw, rh) = ((x1 - x0) as usize, (y1 - y0) as usize);
+    if rw < 3 || rh < 3 {
+        return Ok(out);
+    }
+    let (sx0, sy0) = (x0 + offset_x, y0 + offset_y);
+    if sx0 < 0 || sy0 < 0 || sx0 + rw as i64 > width as i64 || sy0 + rh as i64 > height as i64 {
+        return Err(anyhow!(
+            "heal source ({sx0}, {sy0}, {rw}x{rh}) lies outside the {width}x{height} frame"
+        ));
+    }
+
+    let stride = width as usize;
+    let read = |px: i64, py: i64, c: usize| {
+        image_data[(py as usize * stride + px as usize) * 4 + c] as f32 / 255.0
+    };
+
+    // Solve for the correction added to the source: harmonic inside the
+    // rectangle and equal to target - source on its border.
+    let max_iterations = (rw.max(rh) * 4).clamp(50, 2000);
+    for c in 0..3 {
+        let mut correction = vec![0.0f32; rw * rh];
+        for j in 0..rh {
+            for i in 0..rw {
+                if i == 0 || j == 0 || i == rw - 1 || j == rh - 1 {
+                    let (tx, ty) = (x0 + i as i64, y0 + j as i64);
+                    correction[j * rw + i] =
+                        read(tx, ty, c) - read(sx0 + i as i64, sy0 + j as i64, c);
+                }
+            }
+        }
+        // Start the interior at the border mean so the solve converges sooner.
+        let border_mean = {
+            let border: Vec<f32> = (0..rw * rh)
+                .filter(|k| {
+                    let (i, j) = (k % rw, k / rw);
+                    i == 0 || j == 0 || i == rw - 1 || j == rh - 1
+                })
+                .map(|k| correction[k])
+                .collect();
+            border.iter().sum::<f32>() / border.len() as f32
+        };
+        for j in 1..rh - 1 {
+            for i in 1..rw - 1 {
+                correction[j * rw + i] = border_mean;
+            }
+        }
+
+        for _ in 0..max_iterations {
+            let mut max_change = 0.0f32;
+            for j in 1..rh - 1 {
+                for i in 1..rw - 1 {
+                    let k = j * rw + i;
+                    let average = (correction[k - 1]
+                        + correction[k + 1]
+                        + correction[k - rw]
+                        + correction[k + rw])
+                        * 0.25;
+                    let change = (average - correction[k]) * SOR_OMEGA;
+                    correction[k] += change;
+                    max_change = max_change.max(change.abs());
+                }
+            }
+            if max_change < SOR_TOLERANCE {
+                break;
+            }
+        }
+
+        for j in 0..rh {
+            for i in 0..rw {
+                let (tx, ty) = (x0 + i as i64, y0 + j as i64);
+                let healed = read(sx0 + i as i64, sy0 + j as i64, c) + correction[j * rw + i];
+                let edge_distance = i.min(j).min(rw - 1 - i).min(rh - 1 - j) as f32;
+                let weight = if feather > 0.0 {
+                    (edge_distance / feather).clamp(0.0, 1.0)
+                } else {
+                    1.0
+                };
+                let original = read(tx, ty, c);
+                let value = original + (healed - original) * weight;
+                out[(ty as usize * stride + tx as usize) * 4 + c] =
+                    (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(frame: &[u8], width: u32, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * width + x) * 4) as usize;
+        [frame[i], frame[i + 1], frame[i + 2], frame[i + 3]]
+    }
+
+    #[test]
+    fn red_pupils_are_neutralised_and_skin_is_kept() {
+        // Left half a flash-lit pupil, right half skin.
+        let frame: Vec<u8> = (0..16 * 16)
+            .flat_map(|i| {
+                if i % 16 < 8 {
+                    [220, 40, 40, 255]
+                } else {
+                    [200, 140, 120, 255]
+                }
+            })
+            .collect();
+        let out = remove_red_eye(&frame, 16, 16, 8.0, 8.0, 8.0).unwrap();
+
+        let [r, g, b, a] = pixel(&out, 16, 6, 8);
+        assert!(r <= g + 1 && r <= b + 1, "pupil still red: {r} {g} {b}");
+        assert_eq!(a, 255);
+        assert_eq!(pixel(&out, 16, 10, 8), [200, 140, 120, 255]);
+        // Outside the circle nothing changes.
+        assert_eq!(pixel(&out, 16, 0, 0), [220, 40, 40, 255]);
+    }
+
+    #[test]
+    fn red_eye_rejects_a_buffer_of_the_wrong_size() {
+        assert!(remove_red_eye(&[0; 10], 2, 2, 1.0, 1.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn healing_takes_the_source_texture_at_the_target_brightness() {
+        // Brightness ramp across the frame with a dark blemish at (20..24, 8..12).
+        let width = 32;
+        let mut frame: Vec<u8> = (0..width * 20)
+            .flat_map(|i| {
+                let v = (60 + (i % width) * 4) as u8;
+                [v, v, v, 255]
+            })
+            .collect();
+        for y in 8..12 {
+            for x in 20..24 {
+                let i = ((y * width + x) * 4) as usize;
+                frame[i..i + 3].copy_from_slice(&[0, 0, 0]);
+            }
+        }
+
+        // Heal from 12 columns to the left, where the ramp is 48 darker.
+        let out = heal_spot(&frame, width, 20, (18, 6, 8, 8), (-12, 0), 0.0).unwrap();
+        for y in 8..12 {
+            for x in 20..24 {
+                let expected = 60 + x as i32 * 4;
+                let got = pixel(&out, width, x, y)[0] as i32;
+                assert!(
+                    (got - expected).abs() <= 3,
+                    "({x}, {y}): {got} vs {expected}"
+                );
+            }
+        }
+        assert_eq!(pixel(&out, width, 2, 2), pixel(&frame, width, 2, 2));
+    }
+
+    #[test]
+    fn healing_source_must_lie_inside_the_frame() {
+        let frame = vec![128; 16 * 16 * 4];
+        assert!(heal_spot(&frame, 16, 16, (2, 2, 6, 6), (-4, 0), 0.0).is_err());
+        // Too small to have an interior: returned as is.
+        assert_eq!(
+            heal_spot(&frame, 16, 16, (2, 2, 2, 6), (4, 0), 0.0).unwrap(),
+            frame
+        );
+    }
+}
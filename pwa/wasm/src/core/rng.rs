@@ -0,0 +1,86 @@
+//! Seeded randomness for the noise-based effects (grain, dithering).
+//!
+//! Streams are derived from the effect seed by key rather than drawn in
+//! sequence, so every pixel gets the same value whichever band, thread or
+//! region processes it. Equal seeds give byte-identical output.
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// SplitMix64, splittable into independent child streams by key.
+#[derive(Debug, Clone, Copy)]
+pub struct EffectRng {
+    state: u64,
+}
+
+impl EffectRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: mix(seed) }
+    }
+
+    /// A stream determined by this one's state and `key` alone; drawing from
+    /// either afterwards does not affect the other.
+    pub fn split(&self, key: u64) -> Self {
+        Self {
+            state: mix(self.state ^ mix(key.wrapping_add(GOLDEN_GAMMA))),
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        mix(self.state)
+    }
+
+    /// Uniform in [0, 1).
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Uniform noise in [-0.5, 0.5) for frame pixel (`x`, `y`) of stream
+/// `effect`, so different effects sharing a seed stay uncorrelated.
+pub fn pixel_noise(seed: u64, effect: u64, x: u32, y: u32) -> f32 {
+    let key = (y as u64) << 32 | x as u64;
+    EffectRng::new(seed).split(effect).split(key).next_f32() - 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_seeds_repeat_and_splits_are_independent() {
+        let mut a = EffectRng::new(42);
+        let mut b = EffectRng::new(42);
+        let draws: Vec<u64> = (0..4).map(|_| a.next_u64()).collect();
+        assert_eq!(draws, (0..4).map(|_| b.next_u64()).collect::<Vec<_>>());
+        assert_ne!(EffectRng::new(43).next_u64(), draws[0]);
+
+        // A child depends only on its parent's state, not on later draws.
+        let parent = EffectRng::new(7);
+        let mut drawn = parent;
+        drawn.next_u64();
+        assert_eq!(parent.split(1).next_u64(), parent.split(1).next_u64());
+        assert_ne!(parent.split(1).next_u64(), parent.split(2).next_u64());
+        assert_ne!(parent.split(1).next_u64(), drawn.split(1).next_u64());
+    }
+
+    #[test]
+    fn pixel_noise_is_centered_and_keyed_by_position() {
+        let samples: Vec<f32> = (0..64)
+            .flat_map(|y| (0..64).map(move |x| pixel_noise(9, 1, x, y)))
+            .collect();
+        assert!(samples.iter().all(|v| (-0.5..0.5).contains(v)));
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        assert!(mean.abs() < 0.02, "{mean}");
+
+        assert_eq!(pixel_noise(9, 1, 3, 5), pixel_noise(9, 1, 3, 5));
+        assert_ne!(pixel_noise(9, 1, 3, 5), pixel_noise(9, 1, 5, 3));
+        assert_ne!(pixel_noise(9, 1, 3, 5), pixel_noise(9, 2, 3, 5));
+    }
+}
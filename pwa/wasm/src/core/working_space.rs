@@ -0,0 +1,97 @@
+//! Transfer function of the f32 buffers passed between pipeline stages.
+//!
+//! Loaders convert into `PIPELINE_SPACE`, every stage assumes it, and encoders
+//! convert back to sRGB on the way out. Both variants share the Rec.709/sRGB
+//! primaries; only the encoding differs. Alpha is linear in both and is never
+//! touched. The conversions go through `core::color`.
+
+use crate::core::color;
+use image::{DynamicImage, Rgb32FImage, Rgba32FImage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WorkingSpace {
+    /// Scene-linear light: exposure is a multiply and blurs and resamples
+    /// average photons rather than code values.
+    #[default]
+    LinearRec709,
+    /// sRGB-encoded, as stored in JPEG/PNG files and expected by encoders.
+    Srgb,
+}
+
+/// The space every core stage receives and returns.
+pub const PIPELINE_SPACE: WorkingSpace = WorkingSpace::LinearRec709;
+
+pub fn convert_buffer(buffer: &mut Rgb32FImage, from: WorkingSpace, to: WorkingSpace) {
+    color::convert_pixels(buffer, 3, from.into(), to.into());
+}
+
+pub fn convert_rgba_buffer(buffer: &mut Rgba32FImage, from: WorkingSpace, to: WorkingSpace) {
+    color::convert_pixels(buffer, 4, from.into(), to.into());
+}
+
+/// Re-encodes `image` from `from` to `to`. Images already in `to` are
+/// returned untouched; anything else comes back as `Rgba32F` if it has alpha
+/// and `Rgb32F` otherwise.
+pub fn convert(image: DynamicImage, from: WorkingSpace, to: WorkingSpace) -> DynamicImage {
+    if from == to {
+        return image;
+    }
+    if image.color().has_alpha() {
+        let mut buffer = image.into_rgba32f();
+        convert_rgba_buffer(&mut buffer, from, to);
+        DynamicImage::ImageRgba32F(buffer)
+    } else {
+        let mut buffer = image.into_rgb32f();
+        convert_buffer(&mut buffer, from, to);
+        DynamicImage::ImageRgb32F(buffer)
+    }
+}
+
+/// sRGB-encoded file content (JPEG previews, generated images) into the
+/// pipeline space.
+pub fn from_srgb(image: DynamicImage) -> DynamicImage {
+    convert(image, WorkingSpace::Srgb, PIPELINE_SPACE)
+}
+
+/// A pipeline image re-encoded for an 8/16-bit encoder.
+pub fn to_srgb(image: &DynamicImage) -> DynamicImage {
+    convert(image.clone(), PIPELINE_SPACE, WorkingSpace::Srgb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_round_trip_keeps_values_and_alpha() {
+        let source = DynamicImage::ImageRgba32F(Rgba32FImage::from_fn(4, 1, |x, _| {
+            let v = x as f32 / 3.0;
+            image::Rgba([v, 0.5, 1.0 - v, 0.25])
+        }));
+        let linear = from_srgb(source.clone());
+        let mid = linear.as_rgba32f().unwrap().get_pixel(0, 0)[1];
+        assert!(
+            (mid - 0.214).abs() < 1e-3,
+            "sRGB 0.5 is about 0.214 linear, got {mid}"
+        );
+        assert_eq!(linear.as_rgba32f().unwrap().get_pixel(2, 0)[3], 0.25);
+
+        let back = to_srgb(&linear);
+        for (a, b) in back
+            .as_rgba32f()
+            .unwrap()
+            .as_raw()
+            .iter()
+            .zip(source.as_rgba32f().unwrap().as_raw())
+        {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn converting_to_the_same_space_is_a_no_op() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::new(2, 2));
+        let same = convert(image.clone(), WorkingSpace::Srgb, WorkingSpace::Srgb);
+        assert!(matches!(same, DynamicImage::ImageRgb8(_)));
+    }
+}
@@ -3,55 +3,190 @@ use js_sys::Promise;
 
 mod core;
 
+use core::error::ProcessingError;
+
+/// Decodes any supported file into the pipeline space. `auto_orient` bakes
+/// in the file's orientation; without it pixels stay as stored (RAWs in
+/// sensor orientation) and `image_orientation` reports the tag.
 fn decode_image_from_bytes(
 	data: &[u8],
 	path: &str,
 	use_fast_raw_dev: bool,
 	highlight_compression: f32,
-) -> Result<image::DynamicImage, JsValue> {
-	let is_raw = core::formats::is_raw_file(path);
-	if is_raw {
-		#[cfg(feature = "raw-processing")]
-		{
-			let mut img = core::raw_processing::develop_raw_image(
-				data,
-				use_fast_raw_dev,
-				highlight_compression,
-				None,
-			)
-			.map_err(|err| JsValue::from_str(&format!("raw decode failed: {err}")))?;
-			if !use_fast_raw_dev {
-				core::image_processing::remove_raw_artifacts_and_enhance(&mut img);
-			}
-			Ok(img)
+	auto_orient: bool,
+) -> Result<image::DynamicImage, ProcessingError> {
+	if data.is_empty() {
+		return Err(ProcessingError::corrupt_data("image buffer is empty"));
+	}
+	let has_extension = std::path::Path::new(path).extension().is_some();
+	let known_content = core::formats::is_supported_image_bytes(data);
+	if has_extension && !core::formats::is_supported_image_file(path) && !known_content {
+		return Err(ProcessingError::unsupported_format(format!(
+			"unsupported file type: {path}"
+		)));
+	}
+	if !has_extension && !known_content {
+		return Err(ProcessingError::unsupported_format(format!(
+			"unrecognised image content: {path}"
+		)));
+	}
+
+	match core::formats::decode_route(data, path) {
+		core::formats::DecodeRoute::Raw => {
+			decode_raw_from_bytes(data, use_fast_raw_dev, highlight_compression, auto_orient)
 		}
-		#[cfg(not(feature = "raw-processing"))]
-		{
-			Err(JsValue::from_str("RAW decoding is not enabled in this build."))
+		core::formats::DecodeRoute::MaybeRaw => {
+			decode_raw_from_bytes(data, use_fast_raw_dev, highlight_compression, auto_orient)
+				.or_else(|_| decode_non_raw_from_bytes(data, path, auto_orient))
 		}
-	} else {
-		#[cfg(feature = "image-decoding")]
-		{
-			let decoded = core::image_loader::load_non_raw_image_from_bytes(data, path)
-				.map_err(|err| JsValue::from_str(&format!("image decode failed: {err}")))?;
-			Ok(decoded)
+		core::formats::DecodeRoute::NonRaw => decode_non_raw_from_bytes(data, path, auto_orient),
+	}
+}
+
+/// A RAW the decoder rejects is reported as `DecodeUnsupported` so the app can
+/// say so, rather than as generic corruption; limit errors keep their kind.
+#[cfg(feature = "raw-processing")]
+fn raw_decode_error(err: &anyhow::Error) -> ProcessingError {
+	let error = ProcessingError::from_decode_error("raw decode failed", err);
+	match error.kind {
+		core::error::ProcessingErrorKind::CorruptData => {
+			ProcessingError::decode_unsupported(error.message)
 		}
-		#[cfg(not(feature = "image-decoding"))]
-		{
-			Err(JsValue::from_str("Image decoding is not enabled in this build."))
+		_ => error,
+	}
+}
+
+#[allow(unused_variables)]
+fn decode_raw_from_bytes(
+	data: &[u8],
+	use_fast_raw_dev: bool,
+	highlight_compression: f32,
+	auto_orient: bool,
+) -> Result<image::DynamicImage, ProcessingError> {
+	#[cfg(feature = "raw-processing")]
+	{
+		let options = core::raw_processing::DevelopOptions {
+			keep_sensor_orientation: !auto_orient,
+			..Default::default()
+		};
+		let (mut img, _) = core::raw_processing::develop_raw_with_info(
+			data,
+			use_fast_raw_dev,
+			highlight_compression,
+			options,
+			None,
+		)
+		.map_err(|err| raw_decode_error(&err))?;
+		// Chroma cleanup is meaningless for monochrome sensors, which develop to Luma16.
+		if !use_fast_raw_dev && img.color().has_color() {
+			core::image_processing::remove_raw_artifacts_and_enhance(
+				&mut img,
+				core::working_space::WorkingSpace::LinearRec709,
+			);
 		}
+		Ok(img)
+	}
+	#[cfg(not(feature = "raw-processing"))]
+	{
+		Err(ProcessingError::feature_disabled("RAW decoding is not enabled in this build."))
+	}
+}
+
+#[allow(unused_variables)]
+fn decode_non_raw_from_bytes(
+	data: &[u8],
+	path: &str,
+	auto_orient: bool,
+) -> Result<image::DynamicImage, ProcessingError> {
+	#[cfg(feature = "image-decoding")]
+	{
+		let limits = core::image_loader::image_limits();
+		core::image_loader::load_non_raw_image_from_bytes_with_limits(data, path, &limits, auto_orient)
+			.map_err(|err| ProcessingError::from_decode_error("image decode failed", &err))
+	}
+	#[cfg(not(feature = "image-decoding"))]
+	{
+		Err(ProcessingError::feature_disabled("Image decoding is not enabled in this build."))
 	}
 }
 
-fn encode_png(image: &image::DynamicImage) -> Result<Vec<u8>, JsValue> {
-	let rgba = image.to_rgba8();
+/// Geometry pass in the desktop order: `transformRotate` straightening (frame
+/// size kept), then `orientationSteps` quarter turns, then mirror flips.
+/// EXIF orientation is already baked in by the decoder.
+fn apply_geometry(image: image::DynamicImage, adjustments_json: &str) -> image::DynamicImage {
+	let adjustments: serde_json::Value =
+		serde_json::from_str(adjustments_json).unwrap_or(serde_json::Value::Null);
+	let params = core::geometry::get_geometry_params_from_json(&adjustments);
+	let image = if params.rotate != 0.0 {
+		core::image_processing::rotate_arbitrary(&image, params.rotate, [0.0; 4], true)
+	} else {
+		image
+	};
+	let orientation_steps = adjustments["orientationSteps"].as_u64().unwrap_or(0) as u8;
+	let image = core::image_processing::apply_coarse_rotation(image, orientation_steps);
+	core::image_processing::apply_flip(image, params.flip_horizontal, params.flip_vertical)
+}
+
+/// Passes that run on the decoded frame before the adjustments: negative
+/// conversion, so later passes see positive colors, defringe, while edges are
+/// still unresampled, then `apply_geometry`.
+fn apply_source_passes(image: image::DynamicImage, adjustments_json: &str) -> image::DynamicImage {
+	let mut image = image;
+	if let Some(negative) = core::negative::negative_from_adjustments(adjustments_json) {
+		core::negative::invert_negative(&mut image, negative.film_base, negative.balance);
+	}
+	let defringe = core::defringe::defringe_from_adjustments(adjustments_json);
+	core::defringe::apply_defringe(&mut image, &defringe);
+	apply_geometry(image, adjustments_json)
+}
+
+/// Passes that run after the adjustments: color grading, then the creative
+/// LUT, which the desktop shader also applies last.
+fn apply_color_passes(image: &mut image::DynamicImage, adjustments_json: &str) {
+	let grading = core::color_grading::color_grading_from_adjustments(adjustments_json);
+	core::color_grading::apply_color_grading_to_image(image, &grading);
+	if let Some((lut, intensity)) = core::color_grading::lut_from_adjustments(adjustments_json) {
+		core::color_grading::apply_lut_to_image(image, &lut, intensity);
+	}
+}
+
+/// 8-bit PNG of a pipeline image, converted back to sRGB with any alpha kept
+/// and dithered unless `set_preview_dither(false)`.
+fn encode_png(image: &image::DynamicImage) -> Result<Vec<u8>, ProcessingError> {
+	let rgba = core::export::preview_rgba8(image);
 	let mut bytes = Vec::new();
 	image::DynamicImage::ImageRgba8(rgba)
 		.write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
-		.map_err(|err| JsValue::from_str(&format!("png encode failed: {err}")))?;
+		.map_err(|err| {
+			ProcessingError::new(
+				core::error::classify_image_error(&err),
+				format!("png encode failed: {err}"),
+			)
+		})?;
 	Ok(bytes)
 }
 
+/// The editor preview of a decoded frame: the source, adjustment and color
+/// passes, then a downscale to `max_edge` (0 keeps the size) and PNG encode.
+fn render_preview_png(
+	image: image::DynamicImage,
+	adjustments_json: &str,
+	max_edge: u32,
+) -> Result<Vec<u8>, ProcessingError> {
+	let mut image = apply_source_passes(image, adjustments_json);
+	let adjustments = core::adjustments::parse_adjustments(adjustments_json);
+	core::adjustments::apply_basic_adjustments(&mut image, &adjustments);
+	apply_color_passes(&mut image, adjustments_json);
+
+	let image = if max_edge > 0 {
+		core::image_utils::downscale_f32_image(&image, max_edge, max_edge)
+	} else {
+		image
+	};
+
+	encode_png(&image)
+}
+
 #[wasm_bindgen]
 pub fn version() -> String {
 	"rapidraw-wasm 0.1.0".to_string()
@@ -68,6 +203,31 @@ pub fn is_supported_image_file(path: &str) -> bool {
 	core::formats::is_supported_image_file(path)
 }
 
+#[wasm_bindgen]
+pub fn is_supported_image_bytes(data: &[u8]) -> bool {
+	core::formats::is_supported_image_bytes(data)
+}
+
+/// Overrides the decode budget (pixel count and allocation size). Pass 0 for
+/// either to lift that limit; intended for trusted desktop-class hosts.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn set_image_limits(max_pixels: f64, max_alloc_bytes: f64) {
+	let to_limit = |value: f64| if value > 0.0 { value as u64 } else { u64::MAX };
+	core::image_loader::set_image_limits(core::image_loader::ImageLimits {
+		max_pixels: to_limit(max_pixels),
+		max_alloc_bytes: to_limit(max_alloc_bytes),
+	});
+}
+
+/// Turns the ordered dithering of 8-bit preview PNGs on or off (on by
+/// default). Exports are unaffected.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn set_preview_dither(enabled: bool) {
+	core::export::set_preview_dither(enabled);
+}
+
 #[wasm_bindgen]
 pub fn default_image_metadata_json() -> String {
 	let metadata = core::metadata::ImageMetadata::default();
@@ -82,18 +242,395 @@ pub fn geometry_from_adjustments(adjustments_json: &str) -> String {
 	serde_json::to_string(&params).unwrap_or_else(|_| "{}".to_string())
 }
 
+#[wasm_bindgen]
+pub fn measure_region(
+	image_data: &[u8],
+	width: u32,
+	x: u32,
+	y: u32,
+	w: u32,
+	h: u32,
+) -> Result<JsValue, JsValue> {
+	let stats = core::analysis::measure_region(image_data, width, x, y, w, h);
+	serde_wasm_bindgen::to_value(&stats).map_err(JsValue::from)
+}
+
+/// Average color of the `w` x `h` rectangle at (`x`, `y`) of a canvas
+/// `ImageData` buffer as `#rrggbb`, for picking the film base (an unexposed
+/// border) used as `filmBaseColor` by negative conversion.
+#[wasm_bindgen]
+pub fn film_base_color(image_data: &[u8], width: u32, x: u32, y: u32, w: u32, h: u32) -> String {
+	let stats = core::analysis::measure_region(image_data, width, x, y, w, h);
+	let [r, g, b] = [stats.mean_red, stats.mean_green, stats.mean_blue]
+		.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+	format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// Top-level keys of an adjustments JSON that no pass reads, for catching
+/// misspelled keys while developing the frontend.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn unknown_adjustment_keys(adjustments_json: &str) -> Result<JsValue, JsValue> {
+	let (_, unknown) = core::adjustments::parse_adjustments_checked(adjustments_json);
+	serde_wasm_bindgen::to_value(&unknown).map_err(JsValue::from)
+}
+
+/// Noise standard deviations of a canvas `ImageData` buffer, measured in its
+/// flattest regions, for picking a denoise strength automatically.
+#[wasm_bindgen]
+pub fn estimate_noise(image_data: &[u8], width: u32) -> Result<JsValue, JsValue> {
+	let profile = core::analysis::estimate_noise(image_data, width);
+	serde_wasm_bindgen::to_value(&profile).map_err(JsValue::from)
+}
+
+/// Max/mean per-channel difference and PSNR of two same-sized canvas
+/// `ImageData` buffers, compared in encoded sRGB values.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn image_diff(before: &[u8], after: &[u8], width: u32, height: u32) -> Result<JsValue, JsValue> {
+	let before = rgba_image_data(before, width, height)?;
+	let after = rgba_image_data(after, width, height)?;
+	let stats = core::image_utils::image_diff(&before, &after)
+		.map_err(|err| ProcessingError::corrupt_data(err.to_string()))?;
+	serde_wasm_bindgen::to_value(&stats).map_err(JsValue::from)
+}
+
+/// Before/after difference view of two canvas `ImageData` buffers: the
+/// absolute difference times `gain`, as an opaque RGBA buffer.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn difference_image(
+	before: &[u8],
+	after: &[u8],
+	width: u32,
+	height: u32,
+	gain: f32,
+) -> Result<Vec<u8>, JsValue> {
+	let before = rgba_image_data(before, width, height)?;
+	let after = rgba_image_data(after, width, height)?;
+	let difference = core::image_utils::difference_image(&before, &after, gain)
+		.map_err(|err| ProcessingError::corrupt_data(err.to_string()))?;
+	Ok(image::DynamicImage::ImageRgb32F(difference).to_rgba8().into_raw())
+}
+
+#[cfg(feature = "image-decoding")]
+fn rgba_image_data(
+	image_data: &[u8],
+	width: u32,
+	height: u32,
+) -> Result<image::DynamicImage, ProcessingError> {
+	image::RgbaImage::from_raw(width, height, image_data.to_vec())
+		.map(image::DynamicImage::ImageRgba8)
+		.ok_or_else(|| ProcessingError::corrupt_data("image_data does not match width * height * 4"))
+}
+
+/// Suggested `transformRotate` that levels the horizon of a canvas
+/// `ImageData` preview, or 0.0 when none is found.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn auto_straighten(image_data: &[u8], width: u32, height: u32) -> Result<f32, JsValue> {
+	let buffer = image::RgbaImage::from_raw(width, height, image_data.to_vec()).ok_or_else(|| {
+		ProcessingError::corrupt_data("image_data does not match width * height * 4")
+	})?;
+	Ok(core::geometry::auto_straighten(&image::DynamicImage::ImageRgba8(buffer)))
+}
+
+/// Centred crop (`{x, y, width, height}` in pixels, as JSON) for the crop
+/// tool's aspect `preset` ("original", "1:1", "4:5", "3:2", "16:9") with
+/// `orientation` "auto", "landscape" or "portrait".
+#[wasm_bindgen]
+pub fn aspect_crop_json(
+	width: u32,
+	height: u32,
+	preset: &str,
+	orientation: &str,
+) -> Result<String, JsValue> {
+	let preset = serde_json::from_value(serde_json::Value::String(preset.to_string()))
+		.map_err(|_| ProcessingError::corrupt_data(format!("unknown aspect preset '{preset}'")))?;
+	let orientation = serde_json::from_value(serde_json::Value::String(orientation.to_string()))
+		.unwrap_or_default();
+	let crop = core::geometry::aspect_crop(width, height, preset, orientation);
+	serde_json::to_string(&crop)
+		.map_err(|err| ProcessingError::metadata(format!("serialize failed: {err}")).into())
+}
+
+#[wasm_bindgen]
+pub fn sample_pixel(image_data: &[u8], width: u32, height: u32, x: f32, y: f32) -> Vec<f32> {
+	core::analysis::sample_pixel(image_data, width, height, x, y).to_vec()
+}
+
+/// Edge-preserving smoothing of a single-channel `width` x `height` float
+/// buffer (a luma or mask plane, for instance).
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn bilateral_filter(
+	buffer: &[f32],
+	width: usize,
+	height: usize,
+	spatial_sigma: f32,
+	range_sigma: f32,
+) -> Result<Vec<f32>, JsValue> {
+	if width.checked_mul(height) != Some(buffer.len()) {
+		return Err(ProcessingError::corrupt_data("buffer length does not match width * height").into());
+	}
+	Ok(core::image_utils::bilateral_filter(
+		buffer,
+		width,
+		height,
+		spatial_sigma,
+		range_sigma,
+	))
+}
+
+/// Re-renders only the brushed rectangle of a canvas `ImageData` buffer and
+/// returns the whole patched buffer. `rect` is `[x, y, width, height]`.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn process_region(
+	image_data: &[u8],
+	width: u32,
+	height: u32,
+	rect: &[i32],
+	adjustments_json: &str,
+) -> Result<Vec<u8>, JsValue> {
+	let &[x, y, w, h] = rect else {
+		return Err(ProcessingError::corrupt_data("rect must be [x, y, width, height]").into());
+	};
+	let adjustments = core::adjustments::parse_adjustments(adjustments_json);
+	core::adjustments::process_region(
+		image_data,
+		width,
+		height,
+		(x as i64, y as i64, w.max(0) as u32, h.max(0) as u32),
+		&adjustments,
+	)
+	.map_err(|err| ProcessingError::corrupt_data(err.to_string()).into())
+}
+
+/// Neutralises red pupils inside the circle of `radius` at (`x`, `y`) of a
+/// canvas `ImageData` buffer and returns the patched buffer.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn remove_red_eye(
+	image_data: &[u8],
+	width: u32,
+	height: u32,
+	x: f32,
+	y: f32,
+	radius: f32,
+) -> Result<Vec<u8>, JsValue> {
+	core::retouch::remove_red_eye(image_data, width, height, x, y, radius)
+		.map_err(|err| ProcessingError::corrupt_data(err.to_string()).into())
+}
+
+/// Heals the `[x, y, width, height]` rectangle of a canvas `ImageData` buffer
+/// with texture from `[dx, dy]` pixels away and returns the patched buffer.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn heal_spot(
+	image_data: &[u8],
+	width: u32,
+	height: u32,
+	target: &[i32],
+	source_offset: &[i32],
+	feather: f32,
+) -> Result<Vec<u8>, JsValue> {
+	let &[x, y, w, h] = target else {
+		return Err(ProcessingError::corrupt_data("target must be [x, y, width, height]").into());
+	};
+	let &[dx, dy] = source_offset else {
+		return Err(ProcessingError::corrupt_data("source_offset must be [dx, dy]").into());
+	};
+	core::retouch::heal_spot(
+		image_data,
+		width,
+		height,
+		(x as i64, y as i64, w.max(0) as u32, h.max(0) as u32),
+		(dx as i64, dy as i64),
+		feather,
+	)
+	.map_err(|err| ProcessingError::corrupt_data(err.to_string()).into())
+}
+
+/// Remaps the RGB channels of a canvas `ImageData` buffer; `mapping[i]` is
+/// the source channel (0-2) of output channel `i`.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn swap_channels(image_data: &[u8], mapping: &[u32]) -> Result<Vec<u8>, JsValue> {
+	core::color_grading::swap_channels(image_data, mapping)
+		.map_err(|err| ProcessingError::corrupt_data(err.to_string()).into())
+}
+
+/// Grades a canvas `ImageData` buffer with the full color grading settings
+/// (HSL bands, wheels, global saturation/vibrance). Malformed or out-of-range
+/// settings are rejected with an `InvalidSettings` error naming the field,
+/// before any pixel is processed.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn apply_color_grading(image_data: &[u8], settings_json: &str) -> Result<Vec<u8>, JsValue> {
+	let settings: core::color_grading::ColorGradingSettings = serde_json::from_str(settings_json)
+		.map_err(|err| ProcessingError::invalid_settings(format!("invalid settings: {err}")))?;
+	core::color_grading::validate_color_grading_settings(&settings)
+		.map_err(|err| ProcessingError::invalid_settings(err.to_string()))?;
+	core::color_grading::apply_color_grading_rgba8(image_data, &settings)
+		.map_err(|err| ProcessingError::corrupt_data(err.to_string()).into())
+}
+
+/// Re-encodes a canvas `ImageData` buffer from one color space to another
+/// ("srgb", "adobeRgb", "displayP3", "proPhoto", "linearRec709"), e.g. for a
+/// `display-p3` canvas. Alpha is kept.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn convert_color_space(
+	image_data: &[u8],
+	width: u32,
+	height: u32,
+	from: &str,
+	to: &str,
+) -> Result<Vec<u8>, JsValue> {
+	let parse = |name: &str| {
+		let value = serde_json::Value::String(name.to_string());
+		serde_json::from_value::<core::color::ColorSpace>(value)
+			.map_err(|_| ProcessingError::corrupt_data(format!("unknown color space '{name}'")))
+	};
+	let (from, to) = (parse(from)?, parse(to)?);
+	let buffer = image::RgbaImage::from_raw(width, height, image_data.to_vec()).ok_or_else(|| {
+		ProcessingError::corrupt_data("image_data does not match width * height * 4")
+	})?;
+	let converted =
+		core::color::convert_color_space(&image::DynamicImage::ImageRgba8(buffer), from, to);
+	Ok(converted.to_rgba8().into_raw())
+}
+
+/// Soft-proofs a canvas `ImageData` buffer (sRGB) against the RGB ICC
+/// profile `profile_icc`, or the built-in `profile_name` space ("srgb",
+/// "adobeRgb", "proPhoto") when no profile bytes are given, with `intent`
+/// ("perceptual", "relativeColorimetric", ...). Returns the preview buffer,
+/// out-of-gamut pixels painted grey when `gamut_warning` is set.
+#[cfg(feature = "soft-proofing")]
+#[wasm_bindgen]
+pub fn soft_proof(
+	image_data: &[u8],
+	width: u32,
+	height: u32,
+	profile_name: &str,
+	profile_icc: &[u8],
+	intent: &str,
+	gamut_warning: bool,
+) -> Result<Vec<u8>, JsValue> {
+	let destination = core::color::proof::ProofProfile::from_name_or_icc(profile_name, profile_icc)
+		.map_err(|err| ProcessingError::corrupt_data(err.to_string()))?;
+	let buffer = image::RgbaImage::from_raw(width, height, image_data.to_vec()).ok_or_else(|| {
+		ProcessingError::corrupt_data("image_data does not match width * height * 4")
+	})?;
+	let image = core::working_space::from_srgb(image::DynamicImage::ImageRgba8(buffer));
+	let intent = serde_json::from_value(serde_json::Value::String(intent.to_string()))
+		.unwrap_or_default();
+	let proofed = core::color::proof::soft_proof(
+		&image,
+		&core::color::proof::ProofProfile::Srgb,
+		&destination,
+		intent,
+		gamut_warning.then_some([128, 128, 128]),
+	)
+	.map_err(|err| ProcessingError::corrupt_data(err.to_string()))?;
+	Ok(core::working_space::to_srgb(&proofed).to_rgba8().into_raw())
+}
+
 #[wasm_bindgen]
 pub fn apply_adjustments() -> Result<(), JsValue> {
 	// Placeholder: wire to core processing pipeline.
 	Ok(())
 }
 
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn export_image(
+	data: &[u8],
+	path: &str,
+	adjustments_json: &str,
+	options_json: &str,
+	use_fast_raw_dev: bool,
+	highlight_compression: f32,
+) -> Result<Vec<u8>, JsValue> {
+	export_image_with_info(
+		data,
+		path,
+		adjustments_json,
+		options_json,
+		use_fast_raw_dev,
+		highlight_compression,
+	)
+	.map(|exported| exported.result.data)
+}
+
+/// `export_image`, also reporting the output dimensions, format and size.
+#[cfg(feature = "image-decoding")]
 #[wasm_bindgen]
-pub fn export_image() -> Vec<u8> {
-	// Placeholder: return encoded image bytes from the pipeline.
-	Vec::new()
+pub fn export_image_with_info(
+	data: &[u8],
+	path: &str,
+	adjustments_json: &str,
+	options_json: &str,
+	use_fast_raw_dev: bool,
+	highlight_compression: f32,
+) -> Result<ExportedImage, JsValue> {
+	let image = decode_image_from_bytes(data, path, use_fast_raw_dev, highlight_compression, true)?;
+	let mut image = apply_source_passes(image, adjustments_json);
+	let adjustments = core::adjustments::parse_adjustments(adjustments_json);
+	core::adjustments::apply_basic_adjustments(&mut image, &adjustments);
+	apply_color_passes(&mut image, adjustments_json);
+
+	let options = core::export::parse_export_options(options_json);
+	core::export::export_image(image, &options)
+		.map(|result| ExportedImage { result })
+		.map_err(|err| ProcessingError::from_decode_error("export failed", &err).into())
 }
 
+/// An encoded export and what was written.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub struct ExportedImage {
+	result: core::export::ExportResult,
+}
+
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+impl ExportedImage {
+	#[wasm_bindgen(getter)]
+	pub fn data(&self) -> Vec<u8> {
+		self.result.data.clone()
+	}
+
+	#[wasm_bindgen(getter)]
+	pub fn width(&self) -> u32 {
+		self.result.width
+	}
+
+	#[wasm_bindgen(getter)]
+	pub fn height(&self) -> u32 {
+		self.result.height
+	}
+
+	/// "jpeg" or "png".
+	#[wasm_bindgen(getter)]
+	pub fn format(&self) -> String {
+		self.result.format.name().to_string()
+	}
+
+	#[wasm_bindgen(getter, js_name = byteLength)]
+	pub fn byte_len(&self) -> usize {
+		self.result.byte_len
+	}
+
+	#[wasm_bindgen(getter, js_name = hasMetadata)]
+	pub fn has_metadata(&self) -> bool {
+		self.result.has_metadata
+	}
+}
+
+/// `auto_orient` (default true) bakes in the EXIF orientation; pass false
+/// for files that are already upright and read `image_orientation` instead.
 #[wasm_bindgen]
 pub fn load_image_preview_png(
 	data: &[u8],
@@ -101,8 +638,11 @@ pub fn load_image_preview_png(
 	max_edge: u32,
 	use_fast_raw_dev: bool,
 	highlight_compression: f32,
+	auto_orient: Option<bool>,
 ) -> Result<Vec<u8>, JsValue> {
-	let image = decode_image_from_bytes(data, path, use_fast_raw_dev, highlight_compression)?;
+	let auto_orient = auto_orient.unwrap_or(true);
+	let image =
+		decode_image_from_bytes(data, path, use_fast_raw_dev, highlight_compression, auto_orient)?;
 
 	let image = if max_edge > 0 {
 		core::image_utils::downscale_f32_image(&image, max_edge, max_edge)
@@ -110,9 +650,10 @@ pub fn load_image_preview_png(
 		image
 	};
 
-	encode_png(&image)
+	Ok(encode_png(&image)?)
 }
 
+/// `auto_orient` is as in `load_image_preview_png`.
 #[wasm_bindgen]
 pub fn load_image_preview_with_adjustments_png(
 	data: &[u8],
@@ -121,34 +662,213 @@ pub fn load_image_preview_with_adjustments_png(
 	adjustments_json: &str,
 	use_fast_raw_dev: bool,
 	highlight_compression: f32,
+	auto_orient: Option<bool>,
 ) -> Result<Vec<u8>, JsValue> {
-	let mut image = decode_image_from_bytes(data, path, use_fast_raw_dev, highlight_compression)?;
+	let auto_orient = auto_orient.unwrap_or(true);
+	let image =
+		decode_image_from_bytes(data, path, use_fast_raw_dev, highlight_compression, auto_orient)?;
+	Ok(render_preview_png(image, adjustments_json, max_edge)?)
+}
+
+/// Rows processed between yields in the `*_async` variants.
+const ASYNC_BAND_ROWS: u32 = 64;
+
+/// Lets queued promise callbacks run before the next band of work.
+async fn yield_to_event_loop() -> Result<(), JsValue> {
+	wasm_bindgen_futures::JsFuture::from(Promise::resolve(&JsValue::UNDEFINED)).await?;
+	Ok(())
+}
+
+/// Promise-returning `load_image_preview_with_adjustments_png` for builds
+/// without a thread pool: the adjustment pass runs in row bands and yields to
+/// the event loop between them. Output matches the synchronous version.
+#[wasm_bindgen]
+pub fn load_image_preview_with_adjustments_png_async(
+	data: Vec<u8>,
+	path: String,
+	max_edge: u32,
+	adjustments_json: String,
+	use_fast_raw_dev: bool,
+	highlight_compression: f32,
+	auto_orient: Option<bool>,
+) -> Promise {
+	wasm_bindgen_futures::future_to_promise(async move {
+		let auto_orient = auto_orient.unwrap_or(true);
+		let image =
+			decode_image_from_bytes(&data, &path, use_fast_raw_dev, highlight_compression, auto_orient)?;
+		yield_to_event_loop().await?;
+
+		let image = apply_source_passes(image, &adjustments_json);
+		let adjustments = core::adjustments::parse_adjustments(&adjustments_json);
+		let mut image = core::adjustments::to_adjustment_buffer(image);
+		let height = image.height();
+		let mut row = 0;
+		while row < height {
+			let end = (row + ASYNC_BAND_ROWS).min(height);
+			core::adjustments::apply_basic_adjustments_to_rows(&mut image, &adjustments, row..end);
+			row = end;
+			yield_to_event_loop().await?;
+		}
+
+		apply_color_passes(&mut image, &adjustments_json);
+		yield_to_event_loop().await?;
+
+		let image = if max_edge > 0 {
+			core::image_utils::downscale_f32_image(&image, max_edge, max_edge)
+		} else {
+			image
+		};
+		let png = encode_png(&image)?;
+		Ok(js_sys::Uint8Array::from(png.as_slice()).into())
+	})
+}
+
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn generate_lut(settings_json: &str, size: usize) -> Vec<f32> {
+	let settings = core::color_grading::parse_color_grading_settings(settings_json);
+	core::color_grading::generate_lut(&settings, size)
+}
+
+/// LUT approximating a Fujifilm film simulation, by the name RAW metadata
+/// reports as `FilmSimulation` (e.g. "Velvia", "Classic Chrome"). Unknown
+/// names give the neutral Provia/Standard LUT. Apply it like any other LUT.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn film_simulation_lut(name: &str, size: usize) -> Vec<f32> {
+	core::film_simulation::FilmSimulation::from_name(name).lut(size)
+}
+
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn export_cube_lut(
+	settings_json: &str,
+	size: usize,
+	title: &str,
+	clamp_domain: bool,
+) -> Result<String, JsValue> {
+	let settings = core::color_grading::parse_color_grading_settings(settings_json);
+	let data = core::color_grading::generate_lut(&settings, size);
+	core::color_grading::export_cube_lut(&data, size, title, clamp_domain)
+		.map_err(|err| ProcessingError::corrupt_data(format!("lut export failed: {err}")).into())
+}
+
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn generate_1d_lut(adjustments_json: &str, size: usize) -> Result<Vec<f32>, JsValue> {
 	let adjustments = core::adjustments::parse_adjustments(adjustments_json);
-	core::adjustments::apply_basic_adjustments(&mut image, &adjustments);
+	core::color_grading::generate_1d_lut(&adjustments, size)
+		.map_err(|err| ProcessingError::corrupt_data(format!("lut generation failed: {err}")).into())
+}
 
-	let image = if max_edge > 0 {
-		core::image_utils::downscale_f32_image(&image, max_edge, max_edge)
-	} else {
-		image
-	};
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn export_cube_1d(adjustments_json: &str, size: usize, title: &str) -> Result<String, JsValue> {
+	let adjustments = core::adjustments::parse_adjustments(adjustments_json);
+	core::color_grading::generate_1d_lut(&adjustments, size)
+		.and_then(|curve| core::color_grading::export_cube_1d(&curve, title))
+		.map_err(|err| ProcessingError::corrupt_data(format!("lut export failed: {err}")).into())
+}
 
-	encode_png(&image)
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn apply_lut_to_thumbnail(
+	image_data: &[u8],
+	width: u32,
+	height: u32,
+	lut_data: &[f32],
+	resolution: usize,
+	max_edge: u32,
+) -> Result<Vec<u8>, JsValue> {
+	core::color_grading::apply_lut_to_thumbnail(image_data, width, height, lut_data, resolution, max_edge)
+		.map_err(|err| ProcessingError::corrupt_data(format!("lut preview failed: {err}")).into())
+}
+
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn lut_thumbnail_dimensions(width: u32, height: u32, max_edge: u32) -> Vec<u32> {
+	let (w, h) = core::color_grading::thumbnail_dimensions(width, height, max_edge);
+	vec![w, h]
 }
 
 #[cfg(feature = "image-decoding")]
 #[wasm_bindgen]
 pub fn non_raw_metadata_json(data: &[u8]) -> Result<String, JsValue> {
 	let map = core::non_raw_metadata::extract_non_raw_metadata(data)
-		.map_err(|err| JsValue::from_str(&format!("metadata failed: {err}")))?;
-	serde_json::to_string(&map).map_err(|err| JsValue::from_str(&format!("serialize failed: {err}")))
+		.map_err(|err| ProcessingError::metadata(format!("metadata failed: {err}")))?;
+	serde_json::to_string(&map)
+		.map_err(|err| ProcessingError::metadata(format!("serialize failed: {err}")).into())
+}
+
+/// Adjustments JSON reproducing the camera's as-shot look of a RAW, for a
+/// "start from camera" reset; see `raw_processing::reset_to_camera_baseline`.
+#[cfg(feature = "raw-processing")]
+#[wasm_bindgen]
+pub fn camera_baseline_adjustments_json(data: &[u8]) -> Result<String, JsValue> {
+	let adjustments = core::raw_processing::reset_to_camera_baseline(data);
+	serde_json::to_string(&adjustments)
+		.map_err(|err| ProcessingError::metadata(format!("serialize failed: {err}")).into())
 }
 
 #[cfg(feature = "raw-processing")]
 #[wasm_bindgen]
 pub fn raw_metadata_json(data: &[u8]) -> Result<String, JsValue> {
 	let map = core::raw_metadata::extract_raw_metadata(data)
-		.map_err(|err| JsValue::from_str(&format!("raw metadata failed: {err}")))?;
-	serde_json::to_string(&map).map_err(|err| JsValue::from_str(&format!("serialize failed: {err}")))
+		.map_err(|err| ProcessingError::metadata(format!("raw metadata failed: {err}")))?;
+	serde_json::to_string(&map)
+		.map_err(|err| ProcessingError::metadata(format!("serialize failed: {err}")).into())
+}
+
+/// Uncurated dump of every TIFF/MakerNote tag, for the advanced metadata view.
+#[cfg(feature = "raw-processing")]
+#[wasm_bindgen]
+pub fn raw_metadata_full_json(data: &[u8]) -> Result<String, JsValue> {
+	let map = core::raw_metadata::extract_raw_metadata_full(data)
+		.map_err(|err| ProcessingError::metadata(format!("raw metadata failed: {err}")))?;
+	serde_json::to_string(&map)
+		.map_err(|err| ProcessingError::metadata(format!("serialize failed: {err}")).into())
+}
+
+/// EXIF orientation value (1-8) of a file, 1 when it records none. Images
+/// loaded with `auto_orient` false still need this rotation to display
+/// upright.
+#[cfg(feature = "image-decoding")]
+#[allow(unused_variables)]
+#[wasm_bindgen]
+pub fn image_orientation(data: &[u8], path: &str) -> u16 {
+	#[cfg(feature = "raw-processing")]
+	if core::formats::decode_route(data, path) != core::formats::DecodeRoute::NonRaw
+		&& let Ok(info) = core::raw_processing::read_raw_develop_info(data)
+	{
+		return info.orientation;
+	}
+	core::image_loader::read_exif_orientation(data).unwrap_or(1)
+}
+
+/// Whether the RAW decoder supports this file, checked without developing
+/// it; false for non-RAW files and unsupported camera bodies.
+#[cfg(feature = "raw-processing")]
+#[wasm_bindgen]
+pub fn can_decode_raw(data: &[u8]) -> bool {
+	core::raw_processing::can_decode_raw(data)
+}
+
+#[cfg(feature = "raw-processing")]
+#[wasm_bindgen]
+pub fn raw_develop_info_json(data: &[u8]) -> Result<String, JsValue> {
+	let info = core::raw_processing::read_raw_develop_info(data)
+		.map_err(|err| ProcessingError::metadata(format!("raw info failed: {err}")))?;
+	serde_json::to_string(&info)
+		.map_err(|err| ProcessingError::metadata(format!("serialize failed: {err}")).into())
+}
+
+/// Per-step develop timings of a RAW file, as JSON (`ProfileReport`).
+#[cfg(feature = "bench")]
+#[wasm_bindgen]
+pub fn develop_profile_json(data: &[u8]) -> Result<String, JsValue> {
+	let report = core::bench::develop_profile(data).map_err(|err| raw_decode_error(&err))?;
+	serde_json::to_string(&report)
+		.map_err(|err| ProcessingError::metadata(format!("serialize failed: {err}")).into())
 }
 
 #[cfg(feature = "image-decoding")]
@@ -158,7 +878,7 @@ pub fn decode_image_preview_png(
 	path: &str,
 	max_edge: u32,
 ) -> Result<Vec<u8>, JsValue> {
-	load_image_preview_png(data, path, max_edge, true, 1.5)
+	load_image_preview_png(data, path, max_edge, true, 1.5, None)
 }
 
 #[cfg(feature = "raw-processing-threads")]
@@ -170,9 +890,260 @@ pub fn init_thread_pool(num_threads: usize) -> Promise {
 #[cfg(not(feature = "raw-processing-threads"))]
 #[wasm_bindgen]
 pub fn init_thread_pool(_num_threads: usize) -> Promise {
-	Promise::reject(&JsValue::from_str(
+	Promise::reject(&JsValue::from(ProcessingError::feature_disabled(
 		"Thread pool not enabled in this WASM build.",
-	))
+	)))
+}
+
+#[cfg(feature = "image-decoding")]
+const THUMBNAIL_JPEG_QUALITY: u8 = 80;
+
+/// Gallery thumbnail as JPEG, in display orientation. RAW files use the
+/// embedded preview when it is large enough and otherwise a fast develop.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn generate_thumbnail(data: &[u8], path: &str, max_edge: u32) -> Result<Vec<u8>, JsValue> {
+	#[cfg(feature = "raw-processing")]
+	let embedded = if core::formats::decode_route(data, path) != core::formats::DecodeRoute::NonRaw {
+		core::raw_processing::extract_embedded_preview(data, max_edge).ok().flatten()
+	} else {
+		None
+	};
+	#[cfg(not(feature = "raw-processing"))]
+	let embedded: Option<image::DynamicImage> = None;
+
+	let image = match embedded {
+		Some(image) => image,
+		None => decode_image_from_bytes(data, path, true, 1.5, true)?,
+	};
+	let image = if max_edge > 0 {
+		core::image_utils::downscale_f32_image(&image, max_edge, max_edge)
+	} else {
+		image
+	};
+
+	let options = core::export::ExportOptions {
+		quality: THUMBNAIL_JPEG_QUALITY,
+		..Default::default()
+	};
+	core::export::encode_image(&image, &options)
+		.map_err(|err| ProcessingError::from_decode_error("thumbnail encode failed", &err).into())
+}
+
+/// RAW preview plus whether it is a stand-in for a file this build could not
+/// decode. Placeholders are only produced when the caller opts in.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub struct RawPreview {
+	png: Vec<u8>,
+	is_placeholder: bool,
+}
+
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+impl RawPreview {
+	#[wasm_bindgen(getter)]
+	pub fn png(&self) -> Vec<u8> {
+		self.png.clone()
+	}
+
+	#[wasm_bindgen(getter, js_name = isPlaceholder)]
+	pub fn is_placeholder(&self) -> bool {
+		self.is_placeholder
+	}
+}
+
+/// Geometry (rotation, quarter turns, flips), defringe and negative
+/// conversion of an `AdjustmentSession`'s cached source frame.
+#[cfg(feature = "image-decoding")]
+type SourceKey = (
+	(f32, u64, bool, bool),
+	core::defringe::DefringeSettings,
+	Option<core::negative::NegativeConversion>,
+);
+
+/// A decoded preview kept between edits. Each render re-runs only the
+/// adjustment stages the changed sliders affect; a geometry change rebuilds
+/// the cache.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub struct AdjustmentSession {
+	decoded: image::DynamicImage,
+	geometry: Option<SourceKey>,
+	cache: Option<core::adjustments::AdjustmentCache>,
+	stats: SessionStats,
+}
+
+/// Timings and cache counters of an `AdjustmentSession`, for diagnosing slow
+/// devices.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+#[derive(Clone, Copy, Default)]
+pub struct SessionStats {
+	/// Decoding and downscaling the preview when the session was created.
+	#[wasm_bindgen(js_name = lastDecodeMs)]
+	pub last_decode_ms: f64,
+	/// The latest `render_png`, PNG encoding included.
+	#[wasm_bindgen(js_name = lastProcessMs)]
+	pub last_process_ms: f64,
+	/// Renders that reused the cached source frame.
+	#[wasm_bindgen(js_name = cacheHits)]
+	pub cache_hits: u32,
+	/// Renders that rebuilt it: the first one, and any after a geometry,
+	/// defringe or negative conversion change.
+	#[wasm_bindgen(js_name = cacheMisses)]
+	pub cache_misses: u32,
+	/// Pixel data held by the session: the decoded preview and the
+	/// adjustment cache.
+	#[wasm_bindgen(js_name = cachedBytes)]
+	pub cached_bytes: usize,
+}
+
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+impl AdjustmentSession {
+	#[wasm_bindgen(constructor)]
+	pub fn new(
+		data: &[u8],
+		path: &str,
+		max_edge: u32,
+		use_fast_raw_dev: bool,
+		highlight_compression: f32,
+	) -> Result<AdjustmentSession, JsValue> {
+		let start = core::clock::now_ms();
+		let image = decode_image_from_bytes(data, path, use_fast_raw_dev, highlight_compression, true)?;
+		let decoded = if max_edge > 0 {
+			core::image_utils::downscale_f32_image(&image, max_edge, max_edge)
+		} else {
+			image
+		};
+		Ok(AdjustmentSession {
+			decoded,
+			geometry: None,
+			cache: None,
+			stats: SessionStats {
+				last_decode_ms: core::clock::now_ms() - start,
+				..SessionStats::default()
+			},
+		})
+	}
+
+	/// Same output as `load_image_preview_with_adjustments_png`, except that
+	/// the preview is downscaled before rather than after the edits.
+	pub fn render_png(&mut self, adjustments_json: &str) -> Result<Vec<u8>, JsValue> {
+		let start = core::clock::now_ms();
+		let value: serde_json::Value =
+			serde_json::from_str(adjustments_json).unwrap_or(serde_json::Value::Null);
+		let params = core::geometry::get_geometry_params_from_json(&value);
+		let geometry = (
+			(
+				params.rotate,
+				value["orientationSteps"].as_u64().unwrap_or(0),
+				params.flip_horizontal,
+				params.flip_vertical,
+			),
+			core::defringe::defringe_from_adjustments(adjustments_json),
+			core::negative::negative_from_adjustments(adjustments_json),
+		);
+		let cache = match &mut self.cache {
+			Some(cache) if self.geometry == Some(geometry) => {
+				self.stats.cache_hits += 1;
+				cache
+			}
+			_ => {
+				self.stats.cache_misses += 1;
+				self.geometry = Some(geometry);
+				let source = apply_source_passes(self.decoded.clone(), adjustments_json);
+				self.cache.insert(core::adjustments::AdjustmentCache::new(source))
+			}
+		};
+
+		let adjustments = core::adjustments::parse_adjustments(adjustments_json);
+		let mut image = cache.apply(&adjustments);
+		apply_color_passes(&mut image, adjustments_json);
+		let png = encode_png(&image)?;
+		self.stats.last_process_ms = core::clock::now_ms() - start;
+		Ok(png)
+	}
+
+	/// Decode and render timings, source cache hits and misses, and the
+	/// pixel memory the session holds.
+	pub fn get_session_stats(&self) -> SessionStats {
+		SessionStats {
+			cached_bytes: self.decoded.as_bytes().len()
+				+ self.cache.as_ref().map_or(0, |cache| cache.memory_bytes()),
+			..self.stats
+		}
+	}
+
+	/// Whole-frame stage passes run so far, for checking that renders skip
+	/// unaffected stages.
+	#[wasm_bindgen(getter, js_name = stagesRun)]
+	pub fn stages_run(&self) -> f64 {
+		self.cache.as_ref().map_or(0, |cache| cache.stages_run()) as f64
+	}
+}
+
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn develop_raw_preview(
+	data: &[u8],
+	max_edge: u32,
+	fast_demosaic: bool,
+	highlight_compression: f32,
+	allow_placeholder: bool,
+) -> Result<RawPreview, JsValue> {
+	let (image, is_placeholder) =
+		match decode_raw_from_bytes(data, fast_demosaic, highlight_compression, true) {
+			Ok(image) => (image, false),
+			Err(_) if allow_placeholder => (core::placeholder::placeholder_image(data), true),
+			Err(err) => return Err(err.into()),
+		};
+
+	let image = if max_edge > 0 {
+		core::image_utils::downscale_f32_image(&image, max_edge, max_edge)
+	} else {
+		image
+	};
+
+	Ok(RawPreview {
+		png: encode_png(&image)?,
+		is_placeholder,
+	})
+}
+
+/// Promise-returning `develop_raw_preview_png`. The develop itself cannot be
+/// split, but the event loop gets a turn before it and between the develop,
+/// downscale and encode stages.
+#[cfg(feature = "raw-processing")]
+#[wasm_bindgen]
+pub fn develop_raw_preview_png_async(
+	data: Vec<u8>,
+	max_edge: u32,
+	fast_demosaic: bool,
+	highlight_compression: f32,
+) -> Promise {
+	wasm_bindgen_futures::future_to_promise(async move {
+		yield_to_event_loop().await?;
+		let image = core::raw_processing::develop_raw_image(
+			&data,
+			fast_demosaic,
+			highlight_compression,
+			None,
+		)
+		.map_err(|err| raw_decode_error(&err))?;
+		yield_to_event_loop().await?;
+
+		let image = if max_edge > 0 {
+			core::image_utils::downscale_f32_image(&image, max_edge, max_edge)
+		} else {
+			image
+		};
+		yield_to_event_loop().await?;
+
+		let png = encode_png(&image)?;
+		Ok(js_sys::Uint8Array::from(png.as_slice()).into())
+	})
 }
 
 #[cfg(feature = "raw-processing")]
@@ -189,7 +1160,7 @@ pub fn develop_raw_preview_png(
 		highlight_compression,
 		None,
 	)
-	.map_err(|err| JsValue::from_str(&format!("raw decode failed: {err}")))?;
+	.map_err(|err| raw_decode_error(&err))?;
 
 	let image = if max_edge > 0 {
 		core::image_utils::downscale_f32_image(&image, max_edge, max_edge)
@@ -197,5 +1168,256 @@ pub fn develop_raw_preview_png(
 		image
 	};
 
-	encode_png(&image)
+	Ok(encode_png(&image)?)
+}
+
+/// `develop_raw_preview_png` with develop options as JSON (`border`:
+/// "clamp" | "mirror" | "crop", `applyCameraCurve`: bool, `applyDngOpcodes`:
+/// bool).
+#[cfg(feature = "raw-processing")]
+#[wasm_bindgen]
+pub fn develop_raw_preview_with_options_png(
+	data: &[u8],
+	max_edge: u32,
+	fast_demosaic: bool,
+	highlight_compression: f32,
+	options_json: &str,
+) -> Result<Vec<u8>, JsValue> {
+	let options: core::raw_processing::DevelopOptions =
+		serde_json::from_str(options_json).unwrap_or_default();
+	let (image, _) = core::raw_processing::develop_raw_with_info(
+		data,
+		fast_demosaic,
+		highlight_compression,
+		options,
+		None,
+	)
+	.map_err(|err| raw_decode_error(&err))?;
+
+	let image = if max_edge > 0 {
+		core::image_utils::downscale_f32_image(&image, max_edge, max_edge)
+	} else {
+		image
+	};
+
+	Ok(encode_png(&image)?)
+}
+
+/// `develop_raw_preview_png` followed by the same geometry, adjustment and
+/// grading passes as `load_image_preview_with_adjustments_png`.
+#[cfg(feature = "raw-processing")]
+#[wasm_bindgen]
+pub fn develop_raw_preview_with_adjustments_png(
+	data: &[u8],
+	max_edge: u32,
+	adjustments_json: &str,
+	fast_demosaic: bool,
+	highlight_compression: f32,
+) -> Result<Vec<u8>, JsValue> {
+	let image = core::raw_processing::develop_raw_image(
+		data,
+		fast_demosaic,
+		highlight_compression,
+		None,
+	)
+	.map_err(|err| raw_decode_error(&err))?;
+
+	Ok(render_preview_png(image, adjustments_json, max_edge)?)
+}
+
+/// Merges bracketed exposures (`images[i]` read from `paths[i]`), tone maps
+/// the result with `tone_mapping_json` (`operator`, `exposure`, `contrast`)
+/// and returns it as a PNG.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn merge_hdr_png(
+	images: Vec<js_sys::Uint8Array>,
+	paths: Vec<String>,
+	align: bool,
+	tone_mapping_json: &str,
+	max_edge: u32,
+) -> Result<Vec<u8>, JsValue> {
+	let buffers: Vec<Vec<u8>> = images.iter().map(|array| array.to_vec()).collect();
+	let image = core::hdr::merge_hdr(
+		buffers.iter().map(Vec::as_slice).collect(),
+		paths.iter().map(String::as_str).collect(),
+		align,
+	)
+	.map_err(|err| ProcessingError::from_decode_error("hdr merge failed", &err))?;
+	let settings = core::hdr::parse_tone_map_settings(tone_mapping_json);
+	let image = core::hdr::tone_map(&image, &settings);
+
+	let image = if max_edge > 0 {
+		core::image_utils::downscale_f32_image(&image, max_edge, max_edge)
+	} else {
+		image
+	};
+
+	Ok(encode_png(&image)?)
+}
+
+/// Focus-stacks pre-aligned frames and returns the result as a PNG.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn focus_stack_png(images: Vec<js_sys::Uint8Array>, max_edge: u32) -> Result<Vec<u8>, JsValue> {
+	let buffers: Vec<Vec<u8>> = images.iter().map(|array| array.to_vec()).collect();
+	let image = core::stack::focus_stack(buffers.iter().map(Vec::as_slice).collect())
+		.map_err(|err| ProcessingError::from_decode_error("focus stack failed", &err))?;
+
+	let image = if max_edge > 0 {
+		core::image_utils::downscale_f32_image(&image, max_edge, max_edge)
+	} else {
+		image
+	};
+
+	Ok(encode_png(&image)?)
+}
+
+/// Stitches a left/right pair into one PNG. `overlap_hint` is the expected
+/// overlap as a fraction of the left frame's width, or 0 to search.
+#[cfg(feature = "image-decoding")]
+#[wasm_bindgen]
+pub fn stitch_pair_png(
+	left: &[u8],
+	right: &[u8],
+	overlap_hint: f32,
+	max_edge: u32,
+) -> Result<Vec<u8>, JsValue> {
+	let image = core::pano::stitch_pair(left, right, overlap_hint)
+		.map_err(|err| ProcessingError::from_decode_error("panorama stitch failed", &err))?;
+
+	let image = if max_edge > 0 {
+		core::image_utils::downscale_f32_image(&image, max_edge, max_edge)
+	} else {
+		image
+	};
+
+	Ok(encode_png(&image)?)
+}
+
+/// Combines a four-shot pixel-shift sequence into one full-color image and
+/// returns it as a PNG. Frames must be in capture order; see
+/// `core::pixel_shift` for the supported shift pattern.
+#[cfg(feature = "pixel-shift")]
+#[wasm_bindgen]
+pub fn develop_pixel_shift_png(
+	images: Vec<js_sys::Uint8Array>,
+	highlight_compression: f32,
+	max_edge: u32,
+) -> Result<Vec<u8>, JsValue> {
+	let buffers: Vec<Vec<u8>> = images.iter().map(|array| array.to_vec()).collect();
+	let frames: Vec<&[u8]> = buffers.iter().map(Vec::as_slice).collect();
+	let image = core::pixel_shift::develop_pixel_shift(&frames, highlight_compression)
+		.map_err(|err| ProcessingError::from_decode_error("pixel shift failed", &err))?;
+
+	let image = if max_edge > 0 {
+		core::image_utils::downscale_f32_image(&image, max_edge, max_edge)
+	} else {
+		image
+	};
+
+	Ok(encode_png(&image)?)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[cfg(feature = "raw-processing")]
+	#[test]
+	fn rejected_raws_are_reported_as_unsupported() {
+		let err = raw_decode_error(&anyhow::anyhow!("no decoder for this file"));
+		assert_eq!(err.kind, core::error::ProcessingErrorKind::DecodeUnsupported);
+		assert!(err.message.starts_with("raw decode failed: no decoder"));
+
+		let too_large = anyhow::Error::new(ProcessingError::too_large("over the pixel limit"));
+		assert_eq!(
+			raw_decode_error(&too_large).kind,
+			core::error::ProcessingErrorKind::TooLarge
+		);
+	}
+
+	#[test]
+	fn empty_buffers_are_corrupt_data() {
+		let err = decode_image_from_bytes(&[], "photo.jpg", false, 0.0, true).unwrap_err();
+		assert_eq!(err.kind, core::error::ProcessingErrorKind::CorruptData);
+	}
+
+	#[test]
+	fn unknown_extensions_are_unsupported_formats() {
+		let err =
+			decode_image_from_bytes(b"plain text, not pixels", "notes.txt", false, 0.0, true)
+				.unwrap_err();
+		assert_eq!(err.kind, core::error::ProcessingErrorKind::UnsupportedFormat);
+	}
+
+	#[test]
+	fn formats_without_a_decoder_are_unsupported() {
+		let cases: [(&str, &[u8]); 5] = [
+			("a.avif", b"\0\0\0\x1cftypavif\0\0\0\0avifmif1"),
+			("a.heic", b"\0\0\0\x18ftypheic\0\0\0\0mif1heic"),
+			("a.heif", b"\0\0\0\x18ftypmif1\0\0\0\0mif1heix"),
+			("a.jxl", b"\xFF\x0A\xFA\x7F"),
+			("download", b"\0\0\0\x18ftypheic\0\0\0\0mif1heic"),
+		];
+		for (path, data) in cases {
+			let err = decode_image_from_bytes(data, path, false, 0.0, true).unwrap_err();
+			assert_eq!(err.kind, core::error::ProcessingErrorKind::UnsupportedFormat, "{path}");
+		}
+	}
+
+	#[cfg(feature = "image-decoding")]
+	#[test]
+	fn thumbnails_are_downscaled_jpegs() {
+		let source = image::DynamicImage::ImageRgb8(image::RgbImage::new(64, 32));
+		let mut png = Vec::new();
+		source
+			.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+			.unwrap();
+
+		let thumbnail = generate_thumbnail(&png, "photo.png", 16).unwrap();
+		assert_eq!(image::guess_format(&thumbnail).unwrap(), image::ImageFormat::Jpeg);
+		let decoded = image::load_from_memory(&thumbnail).unwrap();
+		assert_eq!((decoded.width(), decoded.height()), (16, 8));
+	}
+
+	#[cfg(feature = "image-decoding")]
+	#[test]
+	fn preview_render_applies_adjustments_and_fits_the_edge() {
+		let frame = image::DynamicImage::ImageRgb32F(image::Rgb32FImage::from_pixel(
+			40,
+			20,
+			image::Rgb([0.18, 0.18, 0.18]),
+		));
+		let decode = |png: Vec<u8>| image::load_from_memory(&png).unwrap().to_rgba8();
+
+		let plain = decode(render_preview_png(frame.clone(), "{}", 10).unwrap());
+		assert_eq!(plain.dimensions(), (10, 5));
+		let brighter = decode(render_preview_png(frame, r#"{"exposure": 1.0}"#, 10).unwrap());
+		assert!(brighter.get_pixel(5, 2)[1] > plain.get_pixel(5, 2)[1]);
+	}
+
+	#[cfg(feature = "image-decoding")]
+	#[test]
+	fn session_stats_count_source_cache_hits_and_misses() {
+		let source = image::RgbImage::from_pixel(32, 16, image::Rgb([90, 120, 60]));
+		let mut png = Vec::new();
+		image::DynamicImage::ImageRgb8(source)
+			.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+			.unwrap();
+		let mut session = AdjustmentSession::new(&png, "photo.png", 16, true, 0.0).unwrap();
+		let fresh = session.get_session_stats();
+		assert_eq!((fresh.cache_hits, fresh.cache_misses), (0, 0));
+		assert!(fresh.last_decode_ms >= 0.0);
+		let decoded_bytes = fresh.cached_bytes;
+		assert_eq!(decoded_bytes, 16 * 8 * 3 * 4);
+
+		session.render_png("{}").unwrap();
+		session.render_png(r#"{"exposure": 0.5}"#).unwrap();
+		session.render_png(r#"{"exposure": 0.5, "defringe": {"purpleAmount": 0.5}}"#).unwrap();
+		let stats = session.get_session_stats();
+		assert_eq!((stats.cache_hits, stats.cache_misses), (1, 2));
+		assert!(stats.last_process_ms >= 0.0);
+		assert!(stats.cached_bytes > decoded_bytes);
+	}
 }
@@ -1,11 +1,14 @@
 use anyhow::{anyhow, Result};
 use base64::{engine::general_purpose, Engine as _};
-use image::{codecs::jpeg::JpegEncoder, imageops, DynamicImage, GenericImageView, ImageFormat, RgbaImage};
+use crate::image_processing::{encode_jpeg, ChromaSubsampling};
+use image::{imageops, imageops::FilterType, DynamicImage, GenericImageView, ImageFormat, RgbaImage};
 use reqwest::{multipart, Client};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::io::Cursor;
+use std::io::{self, Cursor};
 use std::path::Path;
+use std::sync::Mutex;
 use std::time::SystemTime;
 
 #[derive(Serialize)]
@@ -24,9 +27,39 @@ struct MiddlewareResponse {
     color: String,
 }
 
-pub fn generate_source_id(path_str: &str) -> Result<String> {
+/// How the middleware's cached source image is identified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceIdMode {
+    /// Path, size and modification time. Cheap, but touching the file
+    /// invalidates the cached source.
+    Metadata,
+    /// blake3 over the file bytes, so copies and touched files keep their id.
+    /// Falls back to `Metadata` for files above `MAX_CONTENT_HASH_BYTES`.
+    Content,
+}
+
+const MAX_CONTENT_HASH_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Content-mode source ids by file path, with the size and modification time
+/// they were computed for, so an unchanged file is hashed only once.
+pub type SourceIdCache = Mutex<HashMap<String, (u64, SystemTime, String)>>;
+
+fn content_hash(path: &Path) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"content:");
+    let mut file = fs::File::open(path)?;
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+pub fn generate_source_id(path_str: &str, mode: SourceIdMode) -> Result<String> {
     let path = Path::new(path_str);
     let metadata = fs::metadata(path)?;
+
+    if mode == SourceIdMode::Content && metadata.len() <= MAX_CONTENT_HASH_BYTES {
+        return content_hash(path);
+    }
+
     let mod_time = metadata
         .modified()
         .unwrap_or(SystemTime::UNIX_EPOCH)
@@ -35,10 +68,42 @@ pub fn generate_source_id(path_str: &str) -> Result<String> {
 
     let mut hasher = blake3::Hasher::new();
     hasher.update(path_str.as_bytes());
+    hasher.update(&metadata.len().to_le_bytes());
     hasher.update(&mod_time.to_le_bytes());
     Ok(hasher.finalize().to_hex().to_string())
 }
 
+/// `generate_source_id` for async callers. Content hashing reads the whole
+/// file, so it runs on the blocking pool, and its result is reused from
+/// `cache` while the file's size and modification time are unchanged.
+pub async fn cached_source_id(
+    path_str: &str,
+    mode: SourceIdMode,
+    cache: &SourceIdCache,
+) -> Result<String> {
+    if mode == SourceIdMode::Metadata {
+        return generate_source_id(path_str, mode);
+    }
+    let metadata = fs::metadata(path_str)?;
+    let stamp = (
+        metadata.len(),
+        metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+    );
+    if let Some((len, modified, id)) = cache.lock().unwrap().get(path_str)
+        && (*len, *modified) == stamp
+    {
+        return Ok(id.clone());
+    }
+
+    let owned_path = path_str.to_string();
+    let id = tokio::task::spawn_blocking(move || generate_source_id(&owned_path, mode)).await??;
+    cache
+        .lock()
+        .unwrap()
+        .insert(path_str.to_string(), (stamp.0, stamp.1, id.clone()));
+    Ok(id)
+}
+
 fn image_to_base64(img: &DynamicImage) -> Result<String> {
     let mut buf = Cursor::new(Vec::new());
     img.write_to(&mut buf, ImageFormat::Png)?;
@@ -46,10 +111,7 @@ fn image_to_base64(img: &DynamicImage) -> Result<String> {
 }
 
 fn image_to_jpeg_bytes(img: &DynamicImage, quality: u8) -> Result<Vec<u8>> {
-    let mut buf = Cursor::new(Vec::new());
-    let mut encoder = JpegEncoder::new_with_quality(&mut buf, quality);
-    encoder.encode_image(&img.to_rgb8())?;
-    Ok(buf.into_inner())
+    encode_jpeg(img, quality, ChromaSubsampling::Auto)
 }
 
 async fn upload_source_image(client: &Client, address: &str, source_id: &str, image: &DynamicImage) -> Result<()> {
@@ -75,16 +137,190 @@ async fn upload_source_image(client: &Client, address: &str, source_id: &str, im
     Ok(())
 }
 
+/// Filter used when the middleware's crop has to be resampled to fit the
+/// masked region.
+const CROP_RESAMPLE_FILTER: FilterType = FilterType::Lanczos3;
+/// Width in pixels of the alpha ramp along the crop's edges.
+const CROP_FEATHER: u32 = 8;
+/// Context sent around the mask, as a fraction of its longer side and at
+/// least `MIN_INPAINT_CONTEXT` pixels, so the model sees what surrounds the
+/// area it fills without receiving the whole frame.
+const INPAINT_CONTEXT_PADDING: f32 = 0.5;
+const MIN_INPAINT_CONTEXT: u32 = 64;
+/// The region's edges are moved out to multiples of this, so masks drawn
+/// near each other send the same region and reuse the middleware's cached
+/// copy instead of uploading a new one for every stroke.
+const INPAINT_REGION_GRID: u32 = 256;
+
+/// Bounding box (x, y, width, height) of the non-zero pixels of `mask`.
+fn mask_bounding_box(mask: &DynamicImage) -> Option<(u32, u32, u32, u32)> {
+    let luma = mask.to_luma8();
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, pixel) in luma.enumerate_pixels() {
+        if pixel[0] > 0 {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+    (min_x <= max_x).then(|| (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+}
+
+/// Fades the crop's alpha across the padding around the mask, so the patch
+/// does not end in a hard rectangle where the model's output meets the
+/// original. `keep` is the mask's bounding box in crop coordinates; the ramp
+/// never reaches into it, so every masked pixel is replaced. Edges lying on
+/// the image border stay opaque.
+fn feather_crop_edges(
+    crop: &mut RgbaImage,
+    keep: (u32, u32, u32, u32),
+    origin: (u32, u32),
+    full_size: (u32, u32),
+) {
+    let (width, height) = crop.dimensions();
+    let (kx, ky, kw, kh) = keep;
+    let side = |padding: u32, at_border: bool| {
+        if at_border {
+            0
+        } else {
+            CROP_FEATHER.min(padding)
+        }
+    };
+    let left = side(kx, origin.0 == 0);
+    let right = side(
+        width.saturating_sub(kx + kw),
+        origin.0 + width >= full_size.0,
+    );
+    let top = side(ky, origin.1 == 0);
+    let bottom = side(
+        height.saturating_sub(ky + kh),
+        origin.1 + height >= full_size.1,
+    );
+    if left == 0 && right == 0 && top == 0 && bottom == 0 {
+        return;
+    }
+    let ramp = |distance: u32, feather: u32| {
+        if distance >= feather {
+            1.0
+        } else {
+            (distance + 1) as f32 / (feather + 1) as f32
+        }
+    };
+    for (px, py, pixel) in crop.enumerate_pixels_mut() {
+        let weight = ramp(px, left)
+            .min(ramp(width - 1 - px, right))
+            .min(ramp(py, top))
+            .min(ramp(height - 1 - py, bottom));
+        pixel[3] = (pixel[3] as f32 * weight).round() as u8;
+    }
+}
+
+/// Where the middleware's crop goes in the region that was sent, as
+/// (x, y, width, height). The crop starts at the middleware's `(x, y)` and
+/// covers the mask's bounding box plus whatever padding the middleware added
+/// around it. A crop that does not cover that area at its own size was
+/// resized by the middleware, so the area is rebuilt from the padding on the
+/// top/left, mirrored on the bottom/right and clamped to the region.
+fn patch_placement(
+    response_xy: (u32, u32),
+    crop_size: (u32, u32),
+    mask_bbox: Option<(u32, u32, u32, u32)>,
+    region_size: (u32, u32),
+) -> (u32, u32, u32, u32) {
+    let (x, y) = response_xy;
+    let (crop_w, crop_h) = crop_size;
+    let Some((bx, by, bw, bh)) = mask_bbox else {
+        return (x, y, crop_w, crop_h);
+    };
+    let covers_mask = x <= bx && y <= by && x + crop_w >= bx + bw && y + crop_h >= by + bh;
+    let inside_region = x + crop_w <= region_size.0 && y + crop_h <= region_size.1;
+    if covers_mask && inside_region {
+        return (x, y, crop_w, crop_h);
+    }
+
+    let (left, top) = (x.min(bx), y.min(by));
+    let right = (bx + bw + (bx - left)).min(region_size.0);
+    let bottom = (by + bh + (by - top)).min(region_size.1);
+    (left, top, right - left, bottom - top)
+}
+
+/// The region sent to the middleware for `mask`: its bounding box grown by
+/// `INPAINT_CONTEXT_PADDING` for surrounding context, widened to the
+/// `INPAINT_REGION_GRID` and clamped to the image. The whole image when the
+/// mask is empty.
+fn inpaint_region(mask: &DynamicImage) -> (u32, u32, u32, u32) {
+    let (width, height) = mask.dimensions();
+    let Some((x, y, w, h)) = mask_bounding_box(mask) else {
+        return (0, 0, width, height);
+    };
+    let pad = ((w.max(h) as f32 * INPAINT_CONTEXT_PADDING) as u32).max(MIN_INPAINT_CONTEXT);
+    let snap_down = |v: u32| v.saturating_sub(pad) / INPAINT_REGION_GRID * INPAINT_REGION_GRID;
+    let snap_up = |v: u32, limit: u32| {
+        v.saturating_add(pad)
+            .div_ceil(INPAINT_REGION_GRID)
+            .saturating_mul(INPAINT_REGION_GRID)
+            .min(limit)
+    };
+    let (left, top) = (snap_down(x), snap_down(y));
+    let (right, bottom) = (snap_up(x + w, width), snap_up(y + h, height));
+    (left, top, right - left, bottom - top)
+}
+
+/// The middleware's id for `region` of the source identified by `source_id`.
+/// Regions are grid-aligned (see `inpaint_region`), so the id is shared by
+/// every mask that maps to the same region; the whole image keeps the plain
+/// source id.
+fn region_source_id(
+    source_id: &str,
+    region: (u32, u32, u32, u32),
+    image_size: (u32, u32),
+) -> String {
+    let (x, y, w, h) = region;
+    if (x, y, w, h) == (0, 0, image_size.0, image_size.1) {
+        source_id.to_string()
+    } else {
+        format!("{source_id}-{x}-{y}-{w}x{h}")
+    }
+}
+
+/// Places the middleware's inpainted crop on a transparent full-size canvas.
+/// `mask` is the mask that was sent, located at `offset` in the full image.
+/// The crop is resampled with `filter` when the middleware resized it (see
+/// `patch_placement`), and the padding the middleware kept around the mask is
+/// feathered so the patch leaves no seam.
 fn composite_full_res(
     response: MiddlewareResponse,
+    mask: &DynamicImage,
+    offset: (u32, u32),
     full_width: u32,
     full_height: u32,
+    filter: FilterType,
 ) -> Result<RgbaImage> {
     let crop_color_bytes = general_purpose::STANDARD.decode(&response.color)?;
-    let crop_color = image::load_from_memory(&crop_color_bytes)?;
+    let crop_color = image::load_from_memory(&crop_color_bytes)?.to_rgba8();
+
+    let mask_bbox = mask_bounding_box(mask);
+    let (x, y, width, height) = patch_placement(
+        (response.x, response.y),
+        crop_color.dimensions(),
+        mask_bbox,
+        mask.dimensions(),
+    );
+    let mut crop = if crop_color.dimensions() != (width, height) {
+        imageops::resize(&crop_color, width, height, filter)
+    } else {
+        crop_color
+    };
+    // The placement always covers the mask's bounding box.
+    let keep = mask_bbox.map_or((0, 0, width, height), |(bx, by, bw, bh)| {
+        (bx - x, by - y, bw, bh)
+    });
+    let (x, y) = (x + offset.0, y + offset.1);
+    feather_crop_edges(&mut crop, keep, (x, y), (full_width, full_height));
 
     let mut full_color = RgbaImage::new(full_width, full_height);
-    imageops::overlay(&mut full_color, &crop_color, response.x.into(), response.y.into());
+    imageops::overlay(&mut full_color, &crop, x.into(), y.into());
 
     Ok(full_color)
 }
@@ -101,11 +337,22 @@ pub async fn process_inpainting(
     full_source_image: &DynamicImage,
     mask_image: &DynamicImage,
     prompt: String,
+    source_id_mode: SourceIdMode,
+    source_ids: &SourceIdCache,
 ) -> Result<RgbaImage> {
     let client = Client::new();
-    let source_id = generate_source_id(source_path)?;
-    let mask_b64 = image_to_base64(mask_image)?;
     let (w, h) = full_source_image.dimensions();
+    // Only the masked region and some context travel to the middleware; it
+    // caches the region under its own id.
+    let (rx, ry, rw, rh) = inpaint_region(mask_image);
+    let source_id = region_source_id(
+        &cached_source_id(source_path, source_id_mode, source_ids).await?,
+        (rx, ry, rw, rh),
+        (w, h),
+    );
+    let source_region = full_source_image.crop_imm(rx, ry, rw, rh);
+    let mask_region = mask_image.crop_imm(rx, ry, rw, rh);
+    let mask_b64 = image_to_base64(&mask_region)?;
 
     let payload = InpaintRequest {
         source_id: source_id.clone(),
@@ -119,7 +366,7 @@ pub async fn process_inpainting(
     let response = client.post(&url).json(&payload).send().await?;
 
     let middleware_data: MiddlewareResponse = if response.status() == 404 {
-        upload_source_image(&client, address, &source_id, full_source_image).await?;
+        upload_source_image(&client, address, &source_id, &source_region).await?;
         let retry_res = client.post(&url).json(&payload).send().await?;
         if !retry_res.status().is_success() {
             return Err(anyhow!("AI generation failed after upload: {}", retry_res.text().await?));
@@ -131,5 +378,250 @@ pub async fn process_inpainting(
         response.json().await?
     };
 
-    composite_full_res(middleware_data, w, h)
-}
\ No newline at end of file
+    composite_full_res(
+        middleware_data,
+        &mask_region,
+        (rx, ry),
+        w,
+        h,
+        CROP_RESAMPLE_FILTER,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma, Rgba};
+
+    fn mask_with_box(width: u32, height: u32, x: u32, y: u32, w: u32, h: u32) -> DynamicImage {
+        let mut mask = GrayImage::new(width, height);
+        for py in y..y + h {
+            for px in x..x + w {
+                mask.put_pixel(px, py, Luma([255]));
+            }
+        }
+        DynamicImage::ImageLuma8(mask)
+    }
+
+    fn response_for(crop: RgbaImage, x: u32, y: u32) -> MiddlewareResponse {
+        MiddlewareResponse {
+            x,
+            y,
+            color: image_to_base64(&DynamicImage::ImageRgba8(crop)).unwrap(),
+        }
+    }
+
+    #[test]
+    fn half_resolution_crop_is_upscaled_over_the_mask() {
+        let mask = mask_with_box(100, 80, 20, 10, 40, 30);
+        let crop = RgbaImage::from_pixel(20, 15, Rgba([200, 100, 50, 255]));
+
+        let full = composite_full_res(
+            response_for(crop, 20, 10),
+            &mask,
+            (5, 7),
+            200,
+            200,
+            FilterType::Triangle,
+        )
+        .unwrap();
+
+        for (x, y, pixel) in full.enumerate_pixels() {
+            let inside = (25..65).contains(&x) && (17..47).contains(&y);
+            assert_eq!(pixel[3] == 255, inside, "alpha at ({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn padding_is_feathered_and_the_mask_is_replaced() {
+        let mask = mask_with_box(100, 80, 30, 20, 20, 20);
+        let crop = RgbaImage::from_pixel(40, 40, Rgba([200, 100, 50, 255]));
+
+        let full = composite_full_res(
+            response_for(crop, 20, 10),
+            &mask,
+            (5, 7),
+            200,
+            200,
+            FilterType::Triangle,
+        )
+        .unwrap();
+
+        // The crop spans x 25..65, y 17..57 with 10px of padding around the
+        // mask; alpha ramps up across the padding only.
+        let alpha = |x: u32, y: u32| full.get_pixel(x, y)[3];
+        assert!(alpha(25, 37) > 0 && alpha(25, 37) < 255);
+        assert!(alpha(64, 37) < 255 && alpha(45, 17) < 255 && alpha(45, 56) < 255);
+        assert!(alpha(25, 37) < alpha(28, 37) && alpha(28, 37) < alpha(32, 37));
+        assert_eq!(alpha(25 + CROP_FEATHER, 37), 255);
+        for y in 27..47 {
+            for x in 35..55 {
+                assert_eq!(*full.get_pixel(x, y), Rgba([200, 100, 50, 255]));
+            }
+        }
+    }
+
+    #[test]
+    fn crop_edges_on_the_image_border_stay_opaque() {
+        let mut crop = RgbaImage::from_pixel(40, 40, Rgba([0, 0, 0, 255]));
+        feather_crop_edges(&mut crop, (10, 10, 20, 20), (0, 0), (40, 100));
+        assert_eq!(crop.get_pixel(0, 20)[3], 255);
+        assert_eq!(crop.get_pixel(39, 20)[3], 255);
+        assert_eq!(crop.get_pixel(20, 0)[3], 255);
+        assert!(crop.get_pixel(20, 39)[3] < 255);
+    }
+
+    #[test]
+    fn crop_padding_from_the_middleware_is_kept() {
+        let bbox = Some((30, 30, 20, 20));
+        // Returned at its own size with 10px of context on every side.
+        assert_eq!(
+            patch_placement((20, 20), (40, 40), bbox, (100, 100)),
+            (20, 20, 40, 40)
+        );
+        // The same crop at half resolution still covers the padded area.
+        assert_eq!(
+            patch_placement((20, 20), (20, 20), bbox, (100, 100)),
+            (20, 20, 40, 40)
+        );
+        // Padding running past the region is clamped.
+        assert_eq!(
+            patch_placement((20, 20), (10, 10), Some((30, 30, 60, 60)), (100, 100)),
+            (20, 20, 80, 80)
+        );
+    }
+
+    #[test]
+    fn empty_mask_places_the_crop_as_returned() {
+        assert_eq!(
+            patch_placement((4, 6), (10, 12), None, (100, 100)),
+            (4, 6, 10, 12)
+        );
+    }
+
+    #[test]
+    fn content_id_follows_the_bytes_not_the_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let original = dir.path().join("a.jpg");
+        let copy = dir.path().join("b.jpg");
+        fs::write(&original, b"the same pixels").unwrap();
+        fs::copy(&original, &copy).unwrap();
+        let id_of = |path: &Path| {
+            generate_source_id(path.to_str().unwrap(), SourceIdMode::Content).unwrap()
+        };
+
+        let original_id = id_of(&original);
+        assert_eq!(original_id, id_of(&copy));
+
+        fs::write(&copy, b"other pixels").unwrap();
+        assert_ne!(original_id, id_of(&copy));
+    }
+
+    #[tokio::test]
+    async fn cached_content_id_is_refreshed_when_the_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.jpg");
+        let path_str = path.to_str().unwrap();
+        let cache = SourceIdCache::default();
+        fs::write(&path, b"first").unwrap();
+
+        let first = cached_source_id(path_str, SourceIdMode::Content, &cache)
+            .await
+            .unwrap();
+        assert_eq!(cache.lock().unwrap().len(), 1);
+        assert_eq!(
+            first,
+            cached_source_id(path_str, SourceIdMode::Content, &cache)
+                .await
+                .unwrap()
+        );
+
+        fs::write(&path, b"second, longer").unwrap();
+        let second = cached_source_id(path_str, SourceIdMode::Content, &cache)
+            .await
+            .unwrap();
+        assert_ne!(first, second);
+        assert_eq!(
+            second,
+            generate_source_id(path_str, SourceIdMode::Content).unwrap()
+        );
+    }
+
+    #[test]
+    fn tiny_central_mask_sends_a_small_region_and_lands_in_place() {
+        let (width, height) = (2000, 1500);
+        let mask = mask_with_box(width, height, 990, 740, 20, 20);
+
+        let (rx, ry, rw, rh) = inpaint_region(&mask);
+        assert_eq!((rx, ry, rw, rh), (768, 512, 512, 512));
+        assert!(rx <= 990 - MIN_INPAINT_CONTEXT && ry <= 740 - MIN_INPAINT_CONTEXT);
+        assert!(rx + rw >= 1010 + MIN_INPAINT_CONTEXT && ry + rh >= 760 + MIN_INPAINT_CONTEXT);
+
+        let mask_region = mask.crop_imm(rx, ry, rw, rh);
+        let crop = RgbaImage::from_pixel(20, 20, Rgba([10, 200, 30, 255]));
+        let full = composite_full_res(
+            response_for(crop, 990 - rx, 740 - ry),
+            &mask_region,
+            (rx, ry),
+            width,
+            height,
+            FilterType::Triangle,
+        )
+        .unwrap();
+
+        assert_eq!(full.dimensions(), (width, height));
+        for (x, y, pixel) in full.enumerate_pixels() {
+            let inside = (990..1010).contains(&x) && (740..760).contains(&y);
+            assert_eq!(pixel[3] == 255, inside, "alpha at ({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn padding_around_a_tiny_mask_is_feathered_outside_the_mask_only() {
+        let (width, height) = (2000, 1500);
+        let mask = mask_with_box(width, height, 990, 740, 20, 20);
+        let (rx, ry, rw, rh) = inpaint_region(&mask);
+
+        // The middleware keeps 16px of context around the mask.
+        let crop = RgbaImage::from_pixel(52, 52, Rgba([10, 200, 30, 255]));
+        let full = composite_full_res(
+            response_for(crop, 990 - 16 - rx, 740 - 16 - ry),
+            &mask.crop_imm(rx, ry, rw, rh),
+            (rx, ry),
+            width,
+            height,
+            FilterType::Triangle,
+        )
+        .unwrap();
+
+        let alpha = |x: u32, y: u32| full.get_pixel(x, y)[3];
+        for y in 740..760 {
+            for x in 990..1010 {
+                assert_eq!(alpha(x, y), 255, "alpha at ({x}, {y})");
+            }
+        }
+        assert!(alpha(974, 750) > 0 && alpha(974, 750) < 255);
+        assert!(alpha(1025, 750) < 255 && alpha(1000, 724) < 255 && alpha(1000, 775) < 255);
+        assert_eq!(alpha(974 + CROP_FEATHER, 750), 255);
+        assert_eq!(alpha(973, 750), 0);
+    }
+
+    #[test]
+    fn nearby_masks_share_a_region_id() {
+        let size = (2000, 1500);
+        let first = inpaint_region(&mask_with_box(size.0, size.1, 990, 740, 20, 20));
+        let second = inpaint_region(&mask_with_box(size.0, size.1, 1000, 760, 30, 10));
+        assert_eq!(
+            region_source_id("abc", first, size),
+            region_source_id("abc", second, size)
+        );
+        assert_eq!(region_source_id("abc", first, size), "abc-768-512-512x512");
+        assert_eq!(region_source_id("abc", (0, 0, 2000, 1500), size), "abc");
+    }
+
+    #[test]
+    fn region_at_the_image_edge_is_clamped() {
+        let mask = mask_with_box(300, 200, 250, 150, 50, 50);
+        assert_eq!(inpaint_region(&mask), (0, 0, 300, 200));
+    }
+}
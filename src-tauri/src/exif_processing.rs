@@ -19,6 +19,15 @@ fn to_ur64(val: &exif::Rational) -> uR64 {
     }
 }
 
+/// The first three values of a rational field (degrees/minutes/seconds or
+/// hours/minutes/seconds), as the GPS position and time tags store them.
+fn rational_triple(value: &exif::Value) -> Option<Vec<uR64>> {
+    match value {
+        exif::Value::Rational(v) if v.len() >= 3 => Some(v[..3].iter().map(to_ur64).collect()),
+        _ => None,
+    }
+}
+
 fn to_ir64(val: &exif::SRational) -> iR64 {
     iR64 {
         nominator: val.num,
@@ -54,6 +63,29 @@ pub fn read_exif_data(path: &str, file_bytes: &[u8]) -> HashMap<String, String>
     exif_data
 }
 
+/// Adds `FocalLength35mmEquivalent` (in mm) from the focal length and the
+/// body's `crop_factor`, unless the entry is already there or either input
+/// is unknown.
+pub fn insert_35mm_equivalent(map: &mut HashMap<String, String>, crop_factor: Option<f32>) {
+    if map.contains_key("FocalLength35mmEquivalent") {
+        return;
+    }
+    let focal_length = map.get("FocalLength").and_then(|value| {
+        value
+            .trim_matches('"')
+            .split_whitespace()
+            .next()?
+            .parse::<f32>()
+            .ok()
+    });
+    if let (Some(focal_length), Some(crop_factor)) = (focal_length, crop_factor) {
+        if focal_length > 0.0 && crop_factor > 0.0 {
+            let equivalent = (focal_length * crop_factor).round();
+            map.insert("FocalLength35mmEquivalent".to_string(), equivalent.to_string());
+        }
+    }
+}
+
 pub fn extract_metadata(path_str: &str) -> Option<HashMap<String, String>> {
     let mut map = HashMap::new();
 
@@ -357,6 +389,64 @@ pub fn get_creation_date_from_path(path: &Path) -> DateTime<Utc> {
         .unwrap_or_else(Utc::now)
 }
 
+const JPEG_APP13: u8 = 0xED;
+const PHOTOSHOP_IRB_HEADER: &[u8] = b"Photoshop 3.0\0";
+
+/// Returns the complete APP13 (Photoshop IRB, which holds IPTC) segment of a
+/// JPEG, marker and length included, if there is one.
+fn find_iptc_segment(jpeg: &[u8]) -> Option<Vec<u8>> {
+    if jpeg.len() < 4 || jpeg[0] != 0xFF || jpeg[1] != 0xD8 {
+        return None;
+    }
+    let mut pos = 2;
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF {
+        let marker = jpeg[pos + 1];
+        // Metadata segments all precede the first scan.
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let length = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        let end = pos + 2 + length;
+        if length < 2 || end > jpeg.len() {
+            break;
+        }
+        if marker == JPEG_APP13 && jpeg[pos + 4..end].starts_with(PHOTOSHOP_IRB_HEADER) {
+            return Some(jpeg[pos..end].to_vec());
+        }
+        pos = end;
+    }
+    None
+}
+
+/// Inserts a complete APPn segment after the leading APP0/APP1 segments
+/// (JFIF/EXIF), replacing any existing APP13 segment.
+fn insert_app_segment(jpeg: &mut Vec<u8>, segment: &[u8]) {
+    if jpeg.len() < 4 || jpeg[0] != 0xFF || jpeg[1] != 0xD8 {
+        return;
+    }
+    let mut pos = 2;
+    let mut insert_at = 2;
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF {
+        let marker = jpeg[pos + 1];
+        let length = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        let end = pos + 2 + length;
+        if length < 2 || end > jpeg.len() {
+            break;
+        }
+        match marker {
+            0xE0 | 0xE1 => {
+                insert_at = end;
+                pos = end;
+            }
+            JPEG_APP13 => {
+                jpeg.drain(pos..end);
+            }
+            _ => break,
+        }
+    }
+    jpeg.splice(insert_at..insert_at, segment.iter().copied());
+}
+
 pub fn write_image_with_metadata(
     image_bytes: &mut Vec<u8>,
     original_path_str: &str,
@@ -429,6 +519,14 @@ pub fn write_image_with_metadata(
             if let Some(f) = exif_obj.get_field(exif::Tag::Copyright, exif::In::PRIMARY) {
                 metadata.set_tag(ExifTag::Copyright(get_string_val(f)));
             }
+            if let Some(f) = exif_obj.get_field(exif::Tag::ImageDescription, exif::In::PRIMARY) {
+                metadata.set_tag(ExifTag::ImageDescription(get_string_val(f)));
+            }
+            if let Some(f) = exif_obj.get_field(exif::Tag::UserComment, exif::In::PRIMARY) {
+                if let exif::Value::Undefined(bytes, _) = &f.value {
+                    metadata.set_tag(ExifTag::UserComment(bytes.clone()));
+                }
+            }
             if let Some(f) = exif_obj.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY) {
                 metadata.set_tag(ExifTag::DateTimeOriginal(get_string_val(f)));
             }
@@ -481,22 +579,14 @@ pub fn write_image_with_metadata(
             }
 
             if !strip_gps {
-                if let Some(f) = exif_obj.get_field(exif::Tag::GPSLatitude, exif::In::PRIMARY) {
-                        if let exif::Value::Rational(v) = &f.value {
-                            if v.len() >= 3 {
-                                metadata.set_tag(ExifTag::GPSLatitude(vec![to_ur64(&v[0]), to_ur64(&v[1]), to_ur64(&v[2])]));
-                            }
-                        }
+                if let Some(v) = exif_obj.get_field(exif::Tag::GPSLatitude, exif::In::PRIMARY).and_then(|f| rational_triple(&f.value)) {
+                    metadata.set_tag(ExifTag::GPSLatitude(v));
                 }
                 if let Some(f) = exif_obj.get_field(exif::Tag::GPSLatitudeRef, exif::In::PRIMARY) {
                     metadata.set_tag(ExifTag::GPSLatitudeRef(get_string_val(f)));
                 }
-                if let Some(f) = exif_obj.get_field(exif::Tag::GPSLongitude, exif::In::PRIMARY) {
-                        if let exif::Value::Rational(v) = &f.value {
-                            if v.len() >= 3 {
-                                metadata.set_tag(ExifTag::GPSLongitude(vec![to_ur64(&v[0]), to_ur64(&v[1]), to_ur64(&v[2])]));
-                            }
-                        }
+                if let Some(v) = exif_obj.get_field(exif::Tag::GPSLongitude, exif::In::PRIMARY).and_then(|f| rational_triple(&f.value)) {
+                    metadata.set_tag(ExifTag::GPSLongitude(v));
                 }
                 if let Some(f) = exif_obj.get_field(exif::Tag::GPSLongitudeRef, exif::In::PRIMARY) {
                     metadata.set_tag(ExifTag::GPSLongitudeRef(get_string_val(f)));
@@ -506,6 +596,17 @@ pub fn write_image_with_metadata(
                         if !v.is_empty() { metadata.set_tag(ExifTag::GPSAltitude(vec![to_ur64(&v[0])])); }
                     }
                 }
+                if let Some(v) = exif_obj.get_field(exif::Tag::GPSTimeStamp, exif::In::PRIMARY).and_then(|f| rational_triple(&f.value)) {
+                    metadata.set_tag(ExifTag::GPSTimeStamp(v));
+                }
+                if let Some(f) = exif_obj.get_field(exif::Tag::GPSDateStamp, exif::In::PRIMARY) {
+                    metadata.set_tag(ExifTag::GPSDateStamp(get_string_val(f)));
+                }
+                if let Some(f) = exif_obj.get_field(exif::Tag::GPSProcessingMethod, exif::In::PRIMARY) {
+                    if let exif::Value::Undefined(bytes, _) = &f.value {
+                        metadata.set_tag(ExifTag::GPSProcessingMethod(bytes.clone()));
+                    }
+                }
             }
         }
     }
@@ -522,6 +623,11 @@ pub fn write_image_with_metadata(
                      
                      if let Some(artist) = exif.artist { metadata.set_tag(ExifTag::Artist(artist)); }
                      if let Some(copyright) = exif.copyright { metadata.set_tag(ExifTag::Copyright(copyright)); }
+                     if let Some(comment) = exif.user_comment {
+                         let mut bytes = b"ASCII\0\0\0".to_vec();
+                         bytes.extend_from_slice(comment.as_bytes());
+                         metadata.set_tag(ExifTag::UserComment(bytes));
+                     }
                      if let Some(dt) = exif.date_time_original { metadata.set_tag(ExifTag::DateTimeOriginal(dt)); }
                      if let Some(dt) = exif.create_date { metadata.set_tag(ExifTag::CreateDate(dt)); }
                      if let Some(lens_make) = exif.lens_make { metadata.set_tag(ExifTag::LensMake(lens_make)); }
@@ -564,6 +670,15 @@ pub fn write_image_with_metadata(
                              if let Some(lon_ref) = gps.gps_longitude_ref { metadata.set_tag(ExifTag::GPSLongitudeRef(lon_ref)); }
                              if let Some(alt) = gps.gps_altitude { metadata.set_tag(ExifTag::GPSAltitude(vec![uR64 { nominator: alt.n, denominator: alt.d }])); }
                              if let Some(alt_ref) = gps.gps_altitude_ref { metadata.set_tag(ExifTag::GPSAltitudeRef(vec![alt_ref])); }
+                             if let Some(ts) = gps.gps_timestamp {
+                                 metadata.set_tag(ExifTag::GPSTimeStamp(vec![
+                                     uR64 { nominator: ts[0].n, denominator: ts[0].d },
+                                     uR64 { nominator: ts[1].n, denominator: ts[1].d },
+                                     uR64 { nominator: ts[2].n, denominator: ts[2].d }
+                                 ]));
+                             }
+                             if let Some(date) = gps.gps_date_stamp { metadata.set_tag(ExifTag::GPSDateStamp(date)); }
+                             if let Some(method) = gps.gps_processing_method { metadata.set_tag(ExifTag::GPSProcessingMethod(method)); }
                          }
                      }
                  }
@@ -579,5 +694,90 @@ pub fn write_image_with_metadata(
         log::warn!("Failed to write metadata: {}", e);
     }
 
+    // Caption, keywords, credit and source usually live in IPTC rather than
+    // EXIF. The IPTC block is carried over as-is between JPEGs.
+    if matches!(output_format.to_lowercase().as_str(), "jpg" | "jpeg") {
+        if let Some(iptc_segment) = fs::read(original_path).ok().and_then(|b| find_iptc_segment(&b)) {
+            insert_app_segment(image_bytes, &iptc_segment);
+        }
+    }
+
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gps_rationals_need_three_values() {
+        let time = exif::Value::Rational(vec![(14, 1).into(), (5, 1).into(), (3125, 100).into()]);
+        let triple = rational_triple(&time).unwrap();
+        assert_eq!(
+            triple
+                .iter()
+                .map(|r| (r.nominator, r.denominator))
+                .collect::<Vec<_>>(),
+            [(14, 1), (5, 1), (3125, 100)]
+        );
+        assert!(
+            rational_triple(&exif::Value::Rational(vec![(14, 1).into(), (5, 1).into()])).is_none()
+        );
+        assert!(rational_triple(&exif::Value::Ascii(vec![b"14:05:31".to_vec()])).is_none());
+    }
+
+    /// SOI, an APP0 segment with a two-byte payload, optional extra
+    /// segments, then SOS and EOI.
+    fn jpeg_with(segments: &[&[u8]]) -> Vec<u8> {
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, b'J', b'F'];
+        for segment in segments {
+            jpeg.extend_from_slice(segment);
+        }
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9]);
+        jpeg
+    }
+
+    fn iptc_segment(payload: &[u8]) -> Vec<u8> {
+        let length = (2 + PHOTOSHOP_IRB_HEADER.len() + payload.len()) as u16;
+        let mut segment = vec![0xFF, JPEG_APP13];
+        segment.extend_from_slice(&length.to_be_bytes());
+        segment.extend_from_slice(PHOTOSHOP_IRB_HEADER);
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    #[test]
+    fn iptc_segment_is_found_before_the_scan() {
+        let iptc = iptc_segment(b"caption");
+        assert_eq!(find_iptc_segment(&jpeg_with(&[&iptc])), Some(iptc));
+        assert_eq!(find_iptc_segment(&jpeg_with(&[])), None);
+        assert_eq!(find_iptc_segment(b"not a jpeg"), None);
+    }
+
+    #[test]
+    fn iptc_segment_replaces_the_old_one_after_app0() {
+        let mut jpeg = jpeg_with(&[&iptc_segment(b"old")]);
+        let new = iptc_segment(b"new caption");
+        insert_app_segment(&mut jpeg, &new);
+        assert_eq!(&jpeg[8..8 + new.len()], new.as_slice());
+        assert_eq!(find_iptc_segment(&jpeg), Some(new.clone()));
+        assert_eq!(jpeg.len(), jpeg_with(&[]).len() + new.len());
+    }
+
+    #[test]
+    fn equivalent_focal_length_uses_the_crop_factor() {
+        let mut map = HashMap::from([("FocalLength".to_string(), "\"23 mm\"".to_string())]);
+        insert_35mm_equivalent(&mut map, Some(1.53));
+        assert_eq!(map["FocalLength35mmEquivalent"], "35");
+
+        // An existing value wins; unknown bodies or focal lengths add nothing.
+        insert_35mm_equivalent(&mut map, Some(2.0));
+        assert_eq!(map["FocalLength35mmEquivalent"], "35");
+        let mut unknown_body = HashMap::from([("FocalLength".to_string(), "50 mm".to_string())]);
+        insert_35mm_equivalent(&mut unknown_body, None);
+        let mut no_focal = HashMap::new();
+        insert_35mm_equivalent(&mut no_focal, Some(1.5));
+        assert!(!unknown_body.contains_key("FocalLength35mmEquivalent"));
+        assert!(no_focal.is_empty());
+    }
+}
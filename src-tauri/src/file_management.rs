@@ -144,12 +144,12 @@ pub enum PasteMode {
 
 fn default_included_adjustments() -> HashSet<String> {
     [
-        "blacks", "brightness", "clarity", "centré", "chromaticAberrationBlueYellow",
+        "blacks", "blueGain", "brightness", "clarity", "centré", "chromaticAberrationBlueYellow",
         "chromaticAberrationRedCyan", "colorCalibration", "colorGrading", "colorNoiseReduction",
-        "contrast", "curves", "dehaze", "enableNegativeConversion", "exposure", "filmBaseColor",
-        "grainAmount", "grainRoughness", "grainSize", "highlights", "hsl", "lutIntensity",
+        "contrast", "contrastMode", "curves", "dehaze", "enableNegativeConversion", "exposure", "filmBaseColor",
+        "grainAmount", "grainRoughness", "grainSize", "greenGain", "highlights", "hsl", "lutIntensity",
         "lutName", "lutPath", "lutSize", "lumaNoiseReduction", "negativeBlueBalance",
-        "negativeGreenBalance", "negativeRedBalance", "saturation", "sectionVisibility",
+        "negativeGreenBalance", "negativeRedBalance", "redGain", "saturation", "sectionVisibility",
         "shadows", "sharpness", "showClipping", "structure", "temperature", "tint",
         "toneMapper", "vibrance", "vignetteAmount", "vignetteFeather", "vignetteMidpoint",
         "vignetteRoundness", "whites",
@@ -274,6 +274,8 @@ pub struct AppSettings {
     pub decorations: Option<bool>,
     #[serde(alias = "comfyuiAddress")]
     pub ai_connector_address: Option<String>,
+    #[serde(default)]
+    pub ai_connector_content_hash: Option<bool>,
     pub last_folder_state: Option<LastFolderState>,
     pub adaptive_editor_theme: Option<bool>,
     pub ui_visibility: Option<Value>,
@@ -336,6 +338,7 @@ impl Default for AppSettings {
             #[cfg(any(target_os = "windows", target_os = "macos"))]
             decorations: Some(false),
             ai_connector_address: None,
+            ai_connector_content_hash: Some(true),
             last_folder_state: None,
             adaptive_editor_theme: Some(false),
             ui_visibility: None,
@@ -750,11 +753,11 @@ pub fn generate_thumbnail_data(
             const THUMBNAIL_PROCESSING_DIM: u32 = 1280;
 
             let geometry_hash = calculate_geometry_hash(&meta.adjustments);
-            let cached_base: Option<(DynamicImage, f32)> = {
+            let cached_base: Option<(DynamicImage, f32, bool)> = {
                 let cache = state.thumbnail_geometry_cache.lock().unwrap();
-                if let Some((cached_hash, img, scale)) = cache.get(path_str) {
+                if let Some((cached_hash, img, scale, degraded)) = cache.get(path_str) {
                     if *cached_hash == geometry_hash {
-                        Some((img.clone(), *scale))
+                        Some((img.clone(), *scale, *degraded))
                     } else {
                         None
                     }
@@ -763,17 +766,17 @@ pub fn generate_thumbnail_data(
                 }
             };
 
-            let (processing_base, scale_for_gpu) = if let Some(hit) = cached_base {
+            let (processing_base, scale_for_gpu, degraded) = if let Some(hit) = cached_base {
                 hit
             } else {
                 
                 let settings = crate::file_management::load_settings(app_handle.clone()).unwrap_or_default();
                 let highlight_compression = settings.raw_highlight_compression.unwrap_or(2.5);
 
-                let composite_image = if let Some(img) = preloaded_image {
-                    image_loader::composite_patches_on_image(img, &adjustments)?
+                let (composite_image, degraded) = if let Some(img) = preloaded_image {
+                    (image_loader::composite_patches_on_image(img, &adjustments)?, false)
                 } else {
-                    match read_file_mapped(&source_path) {
+                    let base = match read_file_mapped(&source_path) {
                         Ok(mmap) => image_loader::load_and_composite(
                             &mmap,
                             &source_path_str,
@@ -795,7 +798,8 @@ pub fn generate_thumbnail_data(
                                 None,
                             )?
                         }
-                    }
+                    };
+                    (base.image, base.degraded)
                 };
 
                 let warped_image = apply_geometry_warp(&composite_image, &meta.adjustments);
@@ -822,9 +826,9 @@ pub fn generate_thumbnail_data(
 
                 let mut cache = state.thumbnail_geometry_cache.lock().unwrap();
                 if cache.len() > 30 { cache.clear(); }
-                cache.insert(path_str.to_string(), (geometry_hash, base.clone(), scale));
+                cache.insert(path_str.to_string(), (geometry_hash, base.clone(), scale, degraded));
 
-                (base, scale)
+                (base, scale, degraded)
             };
             
             let rotation_degrees = meta.adjustments["rotation"].as_f64().unwrap_or(0.0) as f32;
@@ -874,7 +878,8 @@ pub fn generate_thumbnail_data(
                 })
                 .collect();
 
-            let gpu_adjustments = get_all_adjustments_from_json(&meta.adjustments, is_raw);
+            let gpu_adjustments =
+                get_all_adjustments_from_json(&meta.adjustments, is_raw && !degraded);
             let lut_path = meta.adjustments["lutPath"].as_str();
             let lut = lut_path.and_then(|p| {
                 let mut cache = state.lut_cache.lock().unwrap();
@@ -914,10 +919,10 @@ pub fn generate_thumbnail_data(
     let settings = crate::file_management::load_settings(app_handle.clone()).unwrap_or_default();
     let highlight_compression = settings.raw_highlight_compression.unwrap_or(2.5);
 
-    let mut final_image = if let Some(img) = preloaded_image {
-        image_loader::composite_patches_on_image(img, &adjustments)?
+    let (mut final_image, degraded) = if let Some(img) = preloaded_image {
+        (image_loader::composite_patches_on_image(img, &adjustments)?, false)
     } else {
-         match read_file_mapped(&source_path) {
+        let base = match read_file_mapped(&source_path) {
             Ok(mmap) => image_loader::load_and_composite(
                 &mmap,
                 &source_path_str,
@@ -938,10 +943,12 @@ pub fn generate_thumbnail_data(
                     None,
                 )?
             }
-        }
+        };
+        (base.image, base.degraded)
     };
 
-    if is_raw && adjustments.is_null() {
+    // An embedded-preview fallback is already tone-mapped by the camera.
+    if is_raw && !degraded && adjustments.is_null() {
         apply_cpu_default_raw_processing(&mut final_image);
     }
 
@@ -7,8 +7,8 @@ use image::{DynamicImage, GenericImageView, ImageBuffer, Luma, Rgba};
 use wgpu::util::{DeviceExt, TextureDataOrder};
 
 use crate::image_processing::{AllAdjustments, GpuContext};
-use crate::lut_processing::Lut;
 use crate::{AppState, GpuImageCache};
+use rapidraw_core::lut::Lut3d;
 
 pub fn get_or_init_gpu_context(state: &tauri::State<AppState>) -> Result<GpuContext, String> {
     let mut context_lock = state.gpu_context.lock().unwrap();
@@ -479,9 +479,9 @@ impl GpuProcessor {
         input_texture_view: &wgpu::TextureView,
         width: u32,
         height: u32,
-        adjustments: AllAdjustments,
+        mut adjustments: AllAdjustments,
         mask_bitmaps: &[ImageBuffer<Luma<u8>, Vec<u8>>],
-        lut: Option<Arc<Lut>>,
+        lut: Option<Arc<Lut3d>>,
     ) -> Result<Vec<u8>, String> {
         let device = &self.context.device;
         let queue = &self.context.queue;
@@ -516,8 +516,10 @@ impl GpuProcessor {
             .collect();
 
         let (lut_texture_view, lut_sampler) = if let Some(lut_arc) = &lut {
+            adjustments.global.lut_domain_min = lut_arc.domain_min;
+            adjustments.global.lut_domain_max = lut_arc.domain_max;
             let lut_data = &lut_arc.data;
-            let size = lut_arc.size;
+            let size = lut_arc.size as u32;
             let mut rgba_lut_data_f16 = Vec::with_capacity(lut_data.len() / 3 * 4);
             for chunk in lut_data.chunks_exact(3) {
                 rgba_lut_data_f16.push(f16::from_f32(chunk[0]));
@@ -781,7 +783,7 @@ pub fn process_and_get_dynamic_image(
     transform_hash: u64,
     all_adjustments: AllAdjustments,
     mask_bitmaps: &[ImageBuffer<Luma<u8>, Vec<u8>>],
-    lut: Option<Arc<Lut>>,
+    lut: Option<Arc<Lut3d>>,
     caller_id: &str,
 ) -> Result<DynamicImage, String> {
     let (width, height) = base_image.dimensions();
@@ -2,7 +2,7 @@ use crate::Cursor;
 use crate::formats::is_raw_file;
 use crate::image_processing::{apply_orientation, remove_raw_artifacts_and_enhance};
 use crate::mask_generation::{generate_mask_bitmap, MaskDefinition, SubMask};
-use crate::raw_processing::develop_raw_image;
+use crate::raw_processing::{develop_raw_image_unoriented, extract_embedded_preview};
 use anyhow::{anyhow, Context, Result};
 use base64::{engine::general_purpose, Engine as _};
 use exif::{Reader as ExifReader, Tag};
@@ -39,15 +39,19 @@ pub fn load_and_composite(
     use_fast_raw_dev: bool,
     highlight_compression: f32,
     cancel_token: Option<(Arc<AtomicUsize>, usize)>,
-) -> Result<DynamicImage> {
-    let base_image = load_base_image_from_bytes(
+) -> Result<BaseImage> {
+    let base = load_base_image_from_bytes_oriented(
         base_image,
         path,
         use_fast_raw_dev,
         highlight_compression,
         cancel_token,
+        true,
     )?;
-    composite_patches_on_image(&base_image, adjustments)
+    Ok(BaseImage {
+        image: composite_patches_on_image(&base.image, adjustments)?,
+        ..base
+    })
 }
 
 fn load_exr_from_bytes(bytes: &[u8]) -> Result<DynamicImage> {
@@ -104,6 +108,49 @@ pub fn load_qoi_from_bytes(bytes: &[u8]) -> Result<DynamicImage> {
     }
 }
 
+/// A decoded base image and how it was obtained.
+pub struct BaseImage {
+    pub image: DynamicImage,
+    /// EXIF orientation value (1-8) the file records, 1 when it has none.
+    pub orientation: u16,
+    /// The RAW could not be developed and `image` is its embedded camera
+    /// preview instead: smaller and already rendered (sRGB, not scene data).
+    pub degraded: bool,
+}
+
+impl BaseImage {
+    fn full(image: DynamicImage, orientation: u16) -> Self {
+        Self {
+            image,
+            orientation,
+            degraded: false,
+        }
+    }
+
+    /// Whether the RAW-only processing path (default tone curve, RAW
+    /// adjustment defaults) applies. A preview fallback is already rendered,
+    /// so it is treated like a JPEG.
+    pub fn is_raw(&self, path: &str) -> bool {
+        !self.degraded && is_raw_file(path)
+    }
+
+    /// The image for export. Fails on an embedded-preview fallback: it would
+    /// ship a small camera JPEG with RAW processing applied on top.
+    pub fn into_export_image(self, path: &str) -> Result<DynamicImage> {
+        if self.degraded {
+            return Err(anyhow!(
+                "'{}' could not be developed; only its embedded preview is available",
+                path
+            ));
+        }
+        Ok(self.image)
+    }
+}
+
+/// Loads a base image, baking in the EXIF orientation. A RAW that fails to
+/// develop is an error here: callers of this function treat the result as RAW
+/// data. Use `load_base_image_from_bytes_oriented` to get the embedded-preview
+/// fallback with its `degraded` flag.
 pub fn load_base_image_from_bytes(
     bytes: &[u8],
     path_for_ext_check: &str,
@@ -111,13 +158,37 @@ pub fn load_base_image_from_bytes(
     highlight_compression: f32,
     cancel_token: Option<(Arc<AtomicUsize>, usize)>,
 ) -> Result<DynamicImage> {
+    load_base_image_from_bytes_oriented(
+        bytes,
+        path_for_ext_check,
+        use_fast_raw_dev,
+        highlight_compression,
+        cancel_token,
+        true,
+    )
+    .and_then(|base| base.into_export_image(path_for_ext_check))
+}
+
+/// Like `load_base_image_from_bytes`, but the EXIF orientation is only baked
+/// in when `auto_orient` is set; otherwise pixels come back as stored (RAWs
+/// in sensor orientation). Also reports the orientation value the file
+/// records and whether a failed RAW develop fell back to the embedded
+/// preview.
+pub fn load_base_image_from_bytes_oriented(
+    bytes: &[u8],
+    path_for_ext_check: &str,
+    use_fast_raw_dev: bool,
+    highlight_compression: f32,
+    cancel_token: Option<(Arc<AtomicUsize>, usize)>,
+    auto_orient: bool,
+) -> Result<BaseImage> {
     let path = std::path::Path::new(path_for_ext_check);
     if path
         .extension()
         .and_then(|s| s.to_str())
         .map_or(false, |s| s.eq_ignore_ascii_case("exr"))
     {
-        return load_exr_from_bytes(bytes);
+        return Ok(BaseImage::full(load_exr_from_bytes(bytes)?, 1));
     }
 
     if path
@@ -125,14 +196,20 @@ pub fn load_base_image_from_bytes(
         .and_then(|s| s.to_str())
         .map_or(false, |s| s.eq_ignore_ascii_case("qoi"))
     {
-        return load_qoi_from_bytes(bytes);
+        return Ok(BaseImage::full(load_qoi_from_bytes(bytes)?, 1));
     }
 
     if is_raw_file(path_for_ext_check) {
+        let cancel_check = cancel_token.clone();
+        let cancelled = move || {
+            cancel_check.as_ref().map_or(false, |(tracker, generation)| {
+                tracker.load(Ordering::SeqCst) != *generation
+            })
+        };
         match panic::catch_unwind(move || {
-            develop_raw_image(bytes, use_fast_raw_dev, highlight_compression, cancel_token)
+            develop_raw_image_unoriented(bytes, use_fast_raw_dev, highlight_compression, cancel_token)
         }) {
-            Ok(Ok(mut image)) => {
+            Ok(Ok((mut image, orientation))) => {
                 if !use_fast_raw_dev {
                     let start = Instant::now();
                     remove_raw_artifacts_and_enhance(&mut image);
@@ -143,31 +220,80 @@ pub fn load_base_image_from_bytes(
                         duration
                     );
                 }
-                Ok(image)
+                let image = if auto_orient {
+                    apply_orientation(image, orientation)
+                } else {
+                    image
+                };
+                Ok(BaseImage::full(image, orientation.to_u16()))
             }
+            Ok(Err(e)) if cancelled() => Err(e),
             Ok(Err(e)) => {
                 log::warn!("Error developing RAW file '{}': {}", path_for_ext_check, e);
-                Err(e)
+                embedded_preview_fallback(bytes, path_for_ext_check, auto_orient).ok_or(e)
             }
             Err(_) => {
                 log::error!(
                     "Panic while processing corrupt RAW file: {}",
                     path_for_ext_check
                 );
-                Err(anyhow!(
-                    "Failed to process corrupt RAW file: {}",
-                    path_for_ext_check
-                ))
+                embedded_preview_fallback(bytes, path_for_ext_check, auto_orient).ok_or_else(|| {
+                    anyhow!(
+                        "Failed to process corrupt RAW file: {}",
+                        path_for_ext_check
+                    )
+                })
             }
         }
     } else {
-        load_image_with_orientation(bytes, cancel_token)
+        let image = load_image_with_orientation(bytes, cancel_token, auto_orient)?;
+        Ok(BaseImage::full(image, read_exif_orientation(bytes).unwrap_or(1)))
     }
 }
 
+/// The embedded preview of a RAW that failed to develop, so the user at
+/// least sees the shot. `None` when there is none or reading it fails too.
+fn embedded_preview_fallback(
+    bytes: &[u8],
+    path_for_ext_check: &str,
+    auto_orient: bool,
+) -> Option<BaseImage> {
+    let (preview, orientation) = panic::catch_unwind(|| extract_embedded_preview(bytes))
+        .ok()?
+        .ok()??;
+    log::warn!(
+        "Showing the embedded preview of '{}' ({}x{}) instead",
+        path_for_ext_check,
+        preview.width(),
+        preview.height()
+    );
+    let image = if auto_orient {
+        apply_orientation(preview, orientation)
+    } else {
+        preview
+    };
+    Some(BaseImage {
+        image,
+        orientation: orientation.to_u16(),
+        degraded: true,
+    })
+}
+
+/// The EXIF orientation value (1-8) a non-RAW file records, if any.
+pub fn read_exif_orientation(bytes: &[u8]) -> Option<u16> {
+    let exif = ExifReader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()?;
+    exif.get_field(Tag::Orientation, exif::In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+        .map(|orientation| orientation as u16)
+}
+
+/// Decodes `bytes`, baking in the EXIF orientation when `auto_orient` is set.
 pub fn load_image_with_orientation(
     bytes: &[u8],
     cancel_token: Option<(Arc<AtomicUsize>, usize)>,
+    auto_orient: bool,
 ) -> Result<DynamicImage> {
     let check_cancel = || -> Result<()> {
         if let Some((tracker, generation)) = &cancel_token {
@@ -190,21 +316,12 @@ pub fn load_image_with_orientation(
     let image = reader.decode().context("Failed to decode image")?;
     check_cancel()?;
 
-    let oriented_image = {
-        let exif_reader = ExifReader::new();
-        if let Ok(exif) = exif_reader.read_from_container(&mut cursor.clone()) {
-            if let Some(orientation) = exif
-                .get_field(Tag::Orientation, exif::In::PRIMARY)
-                .and_then(|f| f.value.get_uint(0))
-            {
-                check_cancel()?;
-                apply_orientation(image, Orientation::from_u16(orientation as u16))
-            } else {
-                image
-            }
-        } else {
-            image
+    let oriented_image = match read_exif_orientation(bytes) {
+        Some(orientation) if auto_orient => {
+            check_cancel()?;
+            apply_orientation(image, Orientation::from_u16(orientation))
         }
+        _ => image,
     };
 
     Ok(DynamicImage::ImageRgb32F(oriented_image.to_rgb32f()))
@@ -287,15 +404,22 @@ pub fn composite_patches_on_image(
             .and_then(|v| v.as_str())
             .context("Missing color data")?;
         let color_bytes = general_purpose::STANDARD.decode(color_b64)?;
-        let color_image_u8 = image::load_from_memory(&color_bytes)?.to_rgb8();
-
-        let (patch_w, patch_h) = color_image_u8.dimensions();
-        let color_image_f32 = if base_w != patch_w || base_h != patch_h {
-            let resized =
-                imageops::resize(&color_image_u8, base_w, base_h, imageops::FilterType::Lanczos3);
-            DynamicImage::ImageRgb8(resized).to_rgb32f()
+        let color_image = image::load_from_memory(&color_bytes)?;
+        let patch_has_alpha = color_image.color().has_alpha();
+        // Resize in float so smooth generated gradients don't pick up 8-bit banding.
+        // Lanczos rings past the input range at hard edges; keep the patch in 0..1
+        // rather than rely on the resampler clamping float output.
+        let color_image_f32 = color_image.to_rgba32f();
+        let color_image_f32 = if color_image_f32.dimensions() != (base_w, base_h) {
+            let mut resized =
+                imageops::resize(&color_image_f32, base_w, base_h, imageops::FilterType::Lanczos3);
+            resized
+                .as_mut()
+                .iter_mut()
+                .for_each(|v| *v = v.clamp(0.0, 1.0));
+            resized
         } else {
-            DynamicImage::ImageRgb8(color_image_u8).to_rgb32f()
+            color_image_f32
         };
 
         composited_rgba
@@ -308,7 +432,8 @@ pub fn composite_patches_on_image(
                     if mask_value > 0 {
                         let patch_pixel = color_image_f32.get_pixel(x as u32, y as u32);
 
-                        let alpha = mask_value as f32 / 255.0;
+                        let patch_alpha = if patch_has_alpha { patch_pixel[3] } else { 1.0 };
+                        let alpha = mask_value as f32 / 255.0 * patch_alpha;
                         let one_minus_alpha = 1.0 - alpha;
 
                         let base_r = row[x * 4 + 0];
@@ -324,4 +449,170 @@ pub fn composite_patches_on_image(
     }
 
     Ok(DynamicImage::ImageRgba32F(composited_rgba))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, ImageFormat, Luma, Rgb32FImage, Rgba, RgbaImage};
+
+    fn png_base64(image: DynamicImage) -> String {
+        let mut bytes = Cursor::new(Vec::new());
+        image.write_to(&mut bytes, ImageFormat::Png).unwrap();
+        general_purpose::STANDARD.encode(bytes.into_inner())
+    }
+
+    #[test]
+    fn upscaled_patch_with_hard_edges_stays_in_range() {
+        let base =
+            DynamicImage::ImageRgb32F(Rgb32FImage::from_pixel(16, 16, image::Rgb([0.5; 3])));
+        let patch = RgbaImage::from_fn(4, 4, |x, _| {
+            if x < 2 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+        let mask = GrayImage::from_pixel(16, 16, Luma([255]));
+        let adjustments = serde_json::json!({
+            "aiPatches": [{
+                "patchData": {
+                    "color": png_base64(DynamicImage::ImageRgba8(patch)),
+                    "mask": png_base64(DynamicImage::ImageLuma8(mask)),
+                }
+            }]
+        });
+
+        let composited = composite_patches_on_image(&base, &adjustments).unwrap();
+        let values = composited.to_rgba32f().into_raw();
+        assert!(values.iter().all(|v| (0.0..=1.0).contains(v)));
+        assert!(values.iter().any(|&v| v < 0.1) && values.iter().any(|&v| v > 0.9));
+    }
+
+    #[test]
+    fn upscaled_smooth_ramp_keeps_its_gradations() {
+        // A 16-bit ramp blown up 4x would stair-step in 8 bits (runs of six
+        // equal codes here); in float every output column keeps rising.
+        const SCALE: u32 = 4;
+        let patch = image::ImageBuffer::<Rgba<u16>, _>::from_fn(32, 2, |x, _| {
+            let v = 16384 + x as u16 * 200;
+            Rgba([v, v, v, u16::MAX])
+        });
+        let base = DynamicImage::ImageRgb32F(Rgb32FImage::new(32 * SCALE, 2 * SCALE));
+        let mask = GrayImage::from_pixel(32 * SCALE, 2 * SCALE, Luma([255]));
+
+        let composited = composite_patches_on_image(
+            &base,
+            &single_patch(DynamicImage::ImageRgba16(patch), mask),
+        )
+        .unwrap()
+        .to_rgba32f();
+
+        let row: Vec<f32> = (0..32 * SCALE)
+            .map(|x| composited.get_pixel(x, SCALE).0[0])
+            .collect();
+        assert!(row.windows(2).all(|pair| pair[1] >= pair[0]), "{row:?}");
+        let longest_plateau = row
+            .chunk_by(|a, b| a == b)
+            .map(|run| run.len())
+            .max()
+            .unwrap();
+        assert!(longest_plateau <= SCALE as usize, "{row:?}");
+    }
+
+    fn single_patch(color: DynamicImage, mask: GrayImage) -> serde_json::Value {
+        serde_json::json!({
+            "aiPatches": [{
+                "patchData": {
+                    "color": png_base64(color),
+                    "mask": png_base64(DynamicImage::ImageLuma8(mask)),
+                }
+            }]
+        })
+    }
+
+    #[test]
+    fn patch_alpha_scales_the_mask() {
+        let base = DynamicImage::ImageRgb32F(Rgb32FImage::new(4, 4));
+        let patch = RgbaImage::from_fn(4, 4, |x, _| {
+            Rgba([255, 255, 255, if x < 2 { 0 } else { 255 }])
+        });
+        let mask = GrayImage::from_pixel(4, 4, Luma([255]));
+
+        let composited =
+            composite_patches_on_image(&base, &single_patch(DynamicImage::ImageRgba8(patch), mask))
+                .unwrap()
+                .to_rgba32f();
+
+        assert_eq!(composited.get_pixel(0, 0).0, [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(composited.get_pixel(3, 0).0, [1.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn opaque_rgb_patch_follows_the_mask_alone() {
+        let base = DynamicImage::ImageRgb32F(Rgb32FImage::new(4, 4));
+        let patch = image::RgbImage::from_pixel(4, 4, image::Rgb([255, 255, 255]));
+        let mask = GrayImage::from_fn(4, 4, |x, _| Luma([if x < 2 { 0 } else { 255 }]));
+
+        let composited =
+            composite_patches_on_image(&base, &single_patch(DynamicImage::ImageRgb8(patch), mask))
+                .unwrap()
+                .to_rgba32f();
+
+        assert_eq!(composited.get_pixel(1, 1).0, [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(composited.get_pixel(2, 1).0, [1.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn a_preview_fallback_cannot_be_exported() {
+        let image = DynamicImage::ImageRgb32F(Rgb32FImage::new(2, 2));
+        let full = BaseImage::full(image.clone(), 1);
+        assert!(!full.degraded);
+        assert!(full.into_export_image("IMG_0001.CR3").is_ok());
+
+        let preview = BaseImage {
+            image,
+            orientation: 6,
+            degraded: true,
+        };
+        let err = preview.into_export_image("IMG_0001.CR3").unwrap_err();
+        assert!(err.to_string().contains("IMG_0001.CR3"));
+    }
+
+    #[test]
+    fn a_preview_fallback_skips_the_raw_default_path() {
+        let image = DynamicImage::ImageRgb32F(Rgb32FImage::new(2, 2));
+        assert!(BaseImage::full(image.clone(), 1).is_raw("IMG_0001.CR3"));
+        assert!(!BaseImage::full(image.clone(), 1).is_raw("a.jpg"));
+
+        let preview = BaseImage {
+            image,
+            orientation: 1,
+            degraded: true,
+        };
+        assert!(!preview.is_raw("IMG_0001.CR3"));
+    }
+
+    #[test]
+    fn undecodable_raw_without_a_preview_still_fails() {
+        let garbage = b"not a raw file at all";
+        assert!(
+            load_base_image_from_bytes_oriented(garbage, "IMG_0001.CR3", true, 0.0, None, true)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn non_raw_images_are_never_degraded() {
+        let mut bytes = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(image::RgbImage::new(3, 2))
+            .write_to(&mut bytes, ImageFormat::Png)
+            .unwrap();
+        let base =
+            load_base_image_from_bytes_oriented(bytes.get_ref(), "a.png", true, 0.0, None, true)
+                .unwrap();
+        assert!(!base.degraded);
+        assert_eq!(base.orientation, 1);
+        assert_eq!(base.image.dimensions(), (3, 2));
+    }
+}
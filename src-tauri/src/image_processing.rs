@@ -3,6 +3,8 @@ use glam::{Mat3, Vec2, Vec3};
 use image::{DynamicImage, GenericImageView, Rgba, Rgb32FImage};
 use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
 use nalgebra::{Matrix3 as NaMatrix3, Vector3 as NaVector3};
+use rapidraw_core::luma::{self, LumaCoefficients};
+use rapidraw_core::srgb::linear_to_srgb;
 use rawler::decoders::Orientation;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -67,6 +69,10 @@ pub struct GeometryParams {
     pub vig_k1: f32,
     pub vig_k2: f32,
     pub vig_k3: f32,
+    /// Zoom in just enough that the distortion correction leaves no empty
+    /// border.
+    #[serde(default)]
+    pub auto_scale_after_distortion: bool,
 }
 
 impl Default for GeometryParams {
@@ -95,6 +101,7 @@ impl Default for GeometryParams {
             vig_k1: 0.0,
             vig_k2: 0.0,
             vig_k3: 0.0,
+            auto_scale_after_distortion: false,
         }
     }
 }
@@ -128,6 +135,7 @@ pub fn get_geometry_params_from_json(adjustments: &serde_json::Value) -> Geometr
         vig_k1: lens_params.and_then(|p| p.get("vig_k1").and_then(|k| k.as_f64())).unwrap_or(0.0) as f32,
         vig_k2: lens_params.and_then(|p| p.get("vig_k2").and_then(|k| k.as_f64())).unwrap_or(0.0) as f32,
         vig_k3: lens_params.and_then(|p| p.get("vig_k3").and_then(|k| k.as_f64())).unwrap_or(0.0) as f32,
+        auto_scale_after_distortion: adjustments["autoScaleAfterDistortion"].as_bool().unwrap_or(false),
     }
 }
 
@@ -336,6 +344,127 @@ fn interpolate_pixel_with_tca(
     pixel_out[2] = sample_channel(bx, by, 2);
 }
 
+/// The radial part of the forward warp: the lens profile correction followed
+/// by the manual distortion slider, mapping an output position to the source
+/// position it samples.
+struct RadialDistortion {
+    cx: f32,
+    cy: f32,
+    half_diagonal: f64,
+    max_radius_sq_inv: f32,
+    k_distortion: f32,
+    lk1: f64,
+    lk2: f64,
+    lk3: f64,
+    lens_dist_amt: f64,
+    has_lens_correction: bool,
+    is_ptlens: bool,
+}
+
+impl RadialDistortion {
+    fn new(params: &GeometryParams, cx: f32, cy: f32, half_diagonal: f64) -> Self {
+        let lk1 = params.lens_dist_k1 as f64;
+        let lk2 = params.lens_dist_k2 as f64;
+        let lk3 = params.lens_dist_k3 as f64;
+        Self {
+            cx,
+            cy,
+            half_diagonal,
+            max_radius_sq_inv: 1.0 / (cx * cx + cy * cy),
+            k_distortion: (params.distortion / 100.0) * 2.5, // hack to align it;
+            lk1,
+            lk2,
+            lk3,
+            lens_dist_amt: (params.lens_distortion_amount as f64) * 2.5, // hack to align it
+            has_lens_correction: params.lens_distortion_enabled && (lk1.abs() > 1e-6 || lk2.abs() > 1e-6 || lk3.abs() > 1e-6),
+            is_ptlens: params.lens_model == 1,
+        }
+    }
+
+    #[inline(always)]
+    fn source(&self, x: f32, y: f32) -> (f32, f32) {
+        let (cx, cy) = (self.cx, self.cy);
+        let (mut src_x, mut src_y) = (x, y);
+
+        if self.has_lens_correction {
+            let dx = src_x - cx;
+            let dy = src_y - cy;
+            let ru = ((dx * dx + dy * dy) as f64).sqrt();
+
+            if ru > 1e-6 {
+                let ru_norm = ru / self.half_diagonal;
+                let ru_norm2 = ru_norm * ru_norm;
+                let (lk1, lk2, lk3) = (self.lk1, self.lk2, self.lk3);
+
+                let rd_norm = if self.is_ptlens {
+                    let a = lk1; let b = lk2; let c = lk3;
+                    let d = 1.0 - a - b - c;
+                    let poly = a * ru_norm2 * ru_norm + b * ru_norm2 + c * ru_norm + d;
+                    ru_norm * poly
+                } else {
+                    let poly = 1.0 + lk1 * ru_norm2 
+                             + lk2 * (ru_norm2 * ru_norm2) 
+                             + lk3 * (ru_norm2 * ru_norm2 * ru_norm2);
+                    ru_norm * poly
+                };
+
+                let effective_r_norm = ru_norm + (rd_norm - ru_norm) * self.lens_dist_amt;
+                let scale = effective_r_norm / ru_norm;
+
+                src_x = cx + dx * scale as f32;
+                src_y = cy + dy * scale as f32;
+            }
+        }
+
+        if self.k_distortion.abs() > 1e-5 {
+            let dx = src_x - cx;
+            let dy = src_y - cy;
+            let r2_norm = (dx * dx + dy * dy) * self.max_radius_sq_inv;
+            let f = 1.0 + self.k_distortion * r2_norm;
+            src_x = cx + dx * f;
+            src_y = cy + dy * f;
+        }
+
+        (src_x, src_y)
+    }
+
+    /// Largest zoom toward the centre (at most 1) for which every pixel on
+    /// the border of a `width` x `height` output samples inside the source,
+    /// found by bisection over points along the frame.
+    fn fill_scale(&self, width: f32, height: f32) -> f32 {
+        const EDGE_SAMPLES: usize = 64;
+        let (max_x, max_y) = (width - 1.0, height - 1.0);
+        let covered = |zoom: f32| {
+            (0..=EDGE_SAMPLES).all(|i| {
+                let t = i as f32 / EDGE_SAMPLES as f32;
+                [(t * max_x, 0.0), (t * max_x, max_y), (0.0, t * max_y), (max_x, t * max_y)]
+                    .into_iter()
+                    .all(|(x, y)| {
+                        let (sx, sy) = self.source(self.cx + (x - self.cx) * zoom, self.cy + (y - self.cy) * zoom);
+                        // Strict upper bound, as in `interpolate_pixel`.
+                        sx >= 0.0 && sy >= 0.0 && sx < max_x && sy < max_y
+                    })
+            })
+        };
+
+        // Pull the border in by a hair so the corners themselves count.
+        let full = 1.0 - 1e-4;
+        if covered(full) {
+            return 1.0;
+        }
+        let (mut low, mut high) = (0.0f32, full);
+        for _ in 0..24 {
+            let mid = 0.5 * (low + high);
+            if covered(mid) {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        low.max(1e-3)
+    }
+}
+
 pub fn warp_image_geometry(image: &DynamicImage, params: GeometryParams) -> DynamicImage {
     let src_img = image.to_rgb32f();
     let (width, height) = src_img.dimensions();
@@ -348,16 +477,12 @@ pub fn warp_image_geometry(image: &DynamicImage, params: GeometryParams) -> Dyna
     let step_vec_y = NaVector3::new(inv[(0, 1)], inv[(1, 1)], inv[(2, 1)]);
     let origin_vec = NaVector3::new(inv[(0, 2)], inv[(1, 2)], inv[(2, 2)]);
 
-    let max_radius_sq_inv = 1.0 / (cx * cx + cy * cy);
-    let k_distortion = (params.distortion / 100.0) * 2.5; // hack to align it;
-
-    let lk1 = params.lens_dist_k1 as f64;
-    let lk2 = params.lens_dist_k2 as f64;
-    let lk3 = params.lens_dist_k3 as f64;
-    let lens_dist_amt = (params.lens_distortion_amount as f64) * 2.5; // hack to align it
-
-    let has_lens_correction = params.lens_distortion_enabled && (lk1.abs() > 1e-6 || lk2.abs() > 1e-6 || lk3.abs() > 1e-6);
-    let is_ptlens = params.lens_model == 1;
+    let radial = RadialDistortion::new(&params, cx, cy, half_diagonal);
+    let fill_scale = if params.auto_scale_after_distortion {
+        radial.fill_scale(width as f32, height as f32)
+    } else {
+        1.0
+    };
 
     let vr = if (params.tca_vr - 1.0).abs() > 1e-5 { params.tca_vr + (1.0 - params.tca_vr) * (1.0 - params.lens_tca_amount) } else { 1.0 };
     let vb = if (params.tca_vb - 1.0).abs() > 1e-5 { params.tca_vb + (1.0 - params.tca_vb) * (1.0 - params.lens_tca_amount) } else { 1.0 };
@@ -388,43 +513,11 @@ pub fn warp_image_geometry(image: &DynamicImage, params: GeometryParams) -> Dyna
                     let mut src_x = current_vec.x * inv_z;
                     let mut src_y = current_vec.y * inv_z;
 
-                    if has_lens_correction {
-                        let dx = src_x - cx;
-                        let dy = src_y - cy;
-                        let ru = ((dx * dx + dy * dy) as f64).sqrt();
-
-                        if ru > 1e-6 {
-                            let ru_norm = ru / half_diagonal;
-                            let ru_norm2 = ru_norm * ru_norm;
-
-                            let rd_norm = if is_ptlens {
-                                let a = lk1; let b = lk2; let c = lk3;
-                                let d = 1.0 - a - b - c;
-                                let poly = a * ru_norm2 * ru_norm + b * ru_norm2 + c * ru_norm + d;
-                                ru_norm * poly
-                            } else {
-                                let poly = 1.0 + lk1 * ru_norm2 
-                                         + lk2 * (ru_norm2 * ru_norm2) 
-                                         + lk3 * (ru_norm2 * ru_norm2 * ru_norm2);
-                                ru_norm * poly
-                            };
-
-                            let effective_r_norm = ru_norm + (rd_norm - ru_norm) * lens_dist_amt;
-                            let scale = effective_r_norm / ru_norm;
-
-                            src_x = cx + dx * scale as f32;
-                            src_y = cy + dy * scale as f32;
-                        }
-                    }
-
-                    if k_distortion.abs() > 1e-5 {
-                        let dx = src_x - cx;
-                        let dy = src_y - cy;
-                        let r2_norm = (dx * dx + dy * dy) * max_radius_sq_inv;
-                        let f = 1.0 + k_distortion * r2_norm;
-                        src_x = cx + dx * f;
-                        src_y = cy + dy * f;
+                    if fill_scale != 1.0 {
+                        src_x = cx + (src_x - cx) * fill_scale;
+                        src_y = cy + (src_y - cy) * fill_scale;
                     }
+                    (src_x, src_y) = radial.source(src_x, src_y);
 
                     if has_tca {
                         interpolate_pixel_with_tca(src_raw, width_usize, height_usize, cx, cy, src_x, src_y, vr, vb, pixel);
@@ -570,14 +663,12 @@ pub fn unwarp_image_geometry(warped_image: &DynamicImage, params: GeometryParams
 pub fn apply_cpu_default_raw_processing(image: &mut DynamicImage) {
     let mut f32_image = image.to_rgb32f();
 
-    const GAMMA: f32 = 2.2;
-    const INV_GAMMA: f32 = 1.0 / GAMMA;
     const CONTRAST: f32 = 1.15;
 
     f32_image.par_chunks_mut(3).for_each(|pixel_chunk| {
-        let r_gamma = pixel_chunk[0].powf(INV_GAMMA);
-        let g_gamma = pixel_chunk[1].powf(INV_GAMMA);
-        let b_gamma = pixel_chunk[2].powf(INV_GAMMA);
+        let r_gamma = linear_to_srgb(pixel_chunk[0]);
+        let g_gamma = linear_to_srgb(pixel_chunk[1]);
+        let b_gamma = linear_to_srgb(pixel_chunk[2]);
 
         let r_contrast = (r_gamma - 0.5) * CONTRAST + 0.5;
         let g_contrast = (g_gamma - 0.5) * CONTRAST + 0.5;
@@ -713,6 +804,61 @@ pub fn apply_unwarp_geometry(
     }
 }
 
+/// JPEG chroma subsampling. `Auto` keeps full chroma resolution at high
+/// quality settings, where subsampling becomes the most visible loss, and
+/// uses 4:2:0 below that.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChromaSubsampling {
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "4:4:4")]
+    Yuv444,
+    #[serde(rename = "4:2:2")]
+    Yuv422,
+    #[serde(rename = "4:2:0")]
+    Yuv420,
+}
+
+const AUTO_FULL_CHROMA_QUALITY: u8 = 90;
+
+impl ChromaSubsampling {
+    pub fn resolve(self, jpeg_quality: u8) -> Self {
+        match self {
+            ChromaSubsampling::Auto if jpeg_quality >= AUTO_FULL_CHROMA_QUALITY => {
+                ChromaSubsampling::Yuv444
+            }
+            ChromaSubsampling::Auto => ChromaSubsampling::Yuv420,
+            other => other,
+        }
+    }
+}
+
+pub fn encode_jpeg(
+    image: &DynamicImage,
+    jpeg_quality: u8,
+    subsampling: ChromaSubsampling,
+) -> anyhow::Result<Vec<u8>> {
+    let rgb_image = image.to_rgb8();
+    let (width, height) = rgb_image.dimensions();
+    let (width, height) = match (u16::try_from(width), u16::try_from(height)) {
+        (Ok(w), Ok(h)) => (w, h),
+        _ => anyhow::bail!("{}x{} exceeds the JPEG size limit of 65535 pixels", width, height),
+    };
+
+    let sampling_factor = match subsampling.resolve(jpeg_quality) {
+        ChromaSubsampling::Yuv422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+        ChromaSubsampling::Yuv420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+        _ => jpeg_encoder::SamplingFactor::R_4_4_4,
+    };
+
+    let mut bytes = Vec::new();
+    let mut encoder = jpeg_encoder::Encoder::new(&mut bytes, jpeg_quality.clamp(1, 100));
+    encoder.set_sampling_factor(sampling_factor);
+    encoder.encode(rgb_image.as_raw(), width, height, jpeg_encoder::ColorType::Rgb)?;
+    Ok(bytes)
+}
+
 pub fn apply_flip(image: DynamicImage, horizontal: bool, vertical: bool) -> DynamicImage {
     let mut img = image;
     if horizontal {
@@ -845,10 +991,12 @@ pub struct GlobalAdjustments {
     pub has_lut: u32,
     pub lut_intensity: f32,
     pub tonemapper_mode: u32,
-    _pad_lut2: f32,
-    _pad_lut3: f32,
-    _pad_lut4: f32,
-    _pad_lut5: f32,
+    /// 0 linear, 1 S-curve, 2 filmic; see `apply_contrast_curve` in the shader.
+    pub contrast_mode: u32,
+    /// Per-channel multipliers applied with exposure; 1 is neutral.
+    pub red_gain: f32,
+    pub green_gain: f32,
+    pub blue_gain: f32,
 
     _pad_agx1: f32,
     _pad_agx2: f32,
@@ -879,6 +1027,12 @@ pub struct GlobalAdjustments {
     pub red_curve_count: u32,
     pub green_curve_count: u32,
     pub blue_curve_count: u32,
+    /// The LUT's input range (`.cube` DOMAIN_MIN/DOMAIN_MAX), mapped onto
+    /// the lattice before sampling. Set from the loaded LUT on upload.
+    pub lut_domain_min: [f32; 3],
+    _pad_lut1: f32,
+    pub lut_domain_max: [f32; 3],
+    _pad_lut2: f32,
     _pad_end1: f32,
     _pad_end2: f32,
     _pad_end3: f32,
@@ -1384,10 +1538,16 @@ fn get_global_adjustments_from_json(
         },
         lut_intensity: js_adjustments["lutIntensity"].as_f64().unwrap_or(100.0) as f32 / 100.0,
         tonemapper_mode: if tone_mapper == "agx" { 1 } else { 0 },
-        _pad_lut2: 0.0,
-        _pad_lut3: 0.0,
-        _pad_lut4: 0.0,
-        _pad_lut5: 0.0,
+        // Edits saved before `contrastMode` existed render linear, as in the
+        // WASM pipeline.
+        contrast_mode: match js_adjustments["contrastMode"].as_str() {
+            Some("sCurve") => 1,
+            Some("filmic") => 2,
+            _ => 0,
+        },
+        red_gain: get_val("basic", "redGain", 1.0, Some(1.0)),
+        green_gain: get_val("basic", "greenGain", 1.0, Some(1.0)),
+        blue_gain: get_val("basic", "blueGain", 1.0, Some(1.0)),
 
         _pad_agx1: 0.0,
         _pad_agx2: 0.0,
@@ -1442,6 +1602,10 @@ fn get_global_adjustments_from_json(
         red_curve_count: red_points.len() as u32,
         green_curve_count: green_points.len() as u32,
         blue_curve_count: blue_points.len() as u32,
+        lut_domain_min: [0.0; 3],
+        _pad_lut1: 0.0,
+        lut_domain_max: [1.0; 3],
+        _pad_lut2: 0.0,
         _pad_end1: 0.0,
         _pad_end2: 0.0,
         _pad_end3: 0.0,
@@ -1856,45 +2020,133 @@ pub fn generate_histogram(
     }
 }
 
-pub fn calculate_histogram_from_image(image: &DynamicImage) -> Result<HistogramData, String> {
-    let mut red_counts = vec![0u32; 256];
-    let mut green_counts = vec![0u32; 256];
-    let mut blue_counts = vec![0u32; 256];
-    let mut luma_counts = vec![0u32; 256];
+/// Rows per band when histogramming an image, so only one band is ever held
+/// as 8-bit RGB next to the source.
+const HISTOGRAM_BAND_ROWS: u32 = 256;
+
+/// Running per-channel and luma counts. Feed it packed 8-bit RGB in any
+/// number of pieces; a pixel split across two pieces is carried over, so the
+/// result does not depend on where the splits fall.
+pub struct HistogramAccumulator {
+    counts: [[u32; 256]; 4],
+    /// Leading bytes of a pixel cut off at the end of the last piece.
+    pending: Vec<u8>,
+}
 
-    for pixel in image.to_rgb8().pixels() {
-        let r = pixel[0] as usize;
-        let g = pixel[1] as usize;
-        let b = pixel[2] as usize;
-        red_counts[r] += 1;
-        green_counts[g] += 1;
-        blue_counts[b] += 1;
-        let luma_val = (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32).round() as usize;
-        luma_counts[luma_val.min(255)] += 1;
-    }
-
-    let mut red: Vec<f32> = red_counts.into_iter().map(|c| c as f32).collect();
-    let mut green: Vec<f32> = green_counts.into_iter().map(|c| c as f32).collect();
-    let mut blue: Vec<f32> = blue_counts.into_iter().map(|c| c as f32).collect();
-    let mut luma: Vec<f32> = luma_counts.into_iter().map(|c| c as f32).collect();
-
-    let smoothing_sigma = 2.5;
-    apply_gaussian_smoothing(&mut red, smoothing_sigma);
-    apply_gaussian_smoothing(&mut green, smoothing_sigma);
-    apply_gaussian_smoothing(&mut blue, smoothing_sigma);
-    apply_gaussian_smoothing(&mut luma, smoothing_sigma);
-
-    normalize_histogram_range(&mut red, 0.99);
-    normalize_histogram_range(&mut green, 0.99);
-    normalize_histogram_range(&mut blue, 0.99);
-    normalize_histogram_range(&mut luma, 0.99);
-
-    Ok(HistogramData {
-        red,
-        green,
-        blue,
-        luma,
-    })
+impl Default for HistogramAccumulator {
+    fn default() -> Self {
+        Self {
+            counts: [[0; 256]; 4],
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl HistogramAccumulator {
+    /// Adds the next piece of packed RGB8 data.
+    pub fn add_rgb8(&mut self, mut pixels: &[u8]) {
+        if !self.pending.is_empty() {
+            let needed = (3 - self.pending.len()).min(pixels.len());
+            self.pending.extend_from_slice(&pixels[..needed]);
+            pixels = &pixels[needed..];
+            if self.pending.len() < 3 {
+                return;
+            }
+            let pixel = std::mem::take(&mut self.pending);
+            self.count_pixels(&pixel);
+        }
+        let whole = pixels.len() - pixels.len() % 3;
+        self.pending.extend_from_slice(&pixels[whole..]);
+        self.count_pixels(&pixels[..whole]);
+    }
+
+    fn count_pixels(&mut self, pixels: &[u8]) {
+        const CHUNK_PIXELS: usize = 64 * 1024;
+
+        let counts = pixels
+            .par_chunks(CHUNK_PIXELS * 3)
+            .fold(
+                || [[0u32; 256]; 4],
+                |mut counts, chunk| {
+                    for pixel in chunk.chunks_exact(3) {
+                        let r = pixel[0] as usize;
+                        let g = pixel[1] as usize;
+                        let b = pixel[2] as usize;
+                        counts[0][r] += 1;
+                        counts[1][g] += 1;
+                        counts[2][b] += 1;
+                        let luma_val =
+                            luma::luma(r as f32, g as f32, b as f32, LumaCoefficients::Rec709)
+                                .round() as usize;
+                        counts[3][luma_val.min(255)] += 1;
+                    }
+                    counts
+                },
+            )
+            .reduce(|| [[0u32; 256]; 4], merge_histogram_counts);
+        self.counts = merge_histogram_counts(self.counts, counts);
+    }
+
+    /// Smooths and normalises the counts for display. Bytes of an incomplete
+    /// final pixel are ignored.
+    pub fn finish(self) -> HistogramData {
+        let [red_counts, green_counts, blue_counts, luma_counts] = self.counts;
+        let mut red: Vec<f32> = red_counts.into_iter().map(|c| c as f32).collect();
+        let mut green: Vec<f32> = green_counts.into_iter().map(|c| c as f32).collect();
+        let mut blue: Vec<f32> = blue_counts.into_iter().map(|c| c as f32).collect();
+        let mut luma: Vec<f32> = luma_counts.into_iter().map(|c| c as f32).collect();
+
+        let smoothing_sigma = 2.5;
+        apply_gaussian_smoothing(&mut red, smoothing_sigma);
+        apply_gaussian_smoothing(&mut green, smoothing_sigma);
+        apply_gaussian_smoothing(&mut blue, smoothing_sigma);
+        apply_gaussian_smoothing(&mut luma, smoothing_sigma);
+
+        normalize_histogram_range(&mut red, 0.99);
+        normalize_histogram_range(&mut green, 0.99);
+        normalize_histogram_range(&mut blue, 0.99);
+        normalize_histogram_range(&mut luma, 0.99);
+
+        HistogramData {
+            red,
+            green,
+            blue,
+            luma,
+        }
+    }
+}
+
+fn merge_histogram_counts(mut a: [[u32; 256]; 4], b: [[u32; 256]; 4]) -> [[u32; 256]; 4] {
+    for (channel_a, channel_b) in a.iter_mut().zip(b.iter()) {
+        for (x, y) in channel_a.iter_mut().zip(channel_b.iter()) {
+            *x += y;
+        }
+    }
+    a
+}
+
+/// Histogram of RGB8 pixel data supplied piecewise, e.g. rows as an export
+/// encodes them, so a full-resolution image never has to be held as one
+/// 8-bit buffer. Pieces need not end on a pixel boundary.
+pub fn generate_histogram_streaming<I, B>(bands: I) -> HistogramData
+where
+    I: IntoIterator<Item = B>,
+    B: AsRef<[u8]>,
+{
+    let mut accumulator = HistogramAccumulator::default();
+    for band in bands {
+        accumulator.add_rgb8(band.as_ref());
+    }
+    accumulator.finish()
+}
+
+pub fn calculate_histogram_from_image(image: &DynamicImage) -> Result<HistogramData, String> {
+    let (width, height) = image.dimensions();
+    let bands = (0..height).step_by(HISTOGRAM_BAND_ROWS as usize).map(|y| {
+        let rows = HISTOGRAM_BAND_ROWS.min(height - y);
+        image.crop_imm(0, y, width, rows).to_rgb8().into_raw()
+    });
+    Ok(generate_histogram_streaming(bands))
 }
 
 fn apply_gaussian_smoothing(histogram: &mut Vec<f32>, sigma: f32) {
@@ -2037,7 +2289,8 @@ pub fn calculate_waveform_from_image(image: &DynamicImage) -> Result<WaveformDat
         green[g_idx] += 1;
         blue[b_idx] += 1;
 
-        let luma_val = (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32).round() as usize;
+        let luma_val =
+            luma::luma(r as f32, g as f32, b as f32, LumaCoefficients::Rec709).round() as usize;
         let luma_idx = (255 - luma_val.min(255)) * WAVEFORM_WIDTH as usize + x as usize;
         luma[luma_idx] += 1;
     }
@@ -2067,7 +2320,7 @@ pub fn perform_auto_analysis(image: &DynamicImage) -> AutoAdjustmentResults {
         let g_f = pixel[1] as f32;
         let b_f = pixel[2] as f32;
 
-        let luma_val = (0.2126 * r_f + 0.7152 * g_f + 0.0722 * b_f).round() as usize;
+        let luma_val = luma::luma(r_f, g_f, b_f, LumaCoefficients::Rec709).round() as usize;
         luma_hist[luma_val.min(255)] += 1;
 
         let r_norm = r_f / 255.0;
@@ -2187,7 +2440,8 @@ pub fn perform_auto_analysis(image: &DynamicImage) -> AutoAdjustmentResults {
     let mut edge_pixel_count = 0;
 
     for (x, y, pixel) in rgb_image.enumerate_pixels() {
-        let luma = (0.2126 * pixel[0] as f32 + 0.7152 * pixel[1] as f32 + 0.0722 * pixel[2] as f32) / 255.0;
+        let [r, g, b] = pixel.0.map(f32::from);
+        let luma = luma::luma(r, g, b, LumaCoefficients::Rec709) / 255.0;
         if x >= center_x_start && x < center_x_end && y >= center_y_start && y < center_y_end {
             center_luma_sum += luma;
             center_pixel_count += 1;
@@ -2265,4 +2519,179 @@ pub fn calculate_auto_adjustments(
     let results = perform_auto_analysis(&original_image);
 
     Ok(auto_results_to_json(&results))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The packed horizontal/vertical sampling factors of each component in
+    /// the baseline SOF segment.
+    fn sof_sampling_factors(jpeg: &[u8]) -> Vec<u8> {
+        let mut at = 2;
+        while jpeg[at + 1] != 0xC0 {
+            at += 2 + u16::from_be_bytes([jpeg[at + 2], jpeg[at + 3]]) as usize;
+        }
+        let components = jpeg[at + 9] as usize;
+        (0..components).map(|i| jpeg[at + 11 + 3 * i]).collect()
+    }
+
+    #[test]
+    fn auto_chroma_subsampling_follows_the_quality() {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(16, 16, |x, y| {
+            image::Rgb([(x * 16) as u8, (y * 16) as u8, 90])
+        }));
+        let full = encode_jpeg(&image, 95, ChromaSubsampling::Auto).unwrap();
+        assert_eq!(sof_sampling_factors(&full), [0x11, 0x11, 0x11]);
+        let subsampled = encode_jpeg(&image, 80, ChromaSubsampling::Auto).unwrap();
+        assert_eq!(sof_sampling_factors(&subsampled), [0x22, 0x11, 0x11]);
+        let explicit = encode_jpeg(&image, 95, ChromaSubsampling::Yuv422).unwrap();
+        assert_eq!(sof_sampling_factors(&explicit), [0x21, 0x11, 0x11]);
+    }
+
+    /// Pseudo-random packed RGB8 pixels.
+    fn noise_rgb8(pixel_count: u32) -> Vec<u8> {
+        (0..pixel_count * 3)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect()
+    }
+
+    /// Single-threaded reference for `HistogramAccumulator::add_rgb8`.
+    fn sequential_histogram_counts(pixels: &[u8]) -> [[u32; 256]; 4] {
+        let mut counts = [[0u32; 256]; 4];
+        for pixel in pixels.chunks_exact(3) {
+            let (r, g, b) = (pixel[0] as usize, pixel[1] as usize, pixel[2] as usize);
+            counts[0][r] += 1;
+            counts[1][g] += 1;
+            counts[2][b] += 1;
+            let luma = luma::luma(r as f32, g as f32, b as f32, LumaCoefficients::Rec709);
+            counts[3][(luma.round() as usize).min(255)] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn parallel_histogram_counts_match_a_sequential_pass() {
+        // More than one parallel chunk, with a partial last one.
+        let pixels = noise_rgb8(150_001);
+        let mut accumulator = HistogramAccumulator::default();
+        accumulator.add_rgb8(&pixels);
+        assert_eq!(accumulator.counts, sequential_histogram_counts(&pixels));
+    }
+
+    /// Serial vs parallel histogram timing on a 24 MP frame. Run with
+    /// `cargo test --release --features bench histogram_timing -- --nocapture`.
+    #[cfg(feature = "bench")]
+    #[test]
+    fn histogram_timing() {
+        use std::time::Instant;
+
+        const RUNS: u32 = 5;
+        let pixels = noise_rgb8(6000 * 4000);
+
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            std::hint::black_box(sequential_histogram_counts(&pixels));
+        }
+        let serial = start.elapsed() / RUNS;
+
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            let mut accumulator = HistogramAccumulator::default();
+            accumulator.add_rgb8(&pixels);
+            std::hint::black_box(accumulator.counts);
+        }
+        let parallel = start.elapsed() / RUNS;
+
+        println!(
+            "histogram of 24 MP: serial {serial:?}, parallel {parallel:?} on {} threads",
+            rayon::current_num_threads()
+        );
+    }
+
+    fn radial(distortion: f32) -> RadialDistortion {
+        let params = GeometryParams {
+            distortion,
+            ..GeometryParams::default()
+        };
+        RadialDistortion::new(&params, 200.0, 150.0, 250.0)
+    }
+
+    #[test]
+    fn fill_scale_zooms_just_enough_to_hide_the_border() {
+        let barrel = radial(20.0);
+        let zoom = barrel.fill_scale(400.0, 300.0);
+        // Corners sit at r² = 1, where the slider pushes samples out by 1.5x.
+        assert!(zoom > 0.7 && zoom < 0.85, "{zoom}");
+        let (sx, sy) = barrel.source(200.0 - 200.0 * zoom, 150.0 - 150.0 * zoom);
+        assert!(
+            sx >= 0.0 && sy >= 0.0 && sx < 2.0 && sy < 2.0,
+            "({sx}, {sy})"
+        );
+    }
+
+    #[test]
+    fn fill_scale_leaves_inward_corrections_alone() {
+        assert_eq!(radial(0.0).fill_scale(400.0, 300.0), 1.0);
+        assert_eq!(radial(-20.0).fill_scale(400.0, 300.0), 1.0);
+    }
+
+    #[test]
+    fn banded_histogram_matches_a_single_pass() {
+        // Taller than one band, with a partial last band.
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(7, 600, |x, y| {
+            image::Rgb([(x * 30) as u8, (y % 256) as u8, ((x + y) * 7 % 256) as u8])
+        }));
+        let banded = calculate_histogram_from_image(&image).unwrap();
+
+        let mut single = HistogramAccumulator::default();
+        single.add_rgb8(image.to_rgb8().as_raw());
+        let single = single.finish();
+        assert_eq!(banded.red, single.red);
+        assert_eq!(banded.green, single.green);
+        assert_eq!(banded.blue, single.blue);
+        assert_eq!(banded.luma, single.luma);
+    }
+
+    #[test]
+    fn streamed_pieces_add_up_to_the_whole() {
+        let pixels: Vec<u8> = (0..3000u32).map(|i| (i * 37 % 251) as u8).collect();
+        let mut whole = HistogramAccumulator::default();
+        whole.add_rgb8(&pixels);
+
+        let mut pieces = HistogramAccumulator::default();
+        for piece in [&pixels[..300], &pixels[300..303], &pixels[303..]] {
+            pieces.add_rgb8(piece);
+        }
+        assert_eq!(pieces.counts, whole.counts);
+        assert_eq!(pieces.counts[3].iter().sum::<u32>(), 1000);
+    }
+
+    #[test]
+    fn pieces_split_mid_pixel_add_up_to_the_whole() {
+        let pixels: Vec<u8> = (0..3000u32).map(|i| (i * 37 % 251) as u8).collect();
+        let mut whole = HistogramAccumulator::default();
+        whole.add_rgb8(&pixels);
+
+        let mut pieces = HistogramAccumulator::default();
+        for bounds in [0..301, 301..302, 302..305, 305..2999, 2999..3000] {
+            pieces.add_rgb8(&pixels[bounds]);
+        }
+        assert_eq!(pieces.counts, whole.counts);
+
+        // An incomplete last pixel is never counted.
+        pieces.add_rgb8(&[200, 10]);
+        assert_eq!(pieces.counts, whole.counts);
+    }
+
+    #[test]
+    fn missing_contrast_mode_is_linear() {
+        let mode = |adjustments: serde_json::Value| {
+            get_global_adjustments_from_json(&adjustments, false).contrast_mode
+        };
+        assert_eq!(mode(json!({ "contrast": 20 })), 0);
+        assert_eq!(mode(json!({ "contrastMode": "sCurve" })), 1);
+        assert_eq!(mode(json!({ "contrastMode": "filmic" })), 2);
+        assert_eq!(mode(json!({ "contrastMode": "linear" })), 0);
+    }
+}
@@ -2,7 +2,10 @@ use crate::AppState;
 use fuzzy_matcher::FuzzyMatcher;
 use log;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use tauri::{Manager, State};
 use walkdir::WalkDir;
 
@@ -380,23 +383,14 @@ fn extract_vig_params(vig: &Vignetting) -> (f64, f64, f64) {
     )
 }
 
-pub fn load_lensfun_db(app_handle: &tauri::AppHandle) -> LensDatabase {
+/// Reads and merges every Lensfun XML file under `dir`.
+fn read_lens_xml_dir(dir: &Path) -> LensDatabase {
     let mut combined_db = LensDatabase {
         cameras: Vec::new(),
         lenses: Vec::new(),
     };
 
-    let resource_path = app_handle
-        .path()
-        .resolve("lensfun_db", tauri::path::BaseDirectory::Resource)
-        .expect("failed to resolve lensfun_db directory");
-
-    if !resource_path.exists() {
-        log::error!("Lensfun DB directory not found at: {:?}", resource_path);
-        return combined_db;
-    }
-
-    for entry in WalkDir::new(resource_path)
+    for entry in WalkDir::new(dir)
         .into_iter()
         .filter_map(Result::ok)
         .filter(|e| e.path().extension().map_or(false, |ext| ext == "xml"))
@@ -420,13 +414,110 @@ pub fn load_lensfun_db(app_handle: &tauri::AppHandle) -> LensDatabase {
         }
     }
 
+    combined_db
+}
+
+/// Directory in the app data dir where users can drop their own Lensfun XML
+/// profiles. Created on first use so it is easy to find.
+fn user_lens_profiles_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join("lens_profiles");
+
+    if !dir.exists() {
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    }
+
+    Ok(dir)
+}
+
+fn camera_key(camera: &Camera) -> (String, String) {
+    let first = |names: &[MultiName]| names.first().map(|n| n.value.to_lowercase()).unwrap_or_default();
+    (first(&camera.maker), first(&camera.model))
+}
+
+fn lens_key(lens: &Lens) -> (String, String) {
+    (lens.get_maker().to_lowercase(), lens.get_full_model_name().to_lowercase())
+}
+
+/// Adds the `user` profiles to `bundled`. A user camera or lens replaces any
+/// bundled one with the same maker and model, ignoring case.
+fn merge_user_profiles(bundled: &mut LensDatabase, user: LensDatabase) {
+    let user_cameras: HashSet<_> = user.cameras.iter().map(camera_key).collect();
+    let user_lenses: HashSet<_> = user.lenses.iter().map(lens_key).collect();
+    bundled.cameras.retain(|camera| !user_cameras.contains(&camera_key(camera)));
+    bundled.lenses.retain(|lens| !user_lenses.contains(&lens_key(lens)));
+    bundled.cameras.extend(user.cameras);
+    bundled.lenses.extend(user.lenses);
+}
+
+/// Loads the bundled Lensfun database, then the user profiles from
+/// `user_lens_profiles_dir`, which take precedence.
+pub fn load_lensfun_db(app_handle: &tauri::AppHandle) -> LensDatabase {
+    let resource_path = app_handle
+        .path()
+        .resolve("lensfun_db", tauri::path::BaseDirectory::Resource)
+        .expect("failed to resolve lensfun_db directory");
+
+    let user_dir = match user_lens_profiles_dir(app_handle) {
+        Ok(user_dir) => Some(user_dir),
+        Err(e) => {
+            log::error!("Failed to open the user lens profiles directory: {}", e);
+            None
+        }
+    };
+
+    load_lens_dirs(&resource_path, user_dir.as_deref())
+}
+
+/// Reads the bundled profiles under `bundled_dir`, then merges in the user
+/// profiles under `user_dir`.
+fn load_lens_dirs(bundled_dir: &Path, user_dir: Option<&Path>) -> LensDatabase {
+    let mut combined_db = if bundled_dir.exists() {
+        read_lens_xml_dir(bundled_dir)
+    } else {
+        log::error!("Lensfun DB directory not found at: {:?}", bundled_dir);
+        LensDatabase {
+            cameras: Vec::new(),
+            lenses: Vec::new(),
+        }
+    };
+
+    if let Some(user_dir) = user_dir {
+        let user_db = read_lens_xml_dir(user_dir);
+        log::info!("Loaded {} user lens profiles from {:?}.", user_db.lenses.len(), user_dir);
+        merge_user_profiles(&mut combined_db, user_db);
+    }
+
     log::info!("Loaded {} lenses and {} cameras from Lensfun database.", combined_db.lenses.len(), combined_db.cameras.len());
     combined_db
 }
 
+/// Re-reads the bundled and user Lensfun XML files and swaps the result in,
+/// so added or updated profiles take effect without a restart. The files are
+/// parsed before the lock is taken, so readers only ever see the old or the new
+/// database. Returns the number of lenses loaded.
+#[tauri::command]
+pub fn reload_lens_db(app_handle: tauri::AppHandle, state: State<AppState>) -> Result<usize, String> {
+    Ok(swap_lens_db(&state.lens_db, load_lensfun_db(&app_handle)))
+}
+
+/// Replaces the loaded database with `db` and returns its lens count.
+fn swap_lens_db(lens_db: &Mutex<Option<LensDatabase>>, db: LensDatabase) -> usize {
+    let lens_count = db.lenses.len();
+    *lens_db.lock().unwrap() = Some(db);
+    lens_count
+}
+
 #[tauri::command]
 pub fn get_lensfun_makers(state: State<AppState>) -> Result<Vec<String>, String> {
-    if let Some(db) = &*state.lens_db.lock().unwrap() {
+    lens_makers(&state.lens_db)
+}
+
+fn lens_makers(lens_db: &Mutex<Option<LensDatabase>>) -> Result<Vec<String>, String> {
+    if let Some(db) = &*lens_db.lock().unwrap() {
         let mut makers: Vec<String> = db
             .lenses
             .iter()
@@ -456,6 +547,60 @@ pub fn get_lensfun_lenses_for_maker(maker: String, state: State<AppState>) -> Re
     }
 }
 
+/// Finds the database lens best matching an EXIF lens `model` from `maker`,
+/// first among that maker's lenses and then across all makers. With `mount`,
+/// only lenses for that mount are considered.
+fn detect_lens<'a>(db: &'a LensDatabase, maker: &str, model: &str, mount: Option<&str>) -> Option<&'a Lens> {
+    let on_mount = |lens: &&Lens| mount.map_or(true, |mount| lens.mount.iter().any(|m| m == mount));
+    let matcher = fuzzy_matcher::skim::SkimMatcherV2::default().ignore_case();
+
+    log::info!("[Attempt 1] Searching for lenses from maker: '{}'", maker);
+
+    let best_match = db
+        .lenses
+        .iter()
+        .filter(on_mount)
+        .filter(|lens| lens.get_maker().eq_ignore_ascii_case(maker))
+        .filter_map(|lens| {
+            let lens_name = lens.get_full_model_name();
+            matcher.fuzzy_match(&lens_name, model).map(|score| {
+                let length_penalty = (lens_name.len() as i64 - model.len() as i64).max(0) / 2;
+                let adjusted_score = score - length_penalty;
+                (adjusted_score, lens)
+            })
+        })
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, lens)| lens);
+
+    if let Some(lens) = best_match {
+        log::info!("[Attempt 1] Success! Found best match: '{} {}'", lens.get_maker(), lens.get_name());
+        return Some(lens);
+    }
+
+    log::warn!("[Attempt 1] Failed. Could not find a match for model '{}' from maker '{}'.", model, maker);
+    log::info!("[Attempt 2] Falling back to searching model name against ALL lens makers.");
+
+    let best_match_fallback = db
+        .lenses
+        .iter()
+        .filter(on_mount)
+        .filter_map(|lens| {
+            matcher.fuzzy_match(&lens.get_full_model_name(), model)
+                .map(|score| (score, lens))
+        })
+        .max_by_key(|(score, _): &(i64, _)| *score)
+        .map(|(score, lens)| {
+            log::info!("[Attempt 2] Found best fallback match with score {}: '{} {}'", score, lens.get_maker(), lens.get_name());
+            lens
+        });
+
+    if best_match_fallback.is_none() {
+        log::warn!("[Attempt 2] Fallback failed. No suitable lens found in the entire database.");
+    }
+
+    best_match_fallback
+}
+
 #[tauri::command]
 pub fn autodetect_lens(maker: String, model: String, state: State<AppState>) -> Result<Option<(String, String)>, String> {
     let clean_maker = maker.trim().trim_matches('"').to_string();
@@ -464,62 +609,72 @@ pub fn autodetect_lens(maker: String, model: String, state: State<AppState>) ->
     log::info!("Attempting to autodetect lens. Cleaned Maker: '{}', Cleaned Model: '{}'", clean_maker, clean_model);
 
     if let Some(db) = &*state.lens_db.lock().unwrap() {
-        let matcher = fuzzy_matcher::skim::SkimMatcherV2::default().ignore_case();
+        Ok(detect_lens(db, &clean_maker, &clean_model, None).map(|lens| (lens.get_maker(), lens.get_name())))
+    } else {
+        log::warn!("Lens database not loaded. Cannot perform autodetect.");
+        Ok(None)
+    }
+}
 
-        log::info!("[Attempt 1] Searching for lenses from maker: '{}'", clean_maker);
+/// The database camera whose maker and model match the EXIF `maker` and
+/// `model`, ignoring case and the quotes EXIF display values carry.
+pub fn find_camera<'a>(db: &'a LensDatabase, maker: &str, model: &str) -> Option<&'a Camera> {
+    let (maker, model) = (maker.trim().trim_matches('"'), model.trim().trim_matches('"'));
+    db.cameras.iter().find(|camera| {
+        camera.maker.iter().any(|m| m.value.eq_ignore_ascii_case(maker))
+            && camera.model.iter().any(|m| m.value.eq_ignore_ascii_case(model))
+    })
+}
 
-        let lenses_from_maker: Vec<_> = db
-            .lenses
-            .iter()
-            .filter(|lens| lens.get_maker().eq_ignore_ascii_case(&clean_maker))
-            .collect();
+/// Which corrections the detected lens has calibration data for. All flags
+/// are false when no lens was detected.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LensAvailability {
+    pub lens_maker: Option<String>,
+    pub lens_name: Option<String>,
+    pub distortion: bool,
+    pub tca: bool,
+    pub vignetting: bool,
+}
 
-        if !lenses_from_maker.is_empty() {
-            let best_match = lenses_from_maker
-                .into_iter()
-                .filter_map(|lens| {
-                    let lens_name = lens.get_full_model_name(); 
-                    matcher.fuzzy_match(&lens_name, &clean_model).map(|score| {
-                        let length_penalty = (lens_name.len() as i64 - clean_model.len() as i64).max(0) / 2;
-                        let adjusted_score = score - length_penalty;
-                        (adjusted_score, lens)
-                    })
-                })
-                .max_by_key(|(score, _)| *score)
-                .map(|(_, lens)| (lens.get_maker(), lens.get_name()));
-
-            if best_match.is_some() {
-                log::info!("[Attempt 1] Success! Found best match: {:?}", best_match);
-                return Ok(best_match);
-            }
+impl LensAvailability {
+    pub fn for_lens(lens: &Lens) -> Self {
+        let elements = lens.calibration.as_ref().map_or(&[][..], |cal| &cal.elements[..]);
+        let has = |f: fn(&CalibrationElement) -> bool| elements.iter().any(f);
+        LensAvailability {
+            lens_maker: Some(lens.get_maker()),
+            lens_name: Some(lens.get_name()),
+            distortion: has(|e| matches!(e, CalibrationElement::Distortion(_))),
+            tca: has(|e| matches!(e, CalibrationElement::Tca(_))),
+            vignetting: has(|e| matches!(e, CalibrationElement::Vignetting(_))),
         }
+    }
+}
 
-        log::warn!("[Attempt 1] Failed. Could not find a match for model '{}' from maker '{}'.", clean_model, clean_maker);
-        log::info!("[Attempt 2] Falling back to searching model name against ALL lens makers.");
+/// Reports per correction whether the Lensfun profile matching a photo's
+/// `lens` exists. `maker` and `model` are the camera's EXIF make and model;
+/// the lens is searched among `maker`'s lenses first, like
+/// `autodetect_lens`, and lenses on the camera's mount are preferred when
+/// the database knows the camera.
+#[tauri::command]
+pub fn lens_correction_available(maker: String, model: String, lens: String, state: State<AppState>) -> Result<LensAvailability, String> {
+    let clean_maker = maker.trim().trim_matches('"');
+    let clean_model = model.trim().trim_matches('"');
+    let clean_lens = lens.trim().trim_matches('"');
+
+    let guard = state.lens_db.lock().unwrap();
+    let Some(db) = &*guard else {
+        return Err("Lens database not loaded".to_string());
+    };
 
-        let best_match_fallback = db
-            .lenses
-            .iter()
-            .filter_map(|lens| {
-                matcher.fuzzy_match(&lens.get_full_model_name(), &clean_model)
-                    .map(|score| (score, lens))
-            })
-            .max_by_key(|(score, _): &(i64, _)| *score)
-            .map(|(score, lens)| {
-                log::info!("[Attempt 2] Found best fallback match with score {}: '{} {}'", score, lens.get_maker(), lens.get_name());
-                (lens.get_maker(), lens.get_name())
-            });
-        
-        if best_match_fallback.is_none() {
-            log::warn!("[Attempt 2] Fallback failed. No suitable lens found in the entire database.");
-        }
-        
-        Ok(best_match_fallback)
+    let camera_mount = find_camera(db, clean_maker, clean_model).map(|camera| camera.mount.as_str());
 
-    } else {
-        log::warn!("Lens database not loaded. Cannot perform autodetect.");
-        Ok(None)
-    }
+    let detected = camera_mount
+        .and_then(|mount| detect_lens(db, clean_maker, clean_lens, Some(mount)))
+        .or_else(|| detect_lens(db, clean_maker, clean_lens, None));
+
+    Ok(detected.map(LensAvailability::for_lens).unwrap_or_default())
 }
 
 #[tauri::command]
@@ -537,4 +692,191 @@ pub fn get_lens_distortion_params(
         }
     }
     Ok(None)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_DB: &str = r#"<lensdatabase>
+    <camera>
+        <maker>Fujifilm</maker>
+        <model>X-T4</model>
+        <mount>Fujifilm X</mount>
+        <cropfactor>1.53</cropfactor>
+    </camera>
+    <lens>
+        <maker>Fujifilm</maker>
+        <model>XF 23mm f/2 R WR</model>
+        <mount>Fujifilm X</mount>
+        <cropfactor>1.53</cropfactor>
+        <calibration>
+            <distortion model="ptlens" focal="23" a="0.01" b="-0.03" c="0.02"/>
+            <vignetting model="pa" focal="23" aperture="2" distance="1000" k1="-0.4" k2="0.1" k3="0"/>
+        </calibration>
+    </lens>
+    <lens>
+        <maker>Fujifilm</maker>
+        <model>XF 23mm f/2 R WR</model>
+        <mount>Canon EF</mount>
+        <cropfactor>1.0</cropfactor>
+        <calibration>
+            <tca model="poly3" focal="23" vr="1.0002" vb="0.9998"/>
+        </calibration>
+    </lens>
+</lensdatabase>"#;
+
+    fn test_db() -> LensDatabase {
+        quick_xml::de::from_str(TEST_DB).unwrap()
+    }
+
+    #[test]
+    fn availability_lists_the_calibrated_corrections() {
+        let db = test_db();
+        let camera = find_camera(&db, "\"FUJIFILM\"", " x-t4 ").unwrap();
+        let lens = detect_lens(
+            &db,
+            "Fujifilm",
+            "XF23mmF2 R WR",
+            Some(camera.mount.as_str()),
+        )
+        .unwrap();
+        assert_eq!(
+            LensAvailability::for_lens(lens),
+            LensAvailability {
+                lens_maker: Some("Fujifilm".to_string()),
+                lens_name: Some("XF 23mm f/2 R WR".to_string()),
+                distortion: true,
+                tca: false,
+                vignetting: true,
+            }
+        );
+
+        let adapted = detect_lens(&db, "Fujifilm", "XF23mmF2 R WR", Some("Canon EF")).unwrap();
+        assert!(LensAvailability::for_lens(adapted).tca);
+        assert!(find_camera(&db, "Fujifilm", "X-T5").is_none());
+        assert_eq!(LensAvailability::default().lens_name, None);
+    }
+
+    #[test]
+    fn reloading_swaps_new_user_profiles_into_the_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let (bundled, user) = (dir.path().join("bundled"), dir.path().join("user"));
+        fs::create_dir_all(&bundled).unwrap();
+        fs::create_dir_all(&user).unwrap();
+        fs::write(bundled.join("fujifilm.xml"), TEST_DB).unwrap();
+        fs::write(bundled.join("notes.txt"), "not a profile").unwrap();
+        let lens_db = Mutex::new(None);
+        assert!(lens_makers(&lens_db).is_err());
+        assert_eq!(swap_lens_db(&lens_db, load_lens_dirs(&bundled, Some(&user))), 2);
+        assert_eq!(lens_makers(&lens_db).unwrap(), ["Fujifilm"]);
+
+        let extra = r#"<lensdatabase>
+    <lens>
+        <maker>Sigma</maker>
+        <model>18-50mm F2.8 DC DN</model>
+        <mount>Fujifilm X</mount>
+        <cropfactor>1.53</cropfactor>
+    </lens>
+</lensdatabase>"#;
+        fs::write(user.join("sigma.xml"), extra).unwrap();
+        fs::write(user.join("broken.xml"), "<lensdatabase><lens>").unwrap();
+        assert_eq!(swap_lens_db(&lens_db, load_lens_dirs(&bundled, Some(&user))), 3);
+        assert_eq!(lens_makers(&lens_db).unwrap(), ["Fujifilm", "Sigma"]);
+        let guard = lens_db.lock().unwrap();
+        assert_eq!(guard.as_ref().unwrap().cameras.len(), 1);
+    }
+
+    #[test]
+    fn user_profiles_replace_bundled_ones_ignoring_case() {
+        let mut bundled = test_db();
+        let user: LensDatabase = quick_xml::de::from_str(
+            r#"<lensdatabase>
+    <camera>
+        <maker>FUJIFILM</maker>
+        <model>x-t4</model>
+        <mount>Fujifilm X</mount>
+        <cropfactor>1.5</cropfactor>
+    </camera>
+    <lens>
+        <maker>fujifilm</maker>
+        <model>xf 23mm f/2 r wr</model>
+        <mount>Fujifilm X</mount>
+        <cropfactor>1.53</cropfactor>
+    </lens>
+    <lens>
+        <maker>Sigma</maker>
+        <model>18-50mm F2.8 DC DN</model>
+        <mount>Fujifilm X</mount>
+        <cropfactor>1.53</cropfactor>
+    </lens>
+</lensdatabase>"#,
+        )
+        .unwrap();
+
+        merge_user_profiles(&mut bundled, user);
+
+        assert_eq!(bundled.cameras.len(), 1);
+        assert_eq!(bundled.cameras[0].maker[0].value, "FUJIFILM");
+        // Both bundled mounts of the 23mm share its maker and model, so both go.
+        let models: Vec<String> = bundled
+            .lenses
+            .iter()
+            .map(Lens::get_full_model_name)
+            .collect();
+        assert_eq!(models.len(), 2);
+        assert!(models.contains(&"xf 23mm f/2 r wr".to_string()));
+        assert!(!models.contains(&"XF 23mm f/2 R WR".to_string()));
+    }
+
+    #[test]
+    fn a_manual_k1_matching_the_profile_warps_identically() {
+        let db: LensDatabase = quick_xml::de::from_str(
+            r#"<lensdatabase>
+    <lens>
+        <maker>Fujifilm</maker>
+        <model>XF 35mm f/2 R WR</model>
+        <mount>Fujifilm X</mount>
+        <cropfactor>1.53</cropfactor>
+        <calibration>
+            <distortion model="poly3" focal="35" k1="-0.02"/>
+        </calibration>
+    </lens>
+</lensdatabase>"#,
+        )
+        .unwrap();
+        let profile = db.lenses[0]
+            .get_distortion_params(35.0, None, None)
+            .unwrap();
+        let from_profile = serde_json::json!({
+            "lensMaker": "Fujifilm",
+            "lensModel": "XF 35mm f/2 R WR",
+            "lensDistortionParams": profile,
+        });
+        // What the modal sends after only the k1 slider moved from identity.
+        let manual = serde_json::json!({
+            "lensMaker": null,
+            "lensModel": null,
+            "lensDistortionParams": {
+                "k1": -0.02, "k2": 0.0, "k3": 0.0,
+                "model": 0,
+                "tca_vr": 1.0, "tca_vb": 1.0,
+                "vig_k1": 0.0, "vig_k2": 0.0, "vig_k3": 0.0,
+            },
+        });
+
+        let image =
+            image::DynamicImage::ImageRgb32F(image::Rgb32FImage::from_fn(48, 32, |x, y| {
+                image::Rgb([x as f32 / 47.0, y as f32 / 31.0, 0.5])
+            }));
+        let warped =
+            crate::image_processing::apply_geometry_warp(&image, &from_profile).to_rgb32f();
+        assert_ne!(warped.as_raw(), image.to_rgb32f().as_raw());
+        assert_eq!(
+            crate::image_processing::apply_geometry_warp(&image, &manual)
+                .to_rgb32f()
+                .as_raw(),
+            warped.as_raw()
+        );
+    }
+}
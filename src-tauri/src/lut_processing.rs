@@ -1,117 +1,15 @@
 use anyhow::{Result, anyhow};
 use image::{DynamicImage, GenericImageView};
+use rapidraw_core::lut::{self, Lut3d};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 
-#[derive(Debug, Clone)]
-pub struct Lut {
-    pub size: u32,
-    pub data: Vec<f32>,
+fn parse_cube(path: &Path) -> Result<Lut3d> {
+    lut::parse_cube(&std::fs::read_to_string(path)?)
 }
 
-fn parse_cube(path: &Path) -> Result<Lut> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-
-    let mut size: Option<u32> = None;
-    let mut data: Vec<f32> = Vec::new();
-    let mut line_num = 0;
-
-    for line in reader.lines() {
-        line_num += 1;
-        let line = line?;
-        let trimmed = line.trim();
-
-        if trimmed.is_empty() || trimmed.starts_with('#') {
-            continue;
-        }
-
-        let parts: Vec<&str> = trimmed.split_whitespace().collect();
-        if parts.is_empty() {
-            continue;
-        }
-
-        match parts[0].to_uppercase().as_str() {
-            "TITLE" | "DOMAIN_MIN" | "DOMAIN_MAX" => continue,
-
-            "LUT_3D_SIZE" => {
-                if parts.len() < 2 {
-                    return Err(anyhow!(
-                        "Malformed LUT_3D_SIZE on line {}: '{}'",
-                        line_num,
-                        line
-                    ));
-                }
-                size = Some(parts[1].parse().map_err(|e| {
-                    anyhow!(
-                        "Failed to parse LUT_3D_SIZE on line {}: '{}'. Error: {}",
-                        line_num,
-                        line,
-                        e
-                    )
-                })?);
-            }
-            _ => {
-                if size.is_some() {
-                    if parts.len() < 3 {
-                        return Err(anyhow!(
-                            "Invalid data line on line {}: '{}'. Expected 3 float values, found {}",
-                            line_num,
-                            line,
-                            parts.len()
-                        ));
-                    }
-                    let r: f32 = parts[0].parse().map_err(|e| {
-                        anyhow!(
-                            "Failed to parse R value on line {}: '{}'. Error: {}",
-                            line_num,
-                            line,
-                            e
-                        )
-                    })?;
-                    let g: f32 = parts[1].parse().map_err(|e| {
-                        anyhow!(
-                            "Failed to parse G value on line {}: '{}'. Error: {}",
-                            line_num,
-                            line,
-                            e
-                        )
-                    })?;
-                    let b: f32 = parts[2].parse().map_err(|e| {
-                        anyhow!(
-                            "Failed to parse B value on line {}: '{}'. Error: {}",
-                            line_num,
-                            line,
-                            e
-                        )
-                    })?;
-                    data.push(r);
-                    data.push(g);
-                    data.push(b);
-                }
-            }
-        }
-    }
-
-    let lut_size = size.ok_or(anyhow!("LUT_3D_SIZE not found in .cube file"))?;
-    let expected_len = (lut_size * lut_size * lut_size * 3) as usize;
-    if data.len() != expected_len {
-        return Err(anyhow!(
-            "LUT data size mismatch. Expected {} float values (for size {}), but found {}. The file may be corrupt or incomplete.",
-            expected_len,
-            lut_size,
-            data.len()
-        ));
-    }
-
-    Ok(Lut {
-        size: lut_size,
-        data,
-    })
-}
-
-fn parse_3dl(path: &Path) -> Result<Lut> {
+fn parse_3dl(path: &Path) -> Result<Lut3d> {
     let file = File::open(path)?;
     let reader = BufReader::new(file);
     let mut data: Vec<f32> = Vec::new();
@@ -147,10 +45,10 @@ fn parse_3dl(path: &Path) -> Result<Lut> {
         ));
     }
 
-    Ok(Lut { size, data })
+    Ok(Lut3d::new(size as usize, data))
 }
 
-fn parse_hald(image: DynamicImage) -> Result<Lut> {
+fn parse_hald(image: DynamicImage) -> Result<Lut3d> {
     let (width, height) = image.dimensions();
     if width != height {
         return Err(anyhow!(
@@ -179,10 +77,10 @@ fn parse_hald(image: DynamicImage) -> Result<Lut> {
         data.push(pixel[2] as f32 / 255.0);
     }
 
-    Ok(Lut { size, data })
+    Ok(Lut3d::new(size as usize, data))
 }
 
-pub fn parse_lut_file(path_str: &str) -> Result<Lut> {
+pub fn parse_lut_file(path_str: &str) -> Result<Lut3d> {
     let path = Path::new(path_str);
     let extension = path
         .extension()
@@ -200,3 +98,35 @@ pub fn parse_lut_file(path_str: &str) -> Result<Lut> {
         _ => Err(anyhow!("Unsupported LUT file format: {}", extension)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_domain_is_kept_and_matches_the_wasm_pixels() {
+        // The same file and pixels as the WASM `color_grading` test; the
+        // shader maps the domain onto the lattice the way `Lut3d::sample` does.
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../crates/rapidraw-core/testdata/domain.cube"
+        );
+        let lut = parse_lut_file(path).unwrap();
+        assert_eq!(lut.domain_min, [0.1, 0.0, 0.0]);
+        assert_eq!(lut.domain_max, [0.9, 1.0, 2.0]);
+
+        let cases = [
+            ([0.0, 0.0, 0.0], [0.05, 0.0, 0.0]),
+            ([0.05, 0.5, 0.9], [0.1, 0.475, 0.2545]),
+            ([0.5, 0.25, 0.75], [0.525, 0.2475, 0.2121]),
+            ([0.8, 0.9, 0.1], [0.89, 0.8725, 0.0283]),
+            ([1.0, 1.0, 1.0], [1.0, 0.97, 0.2828]),
+        ];
+        for (input, expected) in cases {
+            let out = lut.apply(input, 1.0);
+            for c in 0..3 {
+                assert!((out[c] - expected[c]).abs() < 1e-3, "{input:?} -> {out:?}");
+            }
+        }
+    }
+}
@@ -69,14 +69,15 @@ use crate::file_management::{
 };
 use crate::formats::is_raw_file;
 use crate::image_loader::{
-    composite_patches_on_image, load_and_composite, load_base_image_from_bytes,
+    BaseImage, composite_patches_on_image, load_and_composite, load_base_image_from_bytes_oriented,
 };
 use crate::image_processing::{
     Crop, GpuContext, ImageMetadata, apply_coarse_rotation, apply_crop, apply_flip, apply_rotation,
     get_all_adjustments_from_json, get_or_init_gpu_context, process_and_get_dynamic_image, apply_unwarp_geometry,
-    downscale_f32_image, apply_cpu_default_raw_processing, GeometryParams, warp_image_geometry, apply_geometry_warp
+    downscale_f32_image, apply_cpu_default_raw_processing, GeometryParams, warp_image_geometry, apply_geometry_warp,
+    ChromaSubsampling, encode_jpeg,
 };
-use crate::lut_processing::Lut;
+use rapidraw_core::lut::Lut3d;
 use crate::mask_generation::{AiPatchDefinition, MaskDefinition, generate_mask_bitmap};
 use tagging_utils::{candidates, hierarchy};
 
@@ -127,15 +128,17 @@ pub struct AppState {
     panorama_result: Arc<Mutex<Option<DynamicImage>>>,
     denoise_result: Arc<Mutex<Option<DynamicImage>>>,
     indexing_task_handle: Mutex<Option<JoinHandle<()>>>,
-    pub lut_cache: Mutex<HashMap<String, Arc<Lut>>>,
+    pub lut_cache: Mutex<HashMap<String, Arc<Lut3d>>>,
     initial_file_path: Mutex<Option<String>>,
     thumbnail_cancellation_token: Arc<AtomicBool>,
+    raw_batch_cancellation_token: Arc<AtomicBool>,
     preview_worker_tx: Mutex<Option<Sender<PreviewJob>>>,
     pub mask_cache: Mutex<HashMap<u64, GrayImage>>,
     pub patch_cache: Mutex<HashMap<String, serde_json::Value>>,
     pub geometry_cache: Mutex<HashMap<u64, DynamicImage>>, 
-    pub thumbnail_geometry_cache: Mutex<HashMap<String, (u64, DynamicImage, f32)>>,
+    pub thumbnail_geometry_cache: Mutex<HashMap<String, (u64, DynamicImage, f32, bool)>>,
     pub lens_db: Mutex<Option<lens_correction::LensDatabase>>,
+    pub ai_source_ids: ai_connector::SourceIdCache,
     pub load_image_generation: Arc<AtomicUsize>,
 }
 
@@ -146,6 +149,12 @@ struct LoadImageResult {
     metadata: ImageMetadata,
     exif: HashMap<String, String>,
     is_raw: bool,
+    /// EXIF orientation value (1-8) of the file; already applied to the
+    /// pixels unless the image was loaded with `auto_orient` false.
+    orientation: u16,
+    /// The RAW could not be developed and the embedded camera preview is
+    /// shown instead.
+    degraded: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -169,6 +178,8 @@ struct ResizeOptions {
 #[serde(rename_all = "camelCase")]
 struct ExportSettings {
     jpeg_quality: u8,
+    #[serde(default)]
+    chroma_subsampling: ChromaSubsampling,
     resize: Option<ResizeOptions>,
     keep_metadata: bool,
     strip_gps: bool,
@@ -176,6 +187,71 @@ struct ExportSettings {
     watermark: Option<WatermarkSettings>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+enum MetadataPolicy {
+    #[default]
+    Keep,
+    KeepWithoutGps,
+    Strip,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ExportJob {
+    input_path: String,
+    adjustments: Value,
+    output_path: String,
+    output_format: Option<String>,
+    #[serde(default = "default_export_quality")]
+    quality: u8,
+    #[serde(default)]
+    metadata_policy: MetadataPolicy,
+    #[serde(default)]
+    chroma_subsampling: ChromaSubsampling,
+}
+
+fn default_export_quality() -> u8 {
+    90
+}
+
+impl ExportJob {
+    /// The requested format, else the output path's extension, else JPEG.
+    fn resolved_output_format(&self) -> String {
+        self.output_format
+            .clone()
+            .or_else(|| {
+                Path::new(&self.output_path)
+                    .extension()
+                    .and_then(|s| s.to_str())
+                    .map(|s| s.to_string())
+            })
+            .unwrap_or_else(|| "jpg".to_string())
+            .to_lowercase()
+    }
+
+    /// Per-job exports keep the full size and carry no watermark.
+    fn export_settings(&self) -> ExportSettings {
+        ExportSettings {
+            jpeg_quality: self.quality,
+            chroma_subsampling: self.chroma_subsampling,
+            resize: None,
+            keep_metadata: !matches!(self.metadata_policy, MetadataPolicy::Strip),
+            strip_gps: matches!(self.metadata_policy, MetadataPolicy::KeepWithoutGps),
+            filename_template: None,
+            watermark: None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ExportJobResult {
+    input_path: String,
+    output_path: String,
+    error: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CommunityPreset {
     pub name: String,
@@ -477,7 +553,7 @@ fn encode_to_base64_png(image: &GrayImage) -> Result<String, String> {
     Ok(format!("data:image/png;base64,{}", base64_str))
 }
 
-fn get_or_load_lut(state: &tauri::State<AppState>, path: &str) -> Result<Arc<Lut>, String> {
+fn get_or_load_lut(state: &tauri::State<AppState>, path: &str) -> Result<Arc<Lut3d>, String> {
     let mut cache = state.lut_cache.lock().unwrap();
     if let Some(lut) = cache.get(path) {
         return Ok(lut.clone());
@@ -492,6 +568,7 @@ fn get_or_load_lut(state: &tauri::State<AppState>, path: &str) -> Result<Arc<Lut
 #[tauri::command]
 async fn load_image(
     path: String,
+    auto_orient: Option<bool>,
     state: tauri::State<'_, AppState>,
     app_handle: tauri::AppHandle,
 ) -> Result<LoadImageResult, String> {
@@ -513,24 +590,31 @@ async fn load_image(
     let highlight_compression = settings.raw_highlight_compression.unwrap_or(2.5);
 
     let path_clone = source_path_str.clone();
+    let auto_orient = auto_orient.unwrap_or(true);
 
-    let (pristine_img, exif_data) = tokio::task::spawn_blocking(move || {
+    let (base, mut exif_data) = tokio::task::spawn_blocking(move || {
         if generation_tracker.load(Ordering::SeqCst) != my_generation {
             return Err("Load cancelled".to_string());
         }
 
-        let result: Result<(DynamicImage, HashMap<String, String>), String> = (|| {
+        let result: Result<(BaseImage, HashMap<String, String>), String> = (|| {
             match read_file_mapped(Path::new(&path_clone)) {
                 Ok(mmap) => {
                     if generation_tracker.load(Ordering::SeqCst) != my_generation {
                         return Err("Load cancelled".to_string());
                     }
 
-                    let img =
-                        load_base_image_from_bytes(&mmap, &path_clone, false, highlight_compression, cancel_token.clone())
-                            .map_err(|e| e.to_string())?;
+                    let base = load_base_image_from_bytes_oriented(
+                        &mmap,
+                        &path_clone,
+                        false,
+                        highlight_compression,
+                        cancel_token.clone(),
+                        auto_orient,
+                    )
+                    .map_err(|e| e.to_string())?;
                     let exif = exif_processing::read_exif_data(&path_clone, &mmap);
-                    Ok((img, exif))
+                    Ok((base, exif))
                 }
                 Err(e) => {
                     log::warn!(
@@ -546,16 +630,17 @@ async fn load_image(
                         return Err("Load cancelled".to_string());
                     }
 
-                    let img = load_base_image_from_bytes(
+                    let base = load_base_image_from_bytes_oriented(
                         &bytes,
                         &path_clone,
                         false,
                         highlight_compression,
-                        cancel_token.clone()
+                        cancel_token.clone(),
+                        auto_orient,
                     )
                     .map_err(|e| e.to_string())?;
                     let exif = exif_processing::read_exif_data(&path_clone, &bytes);
-                    Ok((img, exif))
+                    Ok((base, exif))
                 }
             }
         })();
@@ -568,12 +653,29 @@ async fn load_image(
         return Err("Load cancelled".to_string());
     }
 
-    let is_raw = is_raw_file(&source_path_str);
+    let is_raw = base.is_raw(&source_path_str);
+    let BaseImage {
+        image: pristine_img,
+        orientation,
+        degraded,
+    } = base;
 
     if state.load_image_generation.load(Ordering::SeqCst) != my_generation {
         return Err("Load cancelled".to_string());
     }
 
+    let crop_factor = match (exif_data.get("Make"), exif_data.get("Model")) {
+        (Some(make), Some(model)) => state
+            .lens_db
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|db| lens_correction::find_camera(db, make, model))
+            .map(|camera| camera.cropfactor),
+        _ => None,
+    };
+    exif_processing::insert_35mm_equivalent(&mut exif_data, crop_factor);
+
     let (orig_width, orig_height) = pristine_img.dimensions();
 
     *state.cached_preview.lock().unwrap() = None;
@@ -594,6 +696,8 @@ async fn load_image(
         metadata,
         exif: exif_data,
         is_raw,
+        orientation,
+        degraded,
     })
 }
 
@@ -605,6 +709,17 @@ fn get_image_dimensions(path: String) -> Result<ImageDimensions, String> {
         .map_err(|e| e.to_string())
 }
 
+/// Whether the RAW at `path` has a decoder, without developing it, so the UI
+/// can grey out unsupported camera bodies.
+#[tauri::command]
+fn can_decode_raw(path: String) -> bool {
+    let (source_path, _) = parse_virtual_path(&path);
+    match read_file_mapped(&source_path) {
+        Ok(mmap) => raw_processing::can_decode_raw(&mmap),
+        Err(_) => fs::read(&source_path).map_or(false, |bytes| raw_processing::can_decode_raw(&bytes)),
+    }
+}
+
 #[tauri::command]
 fn cancel_thumbnail_generation(state: tauri::State<AppState>) -> Result<(), String> {
     state
@@ -1436,17 +1551,14 @@ fn encode_image_to_bytes(
     image: &DynamicImage,
     output_format: &str,
     jpeg_quality: u8,
+    chroma_subsampling: ChromaSubsampling,
 ) -> Result<Vec<u8>, String> {
     let mut image_bytes = Vec::new();
     let mut cursor = Cursor::new(&mut image_bytes);
 
     match output_format.to_lowercase().as_str() {
         "jpg" | "jpeg" => {
-            let rgb_image = image.to_rgb8();
-            let encoder = JpegEncoder::new_with_quality(&mut cursor, jpeg_quality);
-            rgb_image
-                .write_with_encoder(encoder)
-                .map_err(|e| e.to_string())?;
+            return encode_jpeg(image, jpeg_quality, chroma_subsampling).map_err(|e| e.to_string());
         }
         "png" => {
             let image_to_encode = if image.as_rgb32f().is_some() {
@@ -1513,7 +1625,12 @@ async fn export_image(
                 .to_lowercase();
 
             let mut image_bytes =
-                encode_image_to_bytes(&final_image, &extension, export_settings.jpeg_quality)?;
+                encode_image_to_bytes(
+                    &final_image,
+                    &extension,
+                    export_settings.jpeg_quality,
+                    export_settings.chroma_subsampling,
+                )?;
 
             exif_processing::write_image_with_metadata(
                 &mut image_bytes,
@@ -1622,49 +1739,14 @@ async fn batch_export_images(
                         } else {
                             ImageMetadata::default()
                         };
-                        let mut js_adjustments = metadata.adjustments; 
-                        hydrate_adjustments(&state, &mut js_adjustments);
-                        let is_raw = is_raw_file(&source_path_str);
-
-                        let base_image = match read_file_mapped(Path::new(&source_path_str)) {
-                            Ok(mmap) => load_and_composite(
-                                &mmap,
-                                &source_path_str,
-                                &js_adjustments,
-                                false,
-                                highlight_compression,
-                                None,
-                            )
-                            .map_err(|e| format!("Failed to load image from mmap: {}", e))?,
-                            Err(e) => {
-                                log::warn!(
-                                    "Failed to memory-map file '{}': {}. Falling back to standard read.",
-                                    source_path_str,
-                                    e
-                                );
-                                let bytes = fs::read(&source_path_str).map_err(|io_err| {
-                                    format!("Fallback read failed for {}: {}", source_path_str, io_err)
-                                })?;
-                                load_and_composite(
-                                    &bytes,
-                                    &source_path_str,
-                                    &js_adjustments,
-                                    false,
-                                    highlight_compression,
-                                    None,
-                                )
-                                .map_err(|e| format!("Failed to load image from bytes: {}", e))?
-                            }
-                        };
-
-                        let final_image = process_image_for_export(
+                        let image_bytes = export_image_to_bytes(
                             &source_path_str,
-                            &base_image,
-                            &js_adjustments,
+                            metadata.adjustments,
                             &export_settings,
+                            &output_format,
                             &context,
                             &state,
-                            is_raw,
+                            highlight_compression,
                         )?;
 
                         let original_path = std::path::Path::new(&source_path_str);
@@ -1684,20 +1766,6 @@ async fn batch_export_images(
                         let new_filename = format!("{}.{}", new_stem, output_format);
                         let output_path = output_folder_path.join(new_filename);
 
-                        let mut image_bytes = encode_image_to_bytes(
-                            &final_image,
-                            &output_format,
-                            export_settings.jpeg_quality,
-                        )?;
-
-                        exif_processing::write_image_with_metadata(
-                            &mut image_bytes,
-                            &source_path_str,
-                            &output_format,
-                            export_settings.keep_metadata,
-                            export_settings.strip_gps,
-                        )?;
-
                         fs::write(&output_path, image_bytes)
                             .map_err(|e| format!("Failed to write output: {}", e))?;
 
@@ -1742,6 +1810,217 @@ async fn batch_export_images(
     Ok(())
 }
 
+/// Loads, renders and encodes one export, carrying over the source metadata
+/// the settings allow. Shared by `batch_export_images` and `export_batch`.
+fn export_image_to_bytes(
+    source_path_str: &str,
+    mut js_adjustments: Value,
+    export_settings: &ExportSettings,
+    output_format: &str,
+    context: &GpuContext,
+    state: &tauri::State<AppState>,
+    highlight_compression: f32,
+) -> Result<Vec<u8>, String> {
+    hydrate_adjustments(state, &mut js_adjustments);
+    let is_raw = is_raw_file(source_path_str);
+
+    let base_image = match read_file_mapped(Path::new(source_path_str)) {
+        Ok(mmap) => load_and_composite(
+            &mmap,
+            source_path_str,
+            &js_adjustments,
+            false,
+            highlight_compression,
+            None,
+        ),
+        Err(e) => {
+            log::warn!(
+                "Failed to memory-map file '{}': {}. Falling back to standard read.",
+                source_path_str,
+                e
+            );
+            let bytes = fs::read(source_path_str).map_err(|io_err| {
+                format!("Fallback read failed for {}: {}", source_path_str, io_err)
+            })?;
+            load_and_composite(
+                &bytes,
+                source_path_str,
+                &js_adjustments,
+                false,
+                highlight_compression,
+                None,
+            )
+        }
+    }
+    .and_then(|base| base.into_export_image(source_path_str))
+    .map_err(|e| format!("Failed to load image: {}", e))?;
+
+    let final_image = process_image_for_export(
+        source_path_str,
+        &base_image,
+        &js_adjustments,
+        export_settings,
+        context,
+        state,
+        is_raw,
+    )?;
+
+    encode_export(
+        &final_image,
+        source_path_str,
+        output_format,
+        export_settings,
+    )
+}
+
+/// Encodes a rendered image and writes the source's metadata into it as the
+/// settings allow.
+fn encode_export(
+    final_image: &DynamicImage,
+    source_path_str: &str,
+    output_format: &str,
+    export_settings: &ExportSettings,
+) -> Result<Vec<u8>, String> {
+    let mut image_bytes = encode_image_to_bytes(
+        final_image,
+        output_format,
+        export_settings.jpeg_quality,
+        export_settings.chroma_subsampling,
+    )?;
+
+    exif_processing::write_image_with_metadata(
+        &mut image_bytes,
+        source_path_str,
+        output_format,
+        export_settings.keep_metadata,
+        export_settings.strip_gps,
+    )?;
+
+    Ok(image_bytes)
+}
+
+/// Renders one job with `export` (source path, adjustments, settings,
+/// format) and writes the result to the job's output path.
+fn run_export_job(
+    job: &ExportJob,
+    export: impl FnOnce(&str, Value, &ExportSettings, &str) -> Result<Vec<u8>, String>,
+) -> Result<(), String> {
+    let (source_path, _) = parse_virtual_path(&job.input_path);
+    let image_bytes = export(
+        &source_path.to_string_lossy(),
+        job.adjustments.clone(),
+        &job.export_settings(),
+        &job.resolved_output_format(),
+    )?;
+
+    fs::write(&job.output_path, image_bytes).map_err(|e| format!("Failed to write output: {}", e))
+}
+
+/// Runs every job with `run` in parallel, so one failure does not stop the
+/// others, and passes each outcome to `report` as it finishes.
+fn run_export_jobs(
+    jobs: &[ExportJob],
+    run: impl Fn(&ExportJob) -> Result<(), String> + Sync,
+    report: impl Fn(&ExportJobResult) + Sync,
+) -> Vec<ExportJobResult> {
+    jobs.par_iter()
+        .map(|job| {
+            let result = ExportJobResult {
+                input_path: job.input_path.clone(),
+                output_path: job.output_path.clone(),
+                error: run(job).err(),
+            };
+            if let Some(e) = &result.error {
+                log::error!("Batch export of '{}' failed: {}", job.input_path, e);
+            }
+            report(&result);
+            result
+        })
+        .collect()
+}
+
+#[tauri::command]
+async fn export_batch(
+    jobs: Vec<ExportJob>,
+    state: tauri::State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    if state.export_task_handle.lock().unwrap().is_some() {
+        return Err("An export is already in progress.".to_string());
+    }
+
+    let context = Arc::new(get_or_init_gpu_context(&state)?);
+    let progress_counter = Arc::new(AtomicUsize::new(0));
+    let available_cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let num_threads = (available_cores / 2).clamp(1, 4);
+
+    let task = tokio::spawn(async move {
+        let state = app_handle.state::<AppState>();
+        let total = jobs.len();
+        let settings = load_settings(app_handle.clone()).unwrap_or_default();
+        let highlight_compression = settings.raw_highlight_compression.unwrap_or(2.5);
+
+        let pool = match rayon::ThreadPoolBuilder::new().num_threads(num_threads).build() {
+            Ok(pool) => pool,
+            Err(e) => {
+                let _ = app_handle.emit("export-error", format!("Failed to initialize worker threads: {}", e));
+                *app_handle.state::<AppState>().export_task_handle.lock().unwrap() = None;
+                return;
+            }
+        };
+
+        let results: Vec<ExportJobResult> = pool.install(|| {
+            run_export_jobs(
+                &jobs,
+                |job| {
+                    let cancelled = app_handle
+                        .state::<AppState>()
+                        .export_task_handle
+                        .lock()
+                        .unwrap()
+                        .is_none();
+                    if cancelled {
+                        return Err("Export cancelled".to_string());
+                    }
+                    run_export_job(job, |source_path, adjustments, settings, format| {
+                        export_image_to_bytes(
+                            source_path,
+                            adjustments,
+                            settings,
+                            format,
+                            &context,
+                            &state,
+                            highlight_compression,
+                        )
+                    })
+                },
+                |result| {
+                    let current = progress_counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    let _ = app_handle.emit(
+                        "export-batch-progress",
+                        serde_json::json!({
+                            "current": current,
+                            "total": total,
+                            "result": result,
+                        }),
+                    );
+                },
+            )
+        });
+
+        let _ = app_handle.emit("export-batch-complete", &results);
+
+        *app_handle
+            .state::<AppState>()
+            .export_task_handle
+            .lock()
+            .unwrap() = None;
+    });
+
+    *state.export_task_handle.lock().unwrap() = Some(task);
+    Ok(())
+}
+
 #[tauri::command]
 fn cancel_export(state: tauri::State<AppState>) -> Result<(), String> {
     match state.export_task_handle.lock().unwrap().take() {
@@ -1756,6 +2035,92 @@ fn cancel_export(state: tauri::State<AppState>) -> Result<(), String> {
     Ok(())
 }
 
+/// Develops each RAW in `paths` to a 16-bit TIFF in `output_folder`, with the
+/// default CPU tone mapping. Emits `raw-batch-progress` per file and returns
+/// how many were written; `cancel_raw_batch` stops it starting new files.
+#[tauri::command]
+async fn develop_raw_batch(
+    paths: Vec<String>,
+    output_folder: String,
+    fast_demosaic: bool,
+    state: tauri::State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<raw_processing::RawBatchSummary, String> {
+    let cancel = state.raw_batch_cancellation_token.clone();
+    cancel.store(false, Ordering::SeqCst);
+
+    let settings = load_settings(app_handle.clone()).unwrap_or_default();
+    let highlight_compression = settings.raw_highlight_compression.unwrap_or(2.5);
+    let available_cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let num_threads = (available_cores / 2).clamp(1, 4);
+
+    tokio::task::spawn_blocking(move || {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .map_err(|e| format!("Failed to initialize worker threads: {}", e))?;
+        let output_folder = PathBuf::from(output_folder);
+        let mut sources: Vec<PathBuf> = paths.iter().map(|p| parse_virtual_path(p).0).collect();
+        let mut seen = std::collections::HashSet::new();
+        sources.retain(|source| seen.insert(source.clone()));
+        let output_paths: HashMap<PathBuf, PathBuf> = sources
+            .iter()
+            .cloned()
+            .zip(raw_processing::batch_output_stems(&sources))
+            .map(|(source, stem)| (source, output_folder.join(format!("{}.tif", stem))))
+            .collect();
+        let total = sources.len();
+        let progress_counter = AtomicUsize::new(0);
+
+        let summary = raw_processing::develop_raw_batch(
+            &sources,
+            &pool,
+            fast_demosaic,
+            highlight_compression,
+            &cancel,
+            |path, developed| {
+                let result = developed.and_then(|mut image| {
+                    apply_cpu_default_raw_processing(&mut image);
+                    DynamicImage::ImageRgb16(image.to_rgb16())
+                        .save_with_format(&output_paths[path], image::ImageFormat::Tiff)?;
+                    Ok(())
+                });
+
+                let current = progress_counter.fetch_add(1, Ordering::SeqCst) + 1;
+                let _ = app_handle.emit(
+                    "raw-batch-progress",
+                    serde_json::json!({
+                        "current": current,
+                        "total": total,
+                        "path": path.to_string_lossy(),
+                        "error": result.as_ref().err().map(|e| e.to_string()),
+                    }),
+                );
+                result
+            },
+        );
+
+        log::info!(
+            "Batch develop finished: {}/{} completed, {} failed, cancelled: {}",
+            summary.completed,
+            summary.total,
+            summary.failed,
+            summary.cancelled
+        );
+        Ok(summary)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+fn cancel_raw_batch(state: tauri::State<AppState>) -> Result<(), String> {
+    state
+        .raw_batch_cancellation_token
+        .store(true, Ordering::SeqCst);
+    Ok(())
+}
+
 #[tauri::command]
 async fn estimate_export_size(
     js_adjustments: Value,
@@ -1835,6 +2200,7 @@ async fn estimate_export_size(
         &processed_preview,
         &output_format,
         export_settings.jpeg_quality,
+        export_settings.chroma_subsampling,
     )?;
     let preview_byte_size = preview_bytes.len();
 
@@ -1927,7 +2293,6 @@ async fn estimate_batch_export_size(
     let first_path = &paths[0];
     let (source_path, sidecar_path) = parse_virtual_path(first_path);
     let source_path_str = source_path.to_string_lossy().to_string();
-    let is_raw = is_raw_file(&source_path_str);
 
     let metadata: ImageMetadata = if sidecar_path.exists() {
         let file_content = fs::read_to_string(sidecar_path).map_err(|e| e.to_string())?;
@@ -1942,8 +2307,8 @@ async fn estimate_batch_export_size(
 
     const ESTIMATE_DIM: u32 = 1280;
 
-    let original_image = match read_file_mapped(Path::new(&source_path_str)) {
-        Ok(mmap) => load_base_image_from_bytes(&mmap, &source_path_str, true, highlight_compression, None)
+    let original = match read_file_mapped(Path::new(&source_path_str)) {
+        Ok(mmap) => load_base_image_from_bytes_oriented(&mmap, &source_path_str, true, highlight_compression, None, true)
             .map_err(|e| e.to_string())?,
         Err(e) => {
             log::warn!(
@@ -1952,10 +2317,12 @@ async fn estimate_batch_export_size(
                 e
             );
             let bytes = fs::read(&source_path_str).map_err(|io_err| io_err.to_string())?;
-            load_base_image_from_bytes(&bytes, &source_path_str, true, highlight_compression, None)
+            load_base_image_from_bytes_oriented(&bytes, &source_path_str, true, highlight_compression, None, true)
                 .map_err(|e| e.to_string())?
         }
     };
+    let is_raw = original.is_raw(&source_path_str);
+    let original_image = original.image;
 
     let base_image_preview = downscale_f32_image(&original_image, ESTIMATE_DIM, ESTIMATE_DIM);
 
@@ -1998,6 +2365,7 @@ async fn estimate_batch_export_size(
         &processed_preview,
         &output_format,
         export_settings.jpeg_quality,
+        export_settings.chroma_subsampling,
     )?;
     let single_image_estimated_size = preview_bytes.len();
 
@@ -2474,13 +2842,20 @@ async fn invoke_generative_replace_with_mask_def(
 
         let (real_path_buf, _) = crate::file_management::parse_virtual_path(&path);
         let real_path_str = real_path_buf.to_string_lossy().to_string();
+        let source_id_mode = if settings.ai_connector_content_hash.unwrap_or(true) {
+            ai_connector::SourceIdMode::Content
+        } else {
+            ai_connector::SourceIdMode::Metadata
+        };
 
         ai_connector::process_inpainting(
             &address,
             &real_path_str,
             &source_image,
             &mask_image_dynamic,
-            patch_definition.prompt
+            patch_definition.prompt,
+            source_id_mode,
+            &state.ai_source_ids,
         ).await.map_err(|e| e.to_string())?
     } else if let Some(auth_token) = token {
         // convenience cloud service
@@ -2641,12 +3016,12 @@ async fn generate_all_community_previews(
         let (source_path, _) = parse_virtual_path(image_path);
         let source_path_str = source_path.to_string_lossy().to_string();
         let image_bytes = fs::read(&source_path).map_err(|e| e.to_string())?;
-        let original_image =
-            crate::image_loader::load_base_image_from_bytes(&image_bytes, &source_path_str, true, highlight_compression, None)
+        let original =
+            load_base_image_from_bytes_oriented(&image_bytes, &source_path_str, true, highlight_compression, None, true)
                 .map_err(|e| e.to_string())?;
-        let is_raw = is_raw_file(&source_path_str);
+        let is_raw = original.is_raw(&source_path_str);
         base_thumbnails.push((
-            downscale_f32_image(&original_image, PROCESSING_DIM, PROCESSING_DIM),
+            downscale_f32_image(&original.image, PROCESSING_DIM, PROCESSING_DIM),
             is_raw,
         ));
     }
@@ -2981,11 +3356,10 @@ fn generate_preview_for_path(
     let context = get_or_init_gpu_context(&state)?;
     let (source_path, _) = parse_virtual_path(&path);
     let source_path_str = source_path.to_string_lossy().to_string();
-    let is_raw = is_raw_file(&source_path_str);
     let settings = load_settings(app_handle.clone()).unwrap_or_default();
     let highlight_compression = settings.raw_highlight_compression.unwrap_or(2.5);
 
-    let base_image = match read_file_mapped(&source_path) {
+    let base = match read_file_mapped(&source_path) {
         Ok(mmap) => load_and_composite(
             &mmap,
             &source_path_str,
@@ -3013,6 +3387,14 @@ fn generate_preview_for_path(
             .map_err(|e| e.to_string())?
         }
     };
+    if base.degraded {
+        log::warn!(
+            "Previewing the embedded preview of '{}' without RAW processing",
+            source_path_str
+        );
+    }
+    let is_raw = base.is_raw(&source_path_str);
+    let base_image = base.image;
 
     let (transformed_image, unscaled_crop_offset) =
         apply_all_transformations(&base_image, &js_adjustments);
@@ -3054,7 +3436,7 @@ async fn load_and_parse_lut(
     state: tauri::State<'_, AppState>,
 ) -> Result<LutParseResult, String> {
     let lut = lut_processing::parse_lut_file(&path).map_err(|e| e.to_string())?;
-    let lut_size = lut.size;
+    let lut_size = lut.size as u32;
 
     let mut cache = state.lut_cache.lock().unwrap();
     cache.insert(path, Arc::new(lut));
@@ -3327,12 +3709,14 @@ fn main() {
             lut_cache: Mutex::new(HashMap::new()),
             initial_file_path: Mutex::new(None),
             thumbnail_cancellation_token: Arc::new(AtomicBool::new(false)),
+            raw_batch_cancellation_token: Arc::new(AtomicBool::new(false)),
             preview_worker_tx: Mutex::new(None),
             mask_cache: Mutex::new(HashMap::new()),
             patch_cache: Mutex::new(HashMap::new()),
             geometry_cache: Mutex::new(HashMap::new()),
             thumbnail_geometry_cache: Mutex::new(HashMap::new()),
             lens_db: Mutex::new(None),
+            ai_source_ids: Mutex::new(HashMap::new()),
             load_image_generation: Arc::new(AtomicUsize::new(0)),
         })
         .invoke_handler(tauri::generate_handler![
@@ -3340,7 +3724,10 @@ fn main() {
             apply_adjustments,
             export_image,
             batch_export_images,
+            export_batch,
             cancel_export,
+            develop_raw_batch,
+            cancel_raw_batch,
             estimate_export_size,
             estimate_batch_export_size,
             generate_fullscreen_preview,
@@ -3369,6 +3756,7 @@ fn main() {
             generate_all_community_previews,
             save_temp_file,
             get_image_dimensions,
+            can_decode_raw,
             frontend_ready,
             cancel_thumbnail_generation,
             image_processing::generate_histogram,
@@ -3416,9 +3804,11 @@ fn main() {
             tagging::remove_tag_for_paths,
             culling::cull_images,
             lens_correction::get_lensfun_makers,
+            lens_correction::reload_lens_db,
             lens_correction::get_lensfun_lenses_for_maker,
             lens_correction::autodetect_lens,
             lens_correction::get_lens_distortion_params,
+            lens_correction::lens_correction_available,
         ])
         .build(tauri::generate_context!())
         .expect("error while building tauri application")
@@ -3442,4 +3832,124 @@ fn main() {
                 _ => {}
             }
         });
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_job_defaults_to_quality_90_and_keeps_metadata() {
+        let job: ExportJob = serde_json::from_value(serde_json::json!({
+            "inputPath": "/photos/a.nef",
+            "adjustments": {},
+            "outputPath": "/out/a.JPG",
+        }))
+        .unwrap();
+        assert_eq!(job.quality, 90);
+        assert!(matches!(job.metadata_policy, MetadataPolicy::Keep));
+        assert_eq!(job.resolved_output_format(), "jpg");
+    }
+
+    #[test]
+    fn export_job_format_prefers_the_explicit_setting() {
+        let job: ExportJob = serde_json::from_value(serde_json::json!({
+            "inputPath": "/photos/a.nef",
+            "adjustments": {},
+            "outputPath": "/out/a",
+            "outputFormat": "TIFF",
+            "metadataPolicy": "keepWithoutGps",
+        }))
+        .unwrap();
+        assert_eq!(job.resolved_output_format(), "tiff");
+        assert!(matches!(job.metadata_policy, MetadataPolicy::KeepWithoutGps));
+
+        let bare = ExportJob {
+            output_format: None,
+            ..job
+        };
+        assert_eq!(bare.resolved_output_format(), "jpg");
+    }
+
+    fn export_job(input: &Path, output: &Path, policy: &str) -> ExportJob {
+        serde_json::from_value(serde_json::json!({
+            "inputPath": input,
+            "adjustments": {},
+            "outputPath": output,
+            "metadataPolicy": policy,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn one_batch_exports_jpeg_and_tiff_with_their_metadata_policy() {
+        use little_exif::{exif_tag::ExifTag, filetype::FileExtension, metadata::Metadata};
+
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.jpg");
+        let pixels = DynamicImage::ImageRgb8(image::RgbImage::from_fn(8, 6, |x, y| {
+            image::Rgb([(x * 30) as u8, (y * 40) as u8, 128])
+        }));
+        let mut source_bytes = Vec::new();
+        pixels
+            .write_to(
+                &mut Cursor::new(&mut source_bytes),
+                image::ImageFormat::Jpeg,
+            )
+            .unwrap();
+        let mut source_exif = Metadata::new();
+        source_exif.set_tag(ExifTag::Make("TestCam".to_string()));
+        source_exif.set_tag(ExifTag::GPSLatitudeRef("N".to_string()));
+        source_exif
+            .write_to_vec(&mut source_bytes, FileExtension::JPEG)
+            .unwrap();
+        fs::write(&source, source_bytes).unwrap();
+
+        let missing = dir.path().join("missing.jpg");
+        let jobs = [
+            export_job(&source, &dir.path().join("out.jpg"), "keepWithoutGps"),
+            export_job(&missing, &dir.path().join("lost.jpg"), "keep"),
+            export_job(&source, &dir.path().join("out.tiff"), "keep"),
+        ];
+        // Decoding stands in for the GPU render, which tests cannot run.
+        let reported = AtomicUsize::new(0);
+        let results = run_export_jobs(
+            &jobs,
+            |job| {
+                run_export_job(job, |source_path, _, settings, format| {
+                    let image = image::open(source_path).map_err(|e| e.to_string())?;
+                    encode_export(&image, source_path, format, settings)
+                })
+            },
+            |_| {
+                reported.fetch_add(1, Ordering::SeqCst);
+            },
+        );
+
+        assert_eq!(reported.load(Ordering::SeqCst), 3);
+        assert!(results[0].error.is_none() && results[2].error.is_none());
+        assert_eq!(results[1].input_path, jobs[1].input_path);
+        assert!(results[1].error.is_some());
+        assert!(!dir.path().join("lost.jpg").exists());
+
+        let jpeg = fs::read(&jobs[0].output_path).unwrap();
+        assert_eq!(
+            image::guess_format(&jpeg).unwrap(),
+            image::ImageFormat::Jpeg
+        );
+        let exif = exif::Reader::new()
+            .read_from_container(&mut Cursor::new(&jpeg))
+            .unwrap();
+        assert!(exif.get_field(exif::Tag::Make, exif::In::PRIMARY).is_some());
+        assert!(exif
+            .get_field(exif::Tag::GPSLatitudeRef, exif::In::PRIMARY)
+            .is_none());
+
+        let tiff = fs::read(&jobs[2].output_path).unwrap();
+        assert_eq!(
+            image::guess_format(&tiff).unwrap(),
+            image::ImageFormat::Tiff
+        );
+        assert_eq!(image::load_from_memory(&tiff).unwrap().dimensions(), (8, 6));
+    }
+}
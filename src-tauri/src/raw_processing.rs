@@ -1,24 +1,60 @@
 use crate::image_processing::apply_orientation;
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use image::{DynamicImage, ImageBuffer, Rgba};
+use rapidraw_core::highlight::rescale_and_compress;
+use rapidraw_core::raw_sanity::{default_crop_in_active_area, sanitize_levels};
 use rawler::{
     decoders::{Orientation, RawDecodeParams},
     imgop::develop::{DemosaicAlgorithm, Intermediate, ProcessingStep, RawDevelop},
     rawimage::RawImage,
     rawsource::RawSource,
 };
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::panic;
+use std::path::{Path, PathBuf};
 use std::sync::{
-    atomic::{AtomicUsize, Ordering},
+    atomic::{AtomicBool, AtomicUsize, Ordering},
     Arc,
 };
 
+/// Whether rawler has a decoder for `file_bytes`. Only the decoder lookup
+/// runs, not the decode, so this is cheap enough to call per file before
+/// offering a develop. A decoder panic on malformed input counts as no.
+pub fn can_decode_raw(file_bytes: &[u8]) -> bool {
+    panic::catch_unwind(|| rawler::get_decoder(&RawSource::new_from_slice(file_bytes)).is_ok())
+        .unwrap_or(false)
+}
+
+/// The embedded camera preview (falling back to the thumbnail) with the
+/// orientation the file records, for showing the shot when the RAW itself
+/// cannot be developed. `None` when the file carries neither.
+pub fn extract_embedded_preview(file_bytes: &[u8]) -> Result<Option<(DynamicImage, Orientation)>> {
+    let source = RawSource::new_from_slice(file_bytes);
+    let decoder = rawler::get_decoder(&source)?;
+    let params = RawDecodeParams::default();
+    let orientation = decoder
+        .raw_metadata(&source, &params)
+        .ok()
+        .and_then(|metadata| metadata.exif.orientation)
+        .map(Orientation::from_u16)
+        .unwrap_or(Orientation::Normal);
+    let preview = match decoder.preview_image(&source, &params) {
+        Ok(Some(preview)) => Some(preview),
+        _ => decoder.thumbnail_image(&source, &params).ok().flatten(),
+    };
+    Ok(preview.map(|preview| (preview, orientation)))
+}
+
+/// Develops a RAW with its orientation applied.
+#[allow(dead_code)]
 pub fn develop_raw_image(
     file_bytes: &[u8],
     fast_demosaic: bool,
     highlight_compression: f32,
     cancel_token: Option<(Arc<AtomicUsize>, usize)>,
 ) -> Result<DynamicImage> {
-    let (developed_image, orientation) = develop_internal(
+    let (developed_image, orientation) = develop_raw_image_unoriented(
         file_bytes,
         fast_demosaic,
         highlight_compression,
@@ -27,11 +63,120 @@ pub fn develop_raw_image(
     Ok(apply_orientation(developed_image, orientation))
 }
 
-fn develop_internal(
+/// Develops a RAW in sensor orientation and returns it with the orientation
+/// the file records, for the caller to apply (or not).
+pub fn develop_raw_image_unoriented(
     file_bytes: &[u8],
     fast_demosaic: bool,
     highlight_compression: f32,
     cancel_token: Option<(Arc<AtomicUsize>, usize)>,
+) -> Result<(DynamicImage, Orientation)> {
+    let source = RawSource::new_from_slice(file_bytes);
+    develop_internal(&source, fast_demosaic, highlight_compression, cancel_token)
+}
+
+/// Develops a RAW from disk with its orientation applied. The file is
+/// memory-mapped instead of read into memory up front, so large RAWs on slow
+/// mobile storage are paged in as the decoder touches them.
+pub fn develop_raw_image_from_path(
+    path: &Path,
+    fast_demosaic: bool,
+    highlight_compression: f32,
+    cancel_token: Option<(Arc<AtomicUsize>, usize)>,
+) -> Result<DynamicImage> {
+    let source = RawSource::new(path)
+        .with_context(|| format!("Failed to open RAW file '{}'", path.display()))?;
+    let (developed_image, orientation) = develop_internal(
+        &source,
+        fast_demosaic,
+        highlight_compression,
+        cancel_token,
+    )?;
+    Ok(apply_orientation(developed_image, orientation))
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RawBatchSummary {
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub cancelled: bool,
+}
+
+/// Output file stems for a batch develop, one per path. Files sharing a stem
+/// (`IMG_0001.CR3` from two cards) get `_2`, `_3`, ... appended so none
+/// overwrites another. Stems are compared case-insensitively, as the output
+/// folder may be on a case-insensitive filesystem.
+pub fn batch_output_stems(paths: &[PathBuf]) -> Vec<String> {
+    let mut taken = HashSet::new();
+    paths
+        .iter()
+        .map(|path| {
+            let stem = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| "developed".to_string());
+            let mut candidate = stem.clone();
+            let mut counter = 2;
+            while !taken.insert(candidate.to_lowercase()) {
+                candidate = format!("{}_{}", stem, counter);
+                counter += 1;
+            }
+            candidate
+        })
+        .collect()
+}
+
+/// Develops `paths` on `pool`, passing each result to `on_developed` as it
+/// finishes. Once `cancel` is set no further file is started; files already
+/// in flight still run to completion and are counted.
+pub fn develop_raw_batch<F>(
+    paths: &[PathBuf],
+    pool: &rayon::ThreadPool,
+    fast_demosaic: bool,
+    highlight_compression: f32,
+    cancel: &AtomicBool,
+    on_developed: F,
+) -> RawBatchSummary
+where
+    F: Fn(&Path, Result<DynamicImage>) -> Result<()> + Sync,
+{
+    let outcomes: Vec<Option<bool>> = pool.install(|| {
+        paths
+            .par_iter()
+            .map(|path| {
+                if cancel.load(Ordering::SeqCst) {
+                    return None;
+                }
+                let developed = panic::catch_unwind(|| {
+                    develop_raw_image_from_path(path, fast_demosaic, highlight_compression, None)
+                })
+                .unwrap_or_else(|_| Err(anyhow!("Panic while developing '{}'", path.display())));
+                match on_developed(path, developed) {
+                    Ok(()) => Some(true),
+                    Err(e) => {
+                        log::warn!("Batch develop of '{}' failed: {}", path.display(), e);
+                        Some(false)
+                    }
+                }
+            })
+            .collect()
+    });
+
+    RawBatchSummary {
+        total: paths.len(),
+        completed: outcomes.iter().filter(|o| **o == Some(true)).count(),
+        failed: outcomes.iter().filter(|o| **o == Some(false)).count(),
+        cancelled: outcomes.iter().any(Option::is_none),
+    }
+}
+
+fn develop_internal(
+    source: &RawSource,
+    fast_demosaic: bool,
+    highlight_compression: f32,
+    cancel_token: Option<(Arc<AtomicUsize>, usize)>,
 ) -> Result<(DynamicImage, Orientation)> {
     let check_cancel = || -> Result<()> {
         if let Some((tracker, generation)) = &cancel_token {
@@ -44,19 +189,28 @@ fn develop_internal(
 
     check_cancel()?;
 
-    let source = RawSource::new_from_slice(file_bytes);
-    let decoder = rawler::get_decoder(&source)?;
-    
+    let decoder = rawler::get_decoder(source)?;
+
     check_cancel()?;
-    let mut raw_image: RawImage = decoder.raw_image(&source, &RawDecodeParams::default(), false)?;
+    let mut raw_image: RawImage = decoder.raw_image(source, &RawDecodeParams::default(), false)?;
+    if raw_image.width == 0 || raw_image.height == 0 {
+        return Err(anyhow!(
+            "RAW reports an empty sensor ({}x{})",
+            raw_image.width,
+            raw_image.height
+        ));
+    }
 
-    let metadata = decoder.raw_metadata(&source, &RawDecodeParams::default())?;
+    let metadata = decoder.raw_metadata(source, &RawDecodeParams::default())?;
     let orientation = metadata
         .exif
         .orientation
         .map(Orientation::from_u16)
         .unwrap_or(Orientation::Normal);
 
+    if let Some(replaced) = sanitize_levels(&mut raw_image) {
+        log::warn!("Replaced unusable RAW levels: {}", replaced);
+    }
     let original_white_level = raw_image
         .whitelevel
         .0
@@ -80,6 +234,14 @@ fn develop_internal(
         developer.demosaic_algorithm = DemosaicAlgorithm::Speed;
     }
     developer.steps.retain(|&step| step != ProcessingStep::SRgb);
+    if !default_crop_in_active_area(&raw_image) {
+        log::warn!(
+            "Ignoring default crop {:?} outside the active area {:?}",
+            raw_image.crop_area,
+            raw_image.active_area
+        );
+        developer.steps.retain(|&step| step != ProcessingStep::CropDefault);
+    }
 
     check_cancel()?;
     let mut developed_intermediate = developer.develop_intermediate(&raw_image)?;
@@ -87,8 +249,6 @@ fn develop_internal(
     let denominator = (original_white_level - original_black_level).max(1.0);
     let rescale_factor = (headroom_white_level - original_black_level) / denominator;
 
-    let safe_highlight_compression = highlight_compression.max(1.01);
-
     check_cancel()?;
 
     match &mut developed_intermediate {
@@ -99,42 +259,7 @@ fn develop_internal(
             });
         }
         Intermediate::ThreeColor(pixels) => {
-            pixels.data.iter_mut().for_each(|p| {
-                let r = (p[0] * rescale_factor).max(0.0);
-                let g = (p[1] * rescale_factor).max(0.0);
-                let b = (p[2] * rescale_factor).max(0.0);
-
-                let max_c = r.max(g).max(b);
-
-                let (final_r, final_g, final_b) = if max_c > 1.0 {
-                    let min_c = r.min(g).min(b);
-                    let compression_factor = (1.0
-                        - (max_c - 1.0) / (safe_highlight_compression - 1.0))
-                        .max(0.0)
-                        .min(1.0);
-                    let compressed_r = min_c + (r - min_c) * compression_factor;
-                    let compressed_g = min_c + (g - min_c) * compression_factor;
-                    let compressed_b = min_c + (b - min_c) * compression_factor;
-                    let compressed_max = compressed_r.max(compressed_g).max(compressed_b);
-
-                    if compressed_max > 1e-6 {
-                        let rescale = max_c / compressed_max;
-                        (
-                            compressed_r * rescale,
-                            compressed_g * rescale,
-                            compressed_b * rescale,
-                        )
-                    } else {
-                        (max_c, max_c, max_c)
-                    }
-                } else {
-                    (r, g, b)
-                };
-
-                p[0] = final_r;
-                p[1] = final_g;
-                p[2] = final_b;
-            });
+            rescale_and_compress(&mut pixels.data, rescale_factor, highlight_compression);
         }
         Intermediate::FourColor(pixels) => {
             pixels.data.iter_mut().for_each(|p| {
@@ -176,4 +301,43 @@ fn develop_internal(
     };
 
     Ok((dynamic_image, orientation))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_output_stems_do_not_collide() {
+        let paths: Vec<PathBuf> = [
+            "a/IMG_0001.CR3",
+            "b/IMG_0001.CR3",
+            "c/img_0001.nef",
+            "IMG_0001_2.arw",
+            "d/IMG_0002.CR3",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        assert_eq!(
+            batch_output_stems(&paths),
+            ["IMG_0001", "IMG_0001_2", "img_0001_3", "IMG_0001_2_2", "IMG_0002"]
+        );
+    }
+
+    #[test]
+    fn missing_file_error_names_the_path() {
+        let path = std::env::temp_dir().join("rapidraw-missing-IMG_0001.CR3");
+        let err = develop_raw_image_from_path(&path, true, 0.0, None).unwrap_err();
+        let message = err.to_string();
+        assert!(message.starts_with("Failed to open RAW file"));
+        assert!(message.contains("rapidraw-missing-IMG_0001.CR3"));
+    }
+
+    #[test]
+    fn garbage_and_empty_input_cannot_be_decoded() {
+        assert!(!can_decode_raw(&[]));
+        assert!(!can_decode_raw(b"II*\x00 truncated tiff"));
+        assert!(!can_decode_raw(&[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10]));
+    }
+}
@@ -76,10 +76,10 @@ struct GlobalAdjustments {
     has_lut: u32,
     lut_intensity: f32,
     tonemapper_mode: u32,
-    _pad_lut2: f32,
-    _pad_lut3: f32,
-    _pad_lut4: f32,
-    _pad_lut5: f32,
+    contrast_mode: u32,
+    red_gain: f32,
+    green_gain: f32,
+    blue_gain: f32,
 
     _pad_agx1: f32,
     _pad_agx2: f32,
@@ -110,6 +110,10 @@ struct GlobalAdjustments {
     red_curve_count: u32,
     green_curve_count: u32,
     blue_curve_count: u32,
+    lut_domain_min: vec3<f32>,
+    _pad_lut1: f32,
+    lut_domain_max: vec3<f32>,
+    _pad_lut2: f32,
     _pad_end1: f32,
     _pad_end2: f32,
     _pad_end3: f32,
@@ -360,7 +364,41 @@ fn apply_curve(val: f32, points: array<Point, 16>, count: u32) -> f32 {
     return local_points[count - 1u].y / 255.0;
 }
 
-fn apply_tonal_adjustments(color: vec3<f32>, con: f32, sh: f32, wh: f32, bl: f32) -> vec3<f32> {
+// Filmic toe and shoulder exponents; the shoulder's lower exponent gives it
+// the longer roll-off and caps the midtone slope.
+const FILMIC_TOE: f32 = 3.0;
+const FILMIC_SHOULDER: f32 = 2.0;
+
+// Power segment k / (n * end^(n-1)) * x^n, meeting the midtone line of slope k
+// with equal value and slope at x = end.
+fn filmic_segment(x: vec3<f32>, n: f32, end: f32, k: f32) -> vec3<f32> {
+    return k / (n * pow(end, n - 1.0)) * pow(x, vec3<f32>(n));
+}
+
+// Shapes perceptual values in 0..1 around mid-gray with midtone slope `slope`.
+// Modes: 0 linear, 1 S-curve, 2 filmic (straight midtones, toe and shoulder).
+fn apply_contrast_curve(p: vec3<f32>, slope: f32, mode: u32) -> vec3<f32> {
+    let linear_part = clamp((p - 0.5) * slope + 0.5, vec3<f32>(0.0), vec3<f32>(1.0));
+    if (mode == 0u || (mode == 2u && slope <= 1.0)) {
+        return linear_part;
+    }
+    if (mode == 2u) {
+        let k = min(slope, FILMIC_SHOULDER);
+        let toe = FILMIC_TOE * (k - 1.0) / (2.0 * k * (FILMIC_TOE - 1.0));
+        let shoulder = FILMIC_SHOULDER * (k - 1.0) / (2.0 * k * (FILMIC_SHOULDER - 1.0));
+        let mid = (p - 0.5) * k + 0.5;
+        let toe_part = filmic_segment(p, FILMIC_TOE, toe, k);
+        let shoulder_part = 1.0 - filmic_segment(1.0 - p, FILMIC_SHOULDER, shoulder, k);
+        let upper = select(mid, shoulder_part, p > vec3<f32>(1.0 - shoulder));
+        return select(upper, toe_part, p < vec3<f32>(toe));
+    }
+    let condition = p < vec3<f32>(0.5);
+    let high_part = 1.0 - 0.5 * pow(2.0 * (1.0 - p), vec3<f32>(slope));
+    let low_part = 0.5 * pow(2.0 * p, vec3<f32>(slope));
+    return select(high_part, low_part, condition);
+}
+
+fn apply_tonal_adjustments(color: vec3<f32>, con: f32, contrast_mode: u32, sh: f32, wh: f32, bl: f32) -> vec3<f32> {
     var rgb = color;
     if (wh != 0.0) {
         let white_level = 1.0 - wh * 0.25;
@@ -392,10 +430,7 @@ fn apply_tonal_adjustments(color: vec3<f32>, con: f32, sh: f32, wh: f32, bl: f32
         let perceptual = pow(safe_rgb, vec3<f32>(1.0 / g));
         let clamped_perceptual = clamp(perceptual, vec3<f32>(0.0), vec3<f32>(1.0));
         let strength = pow(2.0, con * 1.25);
-        let condition = clamped_perceptual < vec3<f32>(0.5);
-        let high_part = 1.0 - 0.5 * pow(2.0 * (1.0 - clamped_perceptual), vec3<f32>(strength));
-        let low_part = 0.5 * pow(2.0 * clamped_perceptual, vec3<f32>(strength));
-        let curved_perceptual = select(high_part, low_part, condition);
+        let curved_perceptual = apply_contrast_curve(clamped_perceptual, strength, contrast_mode);
         let contrast_adjusted_rgb = pow(curved_perceptual, vec3<f32>(g));
         let mix_factor = smoothstep(vec3<f32>(1.0), vec3<f32>(1.01), safe_rgb);
         rgb = mix(contrast_adjusted_rgb, rgb, mix_factor);
@@ -988,7 +1023,7 @@ fn apply_all_adjustments(initial_rgb: vec3<f32>, adj: GlobalAdjustments, coords_
     processed_rgb = apply_centre_tonal_and_color(processed_rgb, adj.centre, coords_i);
     processed_rgb = apply_white_balance(processed_rgb, adj.temperature, adj.tint);
     processed_rgb = apply_filmic_exposure(processed_rgb, adj.brightness);
-    processed_rgb = apply_tonal_adjustments(processed_rgb, adj.contrast, adj.shadows, adj.whites, adj.blacks);
+    processed_rgb = apply_tonal_adjustments(processed_rgb, adj.contrast, adj.contrast_mode, adj.shadows, adj.whites, adj.blacks);
     processed_rgb = apply_highlights_adjustment(processed_rgb, adj.highlights);
 
     processed_rgb = apply_color_calibration(processed_rgb, adj.color_calibration);
@@ -999,7 +1034,7 @@ fn apply_all_adjustments(initial_rgb: vec3<f32>, adj: GlobalAdjustments, coords_
     return processed_rgb;
 }
 
-fn apply_all_mask_adjustments(initial_rgb: vec3<f32>, adj: MaskAdjustments, coords_i: vec2<i32>, id: vec2<u32>, scale: f32, is_raw: u32, tonemapper_mode: u32) -> vec3<f32> {
+fn apply_all_mask_adjustments(initial_rgb: vec3<f32>, adj: MaskAdjustments, coords_i: vec2<i32>, id: vec2<u32>, scale: f32, is_raw: u32, tonemapper_mode: u32, contrast_mode: u32) -> vec3<f32> {
     var processed_rgb = apply_noise_reduction(initial_rgb, coords_i, adj.luma_noise_reduction, adj.color_noise_reduction, scale);
 
     processed_rgb = apply_dehaze(processed_rgb, adj.dehaze);
@@ -1007,7 +1042,7 @@ fn apply_all_mask_adjustments(initial_rgb: vec3<f32>, adj: MaskAdjustments, coor
     processed_rgb = apply_white_balance(processed_rgb, adj.temperature, adj.tint);
     processed_rgb = apply_filmic_exposure(processed_rgb, adj.brightness);
     processed_rgb = apply_highlights_adjustment(processed_rgb, adj.highlights);
-    processed_rgb = apply_tonal_adjustments(processed_rgb, adj.contrast, adj.shadows, adj.whites, adj.blacks);
+    processed_rgb = apply_tonal_adjustments(processed_rgb, adj.contrast, contrast_mode, adj.shadows, adj.whites, adj.blacks);
 
     processed_rgb = apply_hsl_panel(processed_rgb, adj.hsl, coords_i);
     processed_rgb = apply_color_grading(processed_rgb, adj.color_grading_shadows, adj.color_grading_midtones, adj.color_grading_highlights, adj.color_grading_blending, adj.color_grading_balance);
@@ -1150,7 +1185,8 @@ fn main(@builtin(global_invocation_id) id: vec3<u32>) {
     locally_contrasted_rgb = apply_local_contrast(locally_contrasted_rgb, structure_blurred, adjustments.global.structure, adjustments.global.is_raw_image, 1u);
     locally_contrasted_rgb = apply_centre_local_contrast(locally_contrasted_rgb, adjustments.global.centre, absolute_coord_i, clarity_blurred, adjustments.global.is_raw_image);
 
-    var processed_rgb = apply_linear_exposure(locally_contrasted_rgb, adjustments.global.exposure);
+    let channel_gains = max(vec3<f32>(adjustments.global.red_gain, adjustments.global.green_gain, adjustments.global.blue_gain), vec3<f32>(0.0));
+    var processed_rgb = apply_linear_exposure(locally_contrasted_rgb * channel_gains, adjustments.global.exposure);
 
     if (adjustments.global.is_raw_image == 1u && adjustments.global.tonemapper_mode != 1u) {
         var srgb_emulated = linear_to_srgb(processed_rgb);
@@ -1174,7 +1210,7 @@ fn main(@builtin(global_invocation_id) id: vec3<u32>) {
             mask_base_linear = apply_local_contrast(mask_base_linear, clarity_blurred, mask_adj.clarity, adjustments.global.is_raw_image, 1u);
             mask_base_linear = apply_local_contrast(mask_base_linear, structure_blurred, mask_adj.structure, adjustments.global.is_raw_image, 1u);
 
-            let mask_adjusted_linear = apply_all_mask_adjustments(mask_base_linear, mask_adj, absolute_coord_i, id.xy, scale, adjustments.global.is_raw_image, adjustments.global.tonemapper_mode);
+            let mask_adjusted_linear = apply_all_mask_adjustments(mask_base_linear, mask_adj, absolute_coord_i, id.xy, scale, adjustments.global.is_raw_image, adjustments.global.tonemapper_mode, adjustments.global.contrast_mode);
             composite_rgb_linear = mix(composite_rgb_linear, mask_adjusted_linear, influence);
         }
     }
@@ -1207,7 +1243,8 @@ fn main(@builtin(global_invocation_id) id: vec3<u32>) {
     }
 
     if (adjustments.global.has_lut == 1u) {
-        let lut_color = sample_lut_tetrahedral(final_rgb);
+        let g = adjustments.global;
+        let lut_color = sample_lut_tetrahedral((final_rgb - g.lut_domain_min) / (g.lut_domain_max - g.lut_domain_min));
         
         final_rgb = mix(final_rgb, lut_color, adjustments.global.lut_intensity);
     }
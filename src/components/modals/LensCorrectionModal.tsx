@@ -94,6 +94,33 @@ const DEFAULT_PARAMS: LensParams = {
   lensDistortionParams: null,
 };
 
+type LensCoefficients = NonNullable<LensParams['lensDistortionParams']>;
+
+const IDENTITY_COEFFICIENTS: LensCoefficients = {
+  k1: 0, k2: 0, k3: 0,
+  model: 0,
+  tca_vr: 1.0, tca_vb: 1.0,
+  vig_k1: 0, vig_k2: 0, vig_k3: 0,
+};
+
+// Ranges cover what Lensfun profiles use for ordinary lenses.
+const MANUAL_COEFFICIENTS: Array<{
+  key: Exclude<keyof LensCoefficients, 'model'>;
+  label: string;
+  min: number;
+  max: number;
+  step: number;
+}> = [
+  { key: 'k1', label: 'Distortion k1', min: -0.2, max: 0.2, step: 0.001 },
+  { key: 'k2', label: 'Distortion k2', min: -0.1, max: 0.1, step: 0.001 },
+  { key: 'k3', label: 'Distortion k3', min: -0.1, max: 0.1, step: 0.001 },
+  { key: 'tca_vr', label: 'Red Scale', min: 0.998, max: 1.002, step: 0.0001 },
+  { key: 'tca_vb', label: 'Blue Scale', min: 0.998, max: 1.002, step: 0.0001 },
+  { key: 'vig_k1', label: 'Vignetting k1', min: -1, max: 1, step: 0.01 },
+  { key: 'vig_k2', label: 'Vignetting k2', min: -1, max: 1, step: 0.01 },
+  { key: 'vig_k3', label: 'Vignetting k3', min: -1, max: 1, step: 0.01 },
+];
+
 const parseFocalLength = (exif: any): number | null => {
   if (!exif || !exif.FocalLength) return null;
   const val = parseFloat(exif.FocalLength);
@@ -375,6 +402,21 @@ export default function LensCorrectionModal({
     updatePreview(newParams);
   };
 
+  // Editing a coefficient detaches the correction from the selected profile,
+  // so the values are kept as entered rather than re-read from the database.
+  const handleCoefficientChange = (key: keyof LensCoefficients, value: number) => {
+    const newParams = {
+      ...params,
+      lensMaker: null,
+      lensModel: null,
+      lensDistortionParams: { ...(params.lensDistortionParams ?? IDENTITY_COEFFICIENTS), [key]: value },
+    };
+    setParams(newParams);
+    setLenses([]);
+    setDetectionStatus('idle');
+    updatePreview(newParams);
+  };
+
   const handleToggleChange = (key: keyof LensParams, val: boolean) => {
     const newParams = { ...params, [key]: val };
     setParams(newParams);
@@ -715,6 +757,27 @@ export default function LensCorrectionModal({
           </div>
         </div>
 
+        <div className="space-y-3">
+          <p className="text-sm font-semibold text-text-primary">Manual Coefficients</p>
+          <p className="text-xs text-text-secondary">
+            For lenses without a profile. Changing a value replaces the selected profile.
+          </p>
+          <div className="flex flex-col gap-2 px-2">
+            {MANUAL_COEFFICIENTS.map(({ key, label, min, max, step }) => (
+              <Slider
+                key={key}
+                label={label}
+                value={(params.lensDistortionParams ?? IDENTITY_COEFFICIENTS)[key]}
+                min={min}
+                max={max}
+                step={step}
+                defaultValue={IDENTITY_COEFFICIENTS[key]}
+                onChange={e => handleCoefficientChange(key, Number(e.target.value))}
+              />
+            ))}
+          </div>
+        </div>
+
         <div className="mt-auto pt-4 space-y-2">
           {currentAdjustments.masks && currentAdjustments.masks.length > 0 && (
             <div className="p-3 bg-surface rounded-md border border-surface flex items-center gap-3">